@@ -0,0 +1,33 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use lox::bench::{parse, run, scan, PROGRAMS};
+
+fn scanner(c: &mut Criterion) {
+    let mut group = c.benchmark_group("scan");
+    for (name, source) in PROGRAMS {
+        group.bench_function(*name, |b| b.iter(|| scan(source).unwrap()));
+    }
+    group.finish();
+}
+
+fn parser(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+    for (name, source) in PROGRAMS {
+        let tokens = scan(source).unwrap();
+        group.bench_function(*name, |b| b.iter(|| parse(&tokens).unwrap()));
+    }
+    group.finish();
+}
+
+fn interpreter(c: &mut Criterion) {
+    let mut group = c.benchmark_group("run");
+    // whole programs take milliseconds, fewer samples keep the suite short
+    group.sample_size(20);
+    for (name, source) in PROGRAMS {
+        let (ast, stmts) = parse(&scan(source).unwrap()).unwrap();
+        group.bench_function(*name, |b| b.iter(|| run(&ast, &stmts).unwrap()));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, scanner, parser, interpreter);
+criterion_main!(benches);
@@ -0,0 +1,11 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use lox::{Parser, Scanner};
+
+// whatever the scanner accepts the parser has to turn into statements or errors
+fuzz_target!(|source: &str| {
+    let mut scanner = Scanner::new(source);
+    scanner.scan_tokens();
+    let _ = Parser::new(&scanner.tokens).parse();
+});
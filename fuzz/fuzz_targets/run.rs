@@ -0,0 +1,39 @@
+#![no_main]
+
+use std::rc::Rc;
+
+use libfuzzer_sys::fuzz_target;
+use lox::{Interpreter, Object, Parser, Resolver, Scanner};
+
+/// statements a single input may execute, so `while (true) {}` ends
+const FUEL: usize = 10_000;
+
+// programs that parse and resolve run to a value or a Lox error,
+// closures keep their environment alive through an Rc cycle so long
+// sessions grow, give them a generous -rss_limit_mb
+fuzz_target!(|source: &str| {
+    let mut scanner = Scanner::new(source);
+    scanner.scan_tokens();
+    if scanner.had_error {
+        return;
+    }
+    let mut parser = Parser::new(&scanner.tokens);
+    let Ok(stmts) = parser.parse() else {
+        return;
+    };
+    let ast = Rc::new(parser.into_ast());
+    let mut interpreter = Interpreter::new();
+    interpreter.set_output(Box::new(std::io::sink()));
+    interpreter.set_fuel(Some(FUEL));
+    // fuzzed programs must not write files
+    interpreter.globals.borrow_mut().define("storeOpen", Object::Nil);
+    let mut resolver = Resolver::new(&mut interpreter, &ast);
+    if resolver.resolve_stmts(&stmts).is_err() || resolver.has_error {
+        return;
+    }
+    interpreter.set_ast(ast);
+    // interpret turns panics into errors, those are still crashes here
+    if let Err(error) = interpreter.interpret(&stmts) {
+        assert_ne!(error.code, "E0438", "{}", error.message());
+    }
+});
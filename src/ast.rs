@@ -0,0 +1,134 @@
+/// the nodes of one parse, stored flat in two vectors instead of boxed
+/// into each other. a node names its children by `ExprId` and `StmtId`,
+/// indices into the tree that made them
+use std::ops::Index;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use crate::{Expr, Stmt};
+
+/// names an expression node. the id of the tree is part of it, so the
+/// resolver's side tables can hold nodes from different parses (REPL
+/// lines, edited documents) without two of them colliding
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ExprId {
+    ast: u32,
+    index: u32,
+}
+
+/// names a statement node, like `ExprId`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StmtId {
+    ast: u32,
+    index: u32,
+}
+
+#[derive(Debug)]
+pub struct Ast {
+    id: u32,
+    exprs: Vec<Expr>,
+    stmts: Vec<Stmt>,
+}
+
+impl Ast {
+    /// an empty tree with an id no other tree of the run has. running
+    /// out panics rather than wrapping around to ids in use
+    pub fn new() -> Self {
+        static NEXT: AtomicU32 = AtomicU32::new(0);
+        let id = NEXT
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |id| id.checked_add(1))
+            .expect("tree ids ran out");
+        Self {
+            id,
+            exprs: Vec::new(),
+            stmts: Vec::new(),
+        }
+    }
+
+    pub fn push_expr(&mut self, expr: Expr) -> ExprId {
+        let id = ExprId {
+            ast: self.id,
+            index: next_index(self.exprs.len()),
+        };
+        self.exprs.push(expr);
+        id
+    }
+
+    pub fn push_stmt(&mut self, stmt: Stmt) -> StmtId {
+        let id = StmtId {
+            ast: self.id,
+            index: next_index(self.stmts.len()),
+        };
+        self.stmts.push(stmt);
+        id
+    }
+
+    /// put `expr` where the node `id` was, the parser turns a read it
+    /// has already made into the target of an assignment this way
+    pub fn replace_expr(&mut self, id: ExprId, expr: Expr) -> Expr {
+        self.check(id.ast);
+        std::mem::replace(&mut self.exprs[id.index as usize], expr)
+    }
+
+    /// how many expressions and statements the tree holds
+    pub fn len(&self) -> usize {
+        self.exprs.len() + self.stmts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// an id from another tree would silently name the wrong node
+    fn check(&self, ast: u32) {
+        assert_eq!(ast, self.id, "node of another tree");
+    }
+}
+
+impl Default for Ast {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn next_index(len: usize) -> u32 {
+    u32::try_from(len).expect("too many nodes in one tree")
+}
+
+impl Index<ExprId> for Ast {
+    type Output = Expr;
+
+    fn index(&self, id: ExprId) -> &Expr {
+        self.check(id.ast);
+        &self.exprs[id.index as usize]
+    }
+}
+
+impl Index<StmtId> for Ast {
+    type Output = Stmt;
+
+    fn index(&self, id: StmtId) -> &Stmt {
+        self.check(id.ast);
+        &self.stmts[id.index as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ids_name_their_tree() {
+        let mut first = Ast::new();
+        let mut second = Ast::new();
+        let a = first.nil();
+        let b = second.nil();
+        assert_ne!(a, b);
+        assert_eq!(first.len(), 1);
+        let print = first.print(a);
+        assert!(std::matches!(first[print], Stmt::PrintStmt { expression, .. } if expression == a));
+        let wrong = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _ = &first[b];
+        }));
+        assert!(wrong.is_err());
+    }
+}
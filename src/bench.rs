@@ -0,0 +1,101 @@
+/// the programs `lox bench` and the criterion suite in benches/ time,
+/// each front-end phase is measured on its own
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use crate::{Ast, Error, ErrorType, Interpreter, Parser, Resolver, Scanner, StmtId, Token};
+
+/// name and source of every benchmark program, they leave their
+/// answer in `result` instead of printing it
+pub const PROGRAMS: &[(&str, &str)] = &[
+    ("fib", include_str!("../benches/programs/fib.lox")),
+    ("loops", include_str!("../benches/programs/loops.lox")),
+    ("strings", include_str!("../benches/programs/strings.lox")),
+    ("methods", include_str!("../benches/programs/methods.lox")),
+    ("closures", include_str!("../benches/programs/closures.lox")),
+];
+
+/// the best time of each phase over a number of rounds
+pub struct Timings {
+    pub scan: Duration,
+    pub parse: Duration,
+    pub run: Duration,
+}
+
+pub fn scan(source: &str) -> Result<Vec<Token>, Error> {
+    let mut scanner = Scanner::new(source);
+    scanner.scan_tokens();
+    match scanner.errors.into_iter().next() {
+        Some(error) => Err(error),
+        None => Ok(scanner.tokens),
+    }
+}
+
+pub fn parse(tokens: &Vec<Token>) -> Result<(Rc<Ast>, Vec<StmtId>), Error> {
+    let mut parser = Parser::new(tokens);
+    let stmts = parser.parse()?;
+    Ok((Rc::new(parser.into_ast()), stmts))
+}
+
+/// resolve and interpret on a fresh interpreter, like running a script
+pub fn run(ast: &Rc<Ast>, stmts: &[StmtId]) -> Result<(), Error> {
+    let mut interpreter = Interpreter::new();
+    let mut resolver = Resolver::new(&mut interpreter, ast);
+    resolver.resolve_stmts(stmts)?;
+    if resolver.has_error {
+        return Err(Error::coded("E0315", &[]).with_type(ErrorType::SyntaxError));
+    }
+    interpreter.set_ast(Rc::clone(ast));
+    interpreter.interpret(stmts)
+}
+
+pub fn time(source: &str, rounds: usize) -> Result<Timings, Error> {
+    let mut timings = Timings {
+        scan: Duration::MAX,
+        parse: Duration::MAX,
+        run: Duration::MAX,
+    };
+    for _ in 0..rounds.max(1) {
+        let start = Instant::now();
+        let tokens = scan(source)?;
+        timings.scan = timings.scan.min(start.elapsed());
+
+        let start = Instant::now();
+        let (ast, stmts) = parse(&tokens)?;
+        timings.parse = timings.parse.min(start.elapsed());
+
+        let start = Instant::now();
+        run(&ast, &stmts)?;
+        timings.run = timings.run.min(start.elapsed());
+    }
+    Ok(timings)
+}
+
+/// time every program and print a table of the phases
+pub fn report(rounds: usize) -> Result<(), Error> {
+    println!(
+        "{:<10} {:>12} {:>12} {:>12}",
+        "program", "scan", "parse", "run"
+    );
+    for (name, source) in PROGRAMS {
+        let timings = time(source, rounds)?;
+        println!(
+            "{:<10} {:>12.3?} {:>12.3?} {:>12.3?}",
+            name, timings.scan, timings.parse, timings.run
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_programs_run() {
+        for (name, source) in PROGRAMS {
+            let (ast, stmts) = parse(&scan(source).unwrap()).unwrap();
+            assert!(run(&ast, &stmts).is_ok(), "{} failed", name);
+        }
+    }
+}
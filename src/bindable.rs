@@ -0,0 +1,189 @@
+/// expose rust values to Lox scripts as class instances
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::{ClassRef, Error, Function, LoxClass, LoxInstance, NativeMethodFn, Object};
+
+/// a host describes the Lox side of its type once with a `ClassBuilder`
+/// and implements `LoxBindable` to hand values over:
+///
+/// ```
+/// use lox::*;
+///
+/// struct Point {
+///     x: i64,
+///     y: i64,
+/// }
+///
+/// impl LoxBindable for Point {
+///     fn lox_class() -> ClassBuilder {
+///         ClassBuilder::new("Point").method("sum", 0, |_, this, _| {
+///             let x = instance_field(this, "x")?;
+///             let y = instance_field(this, "y")?;
+///             match (x, y) {
+///                 (Object::Number(x), Object::Number(y)) => Ok(Object::Number(x.add(&y)?)),
+///                 _ => Ok(Object::Nil),
+///             }
+///         })
+///     }
+///
+///     fn lox_fields(&self) -> Vec<(String, Object)> {
+///         vec![
+///             ("x".to_string(), Object::Number(NumberType::Integer(self.x))),
+///             ("y".to_string(), Object::Number(NumberType::Integer(self.y))),
+///         ]
+///     }
+/// }
+///
+/// let mut interpreter = Interpreter::new();
+/// let class = interpreter.define_class::<Point>();
+/// let point = Point { x: 1, y: 2 }.to_lox(&class);
+/// interpreter.globals.borrow_mut().define("origin", point);
+/// ```
+pub trait LoxBindable {
+    /// describe the class values of this type become instances of
+    fn lox_class() -> ClassBuilder;
+
+    /// the fields a script sees on the instance
+    fn lox_fields(&self) -> Vec<(String, Object)>;
+
+    /// turn the value into an instance of `class`
+    fn to_lox(&self, class: &ClassRef) -> Object {
+        let mut instance = LoxInstance::new(class.clone());
+        for (name, value) in self.lox_fields() {
+            instance.set(&name, &value);
+        }
+        Object::Instance(Rc::new(RefCell::new(instance)))
+    }
+}
+
+/// build a LoxClass whose methods are implemented in rust
+pub struct ClassBuilder {
+    name: String,
+    methods: HashMap<String, Function>,
+    super_class: Option<ClassRef>,
+    doc: Option<String>,
+}
+
+impl ClassBuilder {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            methods: HashMap::new(),
+            super_class: None,
+            doc: None,
+        }
+    }
+
+    pub fn method(mut self, name: &str, arity: usize, body: NativeMethodFn) -> Self {
+        self.methods.insert(
+            name.to_string(),
+            Function::NativeMethod {
+                name: name.to_string(),
+                arity,
+                body: Box::new(body),
+                this: None,
+            },
+        );
+        self
+    }
+
+    pub fn super_class(mut self, super_class: ClassRef) -> Self {
+        self.super_class = Some(super_class);
+        self
+    }
+
+    /// the text `help` shows for the class
+    pub fn doc(mut self, doc: &str) -> Self {
+        self.doc = Some(doc.to_string());
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn build(self) -> ClassRef {
+        let mut class = LoxClass::new(self.name, self.methods, self.super_class);
+        class.doc = self.doc;
+        let class = Rc::new(RefCell::new(class));
+        LoxClass::initializer(&class);
+        class
+    }
+}
+
+/// read a field of the instance a native method is bound to
+pub fn instance_field(this: &Object, name: &str) -> Result<Object, Error> {
+    let field = match this {
+        Object::Instance(instance) => instance.borrow().field(name).cloned(),
+        _ => None,
+    };
+    field.ok_or_else(|| Error::coded("E0404", &[&name]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+
+    struct Counter {
+        count: i64,
+    }
+
+    impl LoxBindable for Counter {
+        fn lox_class() -> ClassBuilder {
+            ClassBuilder::new("Counter").method("next", 0, |_, this, _| {
+                let count = match instance_field(this, "count")? {
+                    Object::Number(n) => n.as_integer() + 1,
+                    _ => 0,
+                };
+                let value = Object::Number(NumberType::Integer(count));
+                if let Object::Instance(instance) = this {
+                    instance.borrow_mut().set("count", &value);
+                }
+                Ok(value)
+            })
+        }
+
+        fn lox_fields(&self) -> Vec<(String, Object)> {
+            vec![(
+                "count".to_string(),
+                Object::Number(NumberType::Integer(self.count)),
+            )]
+        }
+    }
+
+    #[test]
+    fn test_bound_instance() {
+        let mut interpreter = Interpreter::new();
+        let class = interpreter.define_class::<Counter>();
+        let counter = Counter { count: 41 }.to_lox(&class);
+        interpreter.globals.borrow_mut().define("counter", counter);
+
+        let mut scanner = Scanner::new("var a = counter.next(); var b = counter.count;");
+        scanner.scan_tokens();
+        let mut parser = Parser::new(&scanner.tokens);
+        let stmts = parser.parse().unwrap();
+        let ast = Rc::new(parser.into_ast());
+        Resolver::new(&mut interpreter, &ast)
+            .resolve_stmts(&stmts)
+            .unwrap();
+        interpreter.set_ast(ast);
+        interpreter.interpret(&stmts).unwrap();
+
+        let globals = interpreter.globals.borrow();
+        assert!(globals
+            .get("a")
+            .unwrap()
+            .equals(&Object::Number(NumberType::Integer(42))));
+        assert!(globals
+            .get("b")
+            .unwrap()
+            .equals(&Object::Number(NumberType::Integer(42))));
+        assert_eq!(
+            globals.get("Counter").unwrap().to_string(),
+            "<class Counter>"
+        );
+    }
+}
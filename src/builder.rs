@@ -0,0 +1,211 @@
+/// helpers for building a tree in Rust instead of parsing one, for tools
+/// that generate scripts. each one adds its node to the `Ast` and gives
+/// back the id, the tokens they make sit at line 1, column 1,
+/// `emit::to_source` turns the tree back into Lox
+use crate::{
+    Ast, Expr, ExprId, Literal, MethodCache, NumberType, Scanner, Stmt, StmtId, Token, TokenType,
+};
+
+/// a token for `lexeme`, at the start of an imaginary source
+fn token(lexeme: &str, token_type: TokenType) -> Token {
+    Token::new(lexeme, token_type, 1, 1)
+}
+
+fn identifier(name: &str) -> Token {
+    token(name, TokenType::Identifier)
+}
+
+/// the token `operator` scans to, panics on anything that isn't a single
+/// operator, which is a mistake in the calling tool rather than in a script
+fn operator_token(operator: &str) -> Token {
+    match Scanner::new(operator).next() {
+        Some(Ok(token)) if token.lexeme == operator && token.token_type != TokenType::Eof => {
+            Token::new(&token.lexeme, token.token_type, 1, 1)
+        }
+        _ => panic!("'{}' is not an operator", operator),
+    }
+}
+
+impl Ast {
+    fn literal(&mut self, value: Literal) -> ExprId {
+        self.push_expr(Expr::Literal { value })
+    }
+
+    pub fn nil(&mut self) -> ExprId {
+        self.literal(Literal::Nil)
+    }
+
+    pub fn boolean(&mut self, value: bool) -> ExprId {
+        self.literal(Literal::Boolean(value))
+    }
+
+    pub fn integer(&mut self, value: i64) -> ExprId {
+        self.literal(Literal::Number(NumberType::Integer(value)))
+    }
+
+    pub fn float(&mut self, value: f64) -> ExprId {
+        self.literal(Literal::Number(NumberType::Float(value)))
+    }
+
+    pub fn string(&mut self, value: &str) -> ExprId {
+        self.literal(Literal::String(value.to_string()))
+    }
+
+    pub fn variable(&mut self, name: &str) -> ExprId {
+        self.push_expr(Expr::Variable {
+            name: identifier(name),
+        })
+    }
+
+    pub fn assign(&mut self, name: &str, value: ExprId) -> ExprId {
+        self.push_expr(Expr::Assign {
+            name: identifier(name),
+            value,
+        })
+    }
+
+    /// `"-"` or `"!"`
+    pub fn unary(&mut self, operator: &str, right: ExprId) -> ExprId {
+        self.push_expr(Expr::Unary {
+            operator: operator_token(operator),
+            right,
+        })
+    }
+
+    /// `ast.binary(a, "+", b)`, `"and"`, `"or"` and `"??"` give the
+    /// logical expression the parser would make for them
+    pub fn binary(&mut self, left: ExprId, operator: &str, right: ExprId) -> ExprId {
+        let operator = operator_token(operator);
+        if matches!(
+            operator.token_type,
+            TokenType::And | TokenType::Or | TokenType::QuestionQuestion
+        ) {
+            return self.push_expr(Expr::Logical {
+                left,
+                operator,
+                right,
+            });
+        }
+        self.push_expr(Expr::Binary {
+            left,
+            operator,
+            right,
+        })
+    }
+
+    pub fn grouping(&mut self, expression: ExprId) -> ExprId {
+        self.push_expr(Expr::Grouping { expression })
+    }
+
+    pub fn call(&mut self, callee: ExprId, arguments: Vec<ExprId>) -> ExprId {
+        self.push_expr(Expr::Call {
+            callee,
+            paren: token(")", TokenType::RightParen),
+            arguments,
+            keywords: Vec::new(),
+        })
+    }
+
+    pub fn get(&mut self, object: ExprId, name: &str) -> ExprId {
+        self.push_expr(Expr::Get {
+            object,
+            name: identifier(name),
+            cache: MethodCache::default(),
+            optional: false,
+        })
+    }
+
+    pub fn set(&mut self, object: ExprId, name: &str, value: ExprId) -> ExprId {
+        self.push_expr(Expr::Set {
+            object,
+            name: identifier(name),
+            value,
+        })
+    }
+
+    pub fn list(&mut self, elements: Vec<ExprId>) -> ExprId {
+        self.push_expr(Expr::List {
+            keyword: token("list", TokenType::List),
+            elements,
+        })
+    }
+
+    pub fn var(&mut self, name: &str, initializer: ExprId) -> StmtId {
+        self.push_stmt(Stmt::VarStmt {
+            name: identifier(name),
+            initializer: Some(initializer),
+        })
+    }
+
+    pub fn expression(&mut self, expression: ExprId) -> StmtId {
+        self.push_stmt(Stmt::ExprStmt { expression })
+    }
+
+    pub fn print(&mut self, expression: ExprId) -> StmtId {
+        self.push_stmt(Stmt::PrintStmt {
+            keyword: token("print", TokenType::Print),
+            expression,
+        })
+    }
+
+    pub fn block(&mut self, statements: Vec<StmtId>) -> StmtId {
+        self.push_stmt(Stmt::BlockStmt {
+            statements,
+            origin: None,
+        })
+    }
+
+    pub fn if_else(
+        &mut self,
+        condition: ExprId,
+        then_branch: StmtId,
+        else_branch: Option<StmtId>,
+    ) -> StmtId {
+        self.push_stmt(Stmt::IfStmt {
+            keyword: token("if", TokenType::If),
+            condition,
+            then_branch,
+            else_branch,
+        })
+    }
+
+    pub fn while_loop(&mut self, condition: ExprId, body: StmtId) -> StmtId {
+        self.push_stmt(Stmt::WhileStmt {
+            keyword: token("while", TokenType::While),
+            condition,
+            body,
+            increment: None,
+            label: None,
+        })
+    }
+
+    pub fn function(&mut self, name: &str, params: &[&str], body: Vec<StmtId>) -> StmtId {
+        self.push_stmt(Stmt::FunStmt {
+            name: identifier(name),
+            params: params.iter().map(|param| identifier(param)).collect(),
+            defaults: Vec::new(),
+            body: body.into(),
+            doc: None,
+            captures: Vec::new(),
+        })
+    }
+
+    pub fn return_value(&mut self, value: Option<ExprId>) -> StmtId {
+        self.push_stmt(Stmt::ReturnStmt {
+            keyword: token("return", TokenType::Return),
+            value,
+        })
+    }
+
+    /// `methods` are made with `Ast::function`
+    pub fn class(&mut self, name: &str, super_class: Option<&str>, methods: Vec<StmtId>) -> StmtId {
+        let super_class = super_class.map(|name| self.variable(name));
+        self.push_stmt(Stmt::ClassStmt {
+            name: identifier(name),
+            super_class,
+            fields: Vec::new(),
+            methods,
+            doc: None,
+        })
+    }
+}
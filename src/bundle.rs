@@ -0,0 +1,136 @@
+/// `lox build` copies the interpreter and appends a script to it, a
+/// binary that finds a script at its end runs that instead of its
+/// arguments. the end of the file is the script, its length as a
+/// little endian u64 and MAGIC
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+const MAGIC: &[u8; 8] = b"LOXBNDL1";
+const FOOTER: usize = 16;
+
+#[derive(Debug, PartialEq)]
+pub struct Bundle {
+    /// the `--` options given to `lox build`, applied again on every run
+    pub options: Vec<String>,
+    pub source: String,
+}
+
+impl Bundle {
+    /// write a copy of `binary` with this bundle at its end to `output`,
+    /// a bundle `binary` already carries is replaced
+    pub fn write(&self, binary: &Path, output: &Path) -> io::Result<()> {
+        let mut bytes = fs::read(binary)?;
+        let footer = &bytes[bytes.len().saturating_sub(FOOTER)..];
+        match payload_len(footer) {
+            Some(payload) if payload <= bytes.len() - FOOTER => {
+                bytes.truncate(bytes.len() - FOOTER - payload);
+            }
+            _ => {}
+        }
+        let payload = format!("{}\n{}", self.options.join(" "), self.source);
+        bytes.extend_from_slice(payload.as_bytes());
+        bytes.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(MAGIC);
+        fs::write(output, bytes)?;
+        fs::set_permissions(output, fs::metadata(binary)?.permissions())
+    }
+
+    /// the bundle at the end of `path`, if it has one
+    pub fn read(path: &Path) -> io::Result<Option<Bundle>> {
+        let mut file = File::open(path)?;
+        let len = file.metadata()?.len();
+        if len < FOOTER as u64 {
+            return Ok(None);
+        }
+        let mut footer = [0; FOOTER];
+        file.seek(SeekFrom::End(-(FOOTER as i64)))?;
+        file.read_exact(&mut footer)?;
+        let payload = match payload_len(&footer) {
+            Some(payload) if payload as u64 <= len - FOOTER as u64 => payload,
+            _ => return Ok(None),
+        };
+        let mut bytes = vec![0; payload];
+        file.seek(SeekFrom::End(-((FOOTER + payload) as i64)))?;
+        file.read_exact(&mut bytes)?;
+        let payload = String::from_utf8(bytes).map_err(|_| {
+            io::Error::new(io::ErrorKind::InvalidData, "bundled script is not UTF-8")
+        })?;
+        let (options, source) = payload.split_once('\n').unwrap_or(("", &payload));
+        Ok(Some(Bundle {
+            options: options.split_whitespace().map(String::from).collect(),
+            source: source.to_string(),
+        }))
+    }
+
+    /// the bundle the running executable carries
+    pub fn embedded() -> Option<Bundle> {
+        let exe = std::env::current_exe().ok()?;
+        Bundle::read(&exe).ok().flatten()
+    }
+}
+
+/// the payload length a footer gives, None when it is no footer
+fn payload_len(footer: &[u8]) -> Option<usize> {
+    if footer.len() != FOOTER || &footer[8..] != MAGIC {
+        return None;
+    }
+    let len = u64::from_le_bytes(footer[..8].try_into().unwrap());
+    usize::try_from(len).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_and_read() {
+        let dir = std::env::temp_dir().join(format!("lox-bundle-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let binary = dir.join("binary");
+        let first = dir.join("first");
+        let second = dir.join("second");
+        fs::write(&binary, b"not really an executable").unwrap();
+        assert_eq!(Bundle::read(&binary).unwrap(), None);
+
+        let bundle = Bundle {
+            options: vec!["--stats".to_string()],
+            source: "print 1;\nprint 2;".to_string(),
+        };
+        bundle.write(&binary, &first).unwrap();
+        assert_eq!(Bundle::read(&first).unwrap(), Some(bundle));
+
+        // building from a bundled binary replaces the script
+        let other = Bundle {
+            options: vec![],
+            source: "print 3;".to_string(),
+        };
+        other.write(&first, &second).unwrap();
+        assert_eq!(Bundle::read(&second).unwrap(), Some(other));
+        assert_eq!(
+            fs::metadata(&second).unwrap().len(),
+            fs::metadata(&binary).unwrap().len() + 1 + 8 + FOOTER as u64
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_footer_longer_than_file() {
+        let dir = std::env::temp_dir().join(format!("lox-footer-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let binary = dir.join("binary");
+        let output = dir.join("output");
+        let mut bytes = u64::MAX.to_le_bytes().to_vec();
+        bytes.extend_from_slice(MAGIC);
+        fs::write(&binary, &bytes).unwrap();
+        assert_eq!(Bundle::read(&binary).unwrap(), None);
+
+        let bundle = Bundle {
+            options: vec![],
+            source: "print 1;".to_string(),
+        };
+        bundle.write(&binary, &output).unwrap();
+        assert_eq!(Bundle::read(&output).unwrap(), Some(bundle));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,81 @@
+/// where the time natives get the time from, the system clock unless
+/// an embedder sets another one with `Interpreter::set_clock`
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+pub trait Clock {
+    /// seconds since the unix epoch, in UTC whatever the locale
+    fn now(&self) -> f64;
+    /// milliseconds since a fixed point, never going backwards even
+    /// when the wall clock is changed
+    fn monotonic_millis(&self) -> u64;
+}
+
+/// the time as the operating system tells it
+pub struct SystemClock {
+    start: Instant,
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> f64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs_f64())
+            .unwrap_or(0.0)
+    }
+
+    fn monotonic_millis(&self) -> u64 {
+        self.start.elapsed().as_millis() as u64
+    }
+}
+
+/// a clock that is stopped, for output that is the same on every run
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FixedClock {
+    pub seconds: f64,
+    pub millis: u64,
+}
+
+impl Clock for FixedClock {
+    fn now(&self) -> f64 {
+        self.seconds
+    }
+
+    fn monotonic_millis(&self) -> u64 {
+        self.millis
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Interpreter, NumberType, Object};
+
+    #[test]
+    fn test_set_clock() {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_clock(Box::new(FixedClock {
+            seconds: 1.5,
+            millis: 20,
+        }));
+        let call = |interpreter: &mut Interpreter, name: &str| {
+            let native = interpreter.globals.borrow().get(name).unwrap();
+            interpreter.call_value(&native, &[]).unwrap()
+        };
+        assert!(std::matches!(
+            call(&mut interpreter, "clock"),
+            Object::Number(NumberType::Float(f)) if f == 1.5
+        ));
+        assert!(std::matches!(
+            call(&mut interpreter, "monotonicMillis"),
+            Object::Number(NumberType::Integer(20))
+        ));
+    }
+}
@@ -0,0 +1,60 @@
+/// `--compat=jlox`, the dialect of jlox from Crafting Interpreters, so
+/// the book's test suite can check the core language: numbers are all
+/// doubles, lists, indexing and the other extensions are gone, and
+/// errors are worded and printed the way jlox does it.
+///
+/// the scanner, parser, resolver and error reporting all need to know,
+/// so like the stack depth in utils it is kept per thread instead of
+/// being threaded through every constructor
+use std::cell::Cell;
+
+thread_local! {
+    static JLOX: Cell<bool> = const { Cell::new(false) };
+}
+
+pub fn set_jlox(jlox: bool) {
+    JLOX.with(|current| current.set(jlox));
+}
+
+pub fn jlox() -> bool {
+    JLOX.with(|current| current.get())
+}
+
+/// keywords this crate added, in jlox they are plain identifiers
+pub fn is_extension_keyword(text: &str) -> bool {
+    matches!(
+        text,
+        "list"
+            | "break"
+            | "continue"
+            | "assert"
+            | "match"
+            | "defer"
+            | "const"
+            | "try"
+            | "catch"
+            | "finally"
+            | "throw"
+            | "in"
+            | "is"
+    )
+}
+
+/// characters only the extensions use, jlox doesn't scan them
+pub fn is_extension_character(c: char) -> bool {
+    matches!(c, '[' | ']' | ':' | '?')
+}
+
+/// a compile error message the way jlox words it, when it differs
+pub fn message(code: &str) -> Option<&'static str> {
+    Some(match code {
+        "E0301" => "Already a variable with this name in this scope.",
+        "E0302" => "Can't read local variable in its own initializer.",
+        "E0303" => "Can't use 'this' outside of a class.",
+        "E0304" => "Can't use 'super' outside of a class.",
+        "E0305" => "Can't use 'super' in a class with no superclass.",
+        "E0307" => "Can't return a value from an initializer.",
+        "E0313" => "A class can't inherit from itself.",
+        _ => return None,
+    })
+}
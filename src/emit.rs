@@ -0,0 +1,1157 @@
+/// turn a tree back into Lox source that parses to the same tree, for
+/// trees from the helpers in builder.rs as much as parsed ones. comments
+/// other than docs and the original layout are not kept, every block is
+/// laid out over lines indented by two spaces
+use crate::expression::expr;
+use crate::statement::stmt;
+use crate::{
+    compat, Ast, Error, ErrorType, Expr, ExprId, Literal, MatchArm, MethodCache, NumberType,
+    Pattern, Scanner, Stmt, StmtId, StringArms, Token, TokenType,
+};
+use std::rc::Rc;
+
+/// the source of `statements` of `ast`, one top-level statement per line
+pub fn to_source(ast: &Ast, statements: &[StmtId]) -> Result<String, Error> {
+    let mut emitter = Emitter { ast, level: 0 };
+    let mut source = String::new();
+    for statement in statements {
+        source.push_str(&emitter.statement(*statement)?);
+        source.push('\n');
+    }
+    Ok(source)
+}
+
+/// the source of a single expression
+pub fn expr_to_source(ast: &Ast, expr: ExprId) -> Result<String, Error> {
+    Emitter { ast, level: 0 }.operand(expr, ASSIGNMENT)
+}
+
+// how tightly each kind of expression binds, an operand binding less
+// tightly than its place needs is put in parentheses
+const TUPLE: u8 = 0;
+const ASSIGNMENT: u8 = 1;
+const COALESCE: u8 = 2;
+const OR: u8 = 3;
+const AND: u8 = 4;
+const EQUALITY: u8 = 5;
+const COMPARISON: u8 = 6;
+const TERM: u8 = 7;
+const FACTOR: u8 = 8;
+const UNARY: u8 = 9;
+const CALL: u8 = 10;
+const PRIMARY: u8 = 11;
+
+fn precedence(expr: &Expr) -> u8 {
+    match expr {
+        Expr::Binary { operator, .. } => binary_precedence(operator),
+        Expr::Tuple { .. } => TUPLE,
+        Expr::Assign { .. } | Expr::Set { .. } | Expr::IndexSet { .. } => ASSIGNMENT,
+        Expr::Logical { operator, .. } => match operator.token_type {
+            TokenType::QuestionQuestion => COALESCE,
+            TokenType::Or => OR,
+            _ => AND,
+        },
+        Expr::Comparison { .. } => COMPARISON,
+        Expr::Unary { .. } => UNARY,
+        // a negative number reads back as a negation
+        Expr::Literal {
+            value: Literal::Number(NumberType::Integer(i)),
+        } if *i < 0 => UNARY,
+        Expr::Literal {
+            value: Literal::Number(NumberType::Float(f)),
+        } if f.is_sign_negative() => UNARY,
+        Expr::Call { .. } | Expr::Get { .. } | Expr::Index { .. } => CALL,
+        _ => PRIMARY,
+    }
+}
+
+fn binary_precedence(operator: &Token) -> u8 {
+    match operator.token_type {
+        TokenType::EqualEqual | TokenType::BangEqual => EQUALITY,
+        TokenType::Plus | TokenType::Minus => TERM,
+        TokenType::Star | TokenType::Slash => FACTOR,
+        _ => COMPARISON,
+    }
+}
+
+fn unwritable(what: &str) -> Error {
+    Error::coded("E0601", &[&what]).with_type(ErrorType::SyntaxError)
+}
+
+fn number(number: &NumberType) -> Result<String, Error> {
+    match number {
+        NumberType::Integer(i) => Ok(i.to_string()),
+        // an integral float keeps a fraction so it reads back as a float
+        NumberType::Float(f) if f.is_finite() => {
+            let text = f.to_string();
+            Ok(if text.contains('.') {
+                text
+            } else {
+                text + ".0"
+            })
+        }
+        NumberType::Float(f) => Err(unwritable(&format!("the number {}", f))),
+    }
+}
+
+/// the inside of a string literal, strings have no escape for '"'
+fn string_body(text: &str) -> Result<String, Error> {
+    if text.contains('"') {
+        return Err(unwritable("a string containing '\"'"));
+    }
+    Ok(if compat::jlox() {
+        text.to_string()
+    } else {
+        text.replace("${", "\\${")
+    })
+}
+
+fn literal(value: &Literal) -> Result<String, Error> {
+    match value {
+        Literal::String(s) => Ok(format!("\"{}\"", string_body(s)?)),
+        Literal::Number(n) => number(n),
+        Literal::Boolean(b) => Ok(b.to_string()),
+        Literal::Nil => Ok("nil".to_string()),
+    }
+}
+
+fn pattern(ast: &Ast, pattern_: &Pattern) -> Result<String, Error> {
+    Ok(match pattern_ {
+        Pattern::Wildcard => "_".to_string(),
+        Pattern::Literal(value) => literal(value)?,
+        Pattern::Range { start, end } => format!("{}..{}", number(start)?, number(end)?),
+        Pattern::Binding(name) => name.lexeme.clone(),
+        Pattern::List { elements, rest, .. } => {
+            let mut parts = elements
+                .iter()
+                .map(|element| pattern(ast, element))
+                .collect::<Result<Vec<_>, _>>()?;
+            if let Some(rest) = rest {
+                parts.push(format!("...{}", pattern(ast, rest)?));
+            }
+            format!("[{}]", parts.join(", "))
+        }
+        Pattern::Instance { class, fields } => {
+            let fields = fields
+                .iter()
+                .map(|(name, field)| match field {
+                    Pattern::Binding(binding) if binding.lexeme == name.lexeme => {
+                        Ok(name.lexeme.clone())
+                    }
+                    field => Ok(format!("{}: {}", name.lexeme, pattern(ast, field)?)),
+                })
+                .collect::<Result<Vec<_>, Error>>()?;
+            let class = match &ast[*class] {
+                Expr::Variable { name, .. } => &name.lexeme,
+                _ => return Err(unwritable("a pattern whose class is not a name")),
+            };
+            if fields.is_empty() {
+                format!("{} {{}}", class)
+            } else {
+                format!("{} {{ {} }}", class, fields.join(", "))
+            }
+        }
+        Pattern::Map { entries, .. } => {
+            let entries = entries
+                .iter()
+                .map(|(key, value)| Ok(format!("{}: {}", literal(key)?, pattern(ast, value)?)))
+                .collect::<Result<Vec<_>, Error>>()?;
+            format!("{{{}}}", entries.join(", "))
+        }
+    })
+}
+
+/// `text` in parentheses when it starts like a statement would, so an
+/// expression statement or the value of a block is read as an expression
+fn guard(text: String) -> String {
+    let first = Scanner::new(&text).next().and_then(Result::ok);
+    let starts_statement = first.is_some_and(|token| {
+        std::matches!(
+            token.token_type,
+            TokenType::LeftBrace | TokenType::If | TokenType::Print
+        )
+    });
+    if starts_statement {
+        format!("({})", text)
+    } else {
+        text
+    }
+}
+
+/// whether an `else` written after `stmt` would be taken as the else of
+/// an `if` inside it
+fn ends_in_open_if(ast: &Ast, stmt: StmtId) -> bool {
+    match &ast[stmt] {
+        Stmt::IfStmt {
+            else_branch: None, ..
+        } => true,
+        Stmt::IfStmt {
+            else_branch: Some(else_branch),
+            ..
+        } => ends_in_open_if(ast, *else_branch),
+        Stmt::WhileStmt { body, .. } | Stmt::ForInStmt { body, .. } => ends_in_open_if(ast, *body),
+        Stmt::DeferStmt { body, .. } => ends_in_open_if(ast, *body),
+        Stmt::BlockStmt {
+            statements,
+            origin: Some(_),
+        } => statements
+            .last()
+            .is_some_and(|last| ends_in_open_if(ast, *last)),
+        _ => false,
+    }
+}
+
+struct Emitter<'a> {
+    ast: &'a Ast,
+    /// how many blocks deep the statement being written is
+    level: usize,
+}
+
+impl Emitter<'_> {
+    fn indent(&self) -> String {
+        "  ".repeat(self.level)
+    }
+
+    /// a statement, its first line not indented, the rest indented for
+    /// the current level
+    fn statement(&mut self, statement: StmtId) -> Result<String, Error> {
+        let ast = self.ast;
+        // a for loop is written as one again, rather than as the block
+        // and while loop it was desugared to
+        if let Stmt::BlockStmt {
+            statements,
+            origin: Some(_),
+        } = &ast[statement]
+        {
+            let nodes: Vec<&Stmt> = statements.iter().map(|id| &ast[*id]).collect();
+            match (statements.as_slice(), nodes.as_slice()) {
+                (
+                    [initializer, _],
+                    [_, Stmt::WhileStmt {
+                        keyword,
+                        condition,
+                        body,
+                        increment,
+                        label,
+                    }],
+                ) if keyword.token_type == TokenType::For => {
+                    return self.for_loop(
+                        Some(*initializer),
+                        *condition,
+                        *body,
+                        *increment,
+                        label.as_ref(),
+                    )
+                }
+                ([loop_], [Stmt::WhileStmt { .. }]) => return self.statement(*loop_),
+                _ => {}
+            }
+        }
+        statement.accept(ast, self)
+    }
+
+    /// `{ ... }` holding `statements` one level further in
+    fn block(&mut self, statements: &[StmtId]) -> Result<String, Error> {
+        self.lines(statements, None)
+    }
+
+    fn lines(&mut self, statements: &[StmtId], value: Option<ExprId>) -> Result<String, Error> {
+        if statements.is_empty() && value.is_none() {
+            return Ok("{}".to_string());
+        }
+        self.level += 1;
+        let mut lines = Vec::new();
+        for statement in statements {
+            lines.push(format!("{}{}", self.indent(), self.statement(*statement)?));
+        }
+        if let Some(value) = value {
+            let value = guard(self.operand(value, ASSIGNMENT)?);
+            lines.push(format!("{}{}", self.indent(), value));
+        }
+        self.level -= 1;
+        Ok(format!("{{\n{}\n{}}}", lines.join("\n"), self.indent()))
+    }
+
+    /// the body of an if or a loop, after its header. a block stays on
+    /// the header's line, anything else goes on a line of its own
+    fn body(&mut self, body: StmtId) -> Result<String, Error> {
+        let ast = self.ast;
+        if let Stmt::BlockStmt {
+            statements,
+            origin: None,
+        } = &ast[body]
+        {
+            return Ok(format!(" {}", self.block(statements)?));
+        }
+        self.level += 1;
+        let body = format!("\n{}{}", self.indent(), self.statement(body)?);
+        self.level -= 1;
+        Ok(body)
+    }
+
+    fn label(label: Option<&Token>) -> String {
+        match label {
+            Some(label) => format!("{}: ", label.lexeme),
+            None => String::new(),
+        }
+    }
+
+    fn for_loop(
+        &mut self,
+        initializer: Option<StmtId>,
+        condition: ExprId,
+        body: StmtId,
+        increment: Option<ExprId>,
+        label: Option<&Token>,
+    ) -> Result<String, Error> {
+        let initializer = match initializer {
+            Some(initializer) => self.statement(initializer)?,
+            None => ";".to_string(),
+        };
+        let increment = match increment {
+            Some(increment) => format!(" {}", self.operand(increment, ASSIGNMENT)?),
+            None => String::new(),
+        };
+        Ok(format!(
+            "{}for ({} {};{}){}",
+            Self::label(label),
+            initializer,
+            self.operand(condition, ASSIGNMENT)?,
+            increment,
+            self.body(body)?
+        ))
+    }
+
+    /// `a, b = 1`
+    fn parameters(&mut self, params: &[Token], defaults: &[ExprId]) -> Result<String, Error> {
+        let first_default = params.len() - defaults.len();
+        let mut parameters = Vec::new();
+        for (index, param) in params.iter().enumerate() {
+            match index.checked_sub(first_default).map(|i| defaults[i]) {
+                Some(default) => parameters.push(format!(
+                    "{} = {}",
+                    param.lexeme,
+                    self.operand(default, ASSIGNMENT)?
+                )),
+                None => parameters.push(param.lexeme.clone()),
+            }
+        }
+        Ok(parameters.join(", "))
+    }
+
+    /// a function declaration or, with an empty `keyword`, a method
+    fn function(
+        &mut self,
+        keyword: &str,
+        name: &Token,
+        parameters: String,
+        body: &[StmtId],
+        doc: Option<&str>,
+        captures: &[ExprId],
+    ) -> Result<String, Error> {
+        let captures = if captures.is_empty() {
+            String::new()
+        } else {
+            let names = captures
+                .iter()
+                .map(|capture| self.operand(*capture, PRIMARY))
+                .collect::<Result<Vec<_>, _>>()?;
+            format!("[{}]", names.join(", "))
+        };
+        Ok(format!(
+            "{}{}{}{}({}) {}",
+            self.doc(doc),
+            keyword,
+            name.lexeme,
+            captures,
+            parameters,
+            self.block(body)?
+        ))
+    }
+
+    /// `///` lines for a doc comment, ending with the indent of the
+    /// declaration they document
+    fn doc(&self, doc: Option<&str>) -> String {
+        doc.map(|doc| {
+            doc.lines()
+                .map(|line| format!("/// {}\n{}", line, self.indent()))
+                .collect()
+        })
+        .unwrap_or_default()
+    }
+
+    /// `expr` in a place that needs at least `precedence`
+    fn operand(&mut self, expr: ExprId, precedence_: u8) -> Result<String, Error> {
+        let ast = self.ast;
+        let text = expr.accept(ast, self)?;
+        if precedence(&ast[expr]) < precedence_ {
+            Ok(format!("({})", text))
+        } else {
+            Ok(text)
+        }
+    }
+
+    fn operands(&mut self, exprs: &[ExprId]) -> Result<Vec<String>, Error> {
+        exprs
+            .iter()
+            .map(|expr| self.operand(*expr, ASSIGNMENT))
+            .collect()
+    }
+
+    /// the then or else branch of an if expression, a block there can't
+    /// be taken for a map
+    fn branch(&mut self, branch: ExprId) -> Result<String, Error> {
+        let ast = self.ast;
+        match &ast[branch] {
+            Expr::Block { statements, value } => self.lines(statements, *value),
+            _ => self.operand(branch, ASSIGNMENT),
+        }
+    }
+}
+
+impl stmt::Visitor<String> for Emitter<'_> {
+    fn visit_expr_stmt(&mut self, expression: ExprId) -> Result<String, Error> {
+        Ok(format!("{};", guard(self.operand(expression, ASSIGNMENT)?)))
+    }
+
+    fn visit_if_stmt(
+        &mut self,
+        _keyword: &Token,
+        condition: ExprId,
+        then_branch: StmtId,
+        else_branch: Option<StmtId>,
+    ) -> Result<String, Error> {
+        let ast = self.ast;
+        let condition = self.operand(condition, ASSIGNMENT)?;
+        let Some(else_branch) = else_branch else {
+            return Ok(format!("if ({}){}", condition, self.body(then_branch)?));
+        };
+        let open_if = ends_in_open_if(ast, then_branch);
+        let then = if open_if {
+            format!(" {}", self.block(std::slice::from_ref(&then_branch))?)
+        } else {
+            self.body(then_branch)?
+        };
+        let block = std::matches!(ast[then_branch], Stmt::BlockStmt { origin: None, .. });
+        let separator = if open_if || block {
+            " ".to_string()
+        } else {
+            format!("\n{}", self.indent())
+        };
+        let otherwise = match ast[else_branch] {
+            Stmt::IfStmt { .. } => format!(" {}", self.statement(else_branch)?),
+            _ => self.body(else_branch)?,
+        };
+        Ok(format!(
+            "if ({}){}{}else{}",
+            condition, then, separator, otherwise
+        ))
+    }
+
+    fn visit_print_stmt(&mut self, _keyword: &Token, expression: ExprId) -> Result<String, Error> {
+        Ok(format!("print {};", self.operand(expression, ASSIGNMENT)?))
+    }
+
+    fn visit_var_stmt(
+        &mut self,
+        name: &Token,
+        initializer: Option<ExprId>,
+    ) -> Result<String, Error> {
+        match initializer {
+            Some(initializer) => Ok(format!(
+                "var {} = {};",
+                name.lexeme,
+                self.operand(initializer, ASSIGNMENT)?
+            )),
+            None => Ok(format!("var {};", name.lexeme)),
+        }
+    }
+
+    fn visit_destructure_stmt(
+        &mut self,
+        names: &[Token],
+        initializer: ExprId,
+    ) -> Result<String, Error> {
+        let names: Vec<&str> = names.iter().map(|name| name.lexeme.as_str()).collect();
+        Ok(format!(
+            "var {} = {};",
+            names.join(", "),
+            self.operand(initializer, ASSIGNMENT)?
+        ))
+    }
+
+    fn visit_block_stmt(&mut self, statements: &[StmtId]) -> Result<String, Error> {
+        self.block(statements)
+    }
+
+    fn visit_while_stmt(
+        &mut self,
+        keyword: &Token,
+        condition: ExprId,
+        body: StmtId,
+        increment: Option<ExprId>,
+        label: Option<&Token>,
+    ) -> Result<String, Error> {
+        if keyword.token_type == TokenType::For || increment.is_some() {
+            return self.for_loop(None, condition, body, increment, label);
+        }
+        Ok(format!(
+            "{}while ({}){}",
+            Self::label(label),
+            self.operand(condition, ASSIGNMENT)?,
+            self.body(body)?
+        ))
+    }
+
+    fn visit_for_in_stmt(
+        &mut self,
+        _keyword: &Token,
+        name: &Token,
+        iterable: ExprId,
+        body: StmtId,
+        label: Option<&Token>,
+    ) -> Result<String, Error> {
+        Ok(format!(
+            "{}for (var {} in {}){}",
+            Self::label(label),
+            name.lexeme,
+            self.operand(iterable, ASSIGNMENT)?,
+            self.body(body)?
+        ))
+    }
+
+    fn visit_func_stmt(
+        &mut self,
+        name: &Token,
+        params: &[Token],
+        defaults: &[ExprId],
+        body: &Rc<[StmtId]>,
+        doc: Option<&str>,
+        captures: &[ExprId],
+    ) -> Result<String, Error> {
+        let parameters = self.parameters(params, defaults)?;
+        self.function("fun ", name, parameters, body, doc, captures)
+    }
+
+    fn visit_return_stmt(
+        &mut self,
+        _keyword: &Token,
+        value: Option<ExprId>,
+    ) -> Result<String, Error> {
+        match value {
+            Some(value) => Ok(format!("return {};", self.operand(value, TUPLE)?)),
+            None => Ok("return;".to_string()),
+        }
+    }
+
+    fn visit_class_stmt(
+        &mut self,
+        name: &Token,
+        super_class: Option<ExprId>,
+        fields: &[StmtId],
+        methods: &[StmtId],
+        doc: Option<&str>,
+    ) -> Result<String, Error> {
+        let ast = self.ast;
+        let super_class = match super_class {
+            Some(super_class) => format!(" < {}", self.operand(super_class, PRIMARY)?),
+            None => String::new(),
+        };
+        let doc = self.doc(doc);
+        if fields.is_empty() && methods.is_empty() {
+            return Ok(format!("{}class {}{} {{}}", doc, name.lexeme, super_class));
+        }
+        self.level += 1;
+        let mut lines = Vec::new();
+        for field in fields {
+            lines.push(format!("{}{}", self.indent(), self.statement(*field)?));
+        }
+        for method in methods {
+            let Stmt::FunStmt {
+                name,
+                params,
+                defaults,
+                body,
+                doc,
+                captures,
+            } = &ast[*method]
+            else {
+                return Err(unwritable("a method that is not a function"));
+            };
+            let parameters = self.parameters(params, defaults)?;
+            let method = self.function("", name, parameters, body, doc.as_deref(), captures)?;
+            lines.push(format!("{}{}", self.indent(), method));
+        }
+        self.level -= 1;
+        Ok(format!(
+            "{}class {}{} {{\n{}\n{}}}",
+            doc,
+            name.lexeme,
+            super_class,
+            lines.join("\n"),
+            self.indent()
+        ))
+    }
+
+    fn visit_break_stmt(
+        &mut self,
+        _keyword: &Token,
+        label: Option<&Token>,
+    ) -> Result<String, Error> {
+        match label {
+            Some(label) => Ok(format!("break {};", label.lexeme)),
+            None => Ok("break;".to_string()),
+        }
+    }
+
+    fn visit_continue_stmt(
+        &mut self,
+        _keyword: &Token,
+        label: Option<&Token>,
+    ) -> Result<String, Error> {
+        match label {
+            Some(label) => Ok(format!("continue {};", label.lexeme)),
+            None => Ok("continue;".to_string()),
+        }
+    }
+
+    fn visit_assert_stmt(
+        &mut self,
+        _keyword: &Token,
+        condition: ExprId,
+        message: Option<ExprId>,
+        _source: &str,
+    ) -> Result<String, Error> {
+        let condition = self.operand(condition, ASSIGNMENT)?;
+        match message {
+            Some(message) => Ok(format!(
+                "assert {}, {};",
+                condition,
+                self.operand(message, ASSIGNMENT)?
+            )),
+            None => Ok(format!("assert {};", condition)),
+        }
+    }
+
+    fn visit_defer_stmt(&mut self, _keyword: &Token, body: StmtId) -> Result<String, Error> {
+        Ok(format!("defer {}", self.statement(body)?))
+    }
+
+    fn visit_try_stmt(
+        &mut self,
+        _keyword: &Token,
+        body: &[StmtId],
+        catch: Option<(&Token, &[StmtId])>,
+        finally: Option<&[StmtId]>,
+    ) -> Result<String, Error> {
+        let mut text = format!("try {}", self.block(body)?);
+        if let Some((name, catch)) = catch {
+            text += &format!(" catch ({}) {}", name.lexeme, self.block(catch)?);
+        }
+        if let Some(finally) = finally {
+            text += &format!(" finally {}", self.block(finally)?);
+        }
+        Ok(text)
+    }
+
+    fn visit_throw_stmt(&mut self, _keyword: &Token, value: ExprId) -> Result<String, Error> {
+        Ok(format!("throw {};", self.operand(value, ASSIGNMENT)?))
+    }
+}
+
+impl expr::Visitor<String> for Emitter<'_> {
+    fn visit_literal_expr(&mut self, value: &Literal) -> Result<String, Error> {
+        literal(value)
+    }
+
+    fn visit_unary_expr(&mut self, operator: &Token, right: ExprId) -> Result<String, Error> {
+        let right = self.operand(right, UNARY)?;
+        // `- -1`, not `--1`
+        let space = if right.starts_with(&operator.lexeme) {
+            " "
+        } else {
+            ""
+        };
+        Ok(format!("{}{}{}", operator.lexeme, space, right))
+    }
+
+    fn visit_binary_expr(
+        &mut self,
+        left: ExprId,
+        operator: &Token,
+        right: ExprId,
+    ) -> Result<String, Error> {
+        let level = binary_precedence(operator);
+        // `(a < b) < c` would read back as the chain `a < b < c`
+        let left_level = if level == COMPARISON {
+            level + 1
+        } else {
+            level
+        };
+        Ok(format!(
+            "{} {} {}",
+            self.operand(left, left_level)?,
+            operator.lexeme,
+            self.operand(right, level + 1)?
+        ))
+    }
+
+    fn visit_grouping_expr(&mut self, expression: ExprId) -> Result<String, Error> {
+        Ok(format!("({})", self.operand(expression, ASSIGNMENT)?))
+    }
+
+    fn visit_variable_expr(&mut self, name: &Token, _id: ExprId) -> Result<String, Error> {
+        Ok(name.lexeme.clone())
+    }
+
+    fn visit_assign_expr(
+        &mut self,
+        name: &Token,
+        value: ExprId,
+        _id: ExprId,
+    ) -> Result<String, Error> {
+        Ok(format!(
+            "{} = {}",
+            name.lexeme,
+            self.operand(value, ASSIGNMENT)?
+        ))
+    }
+
+    fn visit_logic_expr(
+        &mut self,
+        left: ExprId,
+        operator: &Token,
+        right: ExprId,
+    ) -> Result<String, Error> {
+        let level = match operator.token_type {
+            TokenType::QuestionQuestion => COALESCE,
+            TokenType::Or => OR,
+            _ => AND,
+        };
+        Ok(format!(
+            "{} {} {}",
+            self.operand(left, level)?,
+            operator.lexeme,
+            self.operand(right, level + 1)?
+        ))
+    }
+
+    fn visit_index_expr(
+        &mut self,
+        object: ExprId,
+        _operator: &Token,
+        index: ExprId,
+        index_end: Option<ExprId>,
+        index_step: Option<ExprId>,
+        optional: bool,
+    ) -> Result<String, Error> {
+        let object = self.operand(object, CALL)?;
+        // a nil bound of a slice is left out, as in `[:2]`
+        let bound = |emitter: &mut Self, bound: ExprId| match emitter.ast[bound] {
+            Expr::Literal {
+                value: Literal::Nil,
+            } => Ok(String::new()),
+            _ => emitter.operand(bound, ASSIGNMENT),
+        };
+        let (index, mut end) = match index_end {
+            Some(end) => (bound(self, index)?, format!(":{}", bound(self, end)?)),
+            None => (self.operand(index, ASSIGNMENT)?, String::new()),
+        };
+        if let Some(step) = index_step {
+            end = format!("{}:{}", end, bound(self, step)?);
+        }
+        let bracket = if optional { "?[" } else { "[" };
+        Ok(format!("{}{}{}{}]", object, bracket, index, end))
+    }
+
+    fn visit_call_expr(
+        &mut self,
+        callee: ExprId,
+        _paren: &Token,
+        arguments: &[ExprId],
+        keywords: &[(Token, ExprId)],
+    ) -> Result<String, Error> {
+        let callee = self.operand(callee, CALL)?;
+        let mut arguments = self.operands(arguments)?;
+        for (name, value) in keywords {
+            arguments.push(format!(
+                "{}: {}",
+                name.lexeme,
+                self.operand(*value, ASSIGNMENT)?
+            ));
+        }
+        Ok(format!("{}({})", callee, arguments.join(", ")))
+    }
+
+    fn visit_get_expr(
+        &mut self,
+        object: ExprId,
+        name: &Token,
+        _cache: &MethodCache,
+        optional: bool,
+    ) -> Result<String, Error> {
+        let dot = if optional { "?." } else { "." };
+        Ok(format!(
+            "{}{}{}",
+            self.operand(object, CALL)?,
+            dot,
+            name.lexeme
+        ))
+    }
+
+    fn visit_set_expr(
+        &mut self,
+        object: ExprId,
+        name: &Token,
+        value: ExprId,
+    ) -> Result<String, Error> {
+        Ok(format!(
+            "{}.{} = {}",
+            self.operand(object, CALL)?,
+            name.lexeme,
+            self.operand(value, ASSIGNMENT)?
+        ))
+    }
+
+    fn visit_index_set_expr(
+        &mut self,
+        object: ExprId,
+        index: ExprId,
+        index_end: Option<ExprId>,
+        index_step: Option<ExprId>,
+        value: ExprId,
+        operator: &Token,
+    ) -> Result<String, Error> {
+        let index = self.visit_index_expr(object, operator, index, index_end, index_step, false)?;
+        Ok(format!("{} = {}", index, self.operand(value, ASSIGNMENT)?))
+    }
+
+    fn visit_this_expr(&mut self, keyword: &Token, _id: ExprId) -> Result<String, Error> {
+        Ok(keyword.lexeme.clone())
+    }
+
+    fn visit_super_expr(
+        &mut self,
+        _keyword: &Token,
+        method: &Token,
+        _id: ExprId,
+    ) -> Result<String, Error> {
+        Ok(format!("super.{}", method.lexeme))
+    }
+
+    fn visit_list_expr(&mut self, _keyword: &Token, elements: &[ExprId]) -> Result<String, Error> {
+        Ok(format!("list({})", self.operands(elements)?.join(", ")))
+    }
+
+    fn visit_map_expr(
+        &mut self,
+        _brace: &Token,
+        keys: &[ExprId],
+        values: &[ExprId],
+    ) -> Result<String, Error> {
+        // the parser tells a map from a block by its first key
+        if let Some(first) = keys.first().map(|first| &self.ast[*first]) {
+            let literal = std::matches!(first, Expr::Literal { .. });
+            if !literal || precedence(first) != PRIMARY {
+                return Err(unwritable("a map whose first key is not a literal"));
+            }
+        }
+        let keys = self.operands(keys)?;
+        let values = self.operands(values)?;
+        let entries: Vec<String> = keys
+            .iter()
+            .zip(&values)
+            .map(|(key, value)| format!("{}: {}", key, value))
+            .collect();
+        Ok(format!("{{{}}}", entries.join(", ")))
+    }
+
+    fn visit_comparison_expr(
+        &mut self,
+        operands: &[ExprId],
+        operators: &[Token],
+    ) -> Result<String, Error> {
+        let mut text = self.operand(operands[0], TERM)?;
+        for (operator, operand) in operators.iter().zip(&operands[1..]) {
+            text += &format!(" {} {}", operator.lexeme, self.operand(*operand, TERM)?);
+        }
+        Ok(text)
+    }
+
+    fn visit_if_expr(
+        &mut self,
+        _keyword: &Token,
+        condition: ExprId,
+        then_branch: ExprId,
+        else_branch: Option<ExprId>,
+    ) -> Result<String, Error> {
+        let mut text = format!(
+            "if ({}) {}",
+            self.operand(condition, ASSIGNMENT)?,
+            self.branch(then_branch)?
+        );
+        if let Some(else_branch) = else_branch {
+            text += &format!(" else {}", self.branch(else_branch)?);
+        }
+        Ok(text)
+    }
+
+    fn visit_block_expr(
+        &mut self,
+        statements: &[StmtId],
+        value: Option<ExprId>,
+    ) -> Result<String, Error> {
+        // outside an if, `{}` is an empty map
+        if statements.is_empty() && value.is_none() {
+            return Err(unwritable("an empty block expression"));
+        }
+        self.lines(statements, value)
+    }
+
+    fn visit_match_expr(
+        &mut self,
+        _keyword: &Token,
+        subject: ExprId,
+        arms: &[MatchArm],
+        _strings: &StringArms,
+    ) -> Result<String, Error> {
+        let subject = self.operand(subject, ASSIGNMENT)?;
+        if arms.is_empty() {
+            return Ok(format!("match ({}) {{}}", subject));
+        }
+        self.level += 1;
+        let mut lines = Vec::new();
+        for arm in arms {
+            lines.push(format!(
+                "{}{} -> {},",
+                self.indent(),
+                pattern(self.ast, &arm.pattern)?,
+                self.operand(arm.body, ASSIGNMENT)?
+            ));
+        }
+        self.level -= 1;
+        Ok(format!(
+            "match ({}) {{\n{}\n{}}}",
+            subject,
+            lines.join("\n"),
+            self.indent()
+        ))
+    }
+
+    fn visit_tuple_expr(&mut self, elements: &[ExprId]) -> Result<String, Error> {
+        Ok(self.operands(elements)?.join(", "))
+    }
+
+    fn visit_interpolation_expr(
+        &mut self,
+        _token: &Token,
+        parts: &[ExprId],
+    ) -> Result<String, Error> {
+        let ast = self.ast;
+        let mut text = String::new();
+        for part in parts {
+            match &ast[*part] {
+                Expr::Literal {
+                    value: Literal::String(s),
+                } => text += &string_body(s)?,
+                _ => text += &format!("${{{}}}", self.operand(*part, ASSIGNMENT)?),
+            }
+        }
+        Ok(format!("\"{}\"", text))
+    }
+
+    fn visit_const_expr(
+        &mut self,
+        _keyword: &Token,
+        value: ExprId,
+        _id: ExprId,
+    ) -> Result<String, Error> {
+        Ok(format!("const {}", self.operand(value, PRIMARY)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Interpreter, Parser, Resolver};
+    use std::cell::RefCell;
+    use std::io::{self, Write};
+
+    fn parse(source: &str) -> (Rc<Ast>, Vec<StmtId>) {
+        let mut scanner = Scanner::new(source);
+        scanner.scan_tokens();
+        assert!(!scanner.had_error);
+        let mut parser = Parser::new(&scanner.tokens);
+        let stmts = parser.parse().unwrap();
+        (Rc::new(parser.into_ast()), stmts)
+    }
+
+    fn reparse(source: &str) -> String {
+        let (ast, stmts) = parse(source);
+        to_source(&ast, &stmts).unwrap()
+    }
+
+    struct Capture(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Capture {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// what running `stmts` prints
+    fn output(ast: &Rc<Ast>, stmts: &[StmtId]) -> String {
+        let buffer = Rc::new(RefCell::new(Vec::new()));
+        let mut interpreter = Interpreter::new();
+        interpreter.set_output(Box::new(Capture(buffer.clone())));
+        Resolver::new(&mut interpreter, ast)
+            .resolve_stmts(stmts)
+            .unwrap();
+        interpreter.set_ast(Rc::clone(ast));
+        interpreter.interpret(stmts).unwrap();
+        // dropping the interpreter flushes what it printed
+        drop(interpreter);
+        String::from_utf8(buffer.take()).unwrap()
+    }
+
+    /// the program written back as source and parsed again prints the
+    /// same as the program itself
+    fn assert_same_meaning(ast: &Rc<Ast>, stmts: &[StmtId]) {
+        let written = to_source(ast, stmts).unwrap();
+        let (reread, restmts) = parse(&written);
+        assert_eq!(output(&reread, &restmts), output(ast, stmts), "{}", written);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let source = r#"
+/// a point
+class Point < Base {
+  var z = 0;
+  init(x, y) { this.x = x; super.init(); }
+}
+fun add[a](b, c = b * 2) { return b + c * a, -(-1); }
+var a, b = add(1, 2.0);
+outer: for (var i = 0; 0 <= i < 3; i = i + 1) {
+  if (a) if (b) print 1; else print 2;
+  else continue outer;
+}
+for (var x in list(1, 2)) print "v ${x + 1} \${x}";
+var m = const {"k": list(1)[0:1], 2: nil};
+m["k"] = m?[(a = b)][:-1][1:][::-2] or !true and (1 - (2 - 3)) / 2;
+var v = match (m) { [x, ...rest] -> x, Point { x, y: 0 } -> {1}, {"k": [_], 2: nil} -> 0, 1..3 -> -1.5, _ -> if (a) {1} else {2} };
+({ print a; a });
+try { throw "e"; } catch (e) { defer print e; } finally { assert a, "no"; }
+while (true) { break; }
+f(1, key: 2);
+print (a ?? b or a)?.x ?? m?.y.z;
+print a is Point == !(b is Base);
+"#;
+        let first = reparse(source);
+        let second = reparse(&first);
+        assert_eq!(first, second);
+        assert!(first.contains("outer: for (var i = 0; 0 <= i < 3; i = i + 1) {"));
+        assert!(first.contains("return b + c * a, -(-1);"));
+        assert!(first.contains("print (a ?? b or a)?.x ?? m?.y.z;"));
+    }
+
+    #[test]
+    fn test_round_trip_meaning() {
+        let source = r#"
+class Base {}
+class Point < Base { init(x) { this.x = x; } }
+var a = 2;
+var b = 3;
+var n = nil;
+print a - (b - 1);
+print (a - b) - 1;
+print -(a - b) * 2;
+print 10 / (5 / 5) * -(-2);
+print !(a < b) == false;
+print (a = 5) + 1;
+print (n ?? a) or b;
+print n ?? (a and b);
+print (n ?? false) ?? true;
+print 1 < a <= 5;
+print (a < b) == (b < a);
+print Point(1) is Base == !(b is Base);
+print list(1, 2, 3)[-1:][0] + list(4)[0];
+print "x${a + b}y" + "z";
+print match (list(1, 2)) { [x, ...r] -> x + r[0], _ -> 0 };
+print (if (a > b) {1} else {2}) * 10;
+print {"k": a}["k"] * 2;
+"#;
+        let (ast, stmts) = parse(source);
+        assert_same_meaning(&ast, &stmts);
+
+        // built trees have no groupings, the emitter has to add them
+        let mut ast = Ast::new();
+        let a = &mut ast;
+        let mut built = Vec::new();
+
+        let (two, three, four) = (a.integer(2), a.integer(3), a.integer(4));
+        let right = a.binary(three, "-", four);
+        built.push(a.binary(two, "-", right));
+
+        let (one, two, three) = (a.integer(1), a.integer(2), a.integer(3));
+        let left = a.binary(one, "+", two);
+        built.push(a.binary(left, "*", three));
+
+        let (one, two) = (a.integer(1), a.integer(2));
+        let right = a.binary(one, "-", two);
+        built.push(a.unary("-", right));
+
+        let (nil, true_) = (a.nil(), a.boolean(true));
+        let right = a.binary(nil, "??", true_);
+        built.push(a.unary("!", right));
+
+        let (nil, false_, one) = (a.nil(), a.boolean(false), a.integer(1));
+        let right = a.binary(false_, "or", one);
+        built.push(a.binary(nil, "??", right));
+
+        let (nil, false_, one) = (a.nil(), a.boolean(false), a.integer(1));
+        let left = a.binary(nil, "??", false_);
+        built.push(a.binary(left, "or", one));
+
+        let (one, two) = (a.integer(1), a.integer(2));
+        let left = a.binary(one, "<", two);
+        let (two, one) = (a.integer(2), a.integer(1));
+        let right = a.binary(two, "<", one);
+        built.push(a.binary(left, "==", right));
+
+        let built: Vec<StmtId> = built.into_iter().map(|expr| a.print(expr)).collect();
+        assert_same_meaning(&Rc::new(ast), &built);
+    }
+
+    #[test]
+    fn test_builders() {
+        let mut ast = Ast::new();
+        let (one, two, three) = (ast.integer(1), ast.integer(2), ast.float(3.0));
+        let sum = ast.binary(one, "+", two);
+        let product = ast.binary(sum, "*", three);
+        let var = ast.var("x", product);
+
+        let (x, five) = (ast.variable("x"), ast.integer(5));
+        let condition = ast.binary(x, ">", five);
+        let big = ast.string("big");
+        let then_branch = ast.print(big);
+        let x = ast.variable("x");
+        let abs = ast.get(x, "abs");
+        let call = ast.call(abs, vec![]);
+        let print = ast.print(call);
+        let else_branch = ast.block(vec![print]);
+        let if_else = ast.if_else(condition, then_branch, Some(else_branch));
+        assert_eq!(
+            to_source(&ast, &[var, if_else]).unwrap(),
+            "var x = (1 + 2) * 3.0;\n\
+             if (x > 5)\n  print \"big\";\nelse {\n  print x.abs();\n}\n"
+        );
+        let hi = ast.string("say \"hi\"");
+        let quoted = ast.print(hi);
+        assert!(to_source(&ast, &[quoted]).is_err());
+    }
+}
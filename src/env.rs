@@ -1,33 +1,109 @@
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 /// environment
 /// binding values to names
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::rc::Rc;
 
-use super::{Error, ErrorType, Object, Token};
+use super::{Error, Object, Token};
 
 pub type EnvironmentRef = Rc<RefCell<Environment>>;
 
+thread_local! {
+    /// environments made and not yet dropped, for `memoryStats()`
+    static LIVE: Cell<usize> = const { Cell::new(0) };
+}
+
+/// names map to slots in `values`, a slot can be reserved before the
+/// name is defined so the resolver can hand out global indices early
 pub struct Environment {
     pub enclosing: Option<EnvironmentRef>,
-    values: HashMap<String, Object>,
+    indices: HashMap<String, usize>,
+    values: Vec<Option<Object>>,
+}
+
+/// a binding `flatten` found, depth counts scopes out from where it
+/// started, 0 being that environment itself
+#[derive(Debug, Clone)]
+pub struct VisibleBinding {
+    pub depth: usize,
+    pub name: String,
+    pub value: Object,
+}
+
+impl Drop for Environment {
+    fn drop(&mut self) {
+        LIVE.with(|live| live.set(live.get() - 1));
+    }
 }
 
 impl Environment {
     pub fn new(enclosing: Option<EnvironmentRef>) -> Self {
+        LIVE.with(|live| live.set(live.get() + 1));
         Self {
             enclosing,
-            values: HashMap::new(),
+            indices: HashMap::new(),
+            values: Vec::new(),
+        }
+    }
+
+    /// an environment with room for `capacity` names, calls size the
+    /// environment from the parameter count so binding never reallocates
+    pub fn with_capacity(enclosing: Option<EnvironmentRef>, capacity: usize) -> Self {
+        LIVE.with(|live| live.set(live.get() + 1));
+        Self {
+            enclosing,
+            indices: HashMap::with_capacity(capacity),
+            values: Vec::with_capacity(capacity),
         }
     }
 
     pub fn define(&mut self, name: &str, value: Object) {
-        self.values.insert(String::from(name), value);
+        let slot = self.reserve(name);
+        self.values[slot] = Some(value);
+    }
+
+    /// the slot of `name`, taking a fresh one if it has none yet
+    pub fn reserve(&mut self, name: &str) -> usize {
+        if let Some(slot) = self.indices.get(name) {
+            return *slot;
+        }
+        self.values.push(None);
+        self.indices
+            .insert(String::from(name), self.values.len() - 1);
+        self.values.len() - 1
+    }
+
+    /// every name with a slot, in slot order
+    pub fn names(&self) -> Vec<&str> {
+        let mut names = vec![""; self.values.len()];
+        for (name, slot) in &self.indices {
+            names[*slot] = name;
+        }
+        names
+    }
+
+    pub fn slot(&self, name: &str) -> Option<usize> {
+        self.indices.get(name).copied()
+    }
+
+    /// the value in a slot, None while its name is not defined
+    pub fn get_slot(&self, slot: usize) -> Option<Object> {
+        self.values.get(slot).cloned().flatten()
+    }
+
+    pub fn assign_slot(&mut self, slot: usize, name: &Token, value: &Object) -> Result<(), Error> {
+        match self.values.get_mut(slot) {
+            Some(Some(current)) => {
+                *current = value.clone();
+                Ok(())
+            }
+            _ => Err(Error::coded("E0401", &[name]).at(name)),
+        }
     }
 
     pub fn get(&self, name: &str) -> Option<Object> {
-        if let Some(query) = self.values.get(name) {
-            Some(query.clone())
+        if let Some(query) = self.slot(name).and_then(|slot| self.get_slot(slot)) {
+            Some(query)
         } else if let Some(enclosing_inner) = self.enclosing.as_ref() {
             enclosing_inner.borrow().get(name)
         } else {
@@ -36,16 +112,70 @@ impl Environment {
     }
 
     pub fn assign(&mut self, name: &Token, value: &Object) -> Result<(), Error> {
-        if self.values.contains_key(&name.lexeme) {
-            self.values.insert(name.lexeme.clone(), value.clone());
+        if let Some(Some(current)) = self.slot(&name.lexeme).map(|slot| &mut self.values[slot]) {
+            *current = value.clone();
             Ok(())
         } else if let Some(enclosing_inner) = self.enclosing.as_mut() {
             enclosing_inner.borrow_mut().assign(name, value)
         } else {
-            Err(Error {
-                message: format!("Undefined variable '{}'.", name),
-                error_type: ErrorType::RuntimeError(name.clone()),
-            })
+            Err(Error::coded("E0401", &[name]).at(name))
+        }
+    }
+
+    /// how many environments exist right now
+    pub fn live() -> usize {
+        LIVE.with(|live| live.get())
+    }
+
+    /// the values bound here, without the enclosing scopes
+    pub fn values(&self) -> impl Iterator<Item = &Object> {
+        self.values.iter().flatten()
+    }
+
+    /// drop every binding and the link to the enclosing scope, the
+    /// collector does this to break a cycle through the environment
+    pub fn clear(&mut self) -> (Vec<Option<Object>>, Option<EnvironmentRef>) {
+        self.indices.clear();
+        (std::mem::take(&mut self.values), self.enclosing.take())
+    }
+
+    /// every binding visible from here, innermost scope first and in
+    /// definition order within a scope, shadowed names left out
+    pub fn flatten(&self) -> Vec<VisibleBinding> {
+        let mut seen = HashSet::new();
+        let mut bindings = Vec::new();
+        self.collect_visible(0, &mut seen, &mut bindings);
+        let mut depth = 1;
+        let mut enclosing = self.enclosing.clone();
+        while let Some(environment) = enclosing {
+            let environment = environment.borrow();
+            environment.collect_visible(depth, &mut seen, &mut bindings);
+            enclosing = environment.enclosing.clone();
+            depth += 1;
+        }
+        bindings
+    }
+
+    fn collect_visible(
+        &self,
+        depth: usize,
+        seen: &mut HashSet<String>,
+        bindings: &mut Vec<VisibleBinding>,
+    ) {
+        let mut names: Vec<(&String, &usize)> = self.indices.iter().collect();
+        names.sort_by_key(|(_, slot)| **slot);
+        for (name, slot) in names {
+            // a reserved slot isn't a binding until its name is defined
+            let Some(value) = &self.values[*slot] else {
+                continue;
+            };
+            if seen.insert(name.clone()) {
+                bindings.push(VisibleBinding {
+                    depth,
+                    name: name.clone(),
+                    value: value.clone(),
+                });
+            }
         }
     }
 
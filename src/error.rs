@@ -1,38 +1,137 @@
-use crate::{token::{Token, TokenType}, Object};
+use std::fmt::Display;
+
+use crate::{render::{self, Diagnostic, Location, Phase}, token::{Token, TokenType}, Object};
 
 #[derive(Debug)]
 pub struct Error {
-    pub message: String,
+    /// the entry of `render::CATALOG` the message is worded from, for
+    /// tools that match on errors
+    pub code: &'static str,
+    /// the values from the program filling the entry's `{}`s
+    pub args: Vec<String>,
     pub error_type: ErrorType,
 }
 
 impl Error {
-    pub fn new(message: &str, error_type: ErrorType) -> Self {
+    /// an error raised by a native, `Error::coded("E0401", &[&name])`.
+    /// the call site fills in where it happened
+    pub fn coded(code: &'static str, args: &[&dyn Display]) -> Self {
         Self {
-            message: message.to_string(),
+            code,
+            args: args.iter().map(|arg| arg.to_string()).collect(),
+            error_type: ErrorType::NativeError,
+        }
+    }
+
+    /// a return, break or continue, which is never reported
+    pub fn control_flow(error_type: ErrorType) -> Self {
+        Self {
+            code: render::UNKNOWN,
+            args: Vec::new(),
             error_type,
         }
     }
-}
 
-pub fn report(line: usize, whr: &str,message: &str) {
-    eprintln!("[line {}] Error{}: {}", line, whr,message);
+    pub fn with_type(mut self, error_type: ErrorType) -> Self {
+        self.error_type = error_type;
+        self
+    }
+
+    /// the same error as a runtime error at `token`
+    pub fn at(self, token: &Token) -> Self {
+        self.with_type(ErrorType::RuntimeError(Box::new(token.clone())))
+    }
+
+    /// the English message, as `CATALOG` words it
+    pub fn message(&self) -> String {
+        render::english(self.code, &self.args)
+    }
+
+    /// a return, break or continue on its way out rather than a failure
+    pub fn is_control_flow(&self) -> bool {
+        matches!(
+            self.error_type,
+            ErrorType::Return(_) | ErrorType::Break(_) | ErrorType::Continue(_)
+        )
+    }
+
+    /// whether a `catch` may stop the error, running out of fuel is a
+    /// limit set by the host and no script may get around it
+    pub fn is_catchable(&self) -> bool {
+        match self.error_type {
+            ErrorType::Thrown(..) => true,
+            ErrorType::RuntimeError(_) | ErrorType::NativeError => self.code != "E0419",
+            _ => false,
+        }
+    }
 }
 
-pub fn parse_error(token: &Token, msg: &str) {
-    if token.token_type == TokenType::Eof {
-        report(token.line, " at end", msg);
+/// report a compile error at `token`, `code` and `args` as for `Error::coded`
+pub fn parse_error(token: &Token, code: &'static str, args: &[&dyn Display]) {
+    let location = if token.token_type == TokenType::Eof {
+        Location::End
     } else {
-        report(token.line, &format!(" at '{}'", token.lexeme), msg);
-    }
+        Location::Token(token.lexeme.clone())
+    };
+    let error = Error::coded(code, args);
+    render::emit(&Diagnostic::new(Phase::Compile, &error).at(token.line, location));
+}
+
+pub fn report_scan_error(error: &Error) {
+    let diagnostic = Diagnostic::new(Phase::Scan, error);
+    let diagnostic = match &error.error_type {
+        ErrorType::ScanError {
+            line, column, text, ..
+        } => {
+            let mut chars = text.chars();
+            let location = match (chars.next(), chars.next()) {
+                (Some(c), None) => Location::Character(c),
+                _ => Location::None,
+            };
+            diagnostic.at(*line, location).column(*column)
+        }
+        _ => diagnostic,
+    };
+    render::emit(&diagnostic);
+}
+
+/// print an error that stopped the program
+pub fn report_runtime_error(error: &Error) {
+    let diagnostic = Diagnostic::new(Phase::Runtime, error);
+    let diagnostic = match &error.error_type {
+        ErrorType::RuntimeError(token) | ErrorType::Thrown(_, token) => {
+            diagnostic.at(token.line, Location::Token(token.lexeme.clone()))
+        }
+        _ => diagnostic,
+    };
+    render::emit(&diagnostic);
 }
 
+/// Return, Break and Continue are control flow unwinding through `Err`:
+/// a return is caught by the function it was raised in, a break or
+/// continue by its loop. none of them gets past a function call, so a
+/// native calling back into Lox only ever sees real errors
 #[derive(Debug)]
 pub enum ErrorType {
-    ScanError(usize),
+    /// `text` is the offending source, found at `span` bytes
+    ScanError {
+        line: usize,
+        column: usize,
+        span: std::ops::Range<usize>,
+        text: String,
+    },
     SyntaxError,
-    RuntimeError(Token),
-    Return(Object)
+    /// the token is boxed to keep `Result<_, Error>` small on the
+    /// happy path, every visit method returns one
+    RuntimeError(Box<Token>),
+    /// raised by a native function, the call site fills in the token
+    NativeError,
+    Return(Object),
+    /// leave the loop with the given label, or the innermost one
+    Break(Option<String>),
+    /// go to the next iteration of the loop with the given label, or the innermost one
+    Continue(Option<String>),
+    /// a value raised by `throw`, unwinding to the nearest `catch`
+    /// through calls and natives alike
+    Thrown(Object, Box<Token>),
 }
-
-
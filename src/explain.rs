@@ -0,0 +1,101 @@
+/// `lox explain`, a script run one top-level statement at a time,
+/// showing the tree each statement parsed to, the value of expression
+/// statements and the globals the statement defined or changed
+use crate::render;
+use crate::stdlib::debug::describe;
+use crate::{compile, AstPrinter, Error, ErrorType, Interpreter, Stmt};
+
+pub fn explain(interpreter: &mut Interpreter, source: &str) -> Result<(), Error> {
+    let program = compile(source).map_err(|diagnostics| {
+        diagnostics.iter().for_each(render::emit);
+        not_compiled()
+    })?;
+    program.load(interpreter);
+    let ast = program.ast();
+
+    for (index, stmt) in program.statements().iter().enumerate() {
+        let line = match ast[*stmt].line(ast) {
+            Some(line) => format!(", line {}", line),
+            None => String::new(),
+        };
+        interpreter.print_line(&format!("--- statement {}{} ---", index + 1, line))?;
+        let tree = AstPrinter::new(ast).print_stmt(*stmt)?;
+        interpreter.print_line(&format!("ast: {}", tree))?;
+
+        let before = describe(&interpreter.globals.borrow());
+        match &ast[*stmt] {
+            Stmt::ExprStmt { expression } => {
+                let value = interpreter.evaluate(*expression)?;
+                let value = interpreter.display(&value)?;
+                interpreter.print_line(&format!("value: {}", value))?;
+            }
+            _ => interpreter.interpret(std::slice::from_ref(stmt))?,
+        }
+        let after = describe(&interpreter.globals.borrow());
+        for change in diff(&before, &after) {
+            interpreter.print_line(&change)?;
+        }
+    }
+    interpreter.flush()
+}
+
+/// `+ binding` for each line only in `after`, `- binding` for each only
+/// in `before`, a changed value shows up as both
+fn diff(before: &[String], after: &[String]) -> Vec<String> {
+    let removed = before
+        .iter()
+        .filter(|line| !after.contains(line))
+        .map(|line| format!("- {}", line));
+    let added = after
+        .iter()
+        .filter(|line| !before.contains(line))
+        .map(|line| format!("+ {}", line));
+    removed.chain(added).collect()
+}
+
+/// the errors are already reported by the time this is returned
+fn not_compiled() -> Error {
+    Error::coded("E0216", &[]).with_type(ErrorType::SyntaxError)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::io::{self, Write};
+    use std::rc::Rc;
+
+    struct Capture(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Capture {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_explain() {
+        let buffer = Rc::new(RefCell::new(Vec::new()));
+        let mut interpreter = Interpreter::new();
+        interpreter.set_output(Box::new(Capture(buffer.clone())));
+        explain(&mut interpreter, "var a = 1;\nprint a;\na = a + 1;").unwrap();
+        assert_eq!(
+            String::from_utf8(buffer.borrow().clone()).unwrap(),
+            "--- statement 1, line 1 ---\n\
+             ast: var: a = 1\n\
+             + [0] a = 1\n\
+             --- statement 2, line 2 ---\n\
+             ast: print: a\n\
+             1\n\
+             --- statement 3, line 3 ---\n\
+             ast: (a = (a + 1))\n\
+             value: 2\n\
+             - [0] a = 1\n\
+             + [0] a = 2\n"
+        );
+    }
+}
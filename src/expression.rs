@@ -1,24 +1,120 @@
 use super::*;
-use std::fmt;
 
 pub mod expr {
-    use super::{Error, Expr, Literal};
+    use super::{Error, ExprId, Literal, MatchArm, MethodCache, StmtId, StringArms, Token};
+    /// each visit method gets the fields of its node, already taken apart,
+    /// and the node itself where the resolver keys a side table by it
     pub trait Visitor<T> {
         fn visit_literal_expr(&mut self, value: &Literal) -> Result<T, Error>;
-        fn visit_unary_expr(&mut self, expr: &Expr) -> Result<T, Error>;
-        fn visit_binary_expr(&mut self, expr: &Expr) -> Result<T, Error>;
-        fn visit_grouping_expr(&mut self, expr: &Expr) -> Result<T, Error>;
-        fn visit_variable_expr(&mut self, expr: &Expr) -> Result<T, Error>;
-        fn visit_assign_expr(&mut self, expr: &Expr) -> Result<T, Error>;
-        fn visit_logic_expr(&mut self, expr: &Expr) -> Result<T, Error>;
-        fn visit_index_expr(&mut self, expr: &Expr) -> Result<T, Error>;
-        fn visit_call_expr(&mut self, expr: &Expr) -> Result<T, Error>;
-        fn visit_get_expr(&mut self, expr: &Expr) -> Result<T, Error>;
-        fn visit_set_expr(&mut self, expr: &Expr) -> Result<T, Error>;
-        fn visit_index_set_expr(&mut self, expr: &Expr) -> Result<T, Error>;
-        fn visit_this_expr(&mut self, expr: &Expr) -> Result<T, Error>;
-        fn visit_super_expr(&mut self, expr: &Expr) -> Result<T, Error>;
-        fn visit_list_expr(&mut self, expr: &Expr) -> Result<T, Error>;
+        fn visit_unary_expr(&mut self, operator: &Token, right: ExprId) -> Result<T, Error>;
+        fn visit_binary_expr(
+            &mut self,
+            left: ExprId,
+            operator: &Token,
+            right: ExprId,
+        ) -> Result<T, Error>;
+        fn visit_grouping_expr(&mut self, expression: ExprId) -> Result<T, Error>;
+        fn visit_variable_expr(&mut self, name: &Token, id: ExprId) -> Result<T, Error>;
+        fn visit_assign_expr(
+            &mut self,
+            name: &Token,
+            value: ExprId,
+            id: ExprId,
+        ) -> Result<T, Error>;
+        fn visit_logic_expr(
+            &mut self,
+            left: ExprId,
+            operator: &Token,
+            right: ExprId,
+        ) -> Result<T, Error>;
+        fn visit_index_expr(
+            &mut self,
+            object: ExprId,
+            operator: &Token,
+            index: ExprId,
+            index_end: Option<ExprId>,
+            index_step: Option<ExprId>,
+            optional: bool,
+        ) -> Result<T, Error>;
+        fn visit_call_expr(
+            &mut self,
+            callee: ExprId,
+            paren: &Token,
+            arguments: &[ExprId],
+            keywords: &[(Token, ExprId)],
+        ) -> Result<T, Error>;
+        fn visit_get_expr(
+            &mut self,
+            object: ExprId,
+            name: &Token,
+            cache: &MethodCache,
+            optional: bool,
+        ) -> Result<T, Error>;
+        fn visit_set_expr(
+            &mut self,
+            object: ExprId,
+            name: &Token,
+            value: ExprId,
+        ) -> Result<T, Error>;
+        fn visit_index_set_expr(
+            &mut self,
+            object: ExprId,
+            index: ExprId,
+            index_end: Option<ExprId>,
+            index_step: Option<ExprId>,
+            value: ExprId,
+            operator: &Token,
+        ) -> Result<T, Error>;
+        fn visit_this_expr(&mut self, keyword: &Token, id: ExprId) -> Result<T, Error>;
+        fn visit_super_expr(
+            &mut self,
+            keyword: &Token,
+            method: &Token,
+            id: ExprId,
+        ) -> Result<T, Error>;
+        fn visit_list_expr(&mut self, keyword: &Token, elements: &[ExprId]) -> Result<T, Error>;
+        fn visit_map_expr(
+            &mut self,
+            brace: &Token,
+            keys: &[ExprId],
+            values: &[ExprId],
+        ) -> Result<T, Error>;
+        fn visit_comparison_expr(
+            &mut self,
+            operands: &[ExprId],
+            operators: &[Token],
+        ) -> Result<T, Error>;
+        fn visit_if_expr(
+            &mut self,
+            keyword: &Token,
+            condition: ExprId,
+            then_branch: ExprId,
+            else_branch: Option<ExprId>,
+        ) -> Result<T, Error>;
+        fn visit_block_expr(
+            &mut self,
+            statements: &[StmtId],
+            value: Option<ExprId>,
+        ) -> Result<T, Error>;
+        fn visit_match_expr(
+            &mut self,
+            keyword: &Token,
+            subject: ExprId,
+            arms: &[MatchArm],
+            strings: &StringArms,
+        ) -> Result<T, Error>;
+        fn visit_tuple_expr(&mut self, elements: &[ExprId]) -> Result<T, Error>;
+        fn visit_interpolation_expr(
+            &mut self,
+            token: &Token,
+            parts: &[ExprId],
+        ) -> Result<T, Error>;
+        fn visit_const_expr(
+            &mut self,
+            keyword: &Token,
+            value: ExprId,
+            id: ExprId,
+        ) -> Result<T, Error>;
     }
 }
 
@@ -35,6 +131,7 @@ pub mod expr {
 //                | "+"  | "-"  | "*" | "/" ;
 /// An expression is a node in the AST that represents a value.
 /// have Arbitrary child nodes
+/// children are ids into the `Ast` the node is stored in
 #[derive(Debug, Clone)]
 pub enum Expr {
     Literal {
@@ -42,53 +139,64 @@ pub enum Expr {
     },
     Unary {
         operator: Token,
-        right: Box<Expr>,
+        right: ExprId,
     },
     Binary {
-        left: Box<Expr>,
+        left: ExprId,
         operator: Token,
-        right: Box<Expr>,
+        right: ExprId,
     },
     Grouping {
-        expression: Box<Expr>,
+        expression: ExprId,
     },
     Variable {
         name: Token,
     },
     Assign {
         name: Token,
-        value: Box<Expr>,
+        value: ExprId,
     },
     Logical {
-        left: Box<Expr>,
+        left: ExprId,
         operator: Token,
-        right: Box<Expr>,
+        right: ExprId,
     },
     Index {
-        object: Box<Expr>,
+        object: ExprId,
         operator: Token,
-        index: Box<Expr>,
-        index_end: Option<Box<Expr>>,
+        index: ExprId,
+        index_end: Option<ExprId>,
+        /// the `c` of `[a:b:c]`
+        index_step: Option<ExprId>,
+        /// `?[`, a nil object gives nil instead of an error
+        optional: bool,
     },
     Call {
-        callee: Box<Expr>,
+        callee: ExprId,
         paren: Token, // right paren
-        arguments: Vec<Expr>,
+        arguments: Vec<ExprId>,
+        /// `name: value` arguments, they come after the positional ones
+        keywords: Vec<(Token, ExprId)>,
     },
     Get {
-        object: Box<Expr>,
+        object: ExprId,
         name: Token,
+        /// the method this access found last time
+        cache: MethodCache,
+        /// `?.`, a nil object gives nil instead of an error
+        optional: bool,
     },
     Set {
-        object: Box<Expr>,
+        object: ExprId,
         name: Token,
-        value: Box<Expr>,
+        value: ExprId,
     },
     IndexSet {
-        object: Box<Expr>,
-        index: Box<Expr>,
-        index_end: Option<Box<Expr>>,
-        value: Box<Expr>,
+        object: ExprId,
+        index: ExprId,
+        index_end: Option<ExprId>,
+        index_step: Option<ExprId>,
+        value: ExprId,
         operator: Token,
     },
     This {
@@ -100,311 +208,481 @@ pub enum Expr {
     },
     List {
         keyword: Token,
-        elements: Vec<Expr>,
+        elements: Vec<ExprId>,
     },
+    /// `{ key: value, ... }`, the value of `keys[i]` is `values[i]`
+    Map {
+        brace: Token,
+        keys: Vec<ExprId>,
+        values: Vec<ExprId>,
+    },
+    /// chained comparison `a < b <= c`, every operand is evaluated once
+    Comparison {
+        operands: Vec<ExprId>,
+        operators: Vec<Token>,
+    },
+    /// `if (cond) { a } else { b }` used as a value
+    If {
+        keyword: Token,
+        condition: ExprId,
+        then_branch: ExprId,
+        else_branch: Option<ExprId>,
+    },
+    /// `{ stmt; ... value }`, the value of the last expression without a ';'
+    Block {
+        statements: Vec<StmtId>,
+        value: Option<ExprId>,
+    },
+    /// `match (subject) { pattern -> value, ... }`, the value of the
+    /// first arm whose pattern fits
+    Match {
+        keyword: Token,
+        subject: ExprId,
+        arms: Vec<MatchArm>,
+        strings: StringArms,
+    },
+    /// `return a, b;` gives back several values at once
+    Tuple {
+        elements: Vec<ExprId>,
+    },
+    /// `"a ${b} c"`, the parts stringified and joined, `token` is the
+    /// string up to the first `${`
+    Interpolation {
+        token: Token,
+        parts: Vec<ExprId>,
+    },
+    /// `const list(...)`, built the first time it is evaluated and
+    /// shared, frozen, by every evaluation after that
+    Const {
+        keyword: Token,
+        value: ExprId,
+    },
+}
+
+impl ExprId {
+    /// every walk over the tree goes through here, so this is where a
+    /// deeply nested expression gets more stack
+    pub fn accept<T>(self, ast: &Ast, visitor: &mut impl expr::Visitor<T>) -> Result<T, Error> {
+        grow_stack(|| ast[self].dispatch(self, visitor))
+    }
 }
 
 impl Expr {
-    #[allow(unused_variables)]
-    pub fn accept<T>(&self, visitor: &mut impl expr::Visitor<T>) -> Result<T, Error> {
+    /// the first token the node keeps, literals keep none
+    pub fn token<'a>(&'a self, ast: &'a Ast) -> Option<&'a Token> {
         match self {
-            Expr::Literal { value } => visitor.visit_literal_expr(value),
-            Expr::Unary { operator, right } => visitor.visit_unary_expr(self),
-            Expr::Binary {
-                left,
-                operator,
-                right,
-            } => visitor.visit_binary_expr(self),
-            Expr::Grouping { expression } => visitor.visit_grouping_expr(self),
-            Expr::Variable { name } => visitor.visit_variable_expr(self),
-            Expr::Assign { name, value } => visitor.visit_assign_expr(self),
-            Expr::Logical {
-                left,
-                operator,
-                right,
-            } => visitor.visit_logic_expr(self),
-            Expr::Index {
-                object: left,
-                operator,
-                index: right,
-                index_end,
-            } => visitor.visit_index_expr(self),
-            Expr::Call {
-                callee,
-                paren,
-                arguments,
-            } => visitor.visit_call_expr(self),
-            Expr::Get { object, name } => visitor.visit_get_expr(self),
-            Expr::Set {
-                object,
-                name,
-                value,
-            } => visitor.visit_set_expr(self),
-            Expr::IndexSet { .. } => visitor.visit_index_set_expr(self),
-            Expr::This { keyword } => visitor.visit_this_expr(self),
-            Expr::Super { keyword, method } => visitor.visit_super_expr(self),
-            Expr::List { keyword, elements } => visitor.visit_list_expr(self),
+            Expr::Literal { .. } => None,
+            Expr::Unary { operator, .. } => Some(operator),
+            Expr::Binary { left, operator, .. } | Expr::Logical { left, operator, .. } => {
+                ast[*left].token(ast).or(Some(operator))
+            }
+            Expr::Grouping { expression } => ast[*expression].token(ast),
+            Expr::Variable { name } | Expr::Assign { name, .. } => Some(name),
+            Expr::Index { object, operator, .. } | Expr::IndexSet { object, operator, .. } => {
+                ast[*object].token(ast).or(Some(operator))
+            }
+            Expr::Call { callee, paren, .. } => ast[*callee].token(ast).or(Some(paren)),
+            Expr::Get { object, name, .. } | Expr::Set { object, name, .. } => {
+                ast[*object].token(ast).or(Some(name))
+            }
+            Expr::This { keyword }
+            | Expr::Super { keyword, .. }
+            | Expr::List { keyword, .. }
+            | Expr::Map {
+                brace: keyword, ..
+            }
+            | Expr::If { keyword, .. }
+            | Expr::Match { keyword, .. }
+            | Expr::Const { keyword, .. }
+            | Expr::Interpolation { token: keyword, .. } => Some(keyword),
+            Expr::Comparison { operands, operators } => {
+                ast[operands[0]].token(ast).or(operators.first())
+            }
+            Expr::Tuple { elements } => elements.iter().find_map(|e| ast[*e].token(ast)),
+            Expr::Block { statements, value } => statements
+                .iter()
+                .find_map(|s| ast[*s].token(ast))
+                .or_else(|| value.and_then(|value| ast[value].token(ast))),
         }
     }
-}
 
-impl fmt::Display for Expr {
-    #[allow(unused_variables)]
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    pub fn line(&self, ast: &Ast) -> Option<usize> {
+        self.token(ast).map(|token| token.line)
+    }
+
+    fn dispatch<T>(&self, id: ExprId, visitor: &mut impl expr::Visitor<T>) -> Result<T, Error> {
         match self {
-            Expr::Literal { value } => write!(f, "{}", value),
-            Expr::Unary { operator, right } => write!(f, "({} {})", operator, right),
+            Expr::Literal { value } => visitor.visit_literal_expr(value),
+            Expr::Unary { operator, right } => visitor.visit_unary_expr(operator, *right),
             Expr::Binary {
                 left,
                 operator,
                 right,
-            } => write!(f, "({} {} {})", left, operator, right),
-            Expr::Grouping { expression } => write!(f, "({})", expression),
-            Expr::Variable { name } => write!(f, "{}", name.lexeme),
-            Expr::Assign { name, value } => write!(f, "({} = {})", name.lexeme, value),
+            } => visitor.visit_binary_expr(*left, operator, *right),
+            Expr::Grouping { expression } => visitor.visit_grouping_expr(*expression),
+            Expr::Variable { name } => visitor.visit_variable_expr(name, id),
+            Expr::Assign { name, value } => visitor.visit_assign_expr(name, *value, id),
             Expr::Logical {
                 left,
                 operator,
                 right,
-            } => write!(f, "({} {} {})", left, operator, right),
+            } => visitor.visit_logic_expr(*left, operator, *right),
             Expr::Index {
-                object: left,
+                object,
                 operator,
-                index: right,
+                index,
                 index_end,
-            } => {
-                write!(f, "{}", self.accept(&mut AstPrinter).unwrap())
-            }
+                index_step,
+                optional,
+            } => visitor.visit_index_expr(
+                *object,
+                operator,
+                *index,
+                *index_end,
+                *index_step,
+                *optional,
+            ),
             Expr::Call {
                 callee,
                 paren,
                 arguments,
-            } => {
-                // println!("{}", self.accept(&mut AstPrinter).unwrap());
-                write!(f, "{}", self.accept(&mut AstPrinter).unwrap())
-            }
-            Expr::Get { object, name } => {
-                write!(f, "{}", self.accept(&mut AstPrinter).unwrap())
-            }
+                keywords,
+            } => visitor.visit_call_expr(*callee, paren, arguments, keywords),
+            Expr::Get {
+                object,
+                name,
+                cache,
+                optional,
+            } => visitor.visit_get_expr(*object, name, cache, *optional),
             Expr::Set {
                 object,
                 name,
                 value,
-            } => {
-                write!(f, "{}", self.accept(&mut AstPrinter).unwrap())
-            }
-            Expr::IndexSet { .. } => {
-                write!(f, "{}", self.accept(&mut AstPrinter).unwrap())
-            }
-            Expr::This { keyword } => {
-                write!(f, "{}", self.accept(&mut AstPrinter).unwrap())
-            }
-            Expr::Super { keyword, method } => {
-                write!(f, "{}", self.accept(&mut AstPrinter).unwrap())
-            }
-            Expr::List { keyword, elements } => {
-                write!(f, "{}", self.accept(&mut AstPrinter).unwrap())
-            }
+            } => visitor.visit_set_expr(*object, name, *value),
+            Expr::IndexSet {
+                object,
+                index,
+                index_end,
+                index_step,
+                value,
+                operator,
+            } => visitor.visit_index_set_expr(
+                *object,
+                *index,
+                *index_end,
+                *index_step,
+                *value,
+                operator,
+            ),
+            Expr::This { keyword } => visitor.visit_this_expr(keyword, id),
+            Expr::Super { keyword, method } => visitor.visit_super_expr(keyword, method, id),
+            Expr::List { keyword, elements } => visitor.visit_list_expr(keyword, elements),
+            Expr::Map {
+                brace,
+                keys,
+                values,
+            } => visitor.visit_map_expr(brace, keys, values),
+            Expr::Comparison {
+                operands,
+                operators,
+            } => visitor.visit_comparison_expr(operands, operators),
+            Expr::If {
+                keyword,
+                condition,
+                then_branch,
+                else_branch,
+            } => visitor.visit_if_expr(keyword, *condition, *then_branch, *else_branch),
+            Expr::Block { statements, value } => visitor.visit_block_expr(statements, *value),
+            Expr::Match {
+                keyword,
+                subject,
+                arms,
+                strings,
+            } => visitor.visit_match_expr(keyword, *subject, arms, strings),
+            Expr::Tuple { elements } => visitor.visit_tuple_expr(elements),
+            Expr::Interpolation { token, parts } => visitor.visit_interpolation_expr(token, parts),
+            Expr::Const { keyword, value } => visitor.visit_const_expr(keyword, *value, id),
         }
     }
 }
 
-pub struct AstPrinter;
+/// prints the tree in a lisp like form, for logs and tests
+pub struct AstPrinter<'a> {
+    ast: &'a Ast,
+}
 
-impl AstPrinter {
-    pub fn new() -> Self {
-        Self {}
+impl<'a> AstPrinter<'a> {
+    pub fn new(ast: &'a Ast) -> Self {
+        Self { ast }
     }
 
-    pub fn print(&mut self, expr: &Expr) -> Result<String, Error> {
-        expr.accept(self)
+    pub fn print(&mut self, expr: ExprId) -> Result<String, Error> {
+        expr.accept(self.ast, self)
+    }
+
+    pub fn print_stmt(&mut self, stmt: StmtId) -> Result<String, Error> {
+        stmt.accept(self.ast, self)
+    }
+
+    fn pattern(&mut self, pattern: &Pattern) -> Result<String, Error> {
+        Ok(match pattern {
+            Pattern::Wildcard => "_".to_string(),
+            Pattern::Literal(value) => value.to_string(),
+            Pattern::Range { start, end } => format!("{}..{}", start, end),
+            Pattern::Binding(name) => name.lexeme.to_string(),
+            Pattern::List { elements, rest, .. } => {
+                let mut parts = elements
+                    .iter()
+                    .map(|e| self.pattern(e))
+                    .collect::<Result<Vec<String>, Error>>()?;
+                if let Some(rest) = rest {
+                    parts.push(format!("...{}", self.pattern(rest)?));
+                }
+                format!("[{}]", parts.join(", "))
+            }
+            Pattern::Instance { class, fields } => {
+                let fields = fields
+                    .iter()
+                    .map(|(name, pattern)| {
+                        Ok(format!("{}: {}", name.lexeme, self.pattern(pattern)?))
+                    })
+                    .collect::<Result<Vec<String>, Error>>()?;
+                format!("{} {{ {} }}", self.print(*class)?, fields.join(", "))
+            }
+            Pattern::Map { entries, .. } => {
+                let entries = entries
+                    .iter()
+                    .map(|(key, pattern)| {
+                        let pattern = self.pattern(pattern)?;
+                        Ok(match key {
+                            Literal::String(key) => format!("\"{}\": {}", key, pattern),
+                            key => format!("{}: {}", key, pattern),
+                        })
+                    })
+                    .collect::<Result<Vec<String>, Error>>()?;
+                format!("{{{}}}", entries.join(", "))
+            }
+        })
     }
-}
 
-impl Default for AstPrinter {
-    fn default() -> Self {
-        Self::new()
+    fn list(&mut self, exprs: &[ExprId]) -> Result<Vec<String>, Error> {
+        exprs.iter().map(|e| self.print(*e)).collect()
     }
 }
 
-impl expr::Visitor<String> for AstPrinter {
+impl expr::Visitor<String> for AstPrinter<'_> {
     fn visit_literal_expr(&mut self, value: &Literal) -> Result<String, Error> {
         Ok(format!("{}", value))
     }
 
-    fn visit_unary_expr(&mut self, expr: &Expr) -> Result<String, Error> {
-        match expr {
-            Expr::Unary { operator, right } => {
-                let right = right.accept(self)?;
-                Ok(format!("({} {})", operator, right))
-            }
-            _ => Err(Error::new(
-                "Expected unary expression",
-                ErrorType::SyntaxError,
-            )),
-        }
+    fn visit_unary_expr(&mut self, operator: &Token, right: ExprId) -> Result<String, Error> {
+        let right = self.print(right)?;
+        Ok(format!("({} {})", operator, right))
     }
 
-    fn visit_binary_expr(&mut self, expr: &Expr) -> Result<String, Error> {
-        match expr {
-            Expr::Binary {
-                left,
-                operator,
-                right,
-            } => {
-                let left = left.accept(self)?;
-                let right = right.accept(self)?;
-                Ok(format!("({} {} {})", left, operator, right))
-            }
-            _ => Err(Error::new(
-                "Expected binary expression",
-                ErrorType::SyntaxError,
-            )),
-        }
+    fn visit_binary_expr(
+        &mut self,
+        left: ExprId,
+        operator: &Token,
+        right: ExprId,
+    ) -> Result<String, Error> {
+        let left = self.print(left)?;
+        let right = self.print(right)?;
+        Ok(format!("({} {} {})", left, operator, right))
     }
 
-    fn visit_grouping_expr(&mut self, expr: &Expr) -> Result<String, Error> {
-        match expr {
-            Expr::Grouping { expression } => {
-                let expression = expression.accept(self)?;
-                Ok(format!("({})", expression))
-            }
-            _ => Err(Error::new(
-                "Expected grouping expression",
-                ErrorType::SyntaxError,
-            )),
-        }
+    fn visit_grouping_expr(&mut self, expression: ExprId) -> Result<String, Error> {
+        let expression = self.print(expression)?;
+        Ok(format!("({})", expression))
     }
-    fn visit_variable_expr(&mut self, expr: &Expr) -> Result<String, Error> {
-        match expr {
-            Expr::Variable { name } => Ok(name.lexeme.to_string()),
-            _ => Err(Error::new(
-                "Expected variable expression",
-                ErrorType::SyntaxError,
-            )),
-        }
+    fn visit_variable_expr(&mut self, name: &Token, _id: ExprId) -> Result<String, Error> {
+        Ok(name.lexeme.to_string())
     }
-    fn visit_assign_expr(&mut self, expr: &Expr) -> Result<String, Error> {
-        match expr {
-            Expr::Assign { name, value } => {
-                Ok(format!("({} = {})", name.lexeme, value.accept(self)?))
-            }
-            _ => Err(Error::new(
-                "Expected assign expression",
-                ErrorType::SyntaxError,
-            )),
-        }
+    fn visit_assign_expr(
+        &mut self,
+        name: &Token,
+        value: ExprId,
+        _id: ExprId,
+    ) -> Result<String, Error> {
+        Ok(format!("({} = {})", name.lexeme, self.print(value)?))
     }
-    fn visit_logic_expr(&mut self, expr: &Expr) -> Result<String, Error> {
-        match expr {
-            Expr::Logical {
-                left,
-                operator,
-                right,
-            } => {
-                let left = left.accept(self)?;
-                let right = right.accept(self)?;
-                Ok(format!("({} {} {})", left, operator, right))
-            }
-            _ => Err(Error::new(
-                "Expected logic expression",
-                ErrorType::SyntaxError,
-            )),
-        }
+    fn visit_logic_expr(
+        &mut self,
+        left: ExprId,
+        operator: &Token,
+        right: ExprId,
+    ) -> Result<String, Error> {
+        let left = self.print(left)?;
+        let right = self.print(right)?;
+        Ok(format!("({} {} {})", left, operator, right))
     }
-    fn visit_index_expr(&mut self, expr: &Expr) -> Result<String, Error> {
-        match expr {
-            Expr::Index {
-                object: left,
-                index: right,
-                ..
-            } => Ok(format!("{}[{}]", left.accept(self)?, right.accept(self)?,)),
-            _ => Err(Error::new(
-                "Expected index expression",
-                ErrorType::SyntaxError,
-            )),
-        }
+    fn visit_index_expr(
+        &mut self,
+        left: ExprId,
+        _operator: &Token,
+        right: ExprId,
+        _index_end: Option<ExprId>,
+        _index_step: Option<ExprId>,
+        optional: bool,
+    ) -> Result<String, Error> {
+        let bracket = if optional { "?[" } else { "[" };
+        Ok(format!("{}{}{}]", self.print(left)?, bracket, self.print(right)?))
     }
-    fn visit_call_expr(&mut self, expr: &Expr) -> Result<String, Error> {
-        match expr {
-            Expr::Call {
-                callee, arguments, ..
-            } => {
-                let callee = callee.accept(self)?;
-                let arguments = arguments
-                    .iter()
-                    .map(|arg| arg.accept(self))
-                    .collect::<Result<Vec<String>, Error>>()?;
-                Ok(format!("{}({})", callee, arguments.join(",")))
-            }
-            _ => Err(Error::new(
-                "Expected call expression",
-                ErrorType::SyntaxError,
-            )),
+    fn visit_call_expr(
+        &mut self,
+        callee: ExprId,
+        _paren: &Token,
+        arguments: &[ExprId],
+        keywords: &[(Token, ExprId)],
+    ) -> Result<String, Error> {
+        let callee = self.print(callee)?;
+        let mut arguments = self.list(arguments)?;
+        for (name, value) in keywords {
+            arguments.push(format!("{}: {}", name.lexeme, self.print(*value)?));
         }
+        Ok(format!("{}({})", callee, arguments.join(",")))
     }
-    fn visit_get_expr(&mut self, expr: &Expr) -> Result<String, Error> {
-        match expr {
-            Expr::Get { object, name } => Ok(format!("({}.{})", object.accept(self)?, name.lexeme)),
-            _ => Err(Error::new(
-                "Expected get expression",
-                ErrorType::SyntaxError,
-            )),
-        }
+    fn visit_get_expr(
+        &mut self,
+        object: ExprId,
+        name: &Token,
+        _cache: &MethodCache,
+        optional: bool,
+    ) -> Result<String, Error> {
+        let dot = if optional { "?." } else { "." };
+        Ok(format!("({}{}{})", self.print(object)?, dot, name.lexeme))
     }
-    fn visit_set_expr(&mut self, expr: &Expr) -> Result<String, Error> {
-        match expr {
-            Expr::Set {
-                object,
-                name,
-                value,
-            } => Ok(format!(
-                "(set: {}.{} = {})",
-                object.accept(self)?,
-                name,
-                value.accept(self)?
-            )),
-            _ => Err(Error::new(
-                "Expected set expression",
-                ErrorType::SyntaxError,
-            )),
-        }
+    fn visit_set_expr(
+        &mut self,
+        object: ExprId,
+        name: &Token,
+        value: ExprId,
+    ) -> Result<String, Error> {
+        Ok(format!(
+            "(set: {}.{} = {})",
+            self.print(object)?,
+            name,
+            self.print(value)?
+        ))
     }
-    fn visit_index_set_expr(&mut self, expr: &Expr) -> Result<String, Error> {
-        match expr {
-            Expr::IndexSet {
-                object,
-                index,
-                index_end,
-                value,
-                ..
-            } => Ok(format!("{}[{}] = {}", object, index, value)),
-            _ => unreachable!(),
+    fn visit_index_set_expr(
+        &mut self,
+        object: ExprId,
+        index: ExprId,
+        _index_end: Option<ExprId>,
+        _index_step: Option<ExprId>,
+        value: ExprId,
+        _operator: &Token,
+    ) -> Result<String, Error> {
+        Ok(format!(
+            "{}[{}] = {}",
+            self.print(object)?,
+            self.print(index)?,
+            self.print(value)?
+        ))
+    }
+    fn visit_this_expr(&mut self, _keyword: &Token, _id: ExprId) -> Result<String, Error> {
+        Ok("this ".to_string())
+    }
+    fn visit_super_expr(
+        &mut self,
+        _keyword: &Token,
+        _method: &Token,
+        _id: ExprId,
+    ) -> Result<String, Error> {
+        Ok("super ".to_string())
+    }
+    fn visit_list_expr(&mut self, _keyword: &Token, elements: &[ExprId]) -> Result<String, Error> {
+        Ok(format!("[{}]", self.list(elements)?.join(",")))
+    }
+    fn visit_map_expr(
+        &mut self,
+        _brace: &Token,
+        keys: &[ExprId],
+        values: &[ExprId],
+    ) -> Result<String, Error> {
+        let entries = keys
+            .iter()
+            .zip(values)
+            .map(|(key, value)| Ok(format!("{}: {}", self.print(*key)?, self.print(*value)?)))
+            .collect::<Result<Vec<String>, Error>>()?;
+        Ok(format!("{{{}}}", entries.join(", ")))
+    }
+    fn visit_comparison_expr(
+        &mut self,
+        operands: &[ExprId],
+        operators: &[Token],
+    ) -> Result<String, Error> {
+        let mut s = format!("({}", self.print(operands[0])?);
+        for (operator, operand) in operators.iter().zip(&operands[1..]) {
+            s.push_str(&format!(" {} {}", operator, self.print(*operand)?));
         }
+        s.push(')');
+        Ok(s)
     }
-    fn visit_this_expr(&mut self, expr: &Expr) -> Result<String, Error> {
-        match expr {
-            Expr::This { .. } => Ok("this ".to_string()),
-            _ => unreachable!(),
+    fn visit_if_expr(
+        &mut self,
+        _keyword: &Token,
+        condition: ExprId,
+        then_branch: ExprId,
+        else_branch: Option<ExprId>,
+    ) -> Result<String, Error> {
+        let mut s = format!("(if {} {}", self.print(condition)?, self.print(then_branch)?);
+        if let Some(else_branch) = else_branch {
+            s.push_str(&format!(" else {}", self.print(else_branch)?));
         }
+        s.push(')');
+        Ok(s)
     }
-    fn visit_super_expr(&mut self, expr: &Expr) -> Result<String, Error> {
-        match expr {
-            Expr::Super { .. } => Ok("super ".to_string()),
-            _ => unreachable!(),
+    fn visit_block_expr(
+        &mut self,
+        statements: &[StmtId],
+        value: Option<ExprId>,
+    ) -> Result<String, Error> {
+        let mut s = String::from("{ ");
+        for stmt in statements {
+            s.push_str(&self.print_stmt(*stmt)?);
+            s.push_str("; ");
         }
+        if let Some(value) = value {
+            s.push_str(&self.print(value)?);
+            s.push(' ');
+        }
+        s.push('}');
+        Ok(s)
     }
-    fn visit_list_expr(&mut self, expr: &Expr) -> Result<String, Error> {
-        match expr {
-            Expr::List { elements, .. } => {
-                let elements = elements
-                    .iter()
-                    .map(|e| e.accept(self))
-                    .collect::<Result<Vec<String>, Error>>()?;
-                Ok(format!("[{}]", elements.join(",")))
-            }
-            _ => unreachable!(),
+    fn visit_tuple_expr(&mut self, elements: &[ExprId]) -> Result<String, Error> {
+        Ok(format!("(tuple {})", self.list(elements)?.join(" ")))
+    }
+    fn visit_interpolation_expr(
+        &mut self,
+        _token: &Token,
+        parts: &[ExprId],
+    ) -> Result<String, Error> {
+        Ok(format!("(interpolate {})", self.list(parts)?.join(" ")))
+    }
+    fn visit_const_expr(
+        &mut self,
+        _keyword: &Token,
+        value: ExprId,
+        _id: ExprId,
+    ) -> Result<String, Error> {
+        Ok(format!("(const {})", self.print(value)?))
+    }
+    fn visit_match_expr(
+        &mut self,
+        _keyword: &Token,
+        subject: ExprId,
+        arms: &[MatchArm],
+        _strings: &StringArms,
+    ) -> Result<String, Error> {
+        let mut s = format!("(match {}", self.print(subject)?);
+        for arm in arms {
+            let pattern = self.pattern(&arm.pattern)?;
+            s.push_str(&format!(" ({} -> {})", pattern, self.print(arm.body)?));
         }
+        s.push(')');
+        Ok(s)
     }
 }
 
@@ -414,21 +692,23 @@ mod tests {
 
     #[test]
     fn test_expr() {
-        let expr = Expr::Binary {
-            left: Box::new(Expr::Unary {
-                operator: Token::new("-", TokenType::Minus, 1, 0),
-                right: Box::new(Expr::Literal {
-                    value: Literal::Number(NumberType::Integer(123)),
-                }),
-            }),
+        let mut ast = Ast::new();
+        let integer = ast.push_expr(Expr::Literal {
+            value: Literal::Number(NumberType::Integer(123)),
+        });
+        let left = ast.push_expr(Expr::Unary {
+            operator: Token::new("-", TokenType::Minus, 1, 0),
+            right: integer,
+        });
+        let float = ast.push_expr(Expr::Literal {
+            value: Literal::Number(NumberType::Float(45.67)),
+        });
+        let right = ast.push_expr(Expr::Grouping { expression: float });
+        let expr = ast.push_expr(Expr::Binary {
+            left,
             operator: Token::new("*", TokenType::Star, 1, 0),
-            right: Box::new(Expr::Grouping {
-                expression: Box::new(Expr::Literal {
-                    value: Literal::Number(NumberType::Float(45.67)),
-                }),
-            }),
-        };
-        println!("{}", expr);
-        println!("{}", expr.accept(&mut AstPrinter).unwrap());
+            right,
+        });
+        println!("{}", AstPrinter::new(&ast).print(expr).unwrap());
     }
 }
@@ -2,10 +2,15 @@ use std::cell::RefCell;
 use std::fmt::{Debug, Display};
 use std::rc::Rc;
 
+use crate::Ast;
 use crate::Error;
+use crate::ExprId;
 use crate::Interpreter;
+use crate::NativeClosureFn;
+use crate::NativeFn;
+use crate::NativeMethodFn;
 use crate::Object;
-use crate::Stmt;
+use crate::StmtId;
 use crate::Token;
 use crate::{Environment, EnvironmentRef, ErrorType};
 
@@ -13,67 +18,233 @@ use crate::{Environment, EnvironmentRef, ErrorType};
 pub enum Function {
     Native {
         name: String,
+        /// the fewest arguments it takes
         arity: usize,
-        body: Box<fn(&Vec<Object>) -> Object>,
+        /// the most, the same as arity unless some are optional
+        max_arity: usize,
+        body: Box<NativeFn>,
+        /// what `help` shows for it, most natives have none
+        doc: Option<&'static str>,
     },
+    /// a method implemented in rust, `this` is filled in by bind
+    NativeMethod {
+        name: String,
+        arity: usize,
+        body: Box<NativeMethodFn>,
+        this: Option<Box<Object>>,
+    },
+    /// a function made by a native at run time, holding on to the
+    /// values it was made from
+    NativeClosure {
+        name: String,
+        arity: usize,
+        body: Box<NativeClosureFn>,
+        captures: Rc<Vec<Object>>,
+    },
+    /// params and body are shared, binding a method copies no statements
     UserDefined {
         name: Token,
-        params: Vec<Token>,
-        body: Vec<Stmt>,
+        params: Rc<Vec<Token>>,
+        /// the defaults of the last params, evaluated in the call
+        defaults: Rc<Vec<ExprId>>,
+        body: Rc<[StmtId]>,
+        /// the tree the defaults and the body are in
+        ast: Rc<Ast>,
         closure: EnvironmentRef,
         is_initializer: bool,
+        doc: Option<String>,
     },
 }
 
 impl Function {
-    pub fn call(&self, interpreter: &mut Interpreter, args: &Vec<Object>) -> Result<Object, Error> {
+    pub fn call(&self, interpreter: &mut Interpreter, args: &[Object]) -> Result<Object, Error> {
+        match self {
+            Function::Native { body, .. } => body(interpreter, args),
+            Function::NativeMethod { body, this, name, .. } => match this {
+                Some(this) => body(interpreter, this, args),
+                None => Err(Error::coded("E0435", &[name])),
+            },
+            Function::NativeClosure { body, captures, .. } => body(interpreter, captures, args),
+            Function::UserDefined { closure, .. } => self.call_in(interpreter, closure, args),
+        }
+    }
+
+    /// call a method on `instance` without building a bound copy first
+    pub fn call_bound(
+        &self,
+        interpreter: &mut Interpreter,
+        instance: Object,
+        args: &[Object],
+    ) -> Result<Object, Error> {
+        match self {
+            Function::UserDefined { closure, .. } => {
+                let mut environment = Environment::with_capacity(Some(closure.clone()), 1);
+                environment.define("this", instance);
+                self.call_in(interpreter, &Rc::new(RefCell::new(environment)), args)
+            }
+            _ => self.bind(instance).call(interpreter, args),
+        }
+    }
+
+    /// run a user defined function with `closure` as the enclosing environment
+    fn call_in(
+        &self,
+        interpreter: &mut Interpreter,
+        closure: &EnvironmentRef,
+        args: &[Object],
+    ) -> Result<Object, Error> {
+        match self {
+            Function::UserDefined { ast, .. } => {
+                let previous = interpreter.set_ast(Rc::clone(ast));
+                let result = self.run(interpreter, closure, args);
+                interpreter.set_ast(previous);
+                result
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// the body of `call_in`, with the function's tree running
+    fn run(
+        &self,
+        interpreter: &mut Interpreter,
+        closure: &EnvironmentRef,
+        args: &[Object],
+    ) -> Result<Object, Error> {
         match self {
-            Function::Native { body, .. } => Ok(body(args)),
             Function::UserDefined {
+                name,
                 params,
+                defaults,
                 body,
-                closure,
                 is_initializer,
                 ..
             } => {
                 // new environment for function call
-                let environment = Rc::new(RefCell::new(Environment::new(Some(closure.clone()))));
+                let environment = Rc::new(RefCell::new(Environment::with_capacity(
+                    Some(closure.clone()),
+                    params.len(),
+                )));
 
-                // define parameters
-                for (i, param) in params.iter().enumerate() {
-                    environment
-                        .borrow_mut()
-                        .define(&param.lexeme, args[i].clone());
+                // define parameters, a left out one gets its default,
+                // worked out with the ones before it already defined
+                let first_default = params.len() - defaults.len();
+                for (index, param) in params.iter().enumerate() {
+                    let value = match args.get(index) {
+                        Some(Object::Uninitialized) | None => {
+                            interpreter.evaluate_in(defaults[index - first_default], &environment)?
+                        }
+                        Some(arg) => arg.clone(),
+                    };
+                    environment.borrow_mut().define(&param.lexeme, value);
                 }
 
-                if let Err(err) = interpreter.execute_block(body, environment) {
-                    if *is_initializer {
-                        return Ok(closure
-                            .borrow()
-                            .get_at(0, &String::from("this"))
-                            .unwrap_or(Object::Nil));
-                    }
-                    // normal function
-                    match err.error_type {
-                        ErrorType::Return(value) => Ok(value),
-                        _ => Err(err),
-                    }
-                } else if *is_initializer {
+                // a return stops at the function it was raised in, a
+                // break or continue must never leave it, everything
+                // else is a real error and goes on to the caller
+                let value = match interpreter.execute_block(body, environment) {
+                    Ok(()) => Object::Nil,
+                    Err(err) => match err.error_type {
+                        ErrorType::Return(value) => value,
+                        ErrorType::Break(_) | ErrorType::Continue(_) => {
+                            return Err(Error::coded("E0434", &[&name.lexeme]).at(name))
+                        }
+                        _ => return Err(err),
+                    },
+                };
+                if *is_initializer {
                     Ok(closure
                         .borrow()
                         .get_at(0, &String::from("this"))
                         .unwrap_or(Object::Nil))
                 } else {
-                    Ok(Object::Nil)
+                    Ok(value)
                 }
             }
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Function::Native { name, .. } => name,
+            Function::NativeMethod { name, .. } => name,
+            Function::NativeClosure { name, .. } => name,
+            Function::UserDefined { name, .. } => &name.lexeme,
         }
     }
 
     pub fn arity(&self) -> usize {
         match self {
             Function::Native { arity, .. } => *arity,
+            Function::NativeMethod { arity, .. } => *arity,
+            Function::NativeClosure { arity, .. } => *arity,
+            Function::UserDefined {
+                params, defaults, ..
+            } => params.len() - defaults.len(),
+        }
+    }
+
+    /// the most arguments the function takes, more than arity when some
+    /// are optional
+    pub fn max_arity(&self) -> usize {
+        match self {
+            Function::Native { max_arity, .. } => *max_arity,
             Function::UserDefined { params, .. } => params.len(),
+            _ => self.arity(),
+        }
+    }
+
+    /// the positional arguments followed by the keyword ones, each in
+    /// the slot of the parameter it names
+    pub fn with_keywords(
+        &self,
+        mut args: Vec<Object>,
+        keywords: Vec<(&Token, Object)>,
+    ) -> Result<Vec<Object>, Error> {
+        let (params, defaults) = match self {
+            Function::UserDefined {
+                params, defaults, ..
+            } => (params, defaults),
+            _ => return Err(Error::coded("E0433", &[&self.name()])),
+        };
+        if args.len() > params.len() {
+            return Err(Error::coded("E0416", &[&params.len(), &(args.len() + keywords.len())]));
+        }
+        let positional = args.len();
+        let mut slots: Vec<Option<Object>> = vec![None; params.len() - positional];
+        for (name, value) in keywords {
+            let index = match params.iter().position(|param| param.lexeme == name.lexeme) {
+                Some(index) => index,
+                None => {
+                    return Err(Error::coded("E0430", &[&self.name(), &name.lexeme]).at(name))
+                }
+            };
+            if index < positional {
+                return Err(Error::coded("E0431", &[&name.lexeme]).at(name));
+            }
+            slots[index - positional] = Some(value);
+        }
+        let first_default = params.len() - defaults.len();
+        for (index, slot) in (positional..).zip(slots) {
+            match slot {
+                Some(value) => args.push(value),
+                // left for the call to fill in with the default
+                None if index >= first_default => args.push(Object::Uninitialized),
+                None => {
+                    return Err(Error::coded("E0432", &[&params[index].lexeme]))
+                }
+            }
+        }
+        Ok(args)
+    }
+
+    /// the `///` comment written above the declaration
+    pub fn doc(&self) -> Option<&str> {
+        match self {
+            Function::UserDefined { doc, .. } => doc.as_deref(),
+            Function::Native { doc, .. } => *doc,
+            _ => None,
         }
     }
 
@@ -82,9 +253,12 @@ impl Function {
             Function::UserDefined {
                 name,
                 params,
+                defaults,
                 body,
+                ast,
                 closure,
                 is_initializer,
+                doc,
             } => {
                 let mut environment_inner = Environment::new(Some(closure.clone()));
                 environment_inner.define(&String::from("this"), instance);
@@ -92,11 +266,22 @@ impl Function {
                 Function::UserDefined {
                     name: name.clone(),
                     params: params.clone(),
+                    defaults: defaults.clone(),
                     body: body.clone(),
+                    ast: ast.clone(),
                     closure: environment,
                     is_initializer: *is_initializer,
+                    doc: doc.clone(),
                 }
             }
+            Function::NativeMethod {
+                name, arity, body, ..
+            } => Function::NativeMethod {
+                name: name.clone(),
+                arity: *arity,
+                body: body.clone(),
+                this: Some(Box::new(instance)),
+            },
             _ => unreachable!(),
         }
     }
@@ -105,7 +290,9 @@ impl Function {
 impl Debug for Function {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Function::Native { name, .. } => write!(f, "native <fn {}>", name),
+            Function::Native { name, .. }
+            | Function::NativeMethod { name, .. }
+            | Function::NativeClosure { name, .. } => write!(f, "native <fn {}>", name),
             Function::UserDefined { name, params, .. } => {
                 write!(
                     f,
@@ -125,7 +312,9 @@ impl Debug for Function {
 impl Display for Function {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Function::Native { name, .. } => write!(f, "native <fn {}>", name),
+            Function::Native { name, .. }
+            | Function::NativeMethod { name, .. }
+            | Function::NativeClosure { name, .. } => write!(f, "native <fn {}>", name),
             Function::UserDefined { name, params, .. } => {
                 write!(
                     f,
@@ -0,0 +1,304 @@
+/// the lists, instances, functions and classes a program has made,
+/// held weakly so `memoryStats()` can count the live ones and
+/// `collectGarbage()` can find the ones kept alive only by Rc cycles
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::{Rc, Weak};
+
+use crate::{Environment, EnvironmentRef, Function, List, LoxClass, LoxInstance, Object};
+
+/// live objects by kind
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MemoryStats {
+    pub instances: usize,
+    pub lists: usize,
+    pub functions: usize,
+    pub classes: usize,
+    pub environments: usize,
+}
+
+#[derive(Default)]
+pub struct Heap {
+    lists: Vec<Weak<RefCell<List>>>,
+    instances: Vec<Weak<RefCell<LoxInstance>>>,
+    functions: Vec<Weak<Function>>,
+    classes: Vec<Weak<RefCell<LoxClass>>>,
+    /// only environments a function closed over, those are the ones
+    /// a cycle can go through
+    environments: Vec<Weak<RefCell<Environment>>>,
+    known_environments: HashSet<usize>,
+    /// dropped objects leave their weak behind, prune past this size
+    limit: usize,
+}
+
+/// an object the collector looks at
+enum Node {
+    List(Rc<RefCell<List>>),
+    Instance(Rc<RefCell<LoxInstance>>),
+    Function(Rc<Function>),
+    Class(Rc<RefCell<LoxClass>>),
+    Environment(EnvironmentRef),
+}
+
+const MIN_LIMIT: usize = 1024;
+
+impl Heap {
+    pub fn track(&mut self, object: &Object) {
+        match object {
+            Object::List(list) => self.lists.push(Rc::downgrade(list)),
+            Object::Instance(instance) => self.instances.push(Rc::downgrade(instance)),
+            Object::Class(class) => {
+                if let Some(closure) = &class.borrow().closure {
+                    self.track_environment(closure);
+                }
+                self.classes.push(Rc::downgrade(class));
+            }
+            Object::Callable(function) => {
+                if let Function::UserDefined { closure, .. } = &**function {
+                    self.track_environment(closure);
+                }
+                self.functions.push(Rc::downgrade(function));
+            }
+            _ => return,
+        }
+        let tracked = self.lists.len()
+            + self.instances.len()
+            + self.functions.len()
+            + self.classes.len()
+            + self.environments.len();
+        if tracked > self.limit {
+            self.prune();
+        }
+    }
+
+    /// a closure and the scopes around it, stopping at one already known
+    fn track_environment(&mut self, environment: &EnvironmentRef) {
+        let mut current = Some(environment.clone());
+        while let Some(environment) = current {
+            if !self
+                .known_environments
+                .insert(Rc::as_ptr(&environment) as usize)
+            {
+                break;
+            }
+            self.environments.push(Rc::downgrade(&environment));
+            current = environment.borrow().enclosing.clone();
+        }
+    }
+
+    fn prune(&mut self) {
+        self.lists.retain(|weak| weak.strong_count() > 0);
+        self.instances.retain(|weak| weak.strong_count() > 0);
+        self.functions.retain(|weak| weak.strong_count() > 0);
+        self.classes.retain(|weak| weak.strong_count() > 0);
+        self.environments.retain(|weak| weak.strong_count() > 0);
+        self.known_environments = self
+            .environments
+            .iter()
+            .map(|weak| weak.as_ptr() as usize)
+            .collect();
+        let tracked = self.lists.len()
+            + self.instances.len()
+            + self.functions.len()
+            + self.classes.len()
+            + self.environments.len();
+        self.limit = (tracked * 2).max(MIN_LIMIT);
+    }
+
+    pub fn stats(&mut self) -> MemoryStats {
+        self.prune();
+        MemoryStats {
+            instances: self.instances.len(),
+            lists: self.lists.len(),
+            functions: self.functions.len(),
+            classes: self.classes.len(),
+            environments: Environment::live(),
+        }
+    }
+
+    /// free the objects that only other tracked objects still point to
+    /// and that nothing held from outside can reach, returning how many.
+    /// anything the heap doesn't track counts as outside, so an object it
+    /// can't see through is kept rather than freed
+    pub fn collect(&mut self) -> usize {
+        self.prune();
+        let nodes: Vec<Node> = self
+            .lists
+            .iter()
+            .filter_map(|weak| weak.upgrade().map(Node::List))
+            .chain(
+                self.instances
+                    .iter()
+                    .filter_map(|weak| weak.upgrade().map(Node::Instance)),
+            )
+            .chain(
+                self.functions
+                    .iter()
+                    .filter_map(|weak| weak.upgrade().map(Node::Function)),
+            )
+            .chain(
+                self.classes
+                    .iter()
+                    .filter_map(|weak| weak.upgrade().map(Node::Class)),
+            )
+            .chain(
+                self.environments
+                    .iter()
+                    .filter_map(|weak| weak.upgrade().map(Node::Environment)),
+            )
+            .collect();
+        let index: HashMap<usize, usize> = nodes
+            .iter()
+            .enumerate()
+            .map(|(i, node)| (node.address(), i))
+            .collect();
+
+        // references each node gets from the other nodes
+        let mut edges: Vec<Vec<usize>> = Vec::with_capacity(nodes.len());
+        let mut internal = vec![0; nodes.len()];
+        for node in &nodes {
+            let mut targets = Vec::new();
+            node.references(&mut targets);
+            let targets: Vec<usize> = targets
+                .iter()
+                .filter_map(|address| index.get(address).copied())
+                .collect();
+            for &target in &targets {
+                internal[target] += 1;
+            }
+            edges.push(targets);
+        }
+
+        // a node with a reference from outside is alive, and so is
+        // everything it reaches. `nodes` holds one reference of its own
+        let mut alive = vec![false; nodes.len()];
+        let mut pending: Vec<usize> = (0..nodes.len())
+            .filter(|&i| nodes[i].strong_count() - 1 > internal[i])
+            .collect();
+        while let Some(i) = pending.pop() {
+            if alive[i] {
+                continue;
+            }
+            alive[i] = true;
+            pending.extend(edges[i].iter().copied().filter(|&target| !alive[target]));
+        }
+
+        let mut freed = 0;
+        for (node, alive) in nodes.iter().zip(alive) {
+            if !alive {
+                node.clear();
+                freed += 1;
+            }
+        }
+        drop(nodes);
+        self.prune();
+        freed
+    }
+}
+
+impl Node {
+    fn address(&self) -> usize {
+        match self {
+            Node::List(list) => Rc::as_ptr(list) as usize,
+            Node::Instance(instance) => Rc::as_ptr(instance) as usize,
+            Node::Function(function) => Rc::as_ptr(function) as *const () as usize,
+            Node::Class(class) => Rc::as_ptr(class) as usize,
+            Node::Environment(environment) => Rc::as_ptr(environment) as usize,
+        }
+    }
+
+    fn strong_count(&self) -> usize {
+        match self {
+            Node::List(list) => Rc::strong_count(list),
+            Node::Instance(instance) => Rc::strong_count(instance),
+            Node::Function(function) => Rc::strong_count(function),
+            Node::Class(class) => Rc::strong_count(class),
+            Node::Environment(environment) => Rc::strong_count(environment),
+        }
+    }
+
+    /// the address of every Rc this node holds one strong reference to
+    fn references(&self, out: &mut Vec<usize>) {
+        match self {
+            Node::List(list) => list
+                .borrow()
+                .inner
+                .iter()
+                .for_each(|item| object_reference(item, out)),
+            Node::Instance(instance) => {
+                let instance = instance.borrow();
+                out.push(Rc::as_ptr(instance.class()) as usize);
+                instance
+                    .fields()
+                    .for_each(|(_, field)| object_reference(field, out));
+            }
+            Node::Function(function) => function_references(function, out),
+            Node::Class(class) => {
+                let class = class.borrow();
+                class
+                    .methods()
+                    .for_each(|method| function_references(method, out));
+                if let Some(super_class) = &class.super_class {
+                    out.push(Rc::as_ptr(super_class) as usize);
+                }
+                if let Some(closure) = &class.closure {
+                    out.push(Rc::as_ptr(closure) as usize);
+                }
+            }
+            Node::Environment(environment) => {
+                let environment = environment.borrow();
+                environment
+                    .values()
+                    .for_each(|value| object_reference(value, out));
+                if let Some(enclosing) = &environment.enclosing {
+                    out.push(Rc::as_ptr(enclosing) as usize);
+                }
+            }
+        }
+    }
+
+    /// break the node's outgoing references, dropping them only once
+    /// the borrow is over
+    fn clear(&self) {
+        match self {
+            Node::List(list) => {
+                let items = std::mem::take(&mut list.borrow_mut().inner);
+                drop(items);
+            }
+            Node::Instance(instance) => {
+                let fields = instance.borrow_mut().clear();
+                drop(fields);
+            }
+            Node::Environment(environment) => {
+                let bindings = environment.borrow_mut().clear();
+                drop(bindings);
+            }
+            // clearing what refers to them frees these
+            Node::Function(_) | Node::Class(_) => {}
+        }
+    }
+}
+
+fn object_reference(object: &Object, out: &mut Vec<usize>) {
+    match object {
+        Object::List(list) => out.push(Rc::as_ptr(list) as usize),
+        Object::Instance(instance) => out.push(Rc::as_ptr(instance) as usize),
+        Object::Callable(function) => out.push(Rc::as_ptr(function) as *const () as usize),
+        Object::Class(class) => out.push(Rc::as_ptr(class) as usize),
+        _ => {}
+    }
+}
+
+fn function_references(function: &Function, out: &mut Vec<usize>) {
+    match function {
+        Function::UserDefined { closure, .. } => out.push(Rc::as_ptr(closure) as usize),
+        Function::NativeMethod {
+            this: Some(this), ..
+        } => object_reference(this, out),
+        // shared captures would be counted once per sharer
+        Function::NativeClosure { captures, .. } if Rc::strong_count(captures) == 1 => captures
+            .iter()
+            .for_each(|capture| object_reference(capture, out)),
+        _ => {}
+    }
+}
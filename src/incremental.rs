@@ -0,0 +1,220 @@
+/// incremental front-end for editors and the REPL
+///
+/// a `Document` keeps the tokens and the top-level declarations of a
+/// source. after an edit only the declarations from the first one the
+/// edit touches onwards are lexed and parsed again, everything before
+/// is reused as is.
+use std::ops::Range;
+use std::rc::Rc;
+
+use crate::{Ast, Error, Parser, Scanner, StmtId, Token};
+
+/// a top-level declaration and where it came from
+#[derive(Debug)]
+struct Item {
+    /// byte span of the declaration's tokens
+    span: Range<usize>,
+    /// index of its first token in `Document::tokens`
+    first_token: usize,
+    /// the tree of the reparse that made it, shared with the items
+    /// parsed alongside it
+    ast: Rc<Ast>,
+    stmt: Result<StmtId, Error>,
+}
+
+#[derive(Debug)]
+pub struct Document {
+    source: String,
+    /// tokens, without the trailing Eof
+    tokens: Vec<Token>,
+    items: Vec<Item>,
+    /// scan errors with the position they were found at
+    scan_errors: Vec<(usize, Error)>,
+}
+
+impl Document {
+    pub fn new(source: &str) -> Self {
+        let mut document = Self {
+            source: source.to_string(),
+            tokens: Vec::new(),
+            items: Vec::new(),
+            scan_errors: Vec::new(),
+        };
+        document.reparse_from(0);
+        document
+    }
+
+    /// replace `range` of the source with `text`, returns how many
+    /// declarations were reused without being parsed again
+    pub fn edit(&mut self, range: Range<usize>, text: &str) -> usize {
+        self.source.replace_range(range.clone(), text);
+        // a declaration ending right where the edit starts may grow,
+        // `foo` followed by `bar` is one identifier
+        let reused = self
+            .items
+            .iter()
+            .take_while(|item| item.span.end < range.start)
+            .count();
+        self.reparse_from(reused);
+        reused
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn tokens(&self) -> &[Token] {
+        &self.tokens
+    }
+
+    /// the declarations that parsed, with the tree each one is in
+    pub fn statements(&self) -> Vec<(&Ast, StmtId)> {
+        self.items
+            .iter()
+            .filter_map(|item| Some((&*item.ast, *item.stmt.as_ref().ok()?)))
+            .collect()
+    }
+
+    /// scan errors followed by parse errors
+    pub fn errors(&self) -> Vec<&Error> {
+        self.scan_errors
+            .iter()
+            .map(|(_, error)| error)
+            .chain(
+                self.items
+                    .iter()
+                    .filter_map(|item| item.stmt.as_ref().err()),
+            )
+            .collect()
+    }
+
+    /// keep the first `reused` items, lex and parse the rest again
+    fn reparse_from(&mut self, reused: usize) {
+        let first_token = match reused {
+            0 => 0,
+            _ => self.token_end(reused - 1),
+        };
+        self.items.truncate(reused);
+        self.tokens.truncate(first_token);
+        let (offset, line) = match (self.items.last(), self.tokens.last()) {
+            (Some(item), Some(last)) => (item.span.end, last.line),
+            _ => (0, 1),
+        };
+        self.scan_errors.retain(|(position, _)| *position <= offset);
+
+        let mut scanner = Scanner::resume(&self.source, offset, line);
+        let mut tokens = Vec::new();
+        while let Some(result) = scanner.next() {
+            match result {
+                Ok(token) => tokens.push(token),
+                Err(error) => self.scan_errors.push((scanner.position(), error)),
+            }
+        }
+
+        let mut parser = Parser::new(&tokens);
+        let mut parsed = Vec::new();
+        while !parser.is_at_end() {
+            let start = parser.position();
+            let stmt = parser.parse_declaration();
+            let end = parser.position().max(start + 1).min(tokens.len() - 1);
+            let last = &tokens[end - 1];
+            parsed.push((
+                tokens[start].offset..last.offset + last.lexeme.len(),
+                first_token + start,
+                stmt,
+            ));
+        }
+        let ast = Rc::new(parser.into_ast());
+        for (span, first_token, stmt) in parsed {
+            self.items.push(Item {
+                span,
+                first_token,
+                ast: Rc::clone(&ast),
+                stmt,
+            });
+        }
+
+        // drop the Eof, it moves with every edit
+        tokens.pop();
+        self.tokens.extend(tokens);
+    }
+
+    /// one past the last token of item `index`
+    fn token_end(&self, index: usize) -> usize {
+        match self.items.get(index + 1) {
+            Some(next) => next.first_token,
+            None => self.tokens.len(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AstPrinter;
+
+    /// the document has to match a parse from scratch
+    fn assert_fresh(document: &Document) {
+        let fresh = Document::new(document.source());
+        let lexemes = |document: &Document| {
+            document
+                .tokens()
+                .iter()
+                .map(|token| (token.lexeme.clone(), token.line, token.offset))
+                .collect::<Vec<_>>()
+        };
+        let printed = |document: &Document| {
+            document
+                .statements()
+                .iter()
+                .map(|(ast, stmt)| AstPrinter::new(ast).print_stmt(*stmt).unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(lexemes(document), lexemes(&fresh));
+        assert_eq!(printed(document), printed(&fresh));
+        assert_eq!(document.errors().len(), fresh.errors().len());
+    }
+
+    #[test]
+    fn test_edit_reuses_prefix() {
+        let mut document = Document::new("var a = 1;\nvar b = 2;\nprint a + b;\n");
+        assert_eq!(document.statements().len(), 3);
+
+        // change the last line only
+        let start = document.source().find("a + b").unwrap();
+        assert_eq!(document.edit(start..start + 1, "b"), 2);
+        assert_eq!(document.source(), "var a = 1;\nvar b = 2;\nprint b + b;\n");
+        assert_fresh(&document);
+
+        // appending to the end reuses everything
+        let end = document.source().len();
+        assert_eq!(document.edit(end..end, "var c = 3;\n"), 3);
+        assert_eq!(document.statements().len(), 4);
+        assert_fresh(&document);
+
+        // an edit at the start parses everything again
+        assert_eq!(document.edit(0..0, "\n\n"), 0);
+        assert_fresh(&document);
+    }
+
+    #[test]
+    fn test_edit_grows_identifier() {
+        let mut document = Document::new("var foo = 1;\nprint foo");
+        let end = document.source().len();
+        assert!(!document.errors().is_empty());
+        document.edit(end..end, "bar;");
+        assert_eq!(document.tokens()[6].lexeme, "foobar");
+        assert_fresh(&document);
+    }
+
+    #[test]
+    fn test_edit_fixes_errors() {
+        let mut document = Document::new("var a = 1;\nvar b = @;\nprint a;\n");
+        assert_eq!(document.errors().len(), 2);
+        let at = document.source().find('@').unwrap();
+        document.edit(at..at + 1, "2");
+        assert!(document.errors().is_empty());
+        assert_eq!(document.statements().len(), 3);
+        assert_fresh(&document);
+    }
+}
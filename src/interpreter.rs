@@ -1,77 +1,425 @@
+use smallvec::SmallVec;
 use std::collections::HashMap;
+use std::any::Any;
+use std::io::{self, BufRead, BufWriter, Write};
+use std::panic::{self, AssertUnwindSafe};
 use std::{cell::RefCell, rc::Rc};
 
 use super::*;
 
+/// where the resolver found a variable
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Binding {
+    /// this many environments up from the current one
+    Local(usize),
+    /// a slot in the globals, no name lookup needed
+    Global(usize),
+}
+
+/// how deep calls may nest before the program is stopped, the stack
+/// grows on the heap so this is what keeps runaway recursion finite
+const MAX_CALL_DEPTH: usize = 10_000;
+
+thread_local! {
+    /// the innermost statement a panic unwound through, see `interpret`
+    static PANIC_SITE: RefCell<Option<Token>> = const { RefCell::new(None) };
+
+    /// the class of runtime errors a `catch` stops
+    static ERROR_CLASS: ClassRef = ClassBuilder::new("Error")
+        .doc("A runtime error caught by 'catch', with its message, code and line.")
+        .build();
+}
+
+/// lives for the execution of a statement, when dropped by a panic it
+/// remembers where the panic happened, unless a deeper statement did
+struct PanicSite<'a>(&'a Ast, StmtId);
+
+impl Drop for PanicSite<'_> {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            PANIC_SITE.with(|site| {
+                let mut site = site.borrow_mut();
+                if site.is_none() {
+                    *site = self.0[self.1].token(self.0).cloned();
+                }
+            });
+        }
+    }
+}
+
+/// counters `--stats` and the `stats()` native report
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Stats {
+    pub statements: usize,
+    pub calls: usize,
+    /// strings, lists, functions, classes and instances made at runtime
+    pub allocations: usize,
+    pub max_call_depth: usize,
+}
+
+impl std::fmt::Display for Stats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "statements executed: {}", self.statements)?;
+        writeln!(f, "function calls:      {}", self.calls)?;
+        writeln!(f, "objects allocated:   {}", self.allocations)?;
+        write!(f, "max call depth:      {}", self.max_call_depth)
+    }
+}
+
 pub struct Interpreter {
     environment: EnvironmentRef,
     pub globals: EnvironmentRef,
-    pub locals: HashMap<Token, usize>,
+    pub locals: HashMap<ExprId, Binding>,
+    /// how many environments up from each `super` expression the
+    /// instance its method is bound to is
+    pub(crate) this_depths: HashMap<ExprId, usize>,
+    /// `"a" + 1` gives "a1" instead of a runtime error
+    coerce_strings: bool,
+    /// conditions have to be booleans instead of any truthy value
+    strict_conditions: bool,
+    /// `var x;` makes x nil, like classic Lox, instead of unassigned
+    uninitialized_nil: bool,
+    /// calls currently running
+    call_depth: usize,
+    /// where `print` writes, stdout unless the host plugs in another
+    /// writer. it is buffered so a loop that prints isn't a write call
+    /// per line, `interpret` flushes it before returning
+    out: BufWriter<Box<dyn Write>>,
+    /// where `eprint` writes, stderr unless plugged like `out`
+    err: Box<dyn Write>,
+    /// where `input()` reads lines from, None is stdin. stdin is not
+    /// wrapped in a reader of its own so the prompt can share it
+    input: Option<Box<dyn BufRead>>,
+    /// the layout `pp` and the prompt show values in
+    pretty: PrettyPrinter,
+    /// everything `allocate` saw, for `memoryStats()` and `collectGarbage()`
+    heap: Heap,
+    /// statements left to execute, None runs without a limit
+    fuel: Option<usize>,
+    /// `--trace-exec`, a JSON line for every statement, call and write
+    trace: Option<Trace>,
+    /// statements waiting for their block to end, with the scope they
+    /// were deferred in, a block runs the ones past its entry length
+    deferred: Vec<(StmtId, EnvironmentRef)>,
+    /// the tree the running code is in, a call switches to the tree of
+    /// the function for as long as it runs
+    ast: Rc<Ast>,
+    /// the value of every frozen literal evaluated so far
+    constants: HashMap<ExprId, Object>,
+    /// methods of strings, lists and the other values that aren't instances
+    builtins: BuiltinMethods,
+    /// what `clock()` and `monotonicMillis()` read
+    clock: Box<dyn Clock>,
+    stats: Stats,
 }
 
 impl Interpreter {
     pub fn new() -> Self {
         let globals = Rc::new(RefCell::new(Environment::new(None)));
 
-        let clock: Object = Object::Callable(Function::Native {
-            name: "clock".to_string(),
-            arity: 0,
-            body: Box::new(|_: &Vec<Object>| -> Object {
-                Object::Number(NumberType::Float(
-                    std::time::SystemTime::now()
-                        .duration_since(std::time::UNIX_EPOCH)
-                        .unwrap()
-                        .as_secs_f64(),
-                ))
-            }),
-        });
-        globals.borrow_mut().define("clock", clock);
+        stdlib::define_globals(&mut globals.borrow_mut());
 
         Self {
             environment: globals.clone(),
             globals,
             locals: HashMap::new(),
+            this_depths: HashMap::new(),
+            coerce_strings: false,
+            strict_conditions: false,
+            uninitialized_nil: false,
+            call_depth: 0,
+            out: BufWriter::new(Box::new(io::stdout())),
+            err: Box::new(io::stderr()),
+            input: None,
+            pretty: PrettyPrinter::default(),
+            heap: Heap::default(),
+            fuel: None,
+            trace: None,
+            deferred: Vec::new(),
+            ast: Rc::new(Ast::new()),
+            constants: HashMap::new(),
+            builtins: BuiltinMethods::standard(),
+            clock: Box::new(SystemClock::default()),
+            stats: Stats::default(),
         }
     }
 
-    pub fn interpret(&mut self, stmts: &Vec<Stmt>) -> Result<(), Error> {
-        for stmt in stmts {
-            self.execute(stmt)?;
+    /// the methods of built-in values, to give them more
+    pub fn builtin_methods_mut(&mut self) -> &mut BuiltinMethods {
+        &mut self.builtins
+    }
+
+    /// send everything the program prints to `out` instead of stdout
+    pub fn set_output(&mut self, out: Box<dyn Write>) {
+        let _ = self.out.flush();
+        self.out = BufWriter::new(out);
+    }
+
+    /// read what `input()` returns from `input` instead of stdin
+    pub fn set_input(&mut self, input: Box<dyn BufRead>) {
+        self.input = Some(input);
+    }
+
+    /// read the time from `clock` instead of the system clock
+    pub fn set_clock(&mut self, clock: Box<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    pub fn clock(&self) -> &dyn Clock {
+        self.clock.as_ref()
+    }
+
+    /// send what `eprint` writes to `err` instead of stderr
+    pub fn set_error_output(&mut self, err: Box<dyn Write>) {
+        self.err = err;
+    }
+
+    pub fn set_pretty_printer(&mut self, pretty: PrettyPrinter) {
+        self.pretty = pretty;
+    }
+
+    pub fn pretty_printer(&self) -> &PrettyPrinter {
+        &self.pretty
+    }
+
+    /// stop the program with an error after this many statements
+    pub fn set_fuel(&mut self, fuel: Option<usize>) {
+        self.fuel = fuel;
+    }
+
+    /// trace execution as JSON lines to `out`, None turns it off
+    pub fn set_trace(&mut self, out: Option<Box<dyn Write>>) {
+        self.trace = out.map(Trace::new);
+    }
+
+    /// the innermost scope of the code running now, for a native that
+    /// is the scope it was called from
+    pub fn environment(&self) -> &EnvironmentRef {
+        &self.environment
+    }
+
+    pub fn stats(&self) -> Stats {
+        self.stats
+    }
+
+    /// count a freshly made heap object, natives that make one call this too
+    pub fn allocate(&mut self, object: Object) -> Object {
+        self.stats.allocations += 1;
+        self.heap.track(&object);
+        object
+    }
+
+    pub fn heap_mut(&mut self) -> &mut Heap {
+        &mut self.heap
+    }
+
+    /// write one line of program output, natives that print use this too
+    pub fn print_line(&mut self, text: &str) -> Result<(), Error> {
+        writeln!(self.out, "{}", text).map_err(Self::output_error)
+    }
+
+    /// write program output without ending the line
+    pub fn print(&mut self, text: &str) -> Result<(), Error> {
+        write!(self.out, "{}", text).map_err(Self::output_error)
+    }
+
+    /// write one line to the error output, after the output so far
+    pub fn eprint_line(&mut self, text: &str) -> Result<(), Error> {
+        self.flush()?;
+        writeln!(self.err, "{}", text).map_err(Self::output_error)
+    }
+
+    /// write out buffered program output, before reading input or
+    /// exiting and whenever a program asks with `flush()`
+    pub fn flush(&mut self) -> Result<(), Error> {
+        self.out.flush().map_err(Self::output_error)
+    }
+
+    /// one line of input without its line ending, None at the end of
+    /// the input. the output so far is flushed first, so a prompt shows
+    pub fn read_line(&mut self) -> Result<Option<String>, Error> {
+        self.flush()?;
+        let mut line = String::new();
+        let read = match &mut self.input {
+            Some(input) => input.read_line(&mut line),
+            None => io::stdin().read_line(&mut line),
+        };
+        match read {
+            Ok(0) => Ok(None),
+            Ok(_) => {
+                let end = line.trim_end_matches(['\n', '\r']).len();
+                line.truncate(end);
+                Ok(Some(line))
+            }
+            Err(err) => Err(Error::coded("E0531", &[&err])),
         }
-        Ok(())
     }
 
-    pub fn evaluate(&mut self, expr: &Expr) -> Result<Object, Error> {
-        expr.accept(self)
+    fn output_error(err: io::Error) -> Error {
+        Error::coded("E0505", &[&err])
+    }
+
+    /// only accept booleans in if, while, assert and logical operators
+    pub fn set_strict_conditions(&mut self, strict: bool) {
+        self.strict_conditions = strict;
+    }
+
+    /// compatibility with classic Lox, `var x;` gives x the value nil
+    /// instead of making reads before the first assignment an error
+    pub fn set_uninitialized_nil(&mut self, nil: bool) {
+        self.uninitialized_nil = nil;
+    }
+
+    /// let `+` turn the other operand into a string when one side is a string
+    pub fn set_string_coercion(&mut self, coerce: bool) {
+        self.coerce_strings = coerce;
     }
 
-    pub fn execute(&mut self, stmt: &Stmt) -> Result<(), Error> {
-        stmt.accept(self)
+    /// run the code that follows in `ast`, giving back the tree that was
+    /// running before
+    pub fn set_ast(&mut self, ast: Rc<Ast>) -> Rc<Ast> {
+        std::mem::replace(&mut self.ast, ast)
+    }
+
+    /// run a program, its statements in the tree last given to `set_ast`.
+    /// a panic inside the interpreter becomes an error instead of taking
+    /// the host down with it
+    pub fn interpret(&mut self, stmts: &[StmtId]) -> Result<(), Error> {
+        PANIC_SITE.with(|site| site.borrow_mut().take());
+        let ast = Rc::clone(&self.ast);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            let mark = self.deferred.len();
+            let mut steps = || -> Result<(), Error> {
+                for stmt in stmts {
+                    self.execute(*stmt)?;
+                }
+                Ok(())
+            };
+            let result = steps();
+            self.run_deferred(mark, result)
+        }));
+        let result = match result {
+            Ok(result) => result,
+            Err(payload) => {
+                self.ast = ast;
+                Err(self.recover(payload))
+            }
+        };
+        let flushed = self.flush();
+        result.and(flushed)
+    }
+
+    /// put the interpreter back at the top level after a panic and turn
+    /// the panic into an error at the statement it happened in
+    fn recover(&mut self, payload: Box<dyn Any + Send>) -> Error {
+        self.environment = self.globals.clone();
+        self.call_depth = 0;
+        self.deferred.clear();
+        let reason = match payload.downcast_ref::<&str>() {
+            Some(reason) => reason.to_string(),
+            None => match payload.downcast_ref::<String>() {
+                Some(reason) => reason.clone(),
+                None => "unknown panic".to_string(),
+            },
+        };
+        let error = Error::coded("E0438", &[&reason]);
+        match PANIC_SITE.with(|site| site.borrow_mut().take()) {
+            Some(token) => error.at(&token),
+            None => error,
+        }
+    }
+
+    pub fn evaluate(&mut self, expr: ExprId) -> Result<Object, Error> {
+        // the node borrows from the tree, not from the interpreter
+        let ast = Rc::clone(&self.ast);
+        expr.accept(&ast, self)
+    }
+
+    pub fn execute(&mut self, stmt: StmtId) -> Result<(), Error> {
+        self.stats.statements += 1;
+        if let Some(fuel) = &mut self.fuel {
+            if *fuel == 0 {
+                return Err(Error::coded("E0419", &[]));
+            }
+            *fuel -= 1;
+        }
+        let ast = Rc::clone(&self.ast);
+        if let Some(trace) = &mut self.trace {
+            trace.stmt(&ast[stmt], &ast)?;
+        }
+        let _site = PanicSite(&ast, stmt);
+        stmt.accept(&ast, self)
+    }
+
+    /// evaluate `expr` as if it were written in `environment`
+    pub fn evaluate_in(
+        &mut self,
+        expr: ExprId,
+        environment: &EnvironmentRef,
+    ) -> Result<Object, Error> {
+        let previous = std::mem::replace(&mut self.environment, environment.clone());
+        let result = self.evaluate(expr);
+        self.environment = previous;
+        result
     }
 
     pub fn execute_block(
         &mut self,
-        stmts: &Vec<Stmt>,
+        stmts: &[StmtId],
         environment: EnvironmentRef,
     ) -> Result<(), Error> {
         let previous = self.environment.clone();
         self.environment = environment;
+        let mark = self.deferred.len();
         let mut steps = || -> Result<(), Error> {
             for statement in stmts {
-                self.execute(statement)?;
+                self.execute(*statement)?;
             }
             Ok(())
         };
         let result = steps();
+        let result = self.run_deferred(mark, result);
         self.environment = previous;
         result
     }
 
+    /// run the statements deferred since `mark`, newest first, at the end
+    /// of a block. an error in one of them replaces a return or break
+    /// passing through, but not an error the block already had
+    fn run_deferred<T>(&mut self, mark: usize, mut result: Result<T, Error>) -> Result<T, Error> {
+        while self.deferred.len() > mark {
+            let (stmt, environment) = self.deferred.pop().unwrap();
+            let previous = std::mem::replace(&mut self.environment, environment);
+            let deferred = self.execute(stmt);
+            self.environment = previous;
+            if let Err(error) = deferred {
+                if !matches!(&result, Err(error) if !error.is_control_flow()) {
+                    result = Err(error);
+                }
+            }
+        }
+        result
+    }
+
     fn number_operand_error(&self, operator: &Token) -> Result<Object, Error> {
-        Err(Error {
-            message: format!("Operand of {} must be a number.", operator.token_type),
-            error_type: ErrorType::RuntimeError(operator.clone()),
-        })
+        if compat::jlox() {
+            return Err(Error::coded("E0409", &[]).at(operator));
+        }
+        Err(Error::coded("E0408", &[&operator.token_type]).at(operator))
+    }
+
+    /// evaluate the condition of `keyword`, respecting strict conditions
+    fn condition(&mut self, condition: ExprId, keyword: &Token) -> Result<bool, Error> {
+        let value = self.evaluate(condition)?;
+        self.check_condition(&value, keyword)
+    }
+
+    fn check_condition(&self, value: &Object, keyword: &Token) -> Result<bool, Error> {
+        match value {
+            Object::Boolean(b) => Ok(*b),
+            _ if self.strict_conditions => Err(Error::coded("E0414", &[]).at(keyword)),
+            _ => Ok(Interpreter::is_truthy(value)),
+        }
     }
 
     fn is_truthy(object: &Object) -> bool {
@@ -82,778 +430,1828 @@ impl Interpreter {
         }
     }
 
+    /// whether `value` fits `pattern`, defining the names it binds in the
+    /// current environment as it goes
+    fn match_pattern(&mut self, pattern: &Pattern, value: &Object) -> Result<bool, Error> {
+        match pattern {
+            Pattern::Wildcard => Ok(true),
+            Pattern::Literal(literal) => {
+                let literal = expr::Visitor::visit_literal_expr(self, literal)?;
+                Ok(Self::is_equal(&literal, value))
+            }
+            Pattern::Range { start, end } => Ok(match value {
+                Object::Number(n) => {
+                    n.compare(start).is_some_and(|o| o.is_ge()) && n.compare(end).is_some_and(|o| o.is_lt())
+                }
+                _ => false,
+            }),
+            Pattern::Binding(name) => {
+                self.environment.borrow_mut().define(&name.lexeme, value.clone());
+                Ok(true)
+            }
+            Pattern::List { elements, rest, .. } => {
+                let items = match value {
+                    Object::List(list) => list.borrow().inner.clone(),
+                    _ => return Ok(false),
+                };
+                let fits_length = match rest {
+                    Some(_) => items.len() >= elements.len(),
+                    None => items.len() == elements.len(),
+                };
+                if !fits_length {
+                    return Ok(false);
+                }
+                for (element, item) in elements.iter().zip(&items) {
+                    if !self.match_pattern(element, item)? {
+                        return Ok(false);
+                    }
+                }
+                match rest {
+                    Some(rest) => {
+                        let mut others = List::new();
+                        for item in &items[elements.len()..] {
+                            others.push(item.clone());
+                        }
+                        let others = self.allocate(Object::List(Rc::new(RefCell::new(others))));
+                        self.match_pattern(rest, &others)
+                    }
+                    None => Ok(true),
+                }
+            }
+            Pattern::Instance { class, fields } => {
+                let ast = Rc::clone(&self.ast);
+                let Expr::Variable { name } = &ast[*class] else {
+                    unreachable!()
+                };
+                let class = match self.evaluate(*class)? {
+                    Object::Class(class) => class,
+                    _ => {
+                        return Err(Error::coded("E0427", &[&name.lexeme]).at(name))
+                    }
+                };
+                let instance = match value {
+                    Object::Instance(instance) => instance.clone(),
+                    _ => return Ok(false),
+                };
+                // an instance of a subclass fits too
+                if !LoxClass::is_subclass(instance.borrow().class(), &class) {
+                    return Ok(false);
+                }
+                for (field, pattern) in fields {
+                    let field = instance.borrow().field(&field.lexeme).cloned();
+                    match field {
+                        Some(field) if self.match_pattern(pattern, &field)? => {}
+                        _ => return Ok(false),
+                    }
+                }
+                Ok(true)
+            }
+            Pattern::Map { entries, .. } => {
+                let map = match value {
+                    Object::Map(map) => map.clone(),
+                    _ => return Ok(false),
+                };
+                for (key, pattern) in entries {
+                    let key = expr::Visitor::visit_literal_expr(self, key)?;
+                    let entry = map.borrow().get(&key).cloned();
+                    match entry {
+                        Some(entry) if self.match_pattern(pattern, &entry)? => {}
+                        _ => return Ok(false),
+                    }
+                }
+                Ok(true)
+            }
+        }
+    }
+
+    /// evaluate `name: value` arguments and line them up with the
+    /// parameters of the function or initializer being called
+    fn keyword_args(
+        &mut self,
+        callee: &Object,
+        args: SmallVec<[Object; 4]>,
+        keywords: &[(Token, ExprId)],
+    ) -> Result<SmallVec<[Object; 4]>, Error> {
+        let mut values = Vec::with_capacity(keywords.len());
+        for (name, value) in keywords {
+            values.push((name, self.evaluate(*value)?));
+        }
+        let function = match callee {
+            Object::Callable(function) => Some(function.clone()),
+            Object::Class(class) => LoxClass::initializer(class),
+            _ => None,
+        };
+        match function {
+            Some(function) => Ok(function.with_keywords(args.into_vec(), values)?.into()),
+            // let the call report what is wrong with it
+            None => {
+                let mut args = args;
+                args.extend(values.into_iter().map(|(_, value)| value));
+                Ok(args)
+            }
+        }
+    }
+
     fn is_equal(left: &Object, right: &Object) -> bool {
         left.equals(right)
     }
 
-    fn stringify(object: &Object) -> String {
+    /// the text `print` shows for a value
+    pub fn stringify(object: &Object) -> String {
         match object {
             Object::Nil => "nil".to_string(),
+            Object::Uninitialized => "uninitialized".to_string(),
             Object::Boolean(b) => b.to_string(),
             Object::Number(n) => n.to_string(),
-            Object::String(s) => s.clone(),
+            Object::String(s) => s.to_string(),
             Object::Callable(function) => function.to_string(),
             Object::Class(class) => class.borrow().to_string(),
             Object::Instance(instance) => instance.borrow().to_string(),
-            Object::List(list) => list.borrow().to_string(),
+            Object::List(_) | Object::Map(_) | Object::Tuple(_) => object.to_string(),
+            Object::Store(store) => store.borrow().to_string(),
         }
     }
-    fn check_integer(obj: &Object) -> Option<i64> {
-        if let Object::Number(NumberType::Integer(n)) = obj {
-            Some(*n)
-        } else {
-            None
-        }
-    }
-
-    pub fn resolve(&mut self, token: &Token, depth: usize) {
-        trace!("Resolving {} at depth {}", token.lexeme, depth);
-        self.locals.insert(token.clone(), depth);
-    }
-
-    fn look_up_variable(&self, name: &Token) -> Result<Object, Error> {
-        let result: Option<Object> = match self.locals.get(name) {
-            Some(distance) => self.environment.borrow().get_at(*distance, &name.lexeme),
-            None => self.globals.borrow().get(&name.lexeme),
+    /// the text print shows for `object`, which for an instance whose
+    /// class has a `toString()` method is what that method returns
+    pub fn display(&mut self, object: &Object) -> Result<String, Error> {
+        let Object::Instance(instance) = object else {
+            return Ok(Self::stringify(object));
         };
-        if let Some(obj) = result {
-            Ok(obj)
-        } else {
-            Err(Error {
-                message: format!("Undefined variable {}.", name.lexeme),
-                error_type: ErrorType::RuntimeError(name.clone()),
-            })
+        let method = instance.borrow().class().borrow().get_method("toString");
+        match method {
+            Some(method) if method.arity() == 0 => {
+                match method.call_bound(self, object.clone(), &[])? {
+                    Object::String(s) => Ok(s.to_string()),
+                    other => Err(Error::coded("E0534", &[&other.type_name()])),
+                }
+            }
+            _ => Ok(Self::stringify(object)),
         }
     }
-}
 
-impl expr::Visitor<Object> for Interpreter {
-    fn visit_literal_expr(&mut self, value: &Literal) -> Result<Object, Error> {
-        match value {
-            Literal::Boolean(b) => Ok(Object::Boolean(*b)),
-            Literal::Nil => Ok(Object::Nil),
-            Literal::Number(n) => Ok(Object::Number(*n)), // TODO
-            Literal::String(s) => Ok(Object::String(s.clone())),
-        }
+    /// `display` for a statement or expression, an error of its own is
+    /// reported at `token`
+    fn display_at(&mut self, object: &Object, token: &Token) -> Result<String, Error> {
+        self.display(object).map_err(|err| match err.error_type {
+            ErrorType::NativeError => err.at(token),
+            _ => err,
+        })
     }
-    fn visit_unary_expr(&mut self, expr: &Expr) -> Result<Object, Error> {
-        match expr {
-            Expr::Unary { operator, right } => {
-                let right = self.evaluate(right)?;
 
-                // -, !
-                match operator.token_type {
-                    TokenType::Minus => {
-                        match right {
-                            // check if right is a number
-                            Object::Number(n) => Ok(Object::Number(n.unary_op(operator)?)),
-                            _ => self.number_operand_error(operator),
-                        }
-                    }
-                    TokenType::Bang => Ok(Object::Boolean(!Interpreter::is_truthy(&right))),
-                    _ => unreachable!(),
+    /// apply a binary operator to two evaluated operands
+    fn binary_op(&mut self, left: Object, operator: &Token, right: Object) -> Result<Object, Error> {
+        match operator.token_type {
+            // an instance of a subclass is one of the superclass too
+            TokenType::Is => match (left, right) {
+                (Object::Instance(instance), Object::Class(class)) => Ok(Object::Boolean(
+                    LoxClass::is_subclass(instance.borrow().class(), &class),
+                )),
+                (_, Object::Class(_)) => Ok(Object::Boolean(false)),
+                (_, right) => Err(Error::coded("E0453", &[&right.type_name()]).at(operator)),
+            },
+            TokenType::Minus => match (left, right) {
+                (Object::Number(l), Object::Number(r)) => {
+                    Ok(Object::Number(l.binary_op(operator, &r)?))
+                }
+                _ => self.number_operand_error(operator),
+            },
+            TokenType::Plus => match (left, right) {
+                (Object::Number(l), Object::Number(r)) => {
+                    Ok(Object::Number(l.binary_op(operator, &r)?))
                 }
+                (Object::String(l), Object::String(r)) => {
+                    Ok(self.allocate(Object::String(format!("{}{}", l, r).into())))
+                }
+                (Object::List(list), Object::List(r)) => Ok(self.allocate(Object::List(
+                    Rc::new(RefCell::new(list.borrow().add(&r.borrow()))),
+                ))),
+                (Object::List(list), obj) => {
+                    let mut new_list = list.borrow().clone();
+                    new_list.push(obj);
+                    Ok(self.allocate(Object::List(Rc::new(RefCell::new(new_list)))))
+                }
+                (Object::String(l), r) if self.coerce_strings => {
+                    let r = self.display_at(&r, operator)?;
+                    Ok(self.allocate(Object::String(format!("{}{}", l, r).into())))
+                }
+                (l, Object::String(r)) if self.coerce_strings => {
+                    let l = self.display_at(&l, operator)?;
+                    Ok(self.allocate(Object::String(format!("{}{}", l, r).into())))
+                }
+                _ if compat::jlox() => Err(Error::coded("E0410", &[]).at(operator)),
+                _ => Err(Error::coded("E0411", &[&operator.token_type]).at(operator)),
+            },
+            TokenType::Slash => match (left, right) {
+                (Object::Number(l), Object::Number(r)) => {
+                    Ok(Object::Number(l.binary_op(operator, &r)?))
+                }
+                _ => self.number_operand_error(operator),
+            },
+            TokenType::Star => match (left, right) {
+                (Object::Number(l), Object::Number(r)) => {
+                    Ok(Object::Number(l.binary_op(operator, &r)?))
+                }
+                _ => self.number_operand_error(operator),
+            },
+            TokenType::Greater
+            | TokenType::GreaterEqual
+            | TokenType::Less
+            | TokenType::LessEqual => {
+                let ordering = Interpreter::compare(&left, &right, operator)?;
+                // nothing is ordered against NaN
+                Ok(Object::Boolean(ordering.is_some_and(|ordering| {
+                    match operator.token_type {
+                        TokenType::Greater => ordering.is_gt(),
+                        TokenType::GreaterEqual => ordering.is_ge(),
+                        TokenType::Less => ordering.is_lt(),
+                        _ => ordering.is_le(),
+                    }
+                })))
+            }
+            TokenType::BangEqual => {
+                Ok(Object::Boolean(!Interpreter::is_equal(&left, &right)))
             }
+
+            TokenType::EqualEqual => {
+                Ok(Object::Boolean(Interpreter::is_equal(&left, &right)))
+            }
+
             _ => unreachable!(),
         }
     }
-    fn visit_binary_expr(&mut self, expr: &Expr) -> Result<Object, Error> {
-        match expr {
-            Expr::Binary {
-                left,
-                operator,
-                right,
-            } => {
-                let left = self.evaluate(left)?;
-                let right = self.evaluate(right)?;
-                match operator.token_type {
-                    TokenType::Minus => match (left, right) {
-                        (Object::Number(l), Object::Number(r)) => {
-                            Ok(Object::Number(l.binary_op(operator, &r)?))
-                        }
-                        _ => self.number_operand_error(operator),
-                    },
-                    TokenType::Plus => match (left, right) {
-                        (Object::Number(l), Object::Number(r)) => {
-                            Ok(Object::Number(l.binary_op(operator, &r)?))
-                        }
-                        (Object::String(l), Object::String(r)) => Ok(Object::String(l + &r)),
-                        (Object::List(list), Object::List(r)) => Ok(Object::List(Rc::new(
-                            RefCell::new(list.borrow().add(&r.borrow())),
-                        ))),
-                        (Object::List(list), obj) => {
-                            let mut new_list = list.borrow().clone();
-                            new_list.push(obj);
-                            Ok(Object::List(Rc::new(RefCell::new(new_list))))
-                        }
-                        _ => Err(Error {
-                            message: format!(
-                                "Operands of {} must be two numbers or two strings.",
-                                operator.token_type
-                            ),
-                            error_type: ErrorType::RuntimeError(operator.clone()),
-                        }),
-                    },
-                    TokenType::Slash => match (left, right) {
-                        (Object::Number(l), Object::Number(r)) => {
-                            Ok(Object::Number(l.binary_op(operator, &r)?))
-                        }
-                        _ => self.number_operand_error(operator),
-                    },
-                    TokenType::Star => match (left, right) {
-                        (Object::Number(l), Object::Number(r)) => {
-                            Ok(Object::Number(l.binary_op(operator, &r)?))
-                        }
-                        _ => self.number_operand_error(operator),
-                    },
-                    TokenType::Greater => match (left, right) {
-                        (Object::Number(l), Object::Number(r)) => {
-                            Ok(Object::Boolean(l.greater(&r)?))
-                        }
-                        (Object::String(l), Object::String(r)) => Ok(Object::Boolean(l > r)),
-                        _ => self.number_operand_error(operator),
-                    },
-                    TokenType::GreaterEqual => match (left, right) {
-                        (Object::Number(l), Object::Number(r)) => {
-                            Ok(Object::Boolean(l.greater_equal(&r)?))
-                        }
-                        (Object::String(l), Object::String(r)) => Ok(Object::Boolean(l >= r)),
-                        _ => self.number_operand_error(operator),
-                    },
-                    TokenType::Less => match (left, right) {
-                        (Object::Number(l), Object::Number(r)) => Ok(Object::Boolean(l.less(&r)?)),
-                        (Object::String(l), Object::String(r)) => Ok(Object::Boolean(l < r)),
-                        _ => self.number_operand_error(operator),
-                    },
-                    TokenType::LessEqual => match (left, right) {
-                        (Object::Number(l), Object::Number(r)) => {
-                            Ok(Object::Boolean(l.less_equal(&r)?))
-                        }
-                        (Object::String(l), Object::String(r)) => Ok(Object::Boolean(l <= r)),
-                        _ => self.number_operand_error(operator),
-                    },
-                    TokenType::BangEqual => {
-                        Ok(Object::Boolean(!Interpreter::is_equal(&left, &right)))
-                    }
 
-                    TokenType::EqualEqual => {
-                        Ok(Object::Boolean(Interpreter::is_equal(&left, &right)))
-                    }
+    /// the value a `catch` binds for `error`, what was thrown, or an
+    /// Error instance with the message, code and line of a runtime error
+    fn caught(&mut self, error: Error) -> Object {
+        let line = match &error.error_type {
+            ErrorType::Thrown(value, _) => return value.clone(),
+            ErrorType::RuntimeError(token) => Object::Number(NumberType::Integer(token.line as i64)),
+            _ => Object::Nil,
+        };
+        let mut instance = LoxInstance::new(ERROR_CLASS.with(ClassRef::clone));
+        instance.set("message", &Object::String(error.message().into()));
+        instance.set("code", &Object::String(error.code.into()));
+        instance.set("line", &line);
+        self.allocate(Object::Instance(Rc::new(RefCell::new(instance))))
+    }
+
+    /// call a function or class with evaluated arguments, natives use
+    /// this to call back into Lox. errors that need a location are
+    /// NativeError, the caller fills in where the call happened
+    pub fn call_value(&mut self, callee: &Object, args: &[Object]) -> Result<Object, Error> {
+        if self.call_depth >= MAX_CALL_DEPTH {
+            return Err(Error::coded("E0418", &[]));
+        }
+        self.call_depth += 1;
+        self.stats.calls += 1;
+        self.stats.max_call_depth = self.stats.max_call_depth.max(self.call_depth);
+        // the name is only worked out when calls are being traced
+        let span = trace_span!(
+            "call",
+            callee = %Interpreter::stringify(callee),
+            depth = self.call_depth
+        );
+        let result = span.in_scope(|| self.call_callee(callee, args));
+        self.call_depth -= 1;
+        result
+    }
 
-                    TokenType::And => {
-                        if !Interpreter::is_truthy(&left) {
-                            Ok(left)
-                        } else {
-                            Ok(right)
+    fn call_callee(&mut self, callee: &Object, args: &[Object]) -> Result<Object, Error> {
+        let arity_error = |min: usize, max: usize| {
+            let expected = if min == max {
+                min.to_string()
+            } else {
+                format!("{} to {}", min, max)
+            };
+            Err(Error::coded("E0416", &[&expected, &args.len()]))
+        };
+        let accepts = |function: &Function| {
+            (function.arity()..=function.max_arity()).contains(&args.len())
+        };
+        match callee {
+            Object::Callable(function) => {
+                trace!("function arity: {}, args.len: {}", function.arity(), args.len());
+                if !accepts(function) {
+                    return arity_error(function.arity(), function.max_arity());
+                }
+                function.call(self, args)
+            }
+            Object::Class(class) => {
+                // get a new instance of the class and run its initializer
+                let fields = Rc::new(RefCell::new(LoxInstance::new(class.clone())));
+                self.initialize_fields(class, &fields)?;
+                let instance = self.allocate(Object::Instance(fields));
+                match LoxClass::initializer(class) {
+                    Some(initializer) => {
+                        if !accepts(&initializer) {
+                            return arity_error(initializer.arity(), initializer.max_arity());
                         }
+                        initializer.call_bound(self, instance.clone(), args)?;
                     }
-
-                    _ => unreachable!(),
+                    None if !args.is_empty() => return arity_error(0, 0),
+                    None => {}
                 }
+                Ok(instance)
             }
-            _ => unreachable!(),
+            _ => Err(Error::coded("E0415", &[])),
         }
     }
-    fn visit_grouping_expr(&mut self, expr: &Expr) -> Result<Object, Error> {
-        match expr {
-            Expr::Grouping { expression } => self.evaluate(expression),
-            _ => unreachable!(),
+
+    /// set the fields `class` declares on a new instance, the superclass's
+    /// first so a subclass's initializer for the same field wins
+    fn initialize_fields(
+        &mut self,
+        class: &ClassRef,
+        instance: &Rc<RefCell<LoxInstance>>,
+    ) -> Result<(), Error> {
+        let (super_class, fields, closure, ast) = {
+            let class = class.borrow();
+            let closure = class.closure.clone();
+            (class.super_class.clone(), class.fields.clone(), closure, class.ast.clone())
+        };
+        if let Some(super_class) = super_class {
+            self.initialize_fields(&super_class, instance)?;
         }
+        let (Some(closure), Some(ast)) = (closure, ast) else {
+            return Ok(());
+        };
+        // the initializers are in the tree the class was declared in
+        let previous = self.set_ast(ast);
+        let result = fields.iter().try_for_each(|(name, initializer)| {
+            let value = match initializer {
+                Some(initializer) => self.evaluate_in(*initializer, &closure)?,
+                None => Object::Nil,
+            };
+            instance.borrow_mut().set(name, &value);
+            Ok(())
+        });
+        self.set_ast(previous);
+        result
     }
-    fn visit_variable_expr(&mut self, expr: &Expr) -> Result<Object, Error> {
-        match expr {
-            Expr::Variable { name } => self.look_up_variable(name),
-            _ => unreachable!(),
+
+    /// one pass through a for-in body with `name` bound to `element`,
+    /// false when a break ends the loop
+    fn for_in_body(
+        &mut self,
+        name: &Token,
+        element: Object,
+        body: StmtId,
+        label: Option<&Token>,
+    ) -> Result<bool, Error> {
+        if let Some(trace) = &mut self.trace {
+            trace.write(name.line, &name.lexeme, &element)?;
+        }
+        let mut scope = Environment::new(Some(self.environment.clone()));
+        scope.define(&name.lexeme, element);
+        let scope = Rc::new(RefCell::new(scope));
+        let targets_this_loop = |target: &Option<String>| match target {
+            Some(target) => label.map(|label| &label.lexeme) == Some(target),
+            None => true,
+        };
+        match self.execute_block(std::slice::from_ref(&body), scope) {
+            Err(err) => match &err.error_type {
+                ErrorType::Break(target) if targets_this_loop(target) => Ok(false),
+                ErrorType::Continue(target) if targets_this_loop(target) => Ok(true),
+                _ => Err(err),
+            },
+            Ok(()) => Ok(true),
         }
     }
 
-    fn visit_assign_expr(&mut self, expr: &Expr) -> Result<Object, Error> {
-        match expr {
-            Expr::Assign { name, value } => {
-                let value = self.evaluate(value)?;
-
-                let distance = self.locals.get(name);
-                if let Some(distance) = distance {
-                    self.environment
-                        .borrow_mut()
-                        .assign_at(*distance, name, &value)?;
-                    Ok(value)
-                } else {
-                    self.environment.borrow_mut().assign(name, &value)?;
-                    Ok(value)
-                }
-            }
-            _ => unreachable!(),
+    /// `and` / `or` / `??`, the right operand is only evaluated when the
+    /// left one doesn't decide the result
+    fn logical(&mut self, left: ExprId, operator: &Token, right: ExprId) -> Result<Object, Error> {
+        let left = self.evaluate(left)?;
+        // `??` looks only at nil, a false left operand is kept
+        if operator.token_type == TokenType::QuestionQuestion {
+            return match left {
+                Object::Nil => self.evaluate(right),
+                left => Ok(left),
+            };
+        }
+        let truthy = self.check_condition(&left, operator)?;
+        let decided = match operator.token_type {
+            TokenType::Or => truthy,
+            _ => !truthy,
+        };
+        if decided {
+            Ok(left)
+        } else {
+            self.evaluate(right)
         }
     }
 
-    fn visit_logic_expr(&mut self, expr: &Expr) -> Result<Object, Error> {
-        match expr {
-            Expr::Logical {
-                left,
-                operator,
-                right,
-            } => {
-                let left_value = self.evaluate(left)?;
-                if operator.token_type == TokenType::Or {
-                    if Interpreter::is_truthy(&left_value) {
-                        Ok(left_value)
-                    } else {
-                        Ok(self.evaluate(right)?)
+    /// order two values of the same type, lists compare element by element
+    fn compare(
+        left: &Object,
+        right: &Object,
+        operator: &Token,
+    ) -> Result<Option<std::cmp::Ordering>, Error> {
+        match (left, right) {
+            (Object::Number(l), Object::Number(r)) => Ok(l.compare(r)),
+            // jlox only orders numbers
+            _ if compat::jlox() => Err(Error::coded("E0409", &[]).at(operator)),
+            (Object::String(l), Object::String(r)) => Ok(Some(l.cmp(r))),
+            (Object::Boolean(l), Object::Boolean(r)) => Ok(Some(l.cmp(r))),
+            (Object::List(l), Object::List(r)) => {
+                let (l, r) = (l.borrow(), r.borrow());
+                for (l, r) in l.inner.iter().zip(r.inner.iter()) {
+                    match Interpreter::compare(l, r, operator)? {
+                        Some(std::cmp::Ordering::Equal) => continue,
+                        ordering => return Ok(ordering),
                     }
-                } else if Interpreter::is_truthy(&left_value) {
-                    Ok(self.evaluate(right)?)
-                } else {
-                    Ok(left_value)
                 }
+                Ok(Some(l.inner.len().cmp(&r.inner.len())))
             }
-            _ => unreachable!(),
+            _ => Err(Error::coded(
+                "E0412",
+                &[&left.type_name(), &right.type_name(), &operator.token_type],
+            )
+            .at(operator)),
         }
     }
-    fn visit_index_expr(&mut self, expr: &Expr) -> Result<Object, Error> {
-        trace!("visit_index_expr: {}", expr);
-        match expr {
-            Expr::Index {
-                object: left,
-                operator,
-                index,
-                index_end,
-            } => {
-                // evaluate left
-                let left = self.evaluate(left)?;
-                let index = self.evaluate(index)?;
-                let index_end = match index_end {
-                    Some(index_end) => Some(self.evaluate(index_end)?),
-                    None => None,
-                };
-                let start: i64;
-                // check if right is a Number
-                if let Some(n) = Interpreter::check_integer(&index) {
-                    start = n;
-                } else {
-                    return Err(Error {
-                        message: format!("Expected integer got {}", index),
-                        error_type: ErrorType::RuntimeError(operator.clone()),
-                    });
-                }
-
-                let mut end: i64 = start + 1;
-                let mut is_slice: bool = false;
-                if let Some(index_end) = index_end {
-                    is_slice = true;
-                    if let Some(n) = Interpreter::check_integer(&index_end) {
-                        end = n;
-                    } else {
-                        return Err(Error {
-                            message: format!("Expected integer got {}", index_end),
-                            error_type: ErrorType::RuntimeError(operator.clone()),
-                        });
-                    }
-                }
-
-                // check if left is a String
-                if let Object::String(s) = left {
-                    // check if nth is in range
-                    if s.len() <= start as usize || start < 0 {
-                        return Err(Error {
-                            message: format!("Index out of range: {}", start),
-                            error_type: ErrorType::RuntimeError(operator.clone()),
-                        });
-                    }
-                    if s.len() < end as usize || end < 0 {
-                        return Err(Error {
-                            message: format!("Index out of range: {}", end),
-                            error_type: ErrorType::RuntimeError(operator.clone()),
-                        });
-                    }
-
-                    // 空串
-                    if start >= end {
-                        return Ok(Object::String("".to_string()));
-                    }
 
-                    // return the substr
-                    return Ok(Object::String(
-                        s.chars()
-                            .skip(start as usize)
-                            .take((end - start) as usize)
-                            .collect(),
-                    ));
-                }
-                // check if left is a List
-                if let Object::List(list) = left {
-                    // check if nth is in range
-                    if list.borrow().inner.len() <= start as usize || start < 0 {
-                        return Err(Error {
-                            message: format!("Index out of range: {}", start),
-                            error_type: ErrorType::RuntimeError(operator.clone()),
-                        });
-                    }
-                    if list.borrow().inner.len() < end as usize || end < 0 {
-                        return Err(Error {
-                            message: format!("Index out of range: {}", end),
-                            error_type: ErrorType::RuntimeError(operator.clone()),
-                        });
-                    }
+    /// define the class of a rust type as a global, see `LoxBindable`
+    pub fn define_class<T: LoxBindable>(&mut self) -> ClassRef {
+        let builder = T::lox_class();
+        let name = builder.name().to_string();
+        let class = builder.build();
+        self.globals
+            .borrow_mut()
+            .define(&name, Object::Class(class.clone()));
+        class
+    }
 
-                    // 空列表
-                    if start >= end {
-                        return Ok(Object::List(Rc::new(RefCell::new(List::new()))));
-                    }
+    pub fn resolve(&mut self, id: ExprId, depth: usize) {
+        trace!("Resolving {:?} at depth {}", id, depth);
+        self.locals.insert(id, Binding::Local(depth));
+    }
 
-                    if !is_slice {
-                        // return the nth element
-                        return Ok(list.borrow().get(start as usize).clone());
-                    }
+    /// `id` refers to a global the resolver already knows the slot of
+    pub fn resolve_global(&mut self, id: ExprId, slot: usize) {
+        trace!("Resolving {:?} to global slot {}", id, slot);
+        self.locals.insert(id, Binding::Global(slot));
+    }
 
-                    // return the sublist
-                    return Ok(Object::List(Rc::new(RefCell::new(
-                        list.borrow().slice(start as usize, end as usize),
-                    ))));
-                }
+    /// the `super` expression `id` binds methods to the `this` found
+    /// `depth` environments up
+    pub fn resolve_this(&mut self, id: ExprId, depth: usize) {
+        trace!("Resolving this of {:?} at depth {}", id, depth);
+        self.this_depths.insert(id, depth);
+    }
 
-                Err(Error {
-                    message: format!("Expected string got {}", left),
-                    error_type: ErrorType::RuntimeError(operator.clone()),
-                })
-                // check if left if an Array TOOD
+    fn look_up_variable(&self, name: &Token, id: ExprId) -> Result<Object, Error> {
+        let result: Option<Object> = match self.locals.get(&id) {
+            Some(Binding::Local(distance)) => {
+                self.environment.borrow().get_at(*distance, &name.lexeme)
             }
-            _ => unreachable!(),
+            Some(Binding::Global(slot)) => self.globals.borrow().get_slot(*slot),
+            None => self.globals.borrow().get(&name.lexeme),
+        };
+        if let Some(Object::Uninitialized) = result {
+            Err(Error::coded("E0403", &[&name.lexeme]).at(name))
+        } else if let Some(obj) = result {
+            Ok(obj)
+        } else {
+            let code = if compat::jlox() { "E0401" } else { "E0402" };
+            Err(Error::coded(code, &[&name.lexeme]).at(name))
         }
     }
-    fn visit_call_expr(&mut self, expr: &Expr) -> Result<Object, Error> {
-        trace!("visit_call_expr");
-        match expr {
-            Expr::Call {
-                callee,
-                paren,
-                arguments,
-            } => {
-                let callee = self.evaluate(callee)?;
-
-                let mut args = Vec::new();
-                for arg in arguments {
-                    args.push(self.evaluate(arg)?);
-                }
-
-                // check if callee is a function
-                if let Object::Callable(function) = callee {
-                    // check if number of arguments matches number of parameters
-                    trace!("function arity: {}", function.arity(),);
-                    trace!("args.len: {}", args.len());
-                    if function.arity() != args.len() {
-                        return Err(Error {
-                            message: format!(
-                                "Expected {} arguments but got {}.",
-                                function.arity(),
-                                args.len()
-                            ),
-                            error_type: ErrorType::RuntimeError(paren.clone()),
-                        });
-                    }
-                    // call function
-                    Ok(function.call(self, &args)?)
-                } else if let Object::Class(class) = callee {
-                    // call class init
-                    // get a new instance of the class
-                    let instance =
-                        Object::Instance(Rc::new(RefCell::new(LoxInstance::new(class.clone()))));
-                    if let Some(initializer) = class.borrow().get_method("init") {
-                        if initializer.arity() != args.len() {
-                            return Err(Error {
-                                message: format!(
-                                    "Expected {} arguments but got {}.",
-                                    initializer.arity(),
-                                    args.len()
-                                ),
-                                error_type: ErrorType::RuntimeError(paren.clone()),
-                            });
-                        }
-                        initializer.bind(instance.clone()).call(self, &args)?;
-                    }
+}
 
-                    Ok(instance)
-                } else {
-                    Err(Error {
-                        message: "Can only call functions and classes.".to_string(),
-                        error_type: ErrorType::RuntimeError(paren.clone()),
-                    })
-                }
+/// make a list and the lists in it read-only
+fn freeze(object: &Object) {
+    match object {
+        Object::List(list) => {
+            list.borrow_mut().freeze();
+            for element in &list.borrow().inner {
+                freeze(element);
+            }
+        }
+        Object::Map(map) => {
+            map.borrow_mut().freeze();
+            for (_, value) in map.borrow().iter() {
+                freeze(value);
             }
-            _ => unreachable!(),
         }
+        _ => {}
     }
-    fn visit_get_expr(&mut self, expr: &Expr) -> Result<Object, Error> {
-        match expr {
-            Expr::Get { object, name } => {
-                let object = object.accept(self)?;
-                if let Object::Instance(ref instance) = object {
-                    let field = instance.borrow().get(&name.lexeme, &object);
-                    if let Some(field) = field {
-                        Ok(field)
-                    } else {
-                        Err(Error {
-                            message: format!("Undefined property '{}'.", name.lexeme),
-                            error_type: ErrorType::RuntimeError(name.clone()),
-                        })
-                    }
-                } else {
-                    Err(Error {
-                        message: "Only instances have properties.".to_string(),
-                        error_type: ErrorType::RuntimeError(name.clone()),
-                    })
+}
+
+/// only values that hash can be map keys
+fn check_key(key: &Object, token: &Token) -> Result<(), Error> {
+    if Map::can_hold(key) {
+        return Ok(());
+    }
+    Err(Error::coded("E0445", &[&key.type_name()]).at(token))
+}
+
+/// the items `index` or `index:index_end` covers in a string or list of
+/// `len` items. negative bounds count back from the end and a nil bound
+/// of a slice is the start or the end. a slice may start at `len` and be
+/// empty, a single item has to be one of the `len`
+fn index_range(
+    index: &Object,
+    index_end: Option<&Object>,
+    len: usize,
+    operator: &Token,
+) -> Result<std::ops::Range<usize>, Error> {
+    let bound = |object: &Object, omitted: i64| match object {
+        Object::Number(NumberType::Integer(n)) => Ok(*n),
+        Object::Nil if index_end.is_some() => Ok(omitted),
+        _ => Err(Error::coded("E0420", &[&object]).at(operator)),
+    };
+    let from_end = |n: i64| if n < 0 { n.saturating_add(len as i64) } else { n };
+    let out_of_range = |n: i64| Error::coded("E0423", &[&n]).at(operator);
+
+    let start = bound(index, 0)?;
+    let first = from_end(start);
+    let past_end = match index_end {
+        Some(_) => first as usize > len,
+        None => first as usize >= len,
+    };
+    if first < 0 || past_end {
+        return Err(out_of_range(start));
+    }
+    let last = match index_end {
+        Some(index_end) => {
+            let end = bound(index_end, len as i64)?;
+            let last = from_end(end);
+            if last < 0 || last as usize > len {
+                return Err(out_of_range(end));
+            }
+            last
+        }
+        None => first + 1,
+    };
+    // an empty slice when the end is before the start
+    Ok(first as usize..last.max(first) as usize)
+}
+
+/// the step of a slice, nil is the same as 1
+fn slice_step(step: &Object, operator: &Token) -> Result<i64, Error> {
+    let error = match step {
+        Object::Number(NumberType::Integer(0)) => Error::coded("E0451", &[]),
+        Object::Number(NumberType::Integer(n)) => return Ok(*n),
+        Object::Nil => return Ok(1),
+        _ => Error::coded("E0420", &[step]),
+    };
+    Err(error.at(operator))
+}
+
+/// the items `index:index_end:step` covers, in the order the step takes
+/// them. bounds past either end are clamped to the items there are, and
+/// walking backwards a nil start is the last item and a nil end is past
+/// the first one
+fn stepped_indices(
+    index: &Object,
+    index_end: &Object,
+    step: i64,
+    len: usize,
+    operator: &Token,
+) -> Result<Vec<usize>, Error> {
+    let len = len as i64;
+    let bound = |object: &Object, omitted: i64| match object {
+        Object::Nil => Ok(omitted),
+        Object::Number(NumberType::Integer(n)) if *n < 0 => Ok(n.saturating_add(len)),
+        Object::Number(NumberType::Integer(n)) => Ok(*n),
+        _ => Err(Error::coded("E0420", &[&object]).at(operator)),
+    };
+    let indices: Vec<i64> = if step > 0 {
+        let first = bound(index, 0)?.clamp(0, len);
+        let last = bound(index_end, len)?.clamp(0, len);
+        (first..last).step_by(step as usize).collect()
+    } else {
+        let first = bound(index, len - 1)?.clamp(-1, len - 1);
+        let last = bound(index_end, -1)?.clamp(-1, len - 1);
+        (last + 1..=first)
+            .rev()
+            .step_by(step.unsigned_abs() as usize)
+            .collect()
+    };
+    Ok(indices.into_iter().map(|i| i as usize).collect())
+}
+
+/// a method of an instance's class, None for other values
+fn instance_method(value: &Object, name: &str) -> Option<Function> {
+    match value {
+        Object::Instance(instance) => instance.borrow().class().borrow().get_method(name),
+        _ => None,
+    }
+}
+
+fn cannot_slice_map(token: &Token) -> Error {
+    Error::coded("E0443", &[]).at(token)
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl expr::Visitor<Object> for Interpreter {
+    fn visit_literal_expr(&mut self, value: &Literal) -> Result<Object, Error> {
+        match value {
+            Literal::Boolean(b) => Ok(Object::Boolean(*b)),
+            Literal::Nil => Ok(Object::Nil),
+            Literal::Number(n) => Ok(Object::Number(*n)), // TODO
+            Literal::String(s) => Ok(self.allocate(Object::String(s.as_str().into()))),
+        }
+    }
+    fn visit_unary_expr(&mut self, operator: &Token, right: ExprId) -> Result<Object, Error> {
+        let right = self.evaluate(right)?;
+
+        // -, !
+        match operator.token_type {
+            TokenType::Minus => {
+                match right {
+                    // check if right is a number
+                    Object::Number(n) => Ok(Object::Number(n.unary_op(operator)?)),
+                    _ if compat::jlox() => Err(Error::coded("E0407", &[]).at(operator)),
+                    _ => self.number_operand_error(operator),
                 }
             }
+            TokenType::Bang => Ok(Object::Boolean(!Interpreter::is_truthy(&right))),
             _ => unreachable!(),
         }
     }
-    fn visit_set_expr(&mut self, expr: &Expr) -> Result<Object, Error> {
-        match expr {
-            Expr::Set {
-                object,
-                name,
-                value,
-            } => {
-                // object.name = value
-                let object = object.accept(self)?;
-                if let Object::Instance(instance) = object {
-                    let value = self.evaluate(value)?;
-                    instance.borrow_mut().set(&name.lexeme, &value);
-                    Ok(value)
-                } else {
-                    Err(Error {
-                        message: "Only instances have fields.".to_string(),
-                        error_type: ErrorType::RuntimeError(name.clone()),
-                    })
+    fn visit_binary_expr(
+        &mut self,
+        left: ExprId,
+        operator: &Token,
+        right: ExprId,
+    ) -> Result<Object, Error> {
+        // and/or only evaluate the right operand when needed,
+        // even in a Binary built by hand
+        if let TokenType::And | TokenType::Or | TokenType::QuestionQuestion = operator.token_type {
+            return self.logical(left, operator, right);
+        }
+        let left = self.evaluate(left)?;
+        let right = self.evaluate(right)?;
+        self.binary_op(left, operator, right)
+    }
+    fn visit_grouping_expr(&mut self, expression: ExprId) -> Result<Object, Error> {
+        self.evaluate(expression)
+    }
+    fn visit_variable_expr(&mut self, name: &Token, id: ExprId) -> Result<Object, Error> {
+        self.look_up_variable(name, id)
+    }
+
+    fn visit_assign_expr(
+        &mut self,
+        name: &Token,
+        value: ExprId,
+        id: ExprId,
+    ) -> Result<Object, Error> {
+        let value = self.evaluate(value)?;
+        if let Some(trace) = &mut self.trace {
+            trace.write(name.line, &name.lexeme, &value)?;
+        }
+
+        match self.locals.get(&id) {
+            Some(Binding::Local(distance)) => self
+                .environment
+                .borrow_mut()
+                .assign_at(*distance, name, &value)?,
+            Some(Binding::Global(slot)) => {
+                self.globals.borrow_mut().assign_slot(*slot, name, &value)?
+            }
+            None => self.environment.borrow_mut().assign(name, &value)?,
+        }
+        Ok(value)
+    }
+
+    fn visit_logic_expr(
+        &mut self,
+        left: ExprId,
+        operator: &Token,
+        right: ExprId,
+    ) -> Result<Object, Error> {
+        self.logical(left, operator, right)
+    }
+    fn visit_index_expr(
+        &mut self,
+        object: ExprId,
+        operator: &Token,
+        index: ExprId,
+        index_end: Option<ExprId>,
+        index_step: Option<ExprId>,
+        optional: bool,
+    ) -> Result<Object, Error> {
+        trace!("visit_index_expr: {:?}", object);
+        let left = self.evaluate(object)?;
+        if let Object::Nil = left {
+            if optional {
+                return Ok(Object::Nil);
+            }
+            // say where the nil came from, a chain of lookups can hide it
+            let error = match &self.ast[object] {
+                Expr::Variable { name, .. } | Expr::Get { name, .. } => {
+                    Error::coded("E0447", &[&name.lexeme])
                 }
+                _ => Error::coded("E0448", &[]),
+            };
+            return Err(error.at(operator));
+        }
+        let index = self.evaluate(index)?;
+        let index_end = match index_end {
+            Some(index_end) => Some(self.evaluate(index_end)?),
+            None => None,
+        };
+        let step = match index_step {
+            Some(step) => slice_step(&self.evaluate(step)?, operator)?,
+            None => 1,
+        };
+        if let Object::Map(map) = &left {
+            if index_end.is_some() {
+                return Err(cannot_slice_map(operator));
             }
-            _ => unreachable!(),
+            check_key(&index, operator)?;
+            return match map.borrow().get(&index) {
+                Some(value) => Ok(value.clone()),
+                None => Err(Error::coded("E0444", &[&index]).at(operator)),
+            };
+        }
+        // check if left is a String
+        if let Object::String(s) = left {
+            if step != 1 {
+                let chars: Vec<char> = s.chars().collect();
+                let end = index_end.as_ref().unwrap_or(&Object::Nil);
+                let indices = stepped_indices(&index, end, step, chars.len(), operator)?;
+                let substr = indices.into_iter().map(|i| chars[i]).collect::<String>();
+                return Ok(self.allocate(Object::String(substr.into())));
+            }
+            let range = index_range(&index, index_end.as_ref(), s.chars().count(), operator)?;
+            // return the substr
+            let substr = s
+                .chars()
+                .skip(range.start)
+                .take(range.len())
+                .collect::<String>();
+            return Ok(self.allocate(Object::String(substr.into())));
         }
+        // check if left is a List
+        if let Object::List(list) = left {
+            let len = list.borrow().inner.len();
+            if step != 1 {
+                let end = index_end.as_ref().unwrap_or(&Object::Nil);
+                let indices = stepped_indices(&index, end, step, len, operator)?;
+                let items: Vec<Object> = indices
+                    .into_iter()
+                    .map(|i| list.borrow().get(i).clone())
+                    .collect();
+                return Ok(self.allocate(Object::List(Rc::new(RefCell::new(items.into())))));
+            }
+            let range = index_range(&index, index_end.as_ref(), len, operator)?;
+            if index_end.is_none() {
+                // return the nth element
+                return Ok(list.borrow().get(range.start).clone());
+            }
+
+            // return the sublist
+            let sublist = list.borrow().slice(range.start, range.end);
+            return Ok(self.allocate(Object::List(Rc::new(RefCell::new(sublist)))));
+        }
+
+        Err(Error::coded("E0421", &[&left]).at(operator))
+        // check if left if an Array TOOD
     }
-    fn visit_index_set_expr(&mut self, expr: &Expr) -> Result<Object, Error> {
-        match expr {
-            Expr::IndexSet {
-                object,
-                index,
-                index_end,
-                value,
-                operator,
-            } => {
-                let object = self.evaluate(object)?;
-                match object {
-                    Object::List(list) => {
-                        let index = self.evaluate(index)?;
-                        let index_end = match index_end {
-                            Some(index_end) => Some(self.evaluate(index_end)?),
-                            None => None,
-                        };
-                        let start: i64;
-                        // check if right is a Number
-                        if let Some(n) = Interpreter::check_integer(&index) {
-                            start = n;
-                        } else {
-                            return Err(Error {
-                                message: format!("Expected integer got {}", index),
-                                error_type: ErrorType::RuntimeError(operator.clone()),
-                            });
-                        }
+    fn visit_call_expr(
+        &mut self,
+        callee: ExprId,
+        paren: &Token,
+        arguments: &[ExprId],
+        keywords: &[(Token, ExprId)],
+    ) -> Result<Object, Error> {
+        trace!("visit_call_expr");
+        let callee = self.evaluate(callee)?;
+        // natives don't know where they were called from
+        let at_paren = |err: Error| match err.error_type {
+            ErrorType::NativeError => err.at(paren),
+            _ => err,
+        };
 
-                        let mut end: i64 = start + 1;
-                        let mut is_slice: bool = false;
-                        if let Some(index_end) = index_end {
-                            is_slice = true;
-                            if let Some(n) = Interpreter::check_integer(&index_end) {
-                                end = n;
-                            } else {
-                                return Err(Error {
-                                    message: format!("Expected integer got {}", index_end),
-                                    error_type: ErrorType::RuntimeError(operator.clone()),
-                                });
-                            }
-                        }
+        // most calls have a handful of arguments, keep them on the stack
+        let mut args: SmallVec<[Object; 4]> = SmallVec::with_capacity(arguments.len());
+        for arg in arguments {
+            args.push(self.evaluate(*arg)?);
+        }
+        if !keywords.is_empty() {
+            args = self.keyword_args(&callee, args, keywords).map_err(at_paren)?;
+        }
+        if let Some(trace) = &mut self.trace {
+            trace.call(paren.line, &callee, args.len())?;
+        }
 
-                        // check if nth is in range
-                        if list.borrow().inner.len() <= start as usize || start < 0 {
-                            return Err(Error {
-                                message: format!("Index out of range: {}", start),
-                                error_type: ErrorType::RuntimeError(operator.clone()),
-                            });
+        self.call_value(&callee, &args).map_err(at_paren)
+    }
+    fn visit_get_expr(
+        &mut self,
+        object: ExprId,
+        name: &Token,
+        cache: &MethodCache,
+        optional: bool,
+    ) -> Result<Object, Error> {
+        let object = self.evaluate(object)?;
+        if optional && matches!(object, Object::Nil) {
+            return Ok(Object::Nil);
+        }
+        if let Object::Instance(ref instance) = object {
+            // fields shadow methods, only the method lookup is cached
+            let field = instance.borrow().field(&name.lexeme).cloned();
+            if let Some(field) = field {
+                return Ok(field);
+            }
+            let class = instance.borrow().class().clone();
+            if let Some(method) = cache.get_method(&class, &name.lexeme) {
+                Ok(self.allocate(Object::Callable(Rc::new(method.bind(object.clone())))))
+            } else {
+                Err(Error::coded("E0404", &[&name.lexeme]).at(name))
+            }
+        } else if let Some(method) = self.builtins.get(&object, &name.lexeme) {
+            let method = method.bind(object.clone());
+            Ok(self.allocate(Object::Callable(Rc::new(method))))
+        } else if self.builtins.has_methods(&object) {
+            Err(Error::coded("E0442", &[&object.type_name(), &name.lexeme]).at(name))
+        } else {
+            Err(Error::coded("E0405", &[]).at(name))
+        }
+    }
+    fn visit_set_expr(
+        &mut self,
+        object: ExprId,
+        name: &Token,
+        value: ExprId,
+    ) -> Result<Object, Error> {
+        // object.name = value
+        let object = self.evaluate(object)?;
+        if let Object::Instance(instance) = object {
+            let value = self.evaluate(value)?;
+            instance.borrow_mut().set(&name.lexeme, &value);
+            Ok(value)
+        } else {
+            Err(Error::coded("E0406", &[]).at(name))
+        }
+    }
+    fn visit_index_set_expr(
+        &mut self,
+        object: ExprId,
+        index: ExprId,
+        index_end: Option<ExprId>,
+        index_step: Option<ExprId>,
+        value: ExprId,
+        operator: &Token,
+    ) -> Result<Object, Error> {
+        let object = self.evaluate(object)?;
+        match object {
+            Object::List(list) => {
+                let index = self.evaluate(index)?;
+                let index_end = match index_end {
+                    Some(index_end) => Some(self.evaluate(index_end)?),
+                    None => None,
+                };
+                let step = match index_step {
+                    Some(step) => slice_step(&self.evaluate(step)?, operator)?,
+                    None => 1,
+                };
+                // the value may resize the list, so check the range after it
+                let value = self.evaluate(value)?;
+                if list.borrow().is_frozen() {
+                    return Err(Error::coded("E0440", &[]).at(operator));
+                }
+                let len = list.borrow().inner.len();
+                if step != 1 {
+                    // every item the slice covers gets one item of the value
+                    let end = index_end.as_ref().unwrap_or(&Object::Nil);
+                    let indices = stepped_indices(&index, end, step, len, operator)?;
+                    let items = match &value {
+                        Object::List(items) if items.borrow().inner.len() == indices.len() => {
+                            items.borrow().inner.clone()
                         }
-                        if list.borrow().inner.len() < end as usize || end < 0 {
-                            return Err(Error {
-                                message: format!("Index out of range: {}", end),
-                                error_type: ErrorType::RuntimeError(operator.clone()),
-                            });
+                        _ => {
+                            return Err(Error::coded("E0452", &[&indices.len()]).at(operator))
                         }
+                    };
+                    for (i, item) in indices.into_iter().zip(items) {
+                        list.borrow_mut().inner[i] = item;
+                    }
+                    return Ok(value);
+                }
+                let range = index_range(&index, index_end.as_ref(), len, operator)?;
 
-                        let value = self.evaluate(value)?;
-                        if !is_slice {
-                            // return the nth element
-                            list.borrow_mut().inner[start as usize] = value.clone();
-                            return Ok(value);
-                        }
+                if index_end.is_none() {
+                    // return the nth element
+                    list.borrow_mut().inner[range.start] = value.clone();
+                    return Ok(value);
+                }
 
-                        // 修改一个连续区间
-                        if let Object::List(other) = value.clone() {
-                            list.borrow_mut().slice_change(
-                                start as usize,
-                                end as usize,
-                                &other.borrow(),
-                            );
-                            return Ok(value);
-                        } else {
-                            list.borrow_mut().slice_change_obj(
-                                start as usize,
-                                end as usize,
-                                value.clone(),
-                            );
-                        }
+                // 修改一个连续区间
+                if let Object::List(other) = value.clone() {
+                    // copy first, the list may be assigned into itself
+                    let other = other.borrow().clone();
+                    list.borrow_mut().slice_change(range.start, range.end, &other);
+                    return Ok(value);
+                } else {
+                    list.borrow_mut().slice_change_obj(range.start, range.end, value.clone());
+                }
 
-                        Ok(value)
-                    }
-                    _ => Err(Error {
-                        message: format!("Expected list got {}", object),
-                        error_type: ErrorType::RuntimeError(operator.clone()),
-                    }),
+                Ok(value)
+            }
+            Object::Map(map) => {
+                if index_end.is_some() {
+                    return Err(cannot_slice_map(operator));
+                }
+                let key = self.evaluate(index)?;
+                check_key(&key, operator)?;
+                let value = self.evaluate(value)?;
+                if map.borrow().is_frozen() {
+                    return Err(Error::coded("E0446", &[]).at(operator));
                 }
+                map.borrow_mut().insert(key, value.clone());
+                Ok(value)
             }
-            _ => unreachable!(),
+            // a string is a value, there is no place to write the result to
+            Object::String(_) => Err(Error::coded("E0441", &[]).at(operator)),
+            _ => Err(Error::coded("E0422", &[&object]).at(operator)),
         }
     }
-    fn visit_this_expr(&mut self, expr: &Expr) -> Result<Object, Error> {
-        match expr {
-            Expr::This { keyword } => Ok(self.look_up_variable(keyword)?),
-            _ => unreachable!(),
+    fn visit_this_expr(&mut self, keyword: &Token, id: ExprId) -> Result<Object, Error> {
+        self.look_up_variable(keyword, id)
+    }
+    fn visit_super_expr(
+        &mut self,
+        keyword: &Token,
+        method: &Token,
+        id: ExprId,
+    ) -> Result<Object, Error> {
+        let super_class = self.look_up_variable(keyword, id)?;
+        let object = self
+            .environment
+            .borrow()
+            .get_at(self.this_depths[&id], "this")
+            .unwrap();
+
+        let Object::Class(super_class) = super_class else {
+            unreachable!()
+        };
+        if let Some(found) = super_class.borrow().get_method(&method.lexeme) {
+            return Ok(self.allocate(Object::Callable(Rc::new(found.bind(object)))));
         }
+        // fields live on the instance, not on any class
+        let is_field = match &object {
+            Object::Instance(instance) => instance.borrow().field(&method.lexeme).is_some(),
+            _ => false,
+        };
+        let error = if is_field && !compat::jlox() {
+            Error::coded("E0439", &[&method.lexeme, &method.lexeme])
+        } else {
+            Error::coded("E0404", &[&method.lexeme])
+        };
+        Err(error.at(method))
     }
-    fn visit_super_expr(&mut self, expr: &Expr) -> Result<Object, Error> {
-        match expr {
-            Expr::Super { keyword, method } => {
-                let distance = self.locals.get(keyword);
-                let super_class = self.look_up_variable(keyword)?;
-                let object = self
-                    .environment
-                    .borrow()
-                    .get_at(*distance.unwrap() - 1, "this")
-                    .unwrap();
-
-                if let Object::Class(super_class) = super_class {
-                    if let Some(method) = super_class.borrow().get_method(&method.lexeme) {
-                        Ok(Object::Callable(method.bind(object)))
-                    } else {
-                        Err(Error {
-                            message: format!("Undefined property '{}'.", method.lexeme),
-                            error_type: ErrorType::RuntimeError(method.clone()),
-                        })
-                    }
-                } else {
-                    unreachable!()
-                }
+    fn visit_list_expr(&mut self, _keyword: &Token, elements: &[ExprId]) -> Result<Object, Error> {
+        let mut list = List::new();
+        for element in elements {
+            list.push(self.evaluate(*element)?);
+        }
+        Ok(self.allocate(Object::List(Rc::new(RefCell::new(list)))))
+    }
+    fn visit_map_expr(
+        &mut self,
+        brace: &Token,
+        keys: &[ExprId],
+        values: &[ExprId],
+    ) -> Result<Object, Error> {
+        let mut map = Map::new();
+        for (key, value) in keys.iter().zip(values) {
+            let key = self.evaluate(*key)?;
+            check_key(&key, brace)?;
+            let value = self.evaluate(*value)?;
+            map.insert(key, value);
+        }
+        Ok(self.allocate(Object::Map(Rc::new(RefCell::new(map)))))
+    }
+    fn visit_comparison_expr(
+        &mut self,
+        operands: &[ExprId],
+        operators: &[Token],
+    ) -> Result<Object, Error> {
+        let mut left = self.evaluate(operands[0])?;
+        for (operator, operand) in operators.iter().zip(&operands[1..]) {
+            let right = self.evaluate(*operand)?;
+            let result = self.binary_op(left, operator, right.clone())?;
+            // stop at the first false link, like `and` does
+            if !Interpreter::is_truthy(&result) {
+                return Ok(result);
             }
-            _ => unreachable!(),
+            left = right;
         }
+        Ok(Object::Boolean(true))
     }
-    fn visit_list_expr(&mut self, expr: &Expr) -> Result<Object, Error> {
-        match expr {
-            Expr::List { elements, .. } => {
-                let mut list = List::new();
-                for element in elements {
-                    list.push(self.evaluate(element)?);
-                }
-                Ok(Object::List(Rc::new(RefCell::new(list))))
+    fn visit_if_expr(
+        &mut self,
+        keyword: &Token,
+        condition: ExprId,
+        then_branch: ExprId,
+        else_branch: Option<ExprId>,
+    ) -> Result<Object, Error> {
+        if self.condition(condition, keyword)? {
+            self.evaluate(then_branch)
+        } else if let Some(else_branch) = else_branch {
+            self.evaluate(else_branch)
+        } else {
+            Ok(Object::Nil)
+        }
+    }
+    fn visit_block_expr(
+        &mut self,
+        statements: &[StmtId],
+        value: Option<ExprId>,
+    ) -> Result<Object, Error> {
+        let previous = self.environment.clone();
+        self.environment = Rc::new(RefCell::new(Environment::new(Some(previous.clone()))));
+        let mark = self.deferred.len();
+        let mut steps = || -> Result<Object, Error> {
+            for statement in statements {
+                self.execute(*statement)?;
             }
-            _ => unreachable!(),
+            match value {
+                Some(value) => self.evaluate(value),
+                None => Ok(Object::Nil),
+            }
+        };
+        let result = steps();
+        let result = self.run_deferred(mark, result);
+        self.environment = previous;
+        result
+    }
+    fn visit_tuple_expr(&mut self, elements: &[ExprId]) -> Result<Object, Error> {
+        let values = elements
+            .iter()
+            .map(|element| self.evaluate(*element))
+            .collect::<Result<Rc<[Object]>, Error>>()?;
+        Ok(self.allocate(Object::Tuple(values)))
+    }
+    fn visit_interpolation_expr(
+        &mut self,
+        token: &Token,
+        parts: &[ExprId],
+    ) -> Result<Object, Error> {
+        let mut s = String::new();
+        for part in parts {
+            let value = self.evaluate(*part)?;
+            s.push_str(&self.display_at(&value, token)?);
         }
+        Ok(self.allocate(Object::String(s.into())))
+    }
+    fn visit_const_expr(
+        &mut self,
+        _keyword: &Token,
+        value: ExprId,
+        id: ExprId,
+    ) -> Result<Object, Error> {
+        if let Some(constant) = self.constants.get(&id) {
+            return Ok(constant.clone());
+        }
+        let constant = self.evaluate(value)?;
+        freeze(&constant);
+        self.constants.insert(id, constant.clone());
+        Ok(constant)
+    }
+    fn visit_match_expr(
+        &mut self,
+        keyword: &Token,
+        subject: ExprId,
+        arms: &[MatchArm],
+        strings: &StringArms,
+    ) -> Result<Object, Error> {
+        let value = self.evaluate(subject)?;
+        // a string only needs the arms its table points at
+        let by_string = match &value {
+            Object::String(s) => Some(strings.candidates(s)),
+            _ => None,
+        };
+        let every = match by_string {
+            Some(_) => None,
+            None => Some(0..arms.len()),
+        };
+        for index in by_string.into_iter().flatten().chain(every.into_iter().flatten()) {
+            let arm = &arms[index];
+            let previous = self.environment.clone();
+            self.environment = Rc::new(RefCell::new(Environment::new(Some(previous.clone()))));
+            let result = match self.match_pattern(&arm.pattern, &value) {
+                Ok(true) => self.evaluate(arm.body).map(Some),
+                Ok(false) => Ok(None),
+                Err(error) => Err(error),
+            };
+            self.environment = previous;
+            if let Some(result) = result? {
+                return Ok(result);
+            }
+        }
+        Err(Error::coded("E0426", &[&value]).at(keyword))
     }
 }
 
 impl stmt::Visitor<()> for Interpreter {
-    fn visit_expr_stmt(&mut self, stmt: &Stmt) -> Result<(), Error> {
-        match stmt {
-            Stmt::ExprStmt { expression } => {
-                self.evaluate(expression)?;
-            }
-            _ => unreachable!(),
-        }
+    fn visit_expr_stmt(&mut self, expression: ExprId) -> Result<(), Error> {
+        self.evaluate(expression)?;
+        Ok(())
+    }
 
+    fn visit_print_stmt(&mut self, keyword: &Token, expression: ExprId) -> Result<(), Error> {
+        let value = self.evaluate(expression)?;
+        let text = self.display_at(&value, keyword)?;
+        self.print_line(&text)?;
         Ok(())
     }
 
-    fn visit_print_stmt(&mut self, stmt: &Stmt) -> Result<(), Error> {
-        match stmt {
-            Stmt::PrintStmt { expression } => {
-                let value = self.evaluate(expression)?;
-                println!("{}", Interpreter::stringify(&value));
-            }
-            _ => unreachable!(),
+    fn visit_var_stmt(&mut self, name: &Token, initializer: Option<ExprId>) -> Result<(), Error> {
+        let value = match initializer {
+            Some(expr) => self.evaluate(expr)?,
+            None if self.uninitialized_nil => Object::Nil,
+            None => Object::Uninitialized,
+        };
+        if let Some(trace) = &mut self.trace {
+            trace.write(name.line, &name.lexeme, &value)?;
         }
-
+        self.environment.borrow_mut().define(&name.lexeme, value);
         Ok(())
     }
 
-    fn visit_var_stmt(&mut self, stmt: &Stmt) -> Result<(), Error> {
-        match stmt {
-            Stmt::VarStmt { name, initializer } => {
-                let value = match initializer {
-                    Some(expr) => self.evaluate(expr)?,
-                    None => Object::Nil,
-                };
-                self.environment.borrow_mut().define(&name.lexeme, value);
+    fn visit_destructure_stmt(
+        &mut self,
+        names: &[Token],
+        initializer: ExprId,
+    ) -> Result<(), Error> {
+        let value = self.evaluate(initializer)?;
+        let values = match &value {
+            Object::Tuple(values) => values.to_vec(),
+            Object::List(list) => list.borrow().inner.clone(),
+            _ => {
+                return Err(Error::coded("E0429", &[&value.type_name(), &names.len()]).at(&names[0]))
             }
-            _ => unreachable!(),
+        };
+        if values.len() != names.len() {
+            return Err(Error::coded("E0428", &[&names.len(), &values.len()]).at(&names[0]));
+        }
+        for (name, value) in names.iter().zip(values) {
+            if let Some(trace) = &mut self.trace {
+                trace.write(name.line, &name.lexeme, &value)?;
+            }
+            self.environment.borrow_mut().define(&name.lexeme, value);
         }
         Ok(())
     }
 
-    fn visit_block_stmt(&mut self, stmt: &Stmt) -> Result<(), Error> {
-        match stmt {
-            Stmt::BlockStmt { statements } => {
-                // create a new scope
-                let sub_env = Rc::new(RefCell::new(Environment::new(Some(
-                    self.environment.clone(),
-                ))));
+    fn visit_block_stmt(&mut self, statements: &[StmtId]) -> Result<(), Error> {
+        // create a new scope
+        let sub_env = Rc::new(RefCell::new(Environment::new(Some(
+            self.environment.clone(),
+        ))));
+
+        self.execute_block(statements, sub_env)
+    }
+
+    fn visit_if_stmt(
+        &mut self,
+        keyword: &Token,
+        condition: ExprId,
+        then_branch: StmtId,
+        else_branch: Option<StmtId>,
+    ) -> Result<(), Error> {
+        if self.condition(condition, keyword)? {
+            self.execute(then_branch)?;
+        } else if let Some(else_branch) = else_branch {
+            self.execute(else_branch)?;
+        }
+        Ok(())
+    }
 
-                self.execute_block(statements, sub_env)
+    fn visit_while_stmt(
+        &mut self,
+        keyword: &Token,
+        condition: ExprId,
+        body: StmtId,
+        increment: Option<ExprId>,
+        label: Option<&Token>,
+    ) -> Result<(), Error> {
+        // an unlabeled break or continue targets the innermost loop
+        let targets_this_loop = |target: &Option<String>| match target {
+            Some(target) => label.as_ref().map(|label| &label.lexeme) == Some(target),
+            None => true,
+        };
+        while self.condition(condition, keyword)? {
+            if let Err(err) = self.execute(body) {
+                match &err.error_type {
+                    ErrorType::Break(target) if targets_this_loop(target) => break,
+                    ErrorType::Continue(target) if targets_this_loop(target) => {}
+                    _ => return Err(err),
+                }
+            }
+            if let Some(increment) = increment {
+                self.evaluate(increment)?;
             }
-            _ => unreachable!(),
         }
+        Ok(())
     }
 
-    fn visit_if_stmt(&mut self, stmt: &Stmt) -> Result<(), Error> {
-        match stmt {
-            Stmt::IfStmt {
-                condition,
-                then_branch,
-                else_branch,
-            } => {
-                if Interpreter::is_truthy(&self.evaluate(condition)?) {
-                    self.execute(then_branch)?;
-                } else if let Some(else_branch) = else_branch {
-                    self.execute(else_branch)?;
+    fn visit_for_in_stmt(
+        &mut self,
+        keyword: &Token,
+        name: &Token,
+        iterable: ExprId,
+        body: StmtId,
+        label: Option<&Token>,
+    ) -> Result<(), Error> {
+        let at_keyword = |err: Error| match err.error_type {
+            ErrorType::NativeError => err.at(keyword),
+            _ => err,
+        };
+        let mut iterable = self.evaluate(iterable)?;
+        // an instance with iter() is iterated through what that gives
+        if let Some(iter) = instance_method(&iterable, "iter") {
+            iterable = iter.call_bound(self, iterable.clone(), &[]).map_err(at_keyword)?;
+        }
+        if let Object::Instance(_) = iterable {
+            let (Some(has_next), Some(next)) = (
+                instance_method(&iterable, "hasNext"),
+                instance_method(&iterable, "next"),
+            ) else {
+                return Err(Error::coded("E0454", &[]).at(keyword));
+            };
+            loop {
+                let more = has_next.call_bound(self, iterable.clone(), &[]).map_err(at_keyword)?;
+                if !self.check_condition(&more, keyword)? {
+                    break;
+                }
+                let element = next.call_bound(self, iterable.clone(), &[]).map_err(at_keyword)?;
+                if !self.for_in_body(name, element, body, label)? {
+                    break;
                 }
-                Ok(())
             }
-            _ => unreachable!(),
+            return Ok(());
+        }
+        // the elements as they were when the loop started, so the body
+        // can change the collection without upsetting the loop
+        let Some(elements) = stdlib::iter::elements(&iterable) else {
+            return Err(Error::coded("E0450", &[&iterable.type_name()]).at(keyword));
+        };
+        for element in elements {
+            if !self.for_in_body(name, element, body, label)? {
+                break;
+            }
         }
+        Ok(())
     }
 
-    fn visit_while_stmt(&mut self, stmt: &Stmt) -> Result<(), Error> {
-        match stmt {
-            Stmt::WhileStmt { condition, body } => {
-                while Interpreter::is_truthy(&self.evaluate(condition)?) {
-                    self.execute(body)?;
+    fn visit_func_stmt(
+        &mut self,
+        name: &Token,
+        params: &[Token],
+        defaults: &[ExprId],
+        body: &Rc<[StmtId]>,
+        doc: Option<&str>,
+        captures: &[ExprId],
+    ) -> Result<(), Error> {
+        let mut closure = self.environment.clone();
+        if !captures.is_empty() {
+            let mut copies = Environment::new(Some(closure));
+            let ast = Rc::clone(&self.ast);
+            for capture in captures {
+                if let Expr::Variable { name } = &ast[*capture] {
+                    copies.define(&name.lexeme, self.look_up_variable(name, *capture)?);
                 }
-                Ok(())
             }
-            _ => unreachable!(),
+            closure = Rc::new(RefCell::new(copies));
         }
+        let function = self.allocate(Object::Callable(Rc::new(Function::UserDefined {
+            name: name.clone(),
+            params: Rc::new(params.to_vec()),
+            defaults: Rc::new(defaults.to_vec()),
+            body: body.clone(),
+            ast: Rc::clone(&self.ast),
+            closure,
+            is_initializer: false,
+            doc: doc.map(String::from),
+        })));
+
+        self.environment.borrow_mut().define(&name.lexeme, function);
+
+        Ok(())
+    }
+    fn visit_return_stmt(&mut self, _keyword: &Token, value: Option<ExprId>) -> Result<(), Error> {
+        let value = match value {
+            Some(expr) => self.evaluate(expr)?,
+            None => Object::Nil,
+        };
+        Err(Error::control_flow(ErrorType::Return(value)))
+    }
+    fn visit_break_stmt(&mut self, _keyword: &Token, label: Option<&Token>) -> Result<(), Error> {
+        let label = label.as_ref().map(|label| label.lexeme.clone());
+        Err(Error::control_flow(ErrorType::Break(label)))
+    }
+    fn visit_assert_stmt(
+        &mut self,
+        keyword: &Token,
+        condition: ExprId,
+        message: Option<ExprId>,
+        source: &str,
+    ) -> Result<(), Error> {
+        if self.condition(condition, keyword)? {
+            return Ok(());
+        }
+        // the message is only evaluated when the assertion fails
+        let error = match message {
+            Some(message) => {
+                let message = self.evaluate(message)?;
+                Error::coded("E0424", &[&source, &self.display_at(&message, keyword)?])
+            }
+            None => Error::coded("E0425", &[&source]),
+        };
+        Err(error.at(keyword))
     }
+    fn visit_continue_stmt(
+        &mut self,
+        _keyword: &Token,
+        label: Option<&Token>,
+    ) -> Result<(), Error> {
+        let label = label.as_ref().map(|label| label.lexeme.clone());
+        Err(Error::control_flow(ErrorType::Continue(label)))
+    }
+    fn visit_class_stmt(
+        &mut self,
+        name: &Token,
+        super_class: Option<ExprId>,
+        fields: &[StmtId],
+        methods: &[StmtId],
+        doc: Option<&str>,
+    ) -> Result<(), Error> {
+        let mut super_class_ref: Option<ClassRef> = None;
+        if let Some(super_class) = super_class {
+            let super_class_obj = self.evaluate(super_class)?;
+            if let Object::Class(super_class) = super_class_obj {
+                super_class_ref = Some(super_class);
+            } else {
+                return Err(Error::coded("E0417", &[]).at(name));
+            }
+        }
 
-    fn visit_func_stmt(&mut self, stmt: &Stmt) -> Result<(), Error> {
-        match stmt {
-            Stmt::FunStmt { name, params, body } => {
-                let function = Object::Callable(Function::UserDefined {
-                    name: name.clone(),
-                    params: params.clone(),
-                    body: body.clone(),
-                    closure: self.environment.clone(),
-                    is_initializer: false,
-                });
+        // evaluated again for every instance, in the scope of the declaration
+        let ast = Rc::clone(&self.ast);
+        let field_initializers: Rc<[(std::string::String, Option<ExprId>)]> = fields
+            .iter()
+            .map(|field| match &ast[*field] {
+                Stmt::VarStmt { name, initializer } => (name.lexeme.clone(), *initializer),
+                _ => unreachable!(),
+            })
+            .collect();
+        let closure = self.environment.clone();
 
-                self.environment.borrow_mut().define(&name.lexeme, function);
+        super_class_ref.as_ref().map(|super_class| -> Option<_> {
+            let sub_env = Rc::new(RefCell::new(Environment::new(Some(
+                self.environment.clone(),
+            ))));
+            self.environment = sub_env;
 
-                Ok(())
+            self.environment
+                .borrow_mut()
+                .define("super", Object::Class(super_class.clone()));
+            Some(())
+        });
+        let mut class_methods = HashMap::new();
+        for method in methods {
+            match &ast[*method] {
+                Stmt::FunStmt {
+                    name,
+                    params,
+                    defaults,
+                    body,
+                    doc,
+                    ..
+                } => {
+                    let function = Function::UserDefined {
+                        name: name.clone(),
+                        params: Rc::new(params.clone()),
+                        defaults: Rc::new(defaults.clone()),
+                        body: body.clone(),
+                        ast: Rc::clone(&ast),
+                        closure: self.environment.clone(),
+                        is_initializer: name.lexeme == "init",
+                        doc: doc.clone(),
+                    };
+
+                    class_methods.insert(name.lexeme.clone(), function);
+                }
+                _ => unreachable!(),
             }
-            _ => unreachable!(),
         }
+
+        super_class_ref.as_ref().map(|_| -> Option<_> {
+            let previous = self
+                .environment
+                .borrow()
+                .enclosing
+                .as_ref()
+                .unwrap()
+                .clone();
+            self.environment = previous;
+            Some(())
+        });
+
+        let mut class_inner =
+            LoxClass::new(name.lexeme.clone(), class_methods, super_class_ref);
+        class_inner.doc = doc.map(String::from);
+        if !field_initializers.is_empty() {
+            class_inner.fields = field_initializers;
+            class_inner.closure = Some(closure);
+            class_inner.ast = Some(ast);
+        }
+        let class_inner = Rc::new(RefCell::new(class_inner));
+        // find init now rather than on the first instantiation
+        LoxClass::initializer(&class_inner);
+
+        let class = self.allocate(Object::Class(class_inner));
+        self.environment.borrow_mut().define(&name.lexeme, class);
+        Ok(())
     }
-    fn visit_return_stmt(&mut self, stmt: &Stmt) -> Result<(), Error> {
-        match stmt {
-            Stmt::ReturnStmt { value, .. } => {
-                let value = match value {
-                    Some(expr) => self.evaluate(expr)?,
-                    None => Object::Nil,
-                };
-                Err(Error {
-                    message: String::from("Return statement"),
-                    error_type: ErrorType::Return(value),
-                })
+
+    fn visit_defer_stmt(&mut self, _keyword: &Token, body: StmtId) -> Result<(), Error> {
+        self.deferred.push((body, self.environment.clone()));
+        Ok(())
+    }
+
+    fn visit_try_stmt(
+        &mut self,
+        _keyword: &Token,
+        body: &[StmtId],
+        catch: Option<(&Token, &[StmtId])>,
+        finally: Option<&[StmtId]>,
+    ) -> Result<(), Error> {
+        let result = match (self.visit_block_stmt(body), catch) {
+            (Err(error), Some((name, handler))) if error.is_catchable() => {
+                let caught = self.caught(error);
+                let sub_env = Rc::new(RefCell::new(Environment::new(Some(
+                    self.environment.clone(),
+                ))));
+                sub_env.borrow_mut().define(&name.lexeme, caught);
+                self.execute_block(handler, sub_env)
             }
-            _ => unreachable!(),
+            (result, _) => result,
+        };
+        // an error or return leaving the finally block wins over whatever
+        // the try and catch blocks were doing
+        if let Some(finally) = finally {
+            self.visit_block_stmt(finally)?;
         }
+        result
     }
-    fn visit_class_stmt(&mut self, stmt: &Stmt) -> Result<(), Error> {
-        match stmt {
-            Stmt::ClassStmt {
-                name,
-                methods,
-                super_class,
-            } => {
-                let mut super_class_ref: Option<ClassRef> = None;
-                if let Some(super_class) = super_class {
-                    let super_class_obj = self.evaluate(super_class)?;
-                    if let Object::Class(super_class) = super_class_obj {
-                        super_class_ref = Some(super_class);
-                    } else {
-                        return Err(Error {
-                            message: "Superclass must be a class.".to_string(),
-                            error_type: ErrorType::RuntimeError(name.clone()),
-                        });
-                    }
-                }
 
-                super_class_ref.as_ref().map(|super_class| -> Option<_> {
-                    let sub_env = Rc::new(RefCell::new(Environment::new(Some(
-                        self.environment.clone(),
-                    ))));
-                    self.environment = sub_env;
-
-                    self.environment
-                        .borrow_mut()
-                        .define("super", Object::Class(super_class.clone()));
-                    Some(())
-                });
-                let mut class_methods = HashMap::new();
-                for method in methods {
-                    match method {
-                        Stmt::FunStmt { name, params, body } => {
-                            let function = Function::UserDefined {
-                                name: name.clone(),
-                                params: params.clone(),
-                                body: body.clone(),
-                                closure: self.environment.clone(),
-                                is_initializer: name.lexeme == "init",
-                            };
-
-                            class_methods.insert(name.lexeme.clone(), function);
-                        }
-                        _ => unreachable!(),
-                    }
-                }
+    fn visit_throw_stmt(&mut self, keyword: &Token, value: ExprId) -> Result<(), Error> {
+        let value = self.evaluate(value)?;
+        let error = Error::coded("E0449", &[&Interpreter::stringify(&value)]);
+        Err(error.with_type(ErrorType::Thrown(value, Box::new(keyword.clone()))))
+    }
+}
 
-                super_class_ref.as_ref().map(|_| -> Option<_> {
-                    let previous = self
-                        .environment
-                        .borrow()
-                        .enclosing
-                        .as_ref()
-                        .unwrap()
-                        .clone();
-                    self.environment = previous;
-                    Some(())
-                });
-
-                let class_inner = Rc::new(RefCell::new(LoxClass::new(
-                    name.lexeme.clone(),
-                    class_methods,
-                    super_class_ref,
-                )));
-
-                let class = Object::Class(class_inner);
-                self.environment.borrow_mut().define(&name.lexeme, class);
-                Ok(())
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// run `source` and return the value of the global `result`
+    fn run(interpreter: &mut Interpreter, source: &str) -> Result<Object, Error> {
+        let mut scanner = Scanner::new(source);
+        scanner.scan_tokens();
+        let mut parser = Parser::new(&scanner.tokens);
+        let stmts = parser.parse()?;
+        let ast = Rc::new(parser.into_ast());
+        Resolver::new(interpreter, &ast).resolve_stmts(&stmts)?;
+        interpreter.set_ast(ast);
+        interpreter.interpret(&stmts)?;
+        let result = interpreter.globals.borrow().get("result");
+        Ok(result.unwrap_or(Object::Nil))
+    }
+
+    #[test]
+    fn test_global_slots() {
+        let mut interpreter = Interpreter::new();
+        let source = "fun fib(n) { if (n < 2) return n; return fib(n - 1) + fib(n - 2); }
+                      var result = fib(10);";
+        let result = run(&mut interpreter, source).unwrap();
+        assert_eq!(result.to_string(), "55");
+        let slot = interpreter.globals.borrow().slot("fib").unwrap();
+        // both recursive calls and the call at the top level
+        let uses = interpreter
+            .locals
+            .values()
+            .filter(|binding| **binding == Binding::Global(slot))
+            .count();
+        assert_eq!(uses, 3);
+
+        // redefining a global keeps its slot, late globals still work
+        let source = "fun fib(n) { return n; } fun late() { return later; } var later = 3;
+                      var result = fib(1) + late();";
+        let result = run(&mut interpreter, source).unwrap();
+        assert_eq!(result.to_string(), "4");
+        assert_eq!(interpreter.globals.borrow().slot("fib"), Some(slot));
+    }
+
+    #[test]
+    fn test_reads_share_values() {
+        let mut interpreter = Interpreter::new();
+        run(&mut interpreter, "var s = \"text\"; var t = s; fun f() {} var g = f;").unwrap();
+        let globals = interpreter.globals.borrow();
+        match (globals.get("s"), globals.get("t")) {
+            (Some(Object::String(s)), Some(Object::String(t))) => assert!(Rc::ptr_eq(&s, &t)),
+            _ => panic!("expected two strings"),
+        }
+        match (globals.get("f"), globals.get("g")) {
+            (Some(Object::Callable(f)), Some(Object::Callable(g))) => assert!(Rc::ptr_eq(&f, &g)),
+            _ => panic!("expected two functions"),
+        }
+    }
+
+    #[test]
+    fn test_method_cache_invalidation() {
+        let mut interpreter = Interpreter::new();
+        let source = "class A { f() { return 1; } } class B < A {}
+                      var b = B(); fun call() { return b.f(); }
+                      var result = call() + call();";
+        assert_eq!(run(&mut interpreter, source).unwrap().to_string(), "2");
+
+        // replacing a superclass method is seen through the cached site
+        let class = match interpreter.globals.borrow().get("A") {
+            Some(Object::Class(class)) => class,
+            _ => panic!("expected a class"),
+        };
+        class.borrow_mut().define_method(
+            "f",
+            Function::NativeMethod {
+                name: "f".to_string(),
+                arity: 0,
+                body: Box::new(|_, _, _| Ok(Object::Number(NumberType::Integer(5)))),
+                this: None,
+            },
+        );
+        let result = run(&mut interpreter, "var result = call();").unwrap();
+        assert_eq!(result.to_string(), "5");
+
+        // a field still shadows the cached method
+        let result = run(&mut interpreter, "b.f = 7; var result = b.f;").unwrap();
+        assert_eq!(result.to_string(), "7");
+    }
+
+    #[test]
+    fn test_cached_initializer() {
+        let mut interpreter = Interpreter::new();
+        let source = "class A { init(x) { this.x = x; } } class B < A {}
+                      var b = B(3); var result = b.x + b.init(4).x;";
+        assert_eq!(run(&mut interpreter, source).unwrap().to_string(), "7");
+
+        let class = match interpreter.globals.borrow().get("A") {
+            Some(Object::Class(class)) => class,
+            _ => panic!("expected a class"),
+        };
+        class.borrow_mut().define_method(
+            "init",
+            Function::NativeMethod {
+                name: "init".to_string(),
+                arity: 0,
+                body: Box::new(|_, _, _| Ok(Object::Nil)),
+                this: None,
+            },
+        );
+        let error = run(&mut interpreter, "B(1);").unwrap_err();
+        assert_eq!(error.message(), "Expected 0 arguments but got 1.");
+    }
+
+    #[test]
+    fn test_deep_nesting() {
+        // far deeper than a test thread's stack could take one frame per level
+        let depth = 2000;
+        let mut interpreter = Interpreter::new();
+        let source = format!("var result = {}1{};", "(".repeat(depth), ")".repeat(depth));
+        assert_eq!(run(&mut interpreter, &source).unwrap().to_string(), "1");
+        let source = format!("{}result = 2;{}", "{".repeat(depth), "}".repeat(depth));
+        assert_eq!(run(&mut interpreter, &source).unwrap().to_string(), "2");
+        let source = format!("result = 0{};", " + 1".repeat(depth));
+        assert_eq!(run(&mut interpreter, &source).unwrap().to_string(), "2000");
+    }
+
+    /// a writer the test keeps a handle to after giving it away
+    struct Capture(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Capture {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_captured_output() {
+        let buffer = Rc::new(RefCell::new(Vec::new()));
+        let mut interpreter = Interpreter::new();
+        interpreter.set_output(Box::new(Capture(buffer.clone())));
+        run(&mut interpreter, "print 1 + 2; print \"two\"; help(nil);").unwrap();
+        assert_eq!(
+            String::from_utf8(buffer.borrow().clone()).unwrap(),
+            "3\ntwo\nNo documentation for nil.\n"
+        );
+    }
+
+    #[test]
+    fn test_print_natives() {
+        let out = Rc::new(RefCell::new(Vec::new()));
+        let err = Rc::new(RefCell::new(Vec::new()));
+        let mut interpreter = Interpreter::new();
+        interpreter.set_output(Box::new(Capture(out.clone())));
+        interpreter.set_error_output(Box::new(Capture(err.clone())));
+        run(&mut interpreter, "write(1); write(2); println(3); flush(); eprint(\"oops\"); map(list(4), print);").unwrap();
+        assert_eq!(String::from_utf8(out.borrow().clone()).unwrap(), "123\n4\n");
+        assert_eq!(String::from_utf8(err.borrow().clone()).unwrap(), "oops\n");
+    }
+
+    #[test]
+    fn test_input() {
+        let out = Rc::new(RefCell::new(Vec::new()));
+        let mut interpreter = Interpreter::new();
+        interpreter.set_output(Box::new(Capture(out.clone())));
+        interpreter.set_input(Box::new(io::Cursor::new("ada\r\nlovelace")));
+        run(&mut interpreter, "print input(\"name? \"); print input(); print input();").unwrap();
+        assert_eq!(
+            String::from_utf8(out.borrow().clone()).unwrap(),
+            "name? ada\nlovelace\nnil\n"
+        );
+    }
+
+    #[test]
+    fn test_trace_exec() {
+        let buffer = Rc::new(RefCell::new(Vec::new()));
+        let mut interpreter = Interpreter::new();
+        interpreter.set_output(Box::new(io::sink()));
+        interpreter.set_trace(Some(Box::new(Capture(buffer.clone()))));
+        let source = "fun f(a) {\n  return a;\n}\nvar x = f(\"a\\b\");\nprint 1;";
+        run(&mut interpreter, source).unwrap();
+        let trace = String::from_utf8(buffer.borrow().clone()).unwrap();
+        assert_eq!(
+            trace.lines().collect::<Vec<_>>(),
+            [
+                r#"{"event":"stmt","line":1,"kind":"fun"}"#,
+                r#"{"event":"stmt","line":4,"kind":"var"}"#,
+                r#"{"event":"call","line":4,"callee":"f","args":1}"#,
+                r#"{"event":"stmt","line":2,"kind":"return"}"#,
+                r#"{"event":"write","line":4,"name":"x","value":"a\\b"}"#,
+                r#"{"event":"stmt","line":5,"kind":"print"}"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_trace_desugared_for() {
+        let buffer = Rc::new(RefCell::new(Vec::new()));
+        let mut interpreter = Interpreter::new();
+        interpreter.set_output(Box::new(io::sink()));
+        interpreter.set_trace(Some(Box::new(Capture(buffer.clone()))));
+        let source = "for (\n  var i = 0;\n  i < 1;\n  i = i + 1\n)\n  print i;";
+        run(&mut interpreter, source).unwrap();
+        let trace = String::from_utf8(buffer.borrow().clone()).unwrap();
+        let stmts: Vec<&str> = trace.lines().filter(|line| line.contains("stmt")).collect();
+        // the loop is reported at the `for`, not at its initializer
+        assert_eq!(
+            stmts,
+            [
+                r#"{"event":"stmt","line":1,"kind":"for"}"#,
+                r#"{"event":"stmt","line":2,"kind":"var"}"#,
+                r#"{"event":"stmt","line":1,"kind":"while"}"#,
+                r#"{"event":"stmt","line":6,"kind":"print"}"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_stats() {
+        let mut interpreter = Interpreter::new();
+        let source = "fun f(n) { if (n > 0) f(n - 1); }
+                      f(3);
+                      var l = list(1, 2) + list(3);
+                      var result = stats().maxCallDepth;";
+        let result = run(&mut interpreter, source).unwrap();
+        assert_eq!(result.to_string(), "4");
+        let stats = interpreter.stats();
+        // f's four calls and stats()
+        assert_eq!(stats.calls, 5);
+        assert_eq!(stats.max_call_depth, 4);
+        // f, both list literals, their sum and the stats instance
+        assert_eq!(stats.allocations, 5);
+        // 4 at the top level, an if per call and the 3 recursive calls
+        assert_eq!(stats.statements, 11);
+    }
+
+    #[test]
+    fn test_panic_becomes_error() {
+        let mut interpreter = Interpreter::new();
+        let boom = stdlib::native("boom", 0, |_, _| panic!("boom"));
+        interpreter.globals.borrow_mut().define("boom", boom);
+        let source = "fun f() {\n  boom();\n}\n{ f(); }";
+        let error = run(&mut interpreter, source).unwrap_err();
+        assert!(error.message().contains("boom"), "{}", error.message());
+        assert!(error.message().contains("please report it"));
+        match error.error_type {
+            ErrorType::RuntimeError(token) => {
+                assert_eq!((token.line, token.lexeme.as_str()), (2, "boom"))
             }
-            _ => unreachable!(),
+            _ => panic!("expected a runtime error"),
+        }
+        // the interpreter is back at the top level and keeps working
+        let result = run(&mut interpreter, "var result = 1 + 2;").unwrap();
+        assert_eq!(result.to_string(), "3");
+    }
+
+    #[test]
+    fn test_strict_conditions() {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_strict_conditions(true);
+        let failing = [
+            "if (1) {}",
+            "var x; if (x = 1) {}",
+            "while (nil) {}",
+            "for (;\"yes\";) {}",
+            "var result = 1 and true;",
+            "var result = nil or true;",
+            "var result = if (0) { 1 };",
+            "assert 1;",
+        ];
+        for source in failing {
+            let error = run(&mut interpreter, source).unwrap_err();
+            assert_eq!(error.code, "E0414", "{}", source);
+        }
+        let result = run(&mut interpreter, "var result = false or !nil;").unwrap();
+        assert!(result.equals(&Object::Boolean(true)));
+
+        // classic truthiness by default
+        let mut interpreter = Interpreter::new();
+        let result = run(&mut interpreter, "var result = if (1) { nil or 2 };").unwrap();
+        assert_eq!(result.to_string(), "2");
+    }
+
+    #[test]
+    fn test_uninitialized_nil() {
+        let source = "var x; var result = x;";
+        let mut interpreter = Interpreter::new();
+        let error = run(&mut interpreter, source).unwrap_err();
+        assert_eq!(error.message(), "Variable 'x' used before being assigned.");
+
+        let mut interpreter = Interpreter::new();
+        interpreter.set_uninitialized_nil(true);
+        assert!(run(&mut interpreter, source).unwrap().equals(&Object::Nil));
+    }
+
+    #[test]
+    fn test_string_coercion() {
+        let source = "var result = \"score: \" + 42 + \", \" + 1.5 + \" \" + nil + \" \" + true;";
+        let mut interpreter = Interpreter::new();
+        assert!(run(&mut interpreter, source).is_err());
+
+        let mut interpreter = Interpreter::new();
+        interpreter.set_string_coercion(true);
+        let result = run(&mut interpreter, source).unwrap();
+        assert_eq!(result.to_string(), "score: 42, 1.5 nil true");
+        let result = run(&mut interpreter, "var result = 1 + \"2\";").unwrap();
+        assert_eq!(result.to_string(), "12");
+        // numbers still add up
+        let result = run(&mut interpreter, "var result = 1 + 2;").unwrap();
+        assert_eq!(result.to_string(), "3");
+    }
+
+    #[test]
+    fn test_binary_logical_short_circuit() {
+        let mut interpreter = Interpreter::new();
+        let mut ast = Ast::new();
+        // the right operand would fail if it was evaluated
+        let undefined = ast.variable("undefined");
+        let cases = [
+            (TokenType::And, "and", false),
+            (TokenType::Or, "or", true),
+        ];
+        let mut exprs = Vec::new();
+        for (token_type, lexeme, left) in cases {
+            let literal = ast.boolean(left);
+            let expr = ast.push_expr(Expr::Binary {
+                left: literal,
+                operator: Token::new(lexeme, token_type, 1, 1),
+                right: undefined,
+            });
+            exprs.push((expr, left));
+        }
+        interpreter.set_ast(Rc::new(ast));
+        for (expr, left) in exprs {
+            let value = interpreter.evaluate(expr).unwrap();
+            assert!(value.equals(&Object::Boolean(left)));
         }
     }
 }
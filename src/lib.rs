@@ -1,38 +1,72 @@
+
+pub mod ast;
+pub mod bench;
+pub mod bindable;
+pub mod builder;
+pub mod bundle;
+pub mod clock;
+pub mod compat;
 pub mod env;
+pub mod emit;
 pub mod error;
+pub mod explain;
 pub mod expression;
 pub mod function;
+pub mod heap;
+pub mod incremental;
 pub mod interpreter;
+pub mod limits;
 pub mod list;
 pub mod logger;
 pub mod loxclass;
 pub mod loxer;
+pub mod map;
 pub mod object;
 pub mod parser;
+pub mod pattern;
+pub mod pretty;
+pub mod program;
+pub mod render;
 pub mod resolver;
 pub mod scanner;
 pub mod statement;
+pub mod stdlib;
 pub mod token;
+pub mod trace;
 pub mod utils;
 
+pub use ast::{Ast, ExprId, StmtId};
+pub use bindable::*;
+pub use clock::{Clock, FixedClock, SystemClock};
+pub use emit::{expr_to_source, to_source};
 pub use env::*;
 pub use error::*;
 pub use expression::*;
 pub use function::*;
+pub use heap::{Heap, MemoryStats};
+pub use incremental::Document;
 pub use interpreter::*;
+pub use limits::Limits;
 pub use list::*;
 pub use logger::*;
 pub use loxclass::*;
 pub use loxer::Loxer;
+pub use map::Map;
 pub use object::*;
 pub use parser::*;
+pub use pattern::{MatchArm, Pattern, StringArms};
+pub use pretty::PrettyPrinter;
+pub use program::{compile, compile_line, Program};
+pub use render::{Diagnostic, ErrorRenderer};
 pub use resolver::*;
 pub use scanner::*;
 pub use statement::*;
+pub use stdlib::{BuiltinMethods, NativeClosureFn, NativeFn, NativeMethodFn, Store, StoreRef};
 pub use token::{Literal, Token, TokenType};
+pub use trace::Trace;
 pub use utils::*;
 
-pub use log::{debug, info, trace};
+pub use tracing::{debug, info, info_span, trace, trace_span};
 
 #[macro_use]
 extern crate enum_display_derive;
@@ -0,0 +1,38 @@
+/// limits on what the scanner and parser accept, so a script from
+/// somewhere untrusted can't run the host out of memory or stack before
+/// fuel ever gets a say. like compat they are kept per thread, every
+/// scanner and parser on the thread follows them
+use std::cell::Cell;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limits {
+    /// characters in one string literal, or one piece of an
+    /// interpolated one
+    pub max_string_length: Option<usize>,
+    /// elements of a list literal or entries of a map literal
+    pub max_list_elements: usize,
+    /// statements and expressions inside one another
+    pub max_nesting_depth: Option<usize>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_string_length: None,
+            max_list_elements: 255,
+            max_nesting_depth: None,
+        }
+    }
+}
+
+thread_local! {
+    static LIMITS: Cell<Limits> = Cell::new(Limits::default());
+}
+
+pub fn set_limits(limits: Limits) {
+    LIMITS.with(|current| current.set(limits));
+}
+
+pub fn limits() -> Limits {
+    LIMITS.with(|current| current.get())
+}
@@ -6,11 +6,21 @@ use crate::Object;
 #[derive(Debug, Clone)]
 pub struct List {
     pub inner: Vec<Object>,
+    /// a frozen literal's list, assigning into it is an error
+    frozen: bool,
 }
 
 impl List {
     pub fn new() -> Self {
-        Self { inner: vec![] }
+        Self::from(vec![])
+    }
+
+    pub fn freeze(&mut self) {
+        self.frozen = true;
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        self.frozen
     }
 
     pub fn push(&mut self, obj: Object) {
@@ -22,9 +32,7 @@ impl List {
     }
 
     pub fn slice(&self, start: usize, end: usize) -> Self {
-        Self {
-            inner: self.inner[start..end].to_vec(),
-        }
+        Self::from(self.inner[start..end].to_vec())
     }
 
     pub fn slice_change(&mut self, start: usize, end: usize, new: &Self) {
@@ -36,15 +44,24 @@ impl List {
     }
 
     pub fn add(&self, other: &Self) -> Self {
-        let mut new_list = self.clone();
-        new_list.inner.extend(other.inner.clone());
-        new_list
+        let mut inner = self.inner.clone();
+        inner.extend(other.inner.iter().cloned());
+        Self::from(inner)
+    }
+}
+
+impl Default for List {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 impl From<Vec<Object>> for List {
     fn from(v: Vec<Object>) -> Self {
-        Self { inner: v }
+        Self {
+            inner: v,
+            frozen: false,
+        }
     }
 }
 
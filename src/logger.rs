@@ -1,13 +1,18 @@
-pub fn set_logger() {
-    if std::env::var("RUST_LOG").is_err() {
-        std::env::set_var("RUST_LOG", "error");
-    }
-    // env_logger::init();
-    pretty_env_logger::init();
-    // log show
-    // log::trace!("test_trace");
-    // log::debug!("test_debug");
-    // log::info!("test_info");
-    // log::warn!("test_warn");
-    // log::error!("test_error");
+use tracing_subscriber::EnvFilter;
+
+/// log the interpreter's phases and calls to stderr. `level` is a
+/// RUST_LOG style filter, like "debug" or "lox::interpreter=trace",
+/// without one RUST_LOG is used and only errors are shown when it isn't
+/// set either. the environment is left as it is, and a program that
+/// already has a subscriber keeps it
+pub fn set_logger(level: Option<&str>) -> Result<(), String> {
+    let filter = match level {
+        Some(level) => EnvFilter::try_new(level).map_err(|err| err.to_string())?,
+        None => EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("error")),
+    };
+    let _ = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr)
+        .try_init();
+    Ok(())
 }
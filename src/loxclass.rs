@@ -1,23 +1,70 @@
+use crate::Ast;
+use crate::EnvironmentRef;
+use crate::ExprId;
 use crate::Function;
 use crate::Object;
 
 use std::collections::HashMap;
 use std::fmt::Display;
-use std::rc::Rc;
-use std::cell::RefCell;
+use std::rc::{Rc, Weak};
+use std::cell::{Cell, RefCell};
 
 pub type ClassRef = Rc<RefCell<LoxClass>>;
 
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct LoxClass {
     name: String,
-    pub methods: HashMap<String, Function>,
+    methods: HashMap<String, Function>,
     pub super_class: Option<ClassRef>,
+    /// the `///` comment written above the declaration
+    pub doc: Option<String>,
+    /// the fields declared in the class body with their initializers,
+    /// run for every new instance before `init`
+    pub fields: Rc<[(String, Option<ExprId>)]>,
+    /// where the class was declared, the field initializers run in it
+    pub closure: Option<EnvironmentRef>,
+    /// the tree the field initializers are in
+    pub ast: Option<Rc<Ast>>,
+    /// `init`, looked up once instead of on every instantiation
+    init: MethodCache,
 }
 
 impl LoxClass {
     pub fn new(name: String, methods: HashMap<String, Function>, super_class: Option<ClassRef>) -> Self {
-        Self { name, methods, super_class }
+        Self { name, methods, super_class, doc: None,
+            fields: Rc::new([]), closure: None, ast: None, init: MethodCache::default() }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn methods(&self) -> impl Iterator<Item = &Function> {
+        self.methods.values()
+    }
+
+    /// the method that runs when the class is called, primed when the
+    /// class is built so instantiation never searches for it
+    pub fn initializer(class: &ClassRef) -> Option<Rc<Function>> {
+        class.borrow().init.get_method(class, "init")
+    }
+
+    /// whether `class` is `ancestor` or inherits from it
+    pub fn is_subclass(class: &ClassRef, ancestor: &ClassRef) -> bool {
+        let mut current = Some(class.clone());
+        while let Some(candidate) = current {
+            if Rc::ptr_eq(&candidate, ancestor) {
+                return true;
+            }
+            current = candidate.borrow().super_class.clone();
+        }
+        false
+    }
+
+    /// add or replace a method, every method cache has to look again
+    pub fn define_method(&mut self, name: &str, method: Function) {
+        self.methods.insert(name.to_string(), method);
+        GENERATION.with(|generation| generation.set(generation.get() + 1));
     }
 
     pub fn get_method(&self, name: &str) -> Option<Function> {
@@ -27,7 +74,7 @@ impl LoxClass {
     }
 
     pub fn arity(&self) -> usize {
-        if let Some(initializer) = self.methods.get("init") {
+        if let Some(initializer) = self.get_method("init") {
             initializer.arity()
         } else {
             0
@@ -35,6 +82,56 @@ impl LoxClass {
     }
 }
 
+thread_local! {
+    /// bumped whenever any class changes its methods, a subclass sees
+    /// the methods of its superclasses so one counter covers them all
+    static GENERATION: Cell<u64> = const { Cell::new(0) };
+}
+
+/// remembers which method a property access found for the last class
+/// it saw, so the next access on that class skips the superclass walk
+#[derive(Debug, Clone, Default)]
+pub struct MethodCache(RefCell<Option<CachedMethod>>);
+
+#[derive(Debug, Clone)]
+struct CachedMethod {
+    /// weak so the cache doesn't keep the class alive, it still keeps
+    /// the allocation so the pointer can't be reused by another class
+    class: Weak<RefCell<LoxClass>>,
+    generation: u64,
+    method: Option<Rc<Function>>,
+}
+
+impl MethodCache {
+    pub fn get_method(&self, class: &ClassRef, name: &str) -> Option<Rc<Function>> {
+        let generation = GENERATION.with(|generation| generation.get());
+        if let Some(cached) = self.0.borrow().as_ref() {
+            let same_class = Weak::as_ptr(&cached.class) == Rc::as_ptr(class);
+            if same_class && cached.generation == generation {
+                return cached.method.clone();
+            }
+        }
+        let method = class.borrow().get_method(name).map(Rc::new);
+        *self.0.borrow_mut() = Some(CachedMethod {
+            class: Rc::downgrade(class),
+            generation,
+            method: method.clone(),
+        });
+        method
+    }
+}
+
+impl std::fmt::Debug for LoxClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LoxClass")
+            .field("name", &self.name)
+            .field("methods", &self.methods)
+            .field("super_class", &self.super_class)
+            .field("fields", &self.fields)
+            .finish_non_exhaustive()
+    }
+}
+
 impl Display for LoxClass {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "<class {}>", self.name)
@@ -49,11 +146,10 @@ pub struct LoxInstance {
 }
 
 impl LoxInstance {
+    /// an instance with no fields yet, calling the class sets the ones
+    /// it declares
     pub fn new(class: Rc<RefCell<LoxClass>>) -> Self {
-        Self { 
-            class ,
-            fields: HashMap::new(),
-        }
+        Self { class, fields: HashMap::new() }
     }
 
     pub fn get(&self, name: &str, instance: &Object) -> Option<Object> {
@@ -63,7 +159,7 @@ impl LoxInstance {
 
         if let Some(method) = self.class.borrow().get_method(name) {
             return Some(
-                Object::Callable(method.bind(instance.clone()))
+                Object::Callable(Rc::new(method.bind(instance.clone())))
             );
         }
 
@@ -72,6 +168,30 @@ impl LoxInstance {
     pub fn set(&mut self, name: &str, value: &Object) {
         self.fields.insert(name.to_string(), value.clone());
     }
+
+    /// read a field without looking at methods
+    pub fn field(&self, name: &str) -> Option<&Object> {
+        self.fields.get(name)
+    }
+
+    /// every field, in no particular order
+    pub fn fields(&self) -> impl Iterator<Item = (&String, &Object)> {
+        self.fields.iter()
+    }
+
+    /// remove one field, handing back its value
+    pub fn remove(&mut self, name: &str) -> Option<Object> {
+        self.fields.remove(name)
+    }
+
+    /// remove every field, handing them back
+    pub fn clear(&mut self) -> HashMap<String, Object> {
+        std::mem::take(&mut self.fields)
+    }
+
+    pub fn class(&self) -> &ClassRef {
+        &self.class
+    }
 }
 
 impl Display for LoxInstance {
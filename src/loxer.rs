@@ -1,8 +1,8 @@
-// use log::{info, debug};
 // use crate::{scanner::Scanner};
 // use crate::parser::Parser;
 use super::*;
 use std::fs;
+use std::time::Instant;
 use rustyline::error::ReadlineError;
 use rustyline::{DefaultEditor, Result};
 
@@ -10,11 +10,18 @@ use rustyline::{DefaultEditor, Result};
 pub enum MODE {
     PROMPT,
     FILE,
+    /// like FILE, but errors and `return` leave the process running so
+    /// `lox watch` can run the script again
+    WATCH,
 }
 
 pub struct Loxer {
     had_error: bool, 
     interpreter: Interpreter,
+    /// how many results the prompt has printed, `_1`, `_2`, ... name them
+    results: usize,
+    /// `--stats`, print the interpreter's counters after running a file
+    show_stats: bool,
 }
 
 impl Loxer {
@@ -22,61 +29,112 @@ impl Loxer {
         Self {
             had_error: false,
             interpreter: Interpreter::new(),
+            results: 0,
+            show_stats: false,
         }
     }
 
+    /// the interpreter, to set its options before running
+    pub fn interpreter_mut(&mut self) -> &mut Interpreter {
+        &mut self.interpreter
+    }
+
+    pub fn set_show_stats(&mut self, show: bool) {
+        self.show_stats = show;
+    }
+
     /// Execute the source code
     pub fn run (&mut self, source: &str, mode: MODE) {
         info!("Running source code: {}", source);
-        let mut scanner = Scanner::new(source);
-        scanner.scan_tokens();
-        let tokens = &scanner.tokens;
-
-        if scanner.had_error {
-            scanner.report_errors();
-            return;
-        }
-
-        let mut parser = Parser::new(tokens);
-        let stmts = parser.parse();
-
-        if let Ok(stmts) = stmts {
-            info!("Parsed expression: {}", stmts.clone().into_iter().map(|s| s.to_string()).collect::<Vec<String>>().join(" "));
-            let mut resolver = Resolver::new(&mut self.interpreter);
-            resolver.resolve_stmts(&stmts).unwrap();
-            if resolver.has_error {
-                std::process::exit(65);
+        // every run compiles on its own, so a line that fails to compile
+        // leaves no bindings behind for the lines after it
+        let compiled = if mode == MODE::PROMPT {
+            compile_line(source)
+        } else {
+            compile(source)
+        };
+        let program = match compiled {
+            Ok(program) => program,
+            Err(diagnostics) => {
+                diagnostics.iter().for_each(render::emit);
+                if mode == MODE::FILE {
+                    std::process::exit(65);
+                }
+                return;
             }
-            let res: std::result::Result<(), Error> = self.interpreter.interpret(&stmts);
-            if let Ok(()) = res {
+        };
+        let mut printer = AstPrinter::new(program.ast());
+        info!("Parsed expression: {}", program.statements().iter().map(|s| printer.print_stmt(*s).unwrap_or_default()).collect::<Vec<String>>().join(" "));
+        program.load(&mut self.interpreter);
 
-            } else {
-                let error = res.err().unwrap();
-                if let ErrorType::RuntimeError(token) = error.error_type {
-                    eprintln!("{}",error.message);
-                    eprintln!("[line {}] Error at {}", token.line, token.lexeme);
-                } else {
-                    eprintln!("{}",error.message);
+        // the prompt prints the value of a trailing expression
+        let (stmts, result) = match program.statements().split_last() {
+            Some((last, rest)) if mode == MODE::PROMPT => match program.ast()[*last] {
+                Stmt::ExprStmt { expression } => (rest, Some(expression)),
+                _ => (program.statements(), None),
+            },
+            _ => (program.statements(), None),
+        };
+        let execute = info_span!("execute").entered();
+        let res: std::result::Result<(), Error> =
+            self.interpreter.interpret(stmts).and_then(|()| {
+                if let Some(result) = result {
+                    let value = self.interpreter.evaluate(result)?;
+                    self.remember(value);
                 }
-
-                // Runtime error
-                if mode == MODE::FILE {
-                    std::process::exit(70);
+                // interpret flushed before the result was evaluated
+                self.interpreter.flush()
+            });
+        drop(execute);
+        // before any exit below, a failing program has stats too
+        if self.show_stats && mode != MODE::PROMPT {
+            eprintln!("{}", self.interpreter.stats());
+        }
+        if let Err(error) = res {
+            if let ErrorType::Return(value) = error.error_type {
+                // `return` at the top level
+                if mode == MODE::PROMPT {
+                    self.remember(value);
+                    return;
+                }
+                if mode == MODE::WATCH {
+                    return;
                 }
+                match exit_status(&value) {
+                    Ok(status) => std::process::exit(status),
+                    Err(error) => {
+                        report_runtime_error(&error);
+                        std::process::exit(70);
+                    }
+                }
+            } else {
+                report_runtime_error(&error);
             }
 
-        } else {
-            // Parse error
+            // Runtime error
             if mode == MODE::FILE {
-                std::process::exit(65);
+                std::process::exit(70);
             }
         }
-         
+    }
+
+    /// print a result in the prompt and keep it as `_` and `_<n>`
+    fn remember(&mut self, value: Object) {
+        if let Object::Nil = value {
+            return;
+        }
+        // a prompt that can't print has nowhere to report that either
+        let text = self.interpreter.pretty_printer().format(&value);
+        let _ = self.interpreter.print_line(&text).and_then(|()| self.interpreter.flush());
+        self.results += 1;
+        let mut globals = self.interpreter.globals.borrow_mut();
+        globals.define(&format!("_{}", self.results), value.clone());
+        globals.define("_", value);
     }
 
     // Run in the command line
     pub fn run_prompt(&mut self) -> Result<()>{
-        log::info!("Running in prompt mode");
+        info!("Running in prompt mode");
 
         // `()` can be used when no completer is required
         let mut rl = DefaultEditor::new()?;
@@ -90,10 +148,18 @@ impl Loxer {
 
             match readline {
                 Ok(line) => {
-                    log::debug!("Read line: {}", line);
+                    debug!("Read line: {}", line);
                     if line.is_empty() {
                         continue;
                     }
+                    if let Some(name) = line.strip_prefix(":doc") {
+                        self.print_doc(name.trim());
+                        continue;
+                    }
+                    if let Some(source) = line.strip_prefix(":time") {
+                        self.time(source.trim());
+                        continue;
+                    }
                     self.run(line.as_str(), MODE::PROMPT);
                     self.had_error = false; // Reset error flag
                 }
@@ -114,6 +180,25 @@ impl Loxer {
         Ok(())
     }
 
+    /// `:doc name` in the prompt, the same text `help(name)` prints
+    fn print_doc(&self, name: &str) {
+        match self.interpreter.globals.borrow().get(name) {
+            Some(object) => println!("{}", stdlib::help::help_text(&object)),
+            None => println!("Undefined variable '{}'.", name),
+        }
+    }
+
+    /// `:time expr` in the prompt, runs the line like any other and
+    /// then says how long it took and how many statements it executed
+    fn time(&mut self, source: &str) {
+        let statements = self.interpreter.stats().statements;
+        let start = Instant::now();
+        self.run(source, MODE::PROMPT);
+        let elapsed = start.elapsed();
+        let statements = self.interpreter.stats().statements - statements;
+        println!("time: {:.3?}, {} statements", elapsed, statements);
+    }
+
     pub fn run_file(&mut self, path: &str) {
         info!("Running file: {}", path);
         let source = fs::read_to_string(path)
@@ -122,6 +207,17 @@ impl Loxer {
     }
 }
 
+/// the process exit status for a value returned from the top level
+fn exit_status(value: &Object) -> std::result::Result<i32, Error> {
+    match value {
+        Object::Nil => Ok(0),
+        Object::Number(NumberType::Integer(status)) => {
+            i32::try_from(*status).map_err(|_| Error::coded("E0437", &[status]))
+        }
+        _ => Err(Error::coded("E0436", &[&value.type_name()])),
+    }
+}
+
 impl Default for Loxer {
     fn default() -> Self {
         Self::new()
@@ -137,9 +233,52 @@ mod test {
 
     #[test]
     fn test_run() {
-        set_logger();
+        set_logger(None).unwrap();
         info!("Running test_run())");
         let mut loxer = Loxer::new();
         loxer.run("print 1+2*(3*4 - 6 / 2);", MODE::PROMPT);
     }
+
+    #[test]
+    fn test_last_result() {
+        let mut loxer = Loxer::new();
+        loxer.run("1 + 2", MODE::PROMPT);
+        loxer.run("var a = _ * 10", MODE::PROMPT);
+        loxer.run("a + 1", MODE::PROMPT);
+        // nil isn't printed or remembered
+        loxer.run("nil", MODE::PROMPT);
+        loxer.run("var b = _1 + _2", MODE::PROMPT);
+
+        let globals = loxer.interpreter.globals.borrow();
+        let number = |n| Object::Number(NumberType::Integer(n));
+        assert!(globals.get("_").unwrap().equals(&number(31)));
+        assert!(globals.get("_1").unwrap().equals(&number(3)));
+        assert!(globals.get("_2").unwrap().equals(&number(31)));
+        assert!(globals.get("_3").is_none());
+        assert!(globals.get("b").unwrap().equals(&number(34)));
+    }
+
+    #[test]
+    fn test_failed_line_leaves_nothing() {
+        let mut loxer = Loxer::new();
+        loxer.run("fun twice(n) { return n * 2; }", MODE::PROMPT);
+        let resolved = loxer.interpreter.locals.len();
+        let slots = loxer.interpreter.globals.borrow().names().len();
+        // resolving fails, the prompt carries on without the line
+        loxer.run("var late = 1; { var a = 1; var a = twice(a); }", MODE::PROMPT);
+        assert_eq!(loxer.interpreter.locals.len(), resolved);
+        assert_eq!(loxer.interpreter.globals.borrow().names().len(), slots);
+
+        loxer.run("var result = twice(21)", MODE::PROMPT);
+        let result = loxer.interpreter.globals.borrow().get("result").unwrap();
+        assert!(result.equals(&Object::Number(NumberType::Integer(42))));
+    }
+
+    #[test]
+    fn test_exit_status() {
+        assert_eq!(exit_status(&Object::Nil).ok(), Some(0));
+        assert_eq!(exit_status(&Object::Number(NumberType::Integer(3))).ok(), Some(3));
+        assert!(exit_status(&Object::Number(NumberType::Integer(1 << 40))).is_err());
+        assert!(exit_status(&Object::String("3".into())).is_err());
+    }
 }
@@ -1,22 +1,151 @@
+use lox::bundle::Bundle;
 use lox::*;
 use std::env;
+use std::fs;
+use std::path::Path;
 
 fn main() {
-    set_logger();
+    // a built executable runs the script it carries with the options it
+    // was built with, its own arguments are left to the script
+    if let Some(bundle) = Bundle::embedded() {
+        start_logger(&bundle.options);
+        configure(&bundle.options).run(&bundle.source, loxer::MODE::FILE);
+        return;
+    }
 
-    let args: Vec<String> = env::args().collect();
-    
-    let mut lox = Loxer::new();
+    let (options, args): (Vec<String>, Vec<String>) =
+        env::args().partition(|arg| arg.starts_with("--"));
+    start_logger(&options);
+    let mut lox = configure(&options);
 
     match args.len() {
         1 => lox.run_prompt().unwrap(),
+        4 if args[1] == "build" => build(&options, &args[2], &args[3]),
+        3 if args[1] == "watch" => watch(&options, &args[2]),
+        3 if args[1] == "explain" => explain(&mut lox, &args[2]),
+        2 | 3 if args[1] == "bench" => bench(args.get(2)),
         2 => lox.run_file(&args[1]),
-        _ => {
-            eprintln!("Usage: lox [script]");
-            std::process::exit(64);
-        } 
+        3 if args[1] == "-e" => lox.run(&args[2], loxer::MODE::FILE),
+        _ => usage(),
     };
     // lox.run_prompt().unwrap();
 
     // lox.run_file(path).unwrap()
 }
+
+/// log at the level the last `--log-level=` asks for
+fn start_logger(options: &[String]) {
+    let level = options
+        .iter()
+        .rev()
+        .find_map(|option| option.strip_prefix("--log-level="));
+    if set_logger(level).is_err() {
+        usage();
+    }
+}
+
+/// a Loxer set up the way the `--` options ask
+fn configure(options: &[String]) -> Loxer {
+    let mut lox = Loxer::new();
+    for option in options {
+        match option.as_str() {
+            "--coerce-strings" => lox.interpreter_mut().set_string_coercion(true),
+            "--strict-conditions" => lox.interpreter_mut().set_strict_conditions(true),
+            "--uninitialized-nil" => lox.interpreter_mut().set_uninitialized_nil(true),
+            "--compat=jlox" => {
+                lox::compat::set_jlox(true);
+                lox.interpreter_mut().set_uninitialized_nil(true);
+            }
+            "--stats" => lox.set_show_stats(true),
+            // set up before the Loxer, see main
+            option if option.starts_with("--log-level=") => {}
+            // the trace goes to stderr so it doesn't mix with the program's output
+            "--trace-exec" => lox.interpreter_mut().set_trace(Some(Box::new(std::io::stderr()))),
+            option if option.starts_with("--precision=") => {
+                match option["--precision=".len()..].parse::<usize>() {
+                    Ok(digits) if digits as i64 <= lox::stdlib::io::MAX_PRECISION => {
+                        lox::object::set_float_precision(Some(digits))
+                    }
+                    _ => usage(),
+                }
+            }
+            _ => usage(),
+        }
+    }
+    lox
+}
+
+/// `lox watch script` runs the script on a fresh interpreter every
+/// time it is saved, until interrupted
+fn watch(options: &[String], script: &str) -> ! {
+    let mut last = None;
+    loop {
+        let modified = fs::metadata(script).and_then(|meta| meta.modified()).ok();
+        if modified.is_some() && modified != last {
+            last = modified;
+            match fs::read_to_string(script) {
+                Ok(source) => {
+                    eprintln!("--- running {} ---", script);
+                    configure(options).run(&source, loxer::MODE::WATCH);
+                }
+                Err(err) => eprintln!("Could not read {}: {}.", script, err),
+            }
+        }
+        std::thread::sleep(std::time::Duration::from_millis(200));
+    }
+}
+
+/// `lox explain script` runs the script a statement at a time and
+/// shows what each one did
+fn explain(lox: &mut Loxer, script: &str) {
+    let source = fs::read_to_string(script).unwrap_or_else(|err| {
+        eprintln!("Could not read {}: {}.", script, err);
+        std::process::exit(66);
+    });
+    let error = match lox::explain::explain(lox.interpreter_mut(), &source) {
+        Ok(()) => return,
+        Err(error) => error,
+    };
+    match error.error_type {
+        ErrorType::SyntaxError => std::process::exit(65),
+        // `return` at the top level ends the script
+        ErrorType::Return(_) => {}
+        _ => {
+            report_runtime_error(&error);
+            std::process::exit(70);
+        }
+    }
+}
+
+/// `lox build script output` writes an executable that runs the script
+fn build(options: &[String], script: &str, output: &str) {
+    let result = fs::read_to_string(script).and_then(|source| {
+        let bundle = Bundle {
+            options: options.to_vec(),
+            source,
+        };
+        bundle.write(&env::current_exe()?, Path::new(output))
+    });
+    if let Err(err) = result {
+        eprintln!("Could not build {}: {}.", output, err);
+        std::process::exit(74);
+    }
+}
+
+/// `lox bench [rounds]` times the built-in benchmark programs
+fn bench(rounds: Option<&String>) {
+    let rounds = match rounds.map(|rounds| rounds.parse::<usize>()) {
+        None => 10,
+        Some(Ok(rounds)) => rounds,
+        Some(Err(_)) => usage(),
+    };
+    if let Err(err) = lox::bench::report(rounds) {
+        eprintln!("{}", err.message());
+        std::process::exit(70);
+    }
+}
+
+fn usage() -> ! {
+    eprintln!("Usage: lox [--coerce-strings] [--strict-conditions] [--uninitialized-nil] [--trace-exec] [--stats] [--compat=jlox] [--precision=digits] [--log-level=filter] [script | -e source | bench [rounds] | build script output | watch script | explain script]");
+    std::process::exit(64);
+}
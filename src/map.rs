@@ -0,0 +1,152 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::rc::Rc;
+
+use crate::{NumberType, Object};
+
+/// map from numbers, strings, booleans and nil to any Object, kept in
+/// the order the keys were first inserted
+#[derive(Debug, Clone, Default)]
+pub struct Map {
+    entries: Vec<(Object, Object)>,
+    /// where each key's entry is in `entries`
+    index: HashMap<Key, usize>,
+    /// a frozen literal's map, assigning into it is an error
+    frozen: bool,
+}
+
+/// the hashable form of a key, an integral float is the same key as
+/// the integer, since `1 == 1.0`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Key {
+    Nil,
+    Boolean(bool),
+    Integer(i64),
+    Float(u64),
+    String(Rc<str>),
+}
+
+impl Key {
+    fn of(object: &Object) -> Option<Self> {
+        Some(match object {
+            Object::Nil => Key::Nil,
+            Object::Boolean(b) => Key::Boolean(*b),
+            Object::Number(NumberType::Integer(i)) => Key::Integer(*i),
+            Object::Number(NumberType::Float(f))
+                if f.fract() == 0.0 && f.abs() < i64::MAX as f64 =>
+            {
+                Key::Integer(*f as i64)
+            }
+            Object::Number(NumberType::Float(f)) => Key::Float(f.to_bits()),
+            Object::String(s) => Key::String(s.clone()),
+            _ => return None,
+        })
+    }
+}
+
+impl Map {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// whether `key` can be used as a key
+    pub fn can_hold(key: &Object) -> bool {
+        Key::of(key).is_some()
+    }
+
+    pub fn freeze(&mut self) {
+        self.frozen = true;
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        self.frozen
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, key: &Object) -> Option<&Object> {
+        let index = self.index.get(&Key::of(key)?)?;
+        Some(&self.entries[*index].1)
+    }
+
+    /// set the value of `key`, a key already there keeps its place.
+    /// false when the key can't be held
+    pub fn insert(&mut self, key: Object, value: Object) -> bool {
+        let Some(hashed) = Key::of(&key) else {
+            return false;
+        };
+        match self.index.get(&hashed) {
+            Some(index) => self.entries[*index].1 = value,
+            None => {
+                self.index.insert(hashed, self.entries.len());
+                self.entries.push((key, value));
+            }
+        }
+        true
+    }
+
+    /// take `key` out, returning its value
+    pub fn remove(&mut self, key: &Object) -> Option<Object> {
+        let index = self.index.remove(&Key::of(key)?)?;
+        let (_, value) = self.entries.remove(index);
+        for later in self.index.values_mut() {
+            if *later > index {
+                *later -= 1;
+            }
+        }
+        Some(value)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Object, &Object)> {
+        self.entries.iter().map(|(key, value)| (key, value))
+    }
+
+    /// same keys holding equal values, in any order
+    pub fn equals(&self, other: &Self) -> bool {
+        self.len() == other.len()
+            && self
+                .iter()
+                .all(|(key, value)| other.get(key).is_some_and(|other| value.equals(other)))
+    }
+}
+
+impl Display for Map {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = self
+            .iter()
+            .map(|(key, value)| format!("{}: {}", key, value))
+            .collect::<Vec<String>>()
+            .join(", ");
+        write!(f, "{{{}}}", s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn int(i: i64) -> Object {
+        Object::Number(NumberType::Integer(i))
+    }
+
+    #[test]
+    fn test_insert_and_remove() {
+        let mut map = Map::new();
+        assert!(map.insert(Object::String("a".into()), int(1)));
+        assert!(map.insert(int(2), int(2)));
+        assert!(map.insert(Object::String("a".into()), int(3)));
+        assert!(!map.insert(Object::List(Default::default()), int(4)));
+        assert_eq!(map.to_string(), "{a: 3, 2: 2}");
+        // an integral float finds the integer key
+        assert!(map.get(&Object::Number(NumberType::Float(2.0))).is_some());
+        assert!(map.remove(&Object::String("a".into())).is_some());
+        assert_eq!(map.to_string(), "{2: 2}");
+        assert!(map.get(&int(2)).is_some());
+    }
+}
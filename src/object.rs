@@ -1,29 +1,76 @@
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::fmt::Display;
 use std::rc::Rc;
+use std::thread::LocalKey;
 
+use crate::compat;
 use crate::Error;
 use crate::ErrorType;
 use crate::Function;
 use crate::List;
 use crate::LoxClass;
 use crate::LoxInstance;
+use crate::Map;
+use crate::StoreRef;
 use crate::Token;
 use crate::TokenType;
 type ClassRef = Rc<RefCell<LoxClass>>;
-type InstanceRef = Rc<RefCell<LoxInstance>>;
+pub type InstanceRef = Rc<RefCell<LoxInstance>>;
 type ListRef = Rc<RefCell<List>>;
+pub type MapRef = Rc<RefCell<Map>>;
 
+thread_local! {
+    static PRECISION: Cell<Option<usize>> = const { Cell::new(None) };
+
+    /// the lists and maps being displayed, a list holding itself shows
+    /// as `[...]` instead of recursing until the stack runs out
+    static DISPLAYING: RefCell<Vec<usize>> = const { RefCell::new(Vec::new()) };
+
+    /// the pairs of maps being compared, meeting a pair again means the
+    /// maps hold themselves and are equal as far as the cycle goes
+    static COMPARING: RefCell<Vec<(usize, usize)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// run `body` with `key` on `stack`, None when `key` already is there
+fn unless_nested<K: PartialEq, T>(
+    stack: &'static LocalKey<RefCell<Vec<K>>>,
+    key: K,
+    body: impl FnOnce() -> T,
+) -> Option<T> {
+    if stack.with(|stack| stack.borrow().contains(&key)) {
+        return None;
+    }
+    stack.with(|stack| stack.borrow_mut().push(key));
+    let result = body();
+    stack.with(|stack| stack.borrow_mut().pop());
+    Some(result)
+}
+
+/// the most digits shown after the point of a float, trailing zeros
+/// dropped, None shows the shortest text that reads back as the same
+/// float. values keep their full precision either way
+pub fn set_float_precision(digits: Option<usize>) {
+    PRECISION.with(|precision| precision.set(digits));
+}
+
+/// cloning an object is cheap: strings and functions are shared
+/// behind an Rc, so reading a variable is a pointer copy
 #[derive(Debug, Clone)]
 pub enum Object {
     Number(NumberType),
-    String(String),
+    String(Rc<str>),
     Boolean(bool),
-    Callable(Function),
+    Callable(Rc<Function>),
     Class(ClassRef),
     Instance(InstanceRef),
     List(ListRef),
+    Map(MapRef),
+    /// several values returned at once, fixed once made
+    Tuple(Rc<[Object]>),
+    Store(StoreRef),
     Nil,
+    /// bound by `var x;`, reading it before an assignment is an error
+    Uninitialized,
 }
 
 impl Display for Object {
@@ -33,10 +80,23 @@ impl Display for Object {
             Object::String(s) => write!(f, "{}", s),
             Object::Boolean(b) => write!(f, "{}", b),
             Object::Nil => write!(f, "nil"),
+            Object::Uninitialized => write!(f, "uninitialized"),
             Object::Callable(_) => write!(f, "<callable>"),
             Object::Class(c) => write!(f, "{}", c.borrow()),
             Object::Instance(i) => write!(f, "{}", i.borrow()),
-            Object::List(l) => write!(f, "{}", l.borrow()),
+            Object::List(l) => unless_nested(&DISPLAYING, l.as_ptr() as usize, || {
+                write!(f, "{}", l.borrow())
+            })
+            .unwrap_or_else(|| write!(f, "[...]")),
+            Object::Map(m) => unless_nested(&DISPLAYING, m.as_ptr() as usize, || {
+                write!(f, "{}", m.borrow())
+            })
+            .unwrap_or_else(|| write!(f, "{{...}}")),
+            Object::Tuple(t) => {
+                let elements: Vec<String> = t.iter().map(|e| e.to_string()).collect();
+                write!(f, "({})", elements.join(", "))
+            }
+            Object::Store(s) => write!(f, "{}", s.borrow()),
         }
     }
 }
@@ -45,12 +105,39 @@ impl Object {
     pub fn equals(&self, other: &Object) -> bool {
         match (self, other) {
             (Object::Number(n1), Object::Number(n2)) => n1 == n2,
-            (Object::String(s1), Object::String(s2)) => s1 == s2,
+            (Object::String(s1), Object::String(s2)) => Rc::ptr_eq(s1, s2) || s1 == s2,
             (Object::Boolean(b1), Object::Boolean(b2)) => b1 == b2,
             (Object::Nil, Object::Nil) => true,
+            (Object::Map(m1), Object::Map(m2)) => {
+                let pair = (m1.as_ptr() as usize, m2.as_ptr() as usize);
+                Rc::ptr_eq(m1, m2)
+                    || unless_nested(&COMPARING, pair, || m1.borrow().equals(&m2.borrow()))
+                        .unwrap_or(true)
+            }
+            (Object::Tuple(t1), Object::Tuple(t2)) => {
+                t1.len() == t2.len() && t1.iter().zip(t2.iter()).all(|(a, b)| a.equals(b))
+            }
             _ => false,
         }
     }
+
+    /// the name of the value's type, used in error messages
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Object::Number(_) => "number",
+            Object::String(_) => "string",
+            Object::Boolean(_) => "boolean",
+            Object::Callable(_) => "function",
+            Object::Class(_) => "class",
+            Object::Instance(_) => "instance",
+            Object::List(_) => "list",
+            Object::Map(_) => "map",
+            Object::Tuple(_) => "tuple",
+            Object::Store(_) => "store",
+            Object::Nil => "nil",
+            Object::Uninitialized => "uninitialized",
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Copy)]
@@ -75,13 +162,13 @@ impl NumberType {
     pub fn unary_op(&self, op: &Token) -> Result<Self, Error> {
         match op.token_type {
             TokenType::Minus => Ok(match self {
-                NumberType::Integer(i) => NumberType::Integer(-i),
+                NumberType::Integer(i) => match i.checked_neg() {
+                    Some(n) => NumberType::Integer(n),
+                    None => NumberType::Float(-(*i as f64)),
+                },
                 NumberType::Float(f) => NumberType::Float(-f),
             }),
-            _ => Err(Error {
-                message: "Unsupported unary operation".to_string(),
-                error_type: ErrorType::RuntimeError(op.clone()),
-            }),
+            _ => Err(Error::coded("E0455", &[]).at(op)),
         }
     }
     pub fn binary_op(&self, op: &Token, other: &NumberType) -> Result<Self, Error> {
@@ -90,17 +177,18 @@ impl NumberType {
             TokenType::Minus => self.sub(other),
             TokenType::Star => self.mul(other),
             TokenType::Slash => self.div(other),
-            _ => Err(Error {
-                message: "Unsupported binary operation".to_string(),
-                error_type: ErrorType::RuntimeError(op.clone()),
-            }),
+            _ => Err(Error::coded("E0456", &[]).at(op)),
         }
     }
     pub fn add(&self, other: &NumberType) -> Result<Self, Error> {
         use NumberType::{Float, Integer};
         Ok(match self {
             Integer(i) => match other {
-                Integer(j) => Integer(i + j),
+                Integer(j) => match i.checked_add(*j) {
+                    Some(n) => Integer(n),
+                    // out of integer range, carry on in floating point
+                    None => Float(*i as f64 + *j as f64),
+                },
                 Float(f) => Float(*i as f64 + f),
             },
             Float(f) => match other {
@@ -113,7 +201,10 @@ impl NumberType {
         use NumberType::{Float, Integer};
         Ok(match self {
             Integer(i) => match other {
-                Integer(j) => Integer(i - j),
+                Integer(j) => match i.checked_sub(*j) {
+                    Some(n) => Integer(n),
+                    None => Float(*i as f64 - *j as f64),
+                },
                 Float(f) => Float(*i as f64 - f),
             },
             Float(f) => match other {
@@ -127,7 +218,10 @@ impl NumberType {
         use NumberType::{Float, Integer};
         Ok(match self {
             Integer(i) => match other {
-                Integer(j) => Integer(i * j),
+                Integer(j) => match i.checked_mul(*j) {
+                    Some(n) => Integer(n),
+                    None => Float(*i as f64 * *j as f64),
+                },
                 Float(f) => Float(*i as f64 * f),
             },
             Float(f) => match other {
@@ -142,12 +236,9 @@ impl NumberType {
             Integer(i) => match other {
                 Integer(j) => {
                     if j == &0 {
-                        return Err(Error {
-                            message: String::from("Division by zero."),
-                            error_type: ErrorType::SyntaxError,
-                        });
+                        return Err(Error::coded("E0413", &[]).with_type(ErrorType::SyntaxError));
                     }
-                    if (i % j) == 0 {
+                    if i.checked_rem(*j) == Some(0) {
                         Integer(i / j)
                     } else {
                         Float((*i as f64) / (*j as f64))
@@ -162,6 +253,14 @@ impl NumberType {
         };
         Ok(result)
     }
+    /// None when a NaN is involved
+    pub fn compare(&self, other: &NumberType) -> Option<std::cmp::Ordering> {
+        use NumberType::Integer;
+        match (self, other) {
+            (Integer(i), Integer(j)) => Some(i.cmp(j)),
+            _ => self.as_float().partial_cmp(&other.as_float()),
+        }
+    }
     pub fn greater(&self, other: &NumberType) -> Result<bool, Error> {
         use NumberType::{Float, Integer};
         Ok(match self {
@@ -220,7 +319,23 @@ impl Display for NumberType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             NumberType::Integer(i) => write!(f, "{}", i),
-            NumberType::Float(fl) => write!(f, "{}", fl),
+            // the way Java prints it
+            NumberType::Float(fl) if fl.is_infinite() && compat::jlox() => {
+                write!(f, "{}Infinity", if *fl < 0.0 { "-" } else { "" })
+            }
+            NumberType::Float(fl) => match PRECISION.with(Cell::get) {
+                Some(digits) if fl.is_finite() => {
+                    let text = format!("{:.*}", digits, fl);
+                    // `{:.*}` pads with zeros, 0.5 stays 0.5 rather than 0.500
+                    let text = if text.contains('.') {
+                        text.trim_end_matches('0').trim_end_matches('.')
+                    } else {
+                        &text
+                    };
+                    write!(f, "{}", text)
+                }
+                _ => write!(f, "{}", fl),
+            },
         }
     }
 }
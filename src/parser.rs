@@ -1,3 +1,4 @@
+use std::fmt::Display;
 use std::vec;
 
 use super::*;
@@ -6,6 +7,13 @@ use TokenType::*;
 pub struct Parser<'a> {
     tokens: &'a Vec<Token>,
     current: usize,
+    optional_semicolons: bool,
+    /// how many statements and expressions the parser is inside of
+    depth: usize,
+    /// stands in for the missing Eof when the tokens don't end with one
+    eof: Token,
+    /// where the parsed nodes go
+    ast: Ast,
 }
 
 macro_rules! matches {
@@ -27,17 +35,24 @@ macro_rules! matches {
 ///                 | funDecl
 ///                 | statement
 ///                 | classDecl ;
-/// funDecl        → "fun" function ;
+/// funDecl        → "fun" IDENTIFIER captures? "(" parameters? ")" block ;
+/// captures       → "[" IDENTIFIER ( "," IDENTIFIER )* "]" ;
 /// function       → IDENTIFIER "(" parameters? ")" block ;
+/// parameters     → parameter ( "," parameter )* ;
+/// parameter      → IDENTIFIER ( "=" expression )? ;
 /// varDecl        → "var" IDENTIFIER ( "=" expression )? ";" ;
-/// classDecl      → "class" IDENTIFIER ( "<" IDENTIFIER )? "{" function* "}" ;
+/// classDecl      → "class" IDENTIFIER ( "<" IDENTIFIER )? "{" ( field | function )* "}" ;
 /// statement      → exprStmt
 ///                | ifStmt
 ///                | printStmt
 ///                | block
 ///                | whileStmt
 ///                | forStmt
-///                | returnStmt ;
+///                | returnStmt
+///                | breakStmt
+///                | continueStmt
+///                | assertStmt
+///                | IDENTIFIER ":" ( whileStmt | forStmt ) ;
 /// exprStmt       → expression ";" ;
 /// ifStmt         → "if" "(" expression ")" statement ( "else" statement )? ;
 /// printStmt      → "print" expression ";" ;
@@ -47,6 +62,9 @@ macro_rules! matches {
 ///                         expression? ";"
 ///                         expression? ")" statement ;
 /// returnStmt     | "return" expression? ";" ;
+/// breakStmt      | "break" IDENTIFIER? ";" ;
+/// continueStmt   | "continue" IDENTIFIER? ";" ;
+/// assertStmt     | "assert" expression ( "," expression )? ";" ;
 /// expression     → assignment ;
 /// assignment     → ( call "." )? IDENTIFIER "=" assignment
 ///                | logicOr ;
@@ -54,6 +72,7 @@ macro_rules! matches {
 /// logicAnd       → equality ( "and" equality )* ;
 /// equality       → comparison ( ( "!=" | "==" ) comparison )* ;
 /// comparison     → term ( ( ">" | ">=" | "<" | "<=" ) term )* ;
+///                  a chain `a < b < c` means `a < b and b < c`
 /// term           → factor ( ( "-" | "+" ) factor )* ;
 /// factor         → unary ( ( "/" | "*" ) unary )* ;
 /// unary          → ( "!" | "-" ) unary
@@ -64,16 +83,39 @@ macro_rules! matches {
 ///                | IDENTIFIER
 ///                | this
 ///                | super "." primary
-///                | list "(" arguments? ")" ;
+///                | list "(" arguments? ")"
+///                | ifExpr
+///                | blockExpr ;
+/// ifExpr         → "if" "(" expression ")" blockExpr ( "else" ( ifExpr | blockExpr ) )? ;
+/// blockExpr      → "{" declaration* expression? "}" ;
 /// arguments      | expression ( "," expression )* ;
 /// parameters     | IDENTIFIER ( "," IDENTIFIER )* ;
 impl<'a> Parser<'a> {
     pub fn new(tokens: &'a Vec<Token>) -> Self {
-        Self { tokens, current: 0 }
+        let line = tokens.last().map_or(1, |token| token.line);
+        let offset = tokens
+            .last()
+            .map_or(0, |token| token.offset + token.lexeme.len());
+        Self {
+            tokens,
+            current: 0,
+            optional_semicolons: false,
+            depth: 0,
+            eof: Token {
+                offset,
+                ..Token::new("", Eof, line, 1)
+            },
+            ast: Ast::new(),
+        }
+    }
+
+    /// let the end of a line terminate a statement, used by the REPL
+    pub fn set_optional_semicolons(&mut self, optional: bool) {
+        self.optional_semicolons = optional;
     }
 
-    pub fn parse(&mut self) -> Result<Vec<Stmt>, Error> {
-        let mut stmts: Vec<Stmt> = Vec::new();
+    pub fn parse(&mut self) -> Result<Vec<StmtId>, Error> {
+        let mut stmts: Vec<StmtId> = Vec::new();
         while !self.is_end() {
             let stmt = self.declaration()?;
             stmts.push(stmt);
@@ -81,19 +123,44 @@ impl<'a> Parser<'a> {
         Ok(stmts)
     }
 
+    /// parse the next top-level declaration, on error the parser
+    /// has already skipped ahead to the next statement
+    pub fn parse_declaration(&mut self) -> Result<StmtId, Error> {
+        self.declaration()
+    }
+
+    /// the nodes parsed so far, the ids `parse` gave back index into it
+    pub fn ast(&self) -> &Ast {
+        &self.ast
+    }
+
+    pub fn into_ast(self) -> Ast {
+        self.ast
+    }
+
+    pub fn is_at_end(&self) -> bool {
+        self.is_end()
+    }
+
+    /// index of the next token to be parsed
+    pub fn position(&self) -> usize {
+        self.current
+    }
+
     // statement parser
 
     /// declaration    → varDecl
     ///                 | statement ;
-    fn declaration(&mut self) -> Result<Stmt, Error> {
-        let res: Result<Stmt, Error> = if matches!(self, Var) {
+    fn declaration(&mut self) -> Result<StmtId, Error> {
+        let res: Result<StmtId, Error> = if matches!(self, Var) {
             self.var_decl()
         } else if matches!(self, Fun) {
-            self.function("function")
+            let doc = self.previous().doc.clone();
+            self.function("function", doc)
         } else if matches!(self, Class) {
             self.class_decl()
         } else {
-            self.statement()
+            self.nested(Self::statement)
         };
 
         if res.is_err() {
@@ -103,78 +170,137 @@ impl<'a> Parser<'a> {
         res
     }
 
-    /// classDecl      → "class" IDENTIFIER ( "<" IDENTIFIER )? "{" function* "}" ;
-    fn class_decl(&mut self) -> Result<Stmt, Error> {
-        let name = self.consume(Identifier, "Expect class name.")?.clone();
-        let mut super_class: Option<Expr> = None;
+    /// classDecl      → "class" IDENTIFIER ( "<" IDENTIFIER )? "{" ( field | function )* "}" ;
+    /// field          → "var" IDENTIFIER ( "=" expression )? ";" ;
+    fn class_decl(&mut self) -> Result<StmtId, Error> {
+        let doc = self.previous().doc.clone();
+        let name = self.consume(Identifier, "E0210", &[&"class name"])?.clone();
+        let mut super_class: Option<ExprId> = None;
 
         if matches!(self, Less) {
-            self.consume(Identifier, "Expect superclass name.")?;
-            super_class = Some(Expr::Variable {
+            self.consume(Identifier, "E0210", &[&"superclass name"])?;
+            super_class = Some(self.ast.push_expr(Expr::Variable {
                 name: self.previous().clone(),
-            });
+            }));
         }
 
-        self.consume(LeftBrace, "Expect '{' before class body.")?;
+        self.consume(LeftBrace, "E0207", &[&"{", &"class body"])?;
 
-        // get methods
+        // get fields and methods
+        let mut fields = Vec::new();
         let mut methods = Vec::new();
         while !self.check(RightBrace) {
-            methods.push(self.function("method")?);
+            // jlox classes only hold methods
+            if !compat::jlox() && matches!(self, Var) {
+                fields.push(self.field()?);
+                continue;
+            }
+            // a method has no `fun`, its doc sits on the name
+            let doc = self.peak().doc.clone();
+            methods.push(self.function("method", doc)?);
         }
-        self.consume(RightBrace, "Expect '}' after class body.")?;
+        self.consume(RightBrace, "E0206", &[&"}", &"class body"])?;
 
-        Ok(Stmt::ClassStmt {
+        Ok(self.ast.push_stmt(Stmt::ClassStmt {
             name,
             super_class,
+            fields,
             methods,
-        })
+            doc,
+        }))
+    }
+
+    /// a field declared in a class body, a plain `var` without destructuring
+    fn field(&mut self) -> Result<StmtId, Error> {
+        let name = self.consume(Identifier, "E0210", &[&"field name"])?.clone();
+        let initializer = if matches!(self, Equal) {
+            Some(self.expression()?)
+        } else {
+            None
+        };
+        self.consume_terminator("E0206", &[&";", &"field declaration"])?;
+        Ok(self.ast.push_stmt(Stmt::VarStmt { name, initializer }))
     }
 
-    /// varDecl        → "var" IDENTIFIER ( "=" expression )? ";" ;
-    fn var_decl(&mut self) -> Result<Stmt, Error> {
-        let name = self.consume(Identifier, "Expect variable name.")?.clone();
+    /// varDecl        → "var" IDENTIFIER ( "=" expression )? ";"
+    ///                | "var" IDENTIFIER ( "," IDENTIFIER )+ "=" expression ";" ;
+    fn var_decl(&mut self) -> Result<StmtId, Error> {
+        let name = self.consume(Identifier, "E0210", &[&"variable name"])?.clone();
+
+        if !compat::jlox() && self.check(Comma) {
+            let mut names = vec![name];
+            while matches!(self, Comma) {
+                names.push(self.consume(Identifier, "E0210", &[&"variable name"])?.clone());
+            }
+            self.consume(Equal, "E0206", &[&"=", &"variable names"])?;
+            let initializer = self.expression()?;
+            self.consume_terminator("E0206", &[&";", &"variable declaration"])?;
+            return Ok(self.ast.push_stmt(Stmt::DestructureStmt { names, initializer }));
+        }
 
-        let mut initializer: Option<Expr> = None;
+        let mut initializer: Option<ExprId> = None;
 
         if matches!(self, Equal) {
             initializer = Some(self.expression()?);
         }
 
-        self.consume(Semicolon, "Expect ';' after variable declaration.")?;
+        self.consume_terminator("E0206", &[&";", &"variable declaration"])?;
 
-        Ok(Stmt::VarStmt { name, initializer })
+        Ok(self.ast.push_stmt(Stmt::VarStmt { name, initializer }))
     }
 
     /// funDecl        → "fun" function ;
     /// function       → IDENTIFIER "(" parameters? ")" block ;
-    fn function(&mut self, kind: &str) -> Result<Stmt, Error> {
-        let name = self.consume(Identifier, "Expect function name.")?.clone();
-        self.consume(LeftParen, "Expect '(' after function name.")?;
+    fn function(&mut self, kind: &str, doc: Option<std::string::String>) -> Result<StmtId, Error> {
+        let name = self.consume(Identifier, "E0210", &[&"function name"])?.clone();
+        // variables copied into the closure when it is created
+        let mut captures: Vec<ExprId> = Vec::new();
+        if kind == "function" && matches!(self, LeftBracket) {
+            loop {
+                let name = self.consume(Identifier, "E0210", &[&"captured variable name"])?.clone();
+                captures.push(self.ast.push_expr(Expr::Variable { name }));
+                if !matches!(self, Comma) {
+                    break;
+                }
+            }
+            self.consume(RightBracket, "E0206", &[&"]", &"captured variables"])?;
+        }
+        self.consume(LeftParen, "E0206", &[&"(", &"function name"])?;
         let mut parameters: Vec<Token> = Vec::new();
+        let mut defaults: Vec<ExprId> = Vec::new();
         if !self.check(RightParen) {
             loop {
                 if parameters.len() >= 255 {
-                    return Err(self.error(self.peak(), "Can't have more than 255 parameters."));
+                    return Err(self.error(self.peak(), "E0203", &[&255, &"parameters"]));
+                }
+                let param = self.consume(Identifier, "E0210", &[&"parameter name"])?.clone();
+                if !compat::jlox() && matches!(self, Equal) {
+                    defaults.push(self.expression()?);
+                } else if !defaults.is_empty() {
+                    return Err(self.error(&param, "E0215", &[]));
                 }
-                parameters.push(self.consume(Identifier, "Expect parameter name.")?.clone());
+                parameters.push(param);
                 if !matches!(self, Comma) {
                     break;
                 }
             }
         }
-        self.consume(RightParen, "Expect ')' after parameters.")?;
+        self.consume(RightParen, "E0206", &[&")", &"parameters"])?;
         self.consume(
             LeftBrace,
-            format!("Expect '{{' before {} body.", kind).as_str(),
+            "E0207",
+            &[&"{", &format!("{} body", kind)],
         )?;
         let body = self.block_statement()?;
 
-        Ok(Stmt::FunStmt {
+        Ok(self.ast.push_stmt(Stmt::FunStmt {
             name,
             params: parameters,
-            body,
-        })
+            defaults,
+            body: body.into(),
+            doc,
+            captures,
+        }))
     }
 
     /// statement      → exprStmt
@@ -182,7 +308,7 @@ impl<'a> Parser<'a> {
     ///                | printStmt ;
     ///                | block ;
     ///                | whileStmt
-    fn statement(&mut self) -> Result<Stmt, Error> {
+    fn statement(&mut self) -> Result<StmtId, Error> {
         // printStmt
         if matches!(self, Print) {
             return self.print_statement();
@@ -190,9 +316,11 @@ impl<'a> Parser<'a> {
 
         // block
         if matches!(self, LeftBrace) {
-            return Ok(Stmt::BlockStmt {
-                statements: self.block_statement()?,
-            });
+            let statements = self.block_statement()?;
+            return Ok(self.ast.push_stmt(Stmt::BlockStmt {
+                statements,
+                origin: None,
+            }));
         }
 
         // ifStmt
@@ -202,12 +330,12 @@ impl<'a> Parser<'a> {
 
         // whileStmt
         if matches!(self, While) {
-            return self.while_statement();
+            return self.while_statement(None);
         }
 
         // forStmt
         if matches!(self, For) {
-            return self.for_statement();
+            return self.for_statement(None);
         }
 
         // returnStmt
@@ -215,76 +343,149 @@ impl<'a> Parser<'a> {
             return self.return_statement();
         }
 
+        // breakStmt, continueStmt
+        if matches!(self, Break, Continue) {
+            return self.loop_control_statement();
+        }
+
+        // assertStmt
+        if matches!(self, Assert) {
+            return self.assert_statement();
+        }
+
+        // deferStmt
+        if matches!(self, Defer) {
+            let keyword = self.previous().clone();
+            let body = self.statement()?;
+            return Ok(self.ast.push_stmt(Stmt::DeferStmt { keyword, body }));
+        }
+
+        // tryStmt
+        if matches!(self, Try) {
+            return self.try_statement();
+        }
+
+        // throwStmt
+        if matches!(self, Throw) {
+            let keyword = self.previous().clone();
+            let value = self.expression()?;
+            self.consume_terminator("E0206", &[&";", &"thrown value"])?;
+            return Ok(self.ast.push_stmt(Stmt::ThrowStmt { keyword, value }));
+        }
+
+        // labeled loop
+        if self.check(Identifier) && self.check_next(Colon) {
+            let label = self.advance().clone();
+            self.advance();
+            if matches!(self, While) {
+                return self.while_statement(Some(label));
+            }
+            if matches!(self, For) {
+                return self.for_statement(Some(label));
+            }
+            return Err(self.error(self.peak(), "E0209", &[&"loop", &"label"]));
+        }
+
         self.expression_statement()
     }
 
     /// exprStmt       → expression ";" ;
-    fn expression_statement(&mut self) -> Result<Stmt, Error> {
+    fn expression_statement(&mut self) -> Result<StmtId, Error> {
         let expr = self.expression()?;
-        self.consume(Semicolon, "Expect ';' after value.")?;
+        self.end_expression_statement(expr)?;
 
-        Ok(Stmt::ExprStmt { expression: expr })
+        Ok(self.ast.push_stmt(Stmt::ExprStmt { expression: expr }))
     }
 
     /// ifStmt         → "if" "(" expression ")" statement ( "else" statement )? ;
-    fn if_statement(&mut self) -> Result<Stmt, Error> {
-        self.consume(LeftParen, "Expect '(' after 'if'.")?;
+    fn if_statement(&mut self) -> Result<StmtId, Error> {
+        let keyword = self.previous().clone();
+        self.consume(LeftParen, "E0206", &[&"(", &"'if'"])?;
         let condition_expr = self.expression()?;
-        self.consume(RightParen, "Expect ')' after if condition.")?;
-        let branch_stmt = self.statement()?;
-        let mut else_stmt: Option<Box<Stmt>> = None;
+        self.consume(RightParen, "E0206", &[&")", &"if condition"])?;
+        let branch_stmt = self.nested(Self::statement)?;
+        let mut else_stmt: Option<StmtId> = None;
         if matches!(self, Else) {
-            else_stmt = Some(Box::new(self.statement()?));
+            else_stmt = Some(self.nested(Self::statement)?);
         }
 
-        Ok(Stmt::IfStmt {
+        Ok(self.ast.push_stmt(Stmt::IfStmt {
+            keyword,
             condition: condition_expr,
-            then_branch: Box::new(branch_stmt),
+            then_branch: branch_stmt,
             else_branch: else_stmt,
-        })
+        }))
     }
 
     /// printStmt      → "print" expression ";" ;
-    fn print_statement(&mut self) -> Result<Stmt, Error> {
+    fn print_statement(&mut self) -> Result<StmtId, Error> {
+        let keyword = self.previous().clone();
         let expr = self.expression()?;
-        self.consume(Semicolon, "Expect ';' after value.")?;
+        self.consume_terminator("E0206", &[&";", &"value"])?;
 
-        Ok(Stmt::PrintStmt { expression: expr })
+        Ok(self.ast.push_stmt(Stmt::PrintStmt {
+            keyword,
+            expression: expr,
+        }))
     }
 
     /// block          | "{" declaration* "}" ;
-    fn block_statement(&mut self) -> Result<Vec<Stmt>, Error> {
-        let mut stmts: Vec<Stmt> = Vec::new();
+    fn block_statement(&mut self) -> Result<Vec<StmtId>, Error> {
+        let mut stmts: Vec<StmtId> = Vec::new();
         while !self.check(RightBrace) && !self.is_end() {
             let stmt = self.declaration()?;
             stmts.push(stmt);
         }
 
-        self.consume(RightBrace, "Expect '}' after block")?;
+        self.consume(RightBrace, "E0205", &[])?;
 
         Ok(stmts)
     }
 
     /// whileStmt      | "while" "(" expression ")" statement ;
-    fn while_statement(&mut self) -> Result<Stmt, Error> {
-        self.consume(LeftParen, "Expect '(' after 'while'.")?;
+    fn while_statement(&mut self, label: Option<Token>) -> Result<StmtId, Error> {
+        let keyword = self.previous().clone();
+        self.consume(LeftParen, "E0206", &[&"(", &"'while'"])?;
         let condition = self.expression()?;
-        self.consume(RightParen, "Expect ')' after condition.")?;
-        let body = self.statement()?;
-        Ok(Stmt::WhileStmt {
+        self.consume(RightParen, "E0206", &[&")", &"condition"])?;
+        let body = self.nested(Self::statement)?;
+        Ok(self.ast.push_stmt(Stmt::WhileStmt {
+            keyword,
             condition,
-            body: Box::new(body),
-        })
+            body,
+            increment: None,
+            label,
+        }))
     }
 
     /// forStmt        | "for" "(" ( varDecl | exprStmt | ";" )
     ///                         expression? ";"
     ///                         expression? ")" statement ;
-    fn for_statement(&mut self) -> Result<Stmt, Error> {
+    /// forInStmt      | "for" "(" "var" IDENTIFIER "in" expression ")" statement ;
+    fn for_statement(&mut self, label: Option<Token>) -> Result<StmtId, Error> {
         // 语法脱糖, convert to while loop
-        self.consume(LeftParen, "Expect '(' after 'for'.")?;
+        let keyword = self.previous().clone();
+        self.consume(LeftParen, "E0206", &[&"(", &"'for'"])?;
 
-        let initializer: Option<Stmt> = if matches!(self, Semicolon) {
+        // `var name in` is a for-in loop, anything else a C-style one
+        let in_after_name = self.tokens.get(self.current + 2).map(|t| &t.token_type) == Some(&In);
+        if self.check(Var) && in_after_name {
+            self.advance();
+            let name = self.consume(Identifier, "E0210", &[&"variable name"])?.clone();
+            self.advance();
+            let iterable = self.expression()?;
+            self.consume(RightParen, "E0206", &[&")", &"for clauses"])?;
+            let body = self.nested(Self::statement)?;
+            return Ok(self.ast.push_stmt(Stmt::ForInStmt {
+                keyword,
+                name,
+                iterable,
+                body,
+                label,
+            }));
+        }
+
+        let initializer: Option<StmtId> = if matches!(self, Semicolon) {
             None
         } else if matches!(self, Var) {
             Some(self.var_decl()?)
@@ -292,58 +493,128 @@ impl<'a> Parser<'a> {
             Some(self.expression_statement()?)
         };
 
-        let condition: Expr = if self.check(Semicolon) {
-            Expr::Literal {
-                value: Literal::Boolean(true),
-            }
+        let condition: ExprId = if self.check(Semicolon) {
+            self.ast.boolean(true)
         } else {
             self.expression()?
         };
-        self.consume(Semicolon, "Expect ';' after loop condition.")?;
+        self.consume(Semicolon, "E0206", &[&";", &"loop condition"])?;
 
-        let increment: Option<Expr> = if self.check(RightParen) {
+        let increment: Option<ExprId> = if self.check(RightParen) {
             None
         } else {
             Some(self.expression()?)
         };
-        self.consume(RightParen, "Expect ')' after for clauses.")?;
-
-        let mut body = self.statement()?;
-
-        if let Some(increment) = increment {
-            body = Stmt::BlockStmt {
-                statements: vec![
-                    body,
-                    Stmt::ExprStmt {
-                        expression: increment,
-                    },
-                ],
-            };
-        }
+        self.consume(RightParen, "E0206", &[&")", &"for clauses"])?;
+
+        let body = self.nested(Self::statement)?;
 
-        body = Stmt::WhileStmt {
+        // the increment lives on the loop so `continue` doesn't skip it
+        let body = self.ast.push_stmt(Stmt::WhileStmt {
+            keyword: keyword.clone(),
             condition,
-            body: Box::new(body),
+            body,
+            increment,
+            label,
+        });
+
+        // the block stands for the whole loop, initializer or not
+        Ok(self.ast.push_stmt(Stmt::BlockStmt {
+            statements: initializer.into_iter().chain([body]).collect(),
+            origin: Some(keyword),
+        }))
+    }
+
+    /// breakStmt      | "break" IDENTIFIER? ";" ;
+    /// continueStmt   | "continue" IDENTIFIER? ";" ;
+    fn loop_control_statement(&mut self) -> Result<StmtId, Error> {
+        let keyword = self.previous().clone();
+        let label = if matches!(self, Identifier) {
+            Some(self.previous().clone())
+        } else {
+            None
         };
+        self.consume_terminator("E0206", &[&";", &format!("'{}'", keyword.lexeme)])?;
 
-        if let Some(initializer) = initializer {
-            body = Stmt::BlockStmt {
-                statements: vec![initializer, body],
-            };
+        if keyword.token_type == Break {
+            Ok(self.ast.push_stmt(Stmt::BreakStmt { keyword, label }))
+        } else {
+            Ok(self.ast.push_stmt(Stmt::ContinueStmt { keyword, label }))
         }
+    }
+
+    /// assertStmt     | "assert" expression ( "," expression )? ";" ;
+    fn assert_statement(&mut self) -> Result<StmtId, Error> {
+        let keyword = self.previous().clone();
+        let start = self.current;
+        let condition = self.expression()?;
+        let source = self.source_text(start, self.current);
+        let message = if matches!(self, Comma) {
+            Some(self.expression()?)
+        } else {
+            None
+        };
+        self.consume_terminator("E0206", &[&";", &"assertion"])?;
 
-        Ok(body)
+        Ok(self.ast.push_stmt(Stmt::AssertStmt {
+            keyword,
+            condition,
+            message,
+            source,
+        }))
     }
 
-    fn return_statement(&mut self) -> Result<Stmt, Error> {
+    /// tryStmt        | "try" block ( "catch" "(" IDENTIFIER ")" block )? ( "finally" block )? ;
+    fn try_statement(&mut self) -> Result<StmtId, Error> {
         let keyword = self.previous().clone();
-        let mut value: Option<Expr> = None;
+        self.consume(LeftBrace, "E0206", &[&"{", &"'try'"])?;
+        let body = self.block_statement()?;
+
+        let catch = if matches!(self, Catch) {
+            self.consume(LeftParen, "E0206", &[&"(", &"'catch'"])?;
+            let name = self.consume(Identifier, "E0210", &[&"exception name"])?.clone();
+            self.consume(RightParen, "E0206", &[&")", &"exception name"])?;
+            self.consume(LeftBrace, "E0207", &[&"{", &"catch block"])?;
+            Some((name, self.block_statement()?))
+        } else {
+            None
+        };
+        let finally = if matches!(self, Finally) {
+            self.consume(LeftBrace, "E0207", &[&"{", &"finally block"])?;
+            Some(self.block_statement()?)
+        } else {
+            None
+        };
+        if catch.is_none() && finally.is_none() {
+            return Err(self.error(self.peak(), "E0209", &[&"'catch' or 'finally'", &"try block"]));
+        }
+
+        Ok(self.ast.push_stmt(Stmt::TryStmt {
+            keyword,
+            body,
+            catch,
+            finally,
+        }))
+    }
+
+    fn return_statement(&mut self) -> Result<StmtId, Error> {
+        let keyword = self.previous().clone();
+        let mut value: Option<ExprId> = None;
         if !self.check(Semicolon) {
-            value = Some(self.expression()?);
+            let first = self.expression()?;
+            value = Some(if !compat::jlox() && self.check(Comma) {
+                let mut elements = vec![first];
+                while matches!(self, Comma) {
+                    elements.push(self.expression()?);
+                }
+                self.ast.push_expr(Expr::Tuple { elements })
+            } else {
+                first
+            });
         }
-        self.consume(Semicolon, "Expect ';' after return value.")?;
+        self.consume_terminator("E0206", &[&";", &"return value"])?;
 
-        Ok(Stmt::ReturnStmt { keyword, value })
+        Ok(self.ast.push_stmt(Stmt::ReturnStmt { keyword, value }))
     }
 
     // ------------------------------------------------
@@ -351,172 +622,221 @@ impl<'a> Parser<'a> {
     // expression parser
 
     /// expression     → assignment ;
-    fn expression(&mut self) -> Result<Expr, Error> {
-        self.assignment()
+    fn expression(&mut self) -> Result<ExprId, Error> {
+        self.nested(Self::assignment)
     }
 
     /// assignment     → ( call "." )? IDENTIFIER "=" assignment
-    ///                | logic_or ;
-    fn assignment(&mut self) -> Result<Expr, Error> {
-        let expr = self.logic_or();
+    ///                | coalesce ;
+    fn assignment(&mut self) -> Result<ExprId, Error> {
+        let expr = self.coalesce();
 
         if matches!(self, Equal) {
+            let equals = self.previous().clone();
             let value = self.assignment()?;
-            if let Ok(Expr::Variable { name }) = expr {
-                return Ok(Expr::Assign {
-                    name,
-                    value: Box::new(value),
-                });
-            } else if let Ok(Expr::Get { object, name }) = expr {
-                return Ok(Expr::Set {
+            // the target becomes the assignment, keeping its children
+            let Ok(target) = expr else {
+                return Err(self.error(&equals, "E0202", &[]));
+            };
+            let assignment = match &self.ast[target] {
+                Expr::Variable { name } => Expr::Assign {
+                    name: name.clone(),
+                    value,
+                },
+                Expr::Get {
                     object,
                     name,
-                    value: Box::new(value),
-                });
-            } else if let Ok(Expr::Index {
-                object,
-                index,
-                index_end,
-                operator,
-            }) = expr
-            {
-                return Ok(Expr::IndexSet {
+                    optional: false,
+                    ..
+                } => Expr::Set {
+                    object: *object,
+                    name: name.clone(),
+                    value,
+                },
+                Expr::Index {
                     object,
                     index,
                     index_end,
-                    value: Box::new(value),
+                    index_step,
                     operator,
-                });
-            }
-            return Err(self.error(self.previous(), "Invalid assignment target."));
+                    optional: false,
+                } => Expr::IndexSet {
+                    object: *object,
+                    index: *index,
+                    index_end: *index_end,
+                    index_step: *index_step,
+                    value,
+                    operator: operator.clone(),
+                },
+                // methods found through super are shared by the whole class
+                Expr::Super { keyword, .. } if !compat::jlox() => {
+                    return Err(self.error(keyword, "E0211", &[]));
+                }
+                _ => return Err(self.error(&equals, "E0202", &[])),
+            };
+            self.ast.replace_expr(target, assignment);
+            return Ok(target);
         }
 
         expr
     }
 
-    fn logic_or(&mut self) -> Result<Expr, Error> {
+    /// coalesce       → logic_or ( "??" logic_or )* ;
+    fn coalesce(&mut self) -> Result<ExprId, Error> {
+        let mut expr = self.logic_or()?;
+        while matches!(self, QuestionQuestion) {
+            let operator = self.previous().clone();
+            let right = self.logic_or()?;
+            expr = self.ast.push_expr(Expr::Logical {
+                left: expr,
+                operator,
+                right,
+            });
+        }
+        Ok(expr)
+    }
+
+    fn logic_or(&mut self) -> Result<ExprId, Error> {
         let mut expr = self.logic_and()?;
         while matches!(self, Or) {
             let operator = self.previous().clone();
             let right = self.logic_and()?;
             let left = expr; // give expr to left
-            expr = Expr::Logical {
-                left: Box::new(left),
+            expr = self.ast.push_expr(Expr::Logical {
+                left,
                 operator,
-                right: Box::new(right),
-            };
+                right,
+            });
         }
         Ok(expr)
     }
 
-    fn logic_and(&mut self) -> Result<Expr, Error> {
+    fn logic_and(&mut self) -> Result<ExprId, Error> {
         let mut expr = self.equality()?;
         while matches!(self, And) {
             let operator = self.previous().clone();
             let right = self.equality()?;
             let left = expr; // give expr to left
-            expr = Expr::Logical {
-                left: Box::new(left),
+            expr = self.ast.push_expr(Expr::Logical {
+                left,
                 operator,
-                right: Box::new(right),
-            };
+                right,
+            });
         }
         Ok(expr)
     }
 
     /// equality       → comparison ( ( "!=" | "==" ) comparison )* ;
-    fn equality(&mut self) -> Result<Expr, Error> {
+    fn equality(&mut self) -> Result<ExprId, Error> {
         let mut expr = self.comparison()?;
 
         while matches!(self, BangEqual, EqualEqual) {
             let operator = self.previous().clone();
             let right = self.comparison()?;
             let left = expr; // give expr to left
-            expr = Expr::Binary {
-                left: Box::new(left),
+            expr = self.ast.push_expr(Expr::Binary {
+                left,
                 operator,
-                right: Box::new(right),
-            };
+                right,
+            });
         }
         Ok(expr)
     }
 
-    /// comparison     → term ( ( ">" | ">=" | "<" | "<=" ) term )* ;
-    fn comparison(&mut self) -> Result<Expr, Error> {
-        let mut expr = self.term()?;
-        while matches!(self, Greater, GreaterEqual, Less, LessEqual) {
-            let operator = self.previous().clone();
-            let right = self.term()?;
-            let left = expr;
-            expr = Expr::Binary {
-                left: Box::new(left),
-                operator,
-                right: Box::new(right),
-            };
+    /// comparison     → term ( ( ">" | ">=" | "<" | "<=" | "is" ) term )* ;
+    fn comparison(&mut self) -> Result<ExprId, Error> {
+        let mut operands = vec![self.term()?];
+        let mut operators = Vec::new();
+        while matches!(self, Greater, GreaterEqual, Less, LessEqual, Is) {
+            operators.push(self.previous().clone());
+            operands.push(self.term()?);
         }
-        Ok(expr)
+
+        // `is` doesn't chain, `a is B is C` asks whether a bool is a C
+        let is_check = operators.iter().any(|operator| operator.token_type == Is);
+        if operators.len() <= 1 || compat::jlox() || is_check {
+            // a single comparison, or a chain in jlox, which compares the
+            // boolean `a < b` with c
+            let mut operands = operands.into_iter();
+            let mut left = operands.next().unwrap();
+            for (operator, right) in operators.into_iter().zip(operands) {
+                left = self.ast.push_expr(Expr::Binary {
+                    left,
+                    operator,
+                    right,
+                });
+            }
+            return Ok(left);
+        }
+
+        // `0 <= x < 10` is `(0 <= x) and (x < 10)`, with `x` evaluated once
+        Ok(self.ast.push_expr(Expr::Comparison {
+            operands,
+            operators,
+        }))
     }
 
     /// term           → factor ( ( "-" | "+" ) factor )* ;
-    fn term(&mut self) -> Result<Expr, Error> {
+    fn term(&mut self) -> Result<ExprId, Error> {
         let mut expr = self.factor()?;
         while matches!(self, Minus, Plus) {
             let operator = self.previous().clone();
             let right = self.factor()?;
             let left = expr;
-            expr = Expr::Binary {
-                left: Box::new(left),
+            expr = self.ast.push_expr(Expr::Binary {
+                left,
                 operator,
-                right: Box::new(right),
-            };
+                right,
+            });
         }
         Ok(expr)
     }
 
     /// factor         → unary ( ( "/" | "*" ) unary )* ;
-    fn factor(&mut self) -> Result<Expr, Error> {
+    fn factor(&mut self) -> Result<ExprId, Error> {
         let mut expr = self.unary()?;
         while matches!(self, Slash, Star) {
             let operator = self.previous().clone();
             let right = self.unary()?;
             let left = expr;
-            expr = Expr::Binary {
-                left: Box::new(left),
+            expr = self.ast.push_expr(Expr::Binary {
+                left,
                 operator,
-                right: Box::new(right),
-            };
+                right,
+            });
         }
         Ok(expr)
     }
 
     /// unary          → ( "!" | "-" ) unary
     ///                | call_index ;
-    fn unary(&mut self) -> Result<Expr, Error> {
+    fn unary(&mut self) -> Result<ExprId, Error> {
         if matches!(self, Bang, Minus) {
             let operator = self.previous().clone();
-            let right = self.unary()?;
-            return Ok(Expr::Unary {
-                operator,
-                right: Box::new(right),
-            });
+            let right = self.nested(Self::unary)?;
+            return Ok(self.ast.push_expr(Expr::Unary { operator, right }));
         }
         self.call_index()
     }
-    /// call_index           → primary ( "(" arguments? ")" | "." IDENTIFIER | "[" index "]")* ;
-    fn call_index(&mut self) -> Result<Expr, Error> {
+    /// call_index           → primary ( "(" arguments? ")" | ( "." | "?." ) IDENTIFIER | ( "[" | "?[" ) index "]")* ;
+    fn call_index(&mut self) -> Result<ExprId, Error> {
         let mut expr = self.primary()?;
-        while matches!(self, LeftParen, Dot, LeftBracket) {
+        while matches!(self, LeftParen, Dot, QuestionDot, LeftBracket, QuestionBracket) {
             let previous_token_type = self.previous().token_type.clone();
             if previous_token_type == LeftParen {
                 expr = self.finish_call(expr)?;
-            } else if previous_token_type == Dot {
-                let name = self.consume(Identifier, "Expect property name after '.'.")?;
-                expr = Expr::Get {
-                    object: Box::new(expr),
-                    name: name.clone(),
-                };
+            } else if previous_token_type == Dot || previous_token_type == QuestionDot {
+                let name = self.consume(Identifier, "E0209", &[&"property name", &"'.'"])?.clone();
+                expr = self.ast.push_expr(Expr::Get {
+                    object: expr,
+                    name,
+                    cache: MethodCache::default(),
+                    optional: previous_token_type == QuestionDot,
+                });
             } else if previous_token_type == LeftBracket {
-                expr = self.finish_index(expr)?;
+                expr = self.finish_index(expr, false)?;
+            } else if previous_token_type == QuestionBracket {
+                expr = self.finish_index(expr, true)?;
             }
         }
         Ok(expr)
@@ -526,95 +846,361 @@ impl<'a> Parser<'a> {
     ///                | "(" expression ")"
     ///                | IDENTIFIER
     ///                | this ;
-    fn primary(&mut self) -> Result<Expr, Error> {
+    fn primary(&mut self) -> Result<ExprId, Error> {
         if matches!(self, False) {
-            return Ok(Expr::Literal {
+            return Ok(self.ast.push_expr(Expr::Literal {
                 value: Literal::Boolean(false),
-            });
+            }));
         }
         if matches!(self, True) {
-            return Ok(Expr::Literal {
+            return Ok(self.ast.push_expr(Expr::Literal {
                 value: Literal::Boolean(true),
-            });
+            }));
         }
 
         if matches!(self, Nil) {
-            return Ok(Expr::Literal {
+            return Ok(self.ast.push_expr(Expr::Literal {
                 value: Literal::Nil,
-            });
+            }));
         }
 
         if matches!(self, String) {
-            return Ok(Expr::Literal {
-                // value: Literal::String(self.previous().lexeme.clone())
-                value: Literal::String(
-                    self.previous().lexeme[1..self.previous().lexeme.len() - 1].to_string(),
-                ),
-            });
+            return Ok(self.ast.push_expr(Expr::Literal {
+                value: self.previous().literal.clone(),
+            }));
+        }
+        if matches!(self, Interpolation) {
+            return self.interpolation();
         }
         if matches!(self, Number) {
-            return Ok(Expr::Literal {
-                value: Literal::Number(if let Ok(number) = self.previous().lexeme.parse::<i64>() {
-                    NumberType::Integer(number)
-                } else if let Ok(number) = self.previous().lexeme.parse::<f64>() {
-                    NumberType::Float(number)
-                } else {
-                    return Err(self.error(self.previous(), "Invalid number."));
-                }),
-            });
+            let token = self.previous();
+            return match &token.literal {
+                Literal::Number(number) => Ok(self.ast.push_expr(Expr::Literal {
+                    value: Literal::Number(*number),
+                })),
+                // the scanner leaves out the value of a number it couldn't read
+                _ if !compat::jlox()
+                    && token.lexeme.ends_with('i')
+                    && token.lexeme.contains('.') =>
+                {
+                    Err(self.error(token, "E0213", &[]))
+                }
+                _ => Err(self.error(token, "E0103", &[])),
+            };
         }
 
-        if matches!(self, Identifier) {
-            return Ok(Expr::Variable {
+        // `print` inside an expression is the native, not the statement
+        if matches!(self, Identifier) || (!compat::jlox() && matches!(self, Print)) {
+            return Ok(self.ast.push_expr(Expr::Variable {
                 name: self.previous().clone(),
-            });
+            }));
         }
 
         if matches!(self, LeftParen) {
             let expr = self.expression()?;
 
-            self.consume(RightParen, "Expect ')' after expression.")?;
+            self.consume(RightParen, "E0206", &[&")", &"expression"])?;
 
-            return Ok(Expr::Grouping {
-                expression: Box::new(expr),
-            });
+            return Ok(self.ast.push_expr(Expr::Grouping { expression: expr }));
         }
 
         if matches!(self, This) {
-            return Ok(Expr::This {
+            return Ok(self.ast.push_expr(Expr::This {
                 keyword: self.previous().clone(),
-            });
+            }));
         }
 
         if matches!(self, Super) {
             let keyword = self.previous().clone();
-            self.consume(Dot, "Expect '.' after 'super'.")?;
+            if !compat::jlox() && !self.check(Dot) {
+                return Err(self.error(&keyword, "E0212", &[]));
+            }
+            self.consume(Dot, "E0206", &[&".", &"'super'"])?;
             let method = self
-                .consume(Identifier, "Expect superclass method name.")?
+                .consume(Identifier, "E0210", &[&"superclass method name"])?
                 .clone();
-            return Ok(Expr::Super { keyword, method });
+            return Ok(self.ast.push_expr(Expr::Super { keyword, method }));
+        }
+
+        if matches!(self, Const) {
+            let keyword = self.previous().clone();
+            if !self.check(List) && !self.starts_map() {
+                return Err(self.error(self.peak(), "E0209", &[&"list or map literal", &"'const'"]));
+            }
+            let value = self.primary()?;
+            return Ok(self.ast.push_expr(Expr::Const { keyword, value }));
         }
 
         if matches!(self, List) {
             // leftparen
             let keyword = self.previous().clone();
-            self.consume(LeftParen, "Expect '(' after 'list'.")?;
+            self.consume(LeftParen, "E0206", &[&"(", &"'list'"])?;
             return self.finish_list(keyword);
         }
 
-        Err(self.error(self.peak(), "Expect expression."))
+        // jlox has no if or block expressions
+        if !compat::jlox() && matches!(self, If) {
+            return self.if_expression();
+        }
+
+        if self.starts_map() {
+            let brace = self.advance().clone();
+            return self.finish_map(brace);
+        }
+
+        if !compat::jlox() && matches!(self, LeftBrace) {
+            return self.block_expression();
+        }
+
+        if matches!(self, Match) {
+            return self.match_expression();
+        }
+
+        Err(self.error(self.peak(), "E0201", &[]))
         // Err(Error {
         //     message: "Expect expression".to_string(),
         //     error_type: ErrorType::SyntaxError
         // })
     }
 
+    /// ifExpr         → "if" "(" expression ")" blockExpr ( "else" ( ifExpr | blockExpr ) )? ;
+    fn if_expression(&mut self) -> Result<ExprId, Error> {
+        let keyword = self.previous().clone();
+        self.consume(LeftParen, "E0206", &[&"(", &"'if'"])?;
+        let condition = self.expression()?;
+        self.consume(RightParen, "E0206", &[&")", &"if condition"])?;
+
+        self.consume(LeftBrace, "E0207", &[&"{", &"if expression branch"])?;
+        let then_branch = self.block_expression()?;
+        let else_branch = if matches!(self, Else) {
+            Some(if matches!(self, If) {
+                self.if_expression()?
+            } else {
+                self.consume(LeftBrace, "E0207", &[&"{", &"else expression branch"])?;
+                self.block_expression()?
+            })
+        } else {
+            None
+        };
+
+        Ok(self.ast.push_expr(Expr::If {
+            keyword,
+            condition,
+            then_branch,
+            else_branch,
+        }))
+    }
+
+    /// blockExpr      → "{" declaration* expression? "}" ;
+    fn block_expression(&mut self) -> Result<ExprId, Error> {
+        let mut statements: Vec<StmtId> = Vec::new();
+        let mut value: Option<ExprId> = None;
+        while !self.check(RightBrace) && !self.is_end() {
+            if self.starts_statement() {
+                statements.push(self.declaration()?);
+                continue;
+            }
+            let expr = self.expression()?;
+            if self.check(RightBrace) {
+                // the trailing expression is the value of the block
+                value = Some(expr);
+            } else {
+                self.end_expression_statement(expr)?;
+                statements.push(self.ast.push_stmt(Stmt::ExprStmt { expression: expr }));
+            }
+        }
+        self.consume(RightBrace, "E0205", &[])?;
+
+        Ok(self.ast.push_expr(Expr::Block { statements, value }))
+    }
+
+    /// the ';' after an expression statement, a match used as a
+    /// statement already ends with its '}' so there it is optional
+    fn end_expression_statement(&mut self, expr: ExprId) -> Result<(), Error> {
+        if let Expr::Match { .. } = self.ast[expr] {
+            matches!(self, Semicolon);
+            return Ok(());
+        }
+        self.consume_terminator("E0206", &[&";", &"value"])
+    }
+
+    /// matchExpr      → "match" "(" expression ")" "{" ( arm ( "," arm )* ","? )? "}" ;
+    /// arm            → pattern "->" expression ;
+    fn match_expression(&mut self) -> Result<ExprId, Error> {
+        let keyword = self.previous().clone();
+        self.consume(LeftParen, "E0206", &[&"(", &"'match'"])?;
+        let subject = self.expression()?;
+        self.consume(RightParen, "E0206", &[&")", &"match subject"])?;
+        self.consume(LeftBrace, "E0207", &[&"{", &"match arms"])?;
+
+        let mut arms = Vec::new();
+        while !self.check(RightBrace) && !self.is_end() {
+            let pattern = self.pattern()?;
+            self.consume(Arrow, "E0206", &[&"->", &"pattern"])?;
+            let body = self.expression()?;
+            // a block value ends with '}', the comma after it may be left out
+            let block = std::matches!(self.ast[body], Expr::Block { .. });
+            arms.push(MatchArm { pattern, body });
+            if !matches!(self, Comma) && !block && !self.check(RightBrace) {
+                return Err(self.error(self.peak(), "E0208", &[&",", &"match arms"]));
+            }
+        }
+        self.consume(RightBrace, "E0206", &[&"}", &"match arms"])?;
+
+        Ok(self.ast.push_expr(Expr::Match {
+            keyword,
+            subject,
+            strings: StringArms::new(&arms),
+            arms,
+        }))
+    }
+
+    /// pattern        → "_" | literal | number ( ".." number )? | IDENTIFIER
+    ///                | IDENTIFIER "{" ( field ( "," field )* ","? )? "}"
+    ///                | "[" ( pattern ( "," pattern )* )? ( ","? "..." IDENTIFIER )? "]"
+    ///                | "{" ( entry ( "," entry )* ","? )? "}" ;
+    /// field          → IDENTIFIER ( ":" pattern )? ;
+    /// entry          → literal ":" pattern ;
+    /// number         → "-"? NUMBER ;
+    fn pattern(&mut self) -> Result<Pattern, Error> {
+        if self.check(Number) || self.check(Minus) {
+            let start = self.number_pattern()?;
+            if matches!(self, DotDot) {
+                let end = self.number_pattern()?;
+                return Ok(Pattern::Range { start, end });
+            }
+            return Ok(Pattern::Literal(Literal::Number(start)));
+        }
+        if [String, True, False, Nil]
+            .into_iter()
+            .any(|token_type| self.check(token_type))
+        {
+            let literal = self.primary()?;
+            return match &self.ast[literal] {
+                Expr::Literal { value } => Ok(Pattern::Literal(value.clone())),
+                _ => unreachable!(),
+            };
+        }
+        if matches!(self, LeftBracket) {
+            return self.list_pattern();
+        }
+        if matches!(self, LeftBrace) {
+            return self.map_pattern();
+        }
+        let name = self.consume(Identifier, "E0210", &[&"pattern"])?.clone();
+        if name.lexeme == "_" {
+            return Ok(Pattern::Wildcard);
+        }
+        if matches!(self, LeftBrace) {
+            return self.instance_pattern(name);
+        }
+        Ok(Pattern::Binding(name))
+    }
+
+    fn number_pattern(&mut self) -> Result<NumberType, Error> {
+        let minus = if matches!(self, Minus) {
+            Some(self.previous().clone())
+        } else {
+            None
+        };
+        if !self.check(Number) {
+            return Err(self.error(self.peak(), "E0210", &[&"number in pattern"]));
+        }
+        let literal = self.primary()?;
+        match self.ast[literal] {
+            Expr::Literal {
+                value: Literal::Number(n),
+            } => match minus {
+                Some(minus) => n.unary_op(&minus),
+                None => Ok(n),
+            },
+            _ => unreachable!(),
+        }
+    }
+
+    fn list_pattern(&mut self) -> Result<Pattern, Error> {
+        let bracket = self.previous().clone();
+        let mut elements = Vec::new();
+        let mut rest = None;
+        while !self.check(RightBracket) && !self.is_end() {
+            if matches!(self, Ellipsis) {
+                let name = self.consume(Identifier, "E0209", &[&"name", &"'...'"])?.clone();
+                rest = Some(Box::new(if name.lexeme == "_" {
+                    Pattern::Wildcard
+                } else {
+                    Pattern::Binding(name)
+                }));
+                break;
+            }
+            elements.push(self.pattern()?);
+            if !matches!(self, Comma) {
+                break;
+            }
+        }
+        self.consume(RightBracket, "E0206", &[&"]", &"list pattern"])?;
+        Ok(Pattern::List {
+            bracket,
+            elements,
+            rest,
+        })
+    }
+
+    fn map_pattern(&mut self) -> Result<Pattern, Error> {
+        let brace = self.previous().clone();
+        let mut entries = Vec::new();
+        while !self.check(RightBrace) && !self.is_end() {
+            let key = match self.pattern()? {
+                Pattern::Literal(key) => key,
+                _ => return Err(self.error(self.previous(), "E0217", &[])),
+            };
+            self.consume(Colon, "E0206", &[&":", &"map pattern key"])?;
+            entries.push((key, self.pattern()?));
+            if !matches!(self, Comma) {
+                break;
+            }
+        }
+        self.consume(RightBrace, "E0206", &[&"}", &"map pattern"])?;
+        Ok(Pattern::Map { brace, entries })
+    }
+
+    fn instance_pattern(&mut self, class: Token) -> Result<Pattern, Error> {
+        let mut fields = Vec::new();
+        while !self.check(RightBrace) && !self.is_end() {
+            let name = self.consume(Identifier, "E0210", &[&"field name"])?.clone();
+            let pattern = if matches!(self, Colon) {
+                self.pattern()?
+            } else {
+                Pattern::Binding(name.clone())
+            };
+            fields.push((name, pattern));
+            if !matches!(self, Comma) {
+                break;
+            }
+        }
+        self.consume(RightBrace, "E0206", &[&"}", &"field patterns"])?;
+        Ok(Pattern::Instance {
+            class: self.ast.push_expr(Expr::Variable { name: class }),
+            fields,
+        })
+    }
+
+    /// whether the next tokens can only start a statement, not an expression
+    fn starts_statement(&self) -> bool {
+        [
+            Var, Fun, Class, Print, LeftBrace, If, While, For, Return, Break, Continue, Assert,
+            Defer, Try, Throw,
+        ]
+        .into_iter()
+        .any(|token_type| self.check(token_type))
+            || (self.check(Identifier) && self.check_next(Colon))
+    }
+
     // expression parser
     // ------------------------------------------------
     // ------------------------------------------------
 
     fn peak(&self) -> &Token {
-        &self.tokens[self.current]
+        self.tokens.get(self.current).unwrap_or(&self.eof)
     }
 
     fn is_end(&self) -> bool {
@@ -629,7 +1215,76 @@ impl<'a> Parser<'a> {
     }
 
     fn previous(&self) -> &Token {
-        &self.tokens[self.current - 1]
+        self.current
+            .checked_sub(1)
+            .and_then(|previous| self.tokens.get(previous))
+            .unwrap_or(&self.eof)
+    }
+
+    /// rebuild the source text of tokens[start..end], keeping a space
+    /// wherever the tokens were apart in the source
+    /// `"a ${b} c"`, the first segment already consumed. the segments
+    /// alternate with the expressions until a String token ends it
+    fn interpolation(&mut self) -> Result<ExprId, Error> {
+        let token = self.previous().clone();
+        let mut parts = Vec::new();
+        let mut segment = token.clone();
+        loop {
+            self.push_segment(&mut parts, &segment);
+            parts.push(self.expression()?);
+            if !matches!(self, Interpolation) {
+                break;
+            }
+            segment = self.previous().clone();
+        }
+        let end = self.consume(String, "E0206", &[&"}", &"interpolated expression"])?.clone();
+        self.push_segment(&mut parts, &end);
+        Ok(self.ast.push_expr(Expr::Interpolation { token, parts }))
+    }
+
+    /// a non-empty segment of an interpolated string, as a literal part
+    fn push_segment(&mut self, parts: &mut Vec<ExprId>, segment: &Token) {
+        if let Literal::String(text) = &segment.literal {
+            if !text.is_empty() {
+                parts.push(self.ast.string(text));
+            }
+        }
+    }
+
+    fn source_text(&self, start: usize, end: usize) -> std::string::String {
+        let mut text = std::string::String::new();
+        let mut last_end: Option<usize> = None;
+        for token in &self.tokens[start..end] {
+            if last_end.is_some_and(|last_end| last_end < token.offset) {
+                text.push(' ');
+            }
+            text.push_str(&token.lexeme);
+            last_end = Some(token.offset + token.lexeme.len());
+        }
+        text
+    }
+
+    /// check the token after the current one
+    /// parse one level further in, within the nesting limit and with
+    /// stack to spare
+    fn nested<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        self.depth += 1;
+        let result = match limits::limits().max_nesting_depth {
+            Some(max) if self.depth > max => Err(self.error(self.peak(), "E0214", &[&max])),
+            _ => grow_stack(|| parse(self)),
+        };
+        self.depth -= 1;
+        result
+    }
+
+    fn check_next(&self, token_type: TokenType) -> bool {
+        match self.tokens.get(self.current + 1) {
+            Some(token) => token.token_type == token_type,
+            None => false,
+        }
     }
 
     fn check(&self, token_type: TokenType) -> bool {
@@ -642,23 +1297,40 @@ impl<'a> Parser<'a> {
         true
     }
 
-    fn consume(&mut self, token_type: TokenType, message: &str) -> Result<&Token, Error> {
+    fn consume(
+        &mut self,
+        token_type: TokenType,
+        code: &'static str,
+        args: &[&dyn Display],
+    ) -> Result<&Token, Error> {
         if self.check(token_type) {
             return Ok(self.advance());
         }
-        Err(self.error(self.peak(), message))
-        // Err(Error {
-        //     message: message.to_string(),
-        //     error_type: ErrorType::SyntaxError,
-        // })
+        Err(self.error(self.peak(), code, args))
     }
 
-    pub fn error(&self, token: &Token, message: &str) -> Error {
-        parse_error(token, message);
-        Error {
-            message: message.to_string(),
-            error_type: ErrorType::SyntaxError,
+    /// consume the ';' ending a statement
+    /// with optional semicolons a line break or the end of input will do
+    fn consume_terminator(
+        &mut self,
+        code: &'static str,
+        args: &[&dyn Display],
+    ) -> Result<(), Error> {
+        if matches!(self, Semicolon) {
+            return Ok(());
         }
+        if self.optional_semicolons
+            && (self.is_end() || self.peak().line > self.previous().line)
+        {
+            return Ok(());
+        }
+        Err(self.error(self.peak(), code, args))
+    }
+
+    /// report a compile error at `token`, `code` and `args` as for `Error::coded`
+    pub fn error(&self, token: &Token, code: &'static str, args: &[&dyn Display]) -> Error {
+        parse_error(token, code, args);
+        Error::coded(code, args).with_type(ErrorType::SyntaxError)
     }
 
     // until we reach a semicolon ';' or a statement keyword
@@ -671,7 +1343,8 @@ impl<'a> Parser<'a> {
             }
 
             match self.peak().token_type {
-                Class | Fun | Var | For | If | While | Print | Return => return,
+                Class | Fun | Var | For | If | While | Print | Return | Break | Continue
+                | Assert | Defer | Try | Throw => return,
                 _ => (),
             }
 
@@ -679,49 +1352,88 @@ impl<'a> Parser<'a> {
         }
     }
 
-    fn finish_index(&mut self, expr: Expr) -> Result<Expr, Error> {
-        let index = self.expression()?;
-        let index_end: Option<Box<Expr>> = if matches!(self, Colon) {
-            Some(Box::new(self.expression()?))
+    /// index          → expression | expression? ":" expression? ( ":" expression? )? ;
+    fn finish_index(&mut self, expr: ExprId, optional: bool) -> Result<ExprId, Error> {
+        // a bound left out of a slice is nil, the start or the end
+        let index = if self.check(Colon) {
+            self.ast.nil()
+        } else {
+            self.expression()?
+        };
+        let index_end: Option<ExprId> = if matches!(self, Colon) {
+            Some(if self.check(RightBracket) || self.check(Colon) {
+                self.ast.nil()
+            } else {
+                self.expression()?
+            })
         } else {
             None
         };
-        self.consume(RightBracket, "Expect ']' after index.")?;
-        Ok(Expr::Index {
-            object: Box::new(expr),
+        // a left out step is the same as no step
+        let stepped = index_end.is_some() && matches!(self, Colon);
+        let index_step = if stepped && !self.check(RightBracket) {
+            Some(self.expression()?)
+        } else {
+            None
+        };
+        self.consume(RightBracket, "E0206", &[&"]", &"index"])?;
+        Ok(self.ast.push_expr(Expr::Index {
+            object: expr,
             operator: self.previous().clone(),
-            index: Box::new(index),
+            index,
             index_end,
-        })
+            index_step,
+            optional,
+        }))
     }
 
-    fn finish_call(&mut self, expr: Expr) -> Result<Expr, Error> {
-        let mut arguments: Vec<Expr> = Vec::new();
+    /// arguments      → argument ( "," argument )* ;
+    /// argument       → ( IDENTIFIER ":" )? expression ;
+    fn finish_call(&mut self, expr: ExprId) -> Result<ExprId, Error> {
+        let mut arguments: Vec<ExprId> = Vec::new();
+        let mut keywords: Vec<(Token, ExprId)> = Vec::new();
         if !self.check(RightParen) {
             loop {
-                if arguments.len() >= 255 {
-                    return Err(self.error(self.peak(), "Can't have more than 255 arguments."));
+                if arguments.len() + keywords.len() >= 255 {
+                    return Err(self.error(self.peak(), "E0203", &[&255, &"arguments"]));
+                }
+                if !compat::jlox() && self.check(Identifier) && self.check_next(Colon) {
+                    let name = self.advance().clone();
+                    self.advance();
+                    if keywords.iter().any(|(other, _)| other.lexeme == name.lexeme) {
+                        return Err(self.error(&name, "E0204", &[]));
+                    }
+                    keywords.push((name, self.expression()?));
+                } else if !keywords.is_empty() {
+                    return Err(self.error(
+                        self.peak(),
+                        "E0209",
+                        &[&"keyword argument", &"keyword arguments"],
+                    ));
+                } else {
+                    arguments.push(self.expression()?);
                 }
-                arguments.push(self.expression()?);
                 if !matches!(self, Comma) {
                     break;
                 }
             }
         }
-        self.consume(RightParen, "Expect ')' after arguments.")?;
-        Ok(Expr::Call {
-            callee: Box::new(expr),
+        self.consume(RightParen, "E0206", &[&")", &"arguments"])?;
+        Ok(self.ast.push_expr(Expr::Call {
+            callee: expr,
             paren: self.previous().clone(),
             arguments,
-        })
+            keywords,
+        }))
     }
 
-    fn finish_list(&mut self, keyword: Token) -> Result<Expr, Error> {
-        let mut elements: Vec<Expr> = Vec::new();
+    fn finish_list(&mut self, keyword: Token) -> Result<ExprId, Error> {
+        let mut elements: Vec<ExprId> = Vec::new();
         if !self.check(RightParen) {
             loop {
-                if elements.len() >= 255 {
-                    return Err(self.error(self.peak(), "Can't have more than 255 elements."));
+                let max = limits::limits().max_list_elements;
+                if elements.len() >= max {
+                    return Err(self.error(self.peak(), "E0203", &[&max, &"elements"]));
                 }
                 elements.push(self.expression()?);
                 if !matches!(self, Comma) {
@@ -729,7 +1441,108 @@ impl<'a> Parser<'a> {
                 }
             }
         }
-        self.consume(RightParen, "Expect ')' after elements.")?;
-        Ok(Expr::List { keyword, elements })
+        self.consume(RightParen, "E0206", &[&")", &"elements"])?;
+        Ok(self.ast.push_expr(Expr::List { keyword, elements }))
+    }
+
+    /// a `{` starts a map instead of a block when it is empty or its
+    /// first key is a literal followed by ':', a name followed by ':'
+    /// would be a labeled loop
+    fn starts_map(&self) -> bool {
+        if compat::jlox() || !self.check(LeftBrace) {
+            return false;
+        }
+        let token_type = |offset: usize| self.tokens.get(self.current + offset).map(|t| &t.token_type);
+        std::matches!(token_type(1), Some(RightBrace))
+            || (std::matches!(token_type(1), Some(String | Number | True | False | Nil))
+                && std::matches!(token_type(2), Some(Colon)))
+    }
+
+    /// mapLiteral     → "{" ( expression ":" expression ( "," expression ":" expression )* ","? )? "}" ;
+    fn finish_map(&mut self, brace: Token) -> Result<ExprId, Error> {
+        let mut keys: Vec<ExprId> = Vec::new();
+        let mut values: Vec<ExprId> = Vec::new();
+        while !self.check(RightBrace) {
+            let max = limits::limits().max_list_elements;
+            if keys.len() >= max {
+                return Err(self.error(self.peak(), "E0203", &[&max, &"entries"]));
+            }
+            keys.push(self.expression()?);
+            self.consume(Colon, "E0206", &[&":", &"map key"])?;
+            values.push(self.expression()?);
+            if !matches!(self, Comma) {
+                break;
+            }
+        }
+        self.consume(RightBrace, "E0206", &[&"}", &"map entries"])?;
+        Ok(self.ast.push_expr(Expr::Map {
+            brace,
+            keys,
+            values,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str, optional_semicolons: bool) -> Result<Vec<StmtId>, Error> {
+        let mut scanner = Scanner::new(source);
+        scanner.scan_tokens();
+        let mut parser = Parser::new(&scanner.tokens);
+        parser.set_optional_semicolons(optional_semicolons);
+        parser.parse()
+    }
+
+    #[test]
+    fn test_optional_semicolons() {
+        assert_eq!(parse("print 1 + 2", true).unwrap().len(), 1);
+        assert_eq!(parse("var a = 1\nprint a", true).unwrap().len(), 2);
+        assert!(parse("print 1 + 2", false).is_err());
+        // two statements on one line still need a separator
+        assert!(parse("print 1 print 2", true).is_err());
+    }
+
+    #[test]
+    fn test_limits() {
+        limits::set_limits(Limits {
+            max_string_length: Some(3),
+            max_list_elements: 2,
+            max_nesting_depth: Some(4),
+        });
+        assert!(parse("print list(1, 2);", false).is_ok());
+        let error = parse("print list(1, 2, 3);", false).unwrap_err();
+        assert_eq!(error.message(), "Can't have more than 2 elements.");
+        assert!(parse("print {1: 1, 2: 2, 3: 3};", false).is_err());
+        // the statement, the expression and two groupings
+        assert!(parse("print ((1));", false).is_ok());
+        let error = parse("print (((1)));", false).unwrap_err();
+        assert_eq!(error.message(), "Nesting is deeper than 4 levels.");
+
+        let mut scanner = Scanner::new("\"abc\" \"abcd\" \"a${1}bcde\"");
+        scanner.scan_tokens();
+        let errors: Vec<std::string::String> = scanner.errors.iter().map(Error::message).collect();
+        assert_eq!(errors, ["String literal is longer than 3 characters."; 2]);
+        limits::set_limits(Limits::default());
+    }
+
+    #[test]
+    fn test_end_of_input() {
+        assert!(parse("", false).unwrap().is_empty());
+        assert!(parse("// only a comment\n", false).unwrap().is_empty());
+        assert!(parse("print", false).is_err());
+
+        // tokens built by hand may lack the Eof
+        let tokens = vec![];
+        assert!(Parser::new(&tokens).parse().unwrap().is_empty());
+        let tokens = vec![
+            Token::new("print", Print, 1, 1),
+            Token {
+                literal: Literal::Number(NumberType::Integer(1)),
+                ..Token::new("1", Number, 1, 1)
+            },
+        ];
+        assert!(Parser::new(&tokens).parse().is_err());
     }
 }
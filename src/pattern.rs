@@ -0,0 +1,87 @@
+/// the left side of a match arm, tested against the matched value and
+/// binding names in the arm's scope when it fits
+use std::collections::HashMap;
+
+use crate::{ExprId, Literal, NumberType, Token};
+
+#[derive(Debug, Clone)]
+pub enum Pattern {
+    /// `_`, fits anything and binds nothing
+    Wildcard,
+    /// a number, string, boolean or nil, fits values equal to it
+    Literal(Literal),
+    /// `start..end`, fits numbers from start up to but not including end
+    Range { start: NumberType, end: NumberType },
+    /// a name, fits anything and binds it
+    Binding(Token),
+    /// `[first, second, ...rest]`, a list with exactly these elements,
+    /// or at least these when there is a rest pattern for the others
+    List {
+        bracket: Token,
+        elements: Vec<Pattern>,
+        rest: Option<Box<Pattern>>,
+    },
+    /// `Point { x, y: 0 }`, an instance of the class or a subclass
+    /// whose fields fit, a field alone binds it under its own name
+    Instance {
+        /// an Expr::Variable naming the class
+        class: ExprId,
+        fields: Vec<(Token, Pattern)>,
+    },
+    /// `{"name": n, 1: _}`, a map holding each of the keys with a value
+    /// that fits, other keys are left alone
+    Map {
+        brace: Token,
+        entries: Vec<(Literal, Pattern)>,
+    },
+}
+
+/// `pattern -> value` inside a match
+#[derive(Debug, Clone)]
+pub struct MatchArm {
+    pub pattern: Pattern,
+    pub body: ExprId,
+}
+
+/// where a string subject can go, built once per match so a dispatch
+/// table of string arms is a lookup instead of a comparison per arm
+#[derive(Debug, Clone, Default)]
+pub struct StringArms {
+    /// the first arm with each string literal
+    literals: HashMap<String, usize>,
+    /// arms that are not literals or ranges and may fit a string too
+    others: Vec<usize>,
+}
+
+impl StringArms {
+    pub fn new(arms: &[MatchArm]) -> Self {
+        let mut table = Self::default();
+        for (index, arm) in arms.iter().enumerate() {
+            match &arm.pattern {
+                Pattern::Literal(Literal::String(s)) => {
+                    table.literals.entry(s.clone()).or_insert(index);
+                }
+                Pattern::Literal(_)
+                | Pattern::Range { .. }
+                | Pattern::List { .. }
+                | Pattern::Map { .. } => {}
+                Pattern::Wildcard | Pattern::Binding(_) | Pattern::Instance { .. } => {
+                    table.others.push(index)
+                }
+            }
+        }
+        table
+    }
+
+    /// the arms that may fit `s`, in order, a literal arm ends the
+    /// list since it always fits
+    pub fn candidates(&self, s: &str) -> impl Iterator<Item = usize> + '_ {
+        let literal = self.literals.get(s).copied();
+        let before = literal.unwrap_or(usize::MAX);
+        self.others
+            .iter()
+            .copied()
+            .take_while(move |&index| index < before)
+            .chain(literal)
+    }
+}
@@ -0,0 +1,173 @@
+/// a multi-line layout for nested values, used by the prompt and
+/// `pp(x)` where Display would give one long line
+use crate::Object;
+
+#[derive(Debug, Clone)]
+pub struct PrettyPrinter {
+    /// spaces per nesting level
+    pub indent: usize,
+    /// a value that fits in this many columns stays on one line
+    pub width: usize,
+    /// lists and instances nested deeper are shown as `[...]`
+    pub max_depth: usize,
+}
+
+impl Default for PrettyPrinter {
+    fn default() -> Self {
+        Self {
+            indent: 2,
+            width: 60,
+            max_depth: 6,
+        }
+    }
+}
+
+impl PrettyPrinter {
+    pub fn format(&self, value: &Object) -> String {
+        match value {
+            // a string on its own prints as it does with print
+            Object::String(s) => s.to_string(),
+            _ => self.render(value, 0, &mut Vec::new(), false),
+        }
+    }
+
+    /// `seen` holds the lists and instances being printed, meeting one
+    /// of them again is a cycle and prints as `...`
+    fn render(&self, value: &Object, level: usize, seen: &mut Vec<usize>, flat: bool) -> String {
+        let (address, open, close) = match value {
+            Object::List(list) => (list.as_ptr() as usize, "[".to_string(), "]"),
+            Object::Tuple(tuple) => (tuple.as_ptr() as usize, "(".to_string(), ")"),
+            Object::Map(map) => (map.as_ptr() as usize, "{".to_string(), "}"),
+            Object::Instance(instance) => (
+                instance.as_ptr() as usize,
+                format!("{} {{", instance.borrow().class().borrow().name()),
+                "}",
+            ),
+            Object::String(s) => return format!("\"{}\"", s),
+            _ => return value.to_string(),
+        };
+        if seen.contains(&address) {
+            return "...".to_string();
+        }
+        if level >= self.max_depth {
+            return format!("{}...{}", open, close);
+        }
+        if !flat {
+            let line = self.render(value, level, seen, true);
+            if level * self.indent + line.len() <= self.width {
+                return line;
+            }
+        }
+
+        seen.push(address);
+        let items: Vec<String> = match value {
+            Object::List(list) => list
+                .borrow()
+                .inner
+                .iter()
+                .map(|item| self.render(item, level + 1, seen, flat))
+                .collect(),
+            Object::Tuple(tuple) => tuple
+                .iter()
+                .map(|item| self.render(item, level + 1, seen, flat))
+                .collect(),
+            Object::Instance(instance) => {
+                let instance = instance.borrow();
+                let mut fields: Vec<_> = instance.fields().collect();
+                fields.sort_by(|a, b| a.0.cmp(b.0));
+                fields
+                    .into_iter()
+                    .map(|(name, field)| {
+                        format!("{}: {}", name, self.render(field, level + 1, seen, flat))
+                    })
+                    .collect()
+            }
+            Object::Map(map) => map
+                .borrow()
+                .iter()
+                .map(|(key, value)| {
+                    format!(
+                        "{}: {}",
+                        self.render(key, level + 1, seen, flat),
+                        self.render(value, level + 1, seen, flat)
+                    )
+                })
+                .collect(),
+            _ => unreachable!(),
+        };
+        seen.pop();
+
+        let spaced = matches!(value, Object::Instance(_));
+        if items.is_empty() {
+            format!("{}{}", open, close)
+        } else if flat && spaced {
+            format!("{} {} {}", open, items.join(", "), close)
+        } else if flat {
+            format!("{}{}{}", open, items.join(", "), close)
+        } else {
+            let inner = " ".repeat((level + 1) * self.indent);
+            let outer = " ".repeat(level * self.indent);
+            format!(
+                "{}\n{}{}\n{}{}",
+                open,
+                inner,
+                items.join(&format!(",\n{}", inner)),
+                outer,
+                close
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::{List, NumberType};
+
+    fn list(items: Vec<Object>) -> Object {
+        Object::List(Rc::new(RefCell::new(List::from(items))))
+    }
+
+    fn number(n: i64) -> Object {
+        Object::Number(NumberType::Integer(n))
+    }
+
+    #[test]
+    fn test_layout() {
+        let printer = PrettyPrinter {
+            width: 14,
+            ..Default::default()
+        };
+        let short = list(vec![number(1), Object::String("a".into())]);
+        assert_eq!(printer.format(&short), "[1, \"a\"]");
+        let long = list(vec![
+            short.clone(),
+            list(vec![number(22), number(33), number(44)]),
+        ]);
+        assert_eq!(printer.format(&long), "[\n  [1, \"a\"],\n  [22, 33, 44]\n]");
+    }
+
+    #[test]
+    fn test_cycle_and_depth() {
+        let printer = PrettyPrinter::default();
+        let cycle = list(vec![number(1)]);
+        if let Object::List(inner) = &cycle {
+            inner.borrow_mut().push(cycle.clone());
+        }
+        assert_eq!(printer.format(&cycle), "[1, ...]");
+        // break the cycle so the test doesn't leak it
+        if let Object::List(inner) = &cycle {
+            inner.borrow_mut().inner.clear();
+        }
+
+        let shallow = PrettyPrinter {
+            max_depth: 1,
+            ..Default::default()
+        };
+        let nested = list(vec![list(vec![number(1)])]);
+        assert_eq!(shallow.format(&nested), "[[...]]");
+    }
+}
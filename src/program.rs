@@ -0,0 +1,156 @@
+/// a script scanned, parsed and resolved once, that can then run on any
+/// number of interpreters without going through those phases again
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::render::{self, Diagnostic, Phase};
+use crate::{
+    info_span, Ast, Binding, Error, ExprId, Interpreter, Parser, Resolver, Scanner, StmtId,
+};
+
+pub struct Program {
+    /// the tree the statements are in, shared with the functions and
+    /// classes the program declares
+    ast: Rc<Ast>,
+    stmts: Vec<StmtId>,
+    /// what the resolver found for each variable, global slots are the
+    /// ones in `globals`
+    locals: HashMap<ExprId, Binding>,
+    this_depths: HashMap<ExprId, usize>,
+    /// the names of the globals the program was resolved with, by slot
+    globals: Vec<String>,
+}
+
+/// scan, parse and resolve `source`, the diagnostics are handed back
+/// instead of printed
+pub fn compile(source: &str) -> Result<Program, Vec<Diagnostic>> {
+    compile_source(source, false)
+}
+
+/// compile a line typed at the prompt, where a trailing ';' can be left out
+pub fn compile_line(source: &str) -> Result<Program, Vec<Diagnostic>> {
+    compile_source(source, true)
+}
+
+fn compile_source(source: &str, optional_semicolons: bool) -> Result<Program, Vec<Diagnostic>> {
+    // resolving on an interpreter of its own keeps a failed compile from
+    // leaving anything behind in the one the program will run on
+    let mut scratch = Interpreter::new();
+    let (parsed, mut diagnostics) = render::capture(|| {
+        let mut scanner = Scanner::new(source);
+        info_span!("scan").in_scope(|| scanner.scan_tokens());
+        if scanner.had_error {
+            scanner.report_errors();
+            return None;
+        }
+        let mut parser = Parser::new(&scanner.tokens);
+        parser.set_optional_semicolons(optional_semicolons);
+        let stmts = info_span!("parse").in_scope(|| parser.parse()).ok()?;
+        let ast = parser.into_ast();
+        let mut resolver = Resolver::new(&mut scratch, &ast);
+        let resolved = info_span!("resolve").in_scope(|| resolver.resolve_stmts(&stmts));
+        if let Err(error) = resolved {
+            render::emit(&Diagnostic::new(Phase::Compile, &error));
+            return None;
+        }
+        (!resolver.has_error).then_some((ast, stmts))
+    });
+    match parsed {
+        Some((ast, stmts)) if diagnostics.is_empty() => Ok(Program {
+            ast: Rc::new(ast),
+            stmts,
+            locals: std::mem::take(&mut scratch.locals),
+            this_depths: std::mem::take(&mut scratch.this_depths),
+            globals: scratch
+                .globals
+                .borrow()
+                .names()
+                .into_iter()
+                .map(String::from)
+                .collect(),
+        }),
+        _ => {
+            if diagnostics.is_empty() {
+                diagnostics.push(Diagnostic::new(Phase::Compile, &Error::coded("E0216", &[])));
+            }
+            Err(diagnostics)
+        }
+    }
+}
+
+impl Program {
+    pub fn statements(&self) -> &[StmtId] {
+        &self.stmts
+    }
+
+    pub fn ast(&self) -> &Ast {
+        &self.ast
+    }
+
+    /// give `interpreter` the tree and what the resolver found, so the
+    /// statements can be executed on it. its globals don't have to be
+    /// laid out like the ones the program was compiled with
+    pub fn load(&self, interpreter: &mut Interpreter) {
+        interpreter.set_ast(Rc::clone(&self.ast));
+        let slots: Vec<usize> = {
+            let mut globals = interpreter.globals.borrow_mut();
+            self.globals
+                .iter()
+                .map(|name| globals.reserve(name))
+                .collect()
+        };
+        for (id, binding) in &self.locals {
+            match binding {
+                Binding::Local(depth) => interpreter.resolve(*id, *depth),
+                Binding::Global(slot) => interpreter.resolve_global(*id, slots[*slot]),
+            }
+        }
+        for (id, depth) in &self.this_depths {
+            interpreter.resolve_this(*id, *depth);
+        }
+    }
+
+    /// run the whole program on `interpreter`
+    pub fn run(&self, interpreter: &mut Interpreter) -> Result<(), Error> {
+        self.load(interpreter);
+        interpreter.interpret(&self.stmts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{NumberType, Object};
+
+    fn result(interpreter: &Interpreter) -> Object {
+        interpreter.globals.borrow().get("result").unwrap()
+    }
+
+    #[test]
+    fn test_run_twice() {
+        let program = compile(
+            "fun fib(n) { if (n < 2) return n; return fib(n - 1) + fib(n - 2); }
+             var result = fib(10);",
+        )
+        .unwrap();
+        let mut first = Interpreter::new();
+        program.run(&mut first).unwrap();
+        // globals defined before the program move its slots along
+        let mut second = Interpreter::new();
+        second.globals.borrow_mut().define("extra", Object::Nil);
+        program.run(&mut second).unwrap();
+        for interpreter in [&first, &second] {
+            assert!(result(interpreter).equals(&Object::Number(NumberType::Integer(55))));
+        }
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let Err(diagnostics) = compile("print 1 +;\nreturn 2;") else {
+            panic!("compiled");
+        };
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].code, "E0201");
+        assert_eq!(diagnostics[0].line, Some(1));
+    }
+}
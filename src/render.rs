@@ -0,0 +1,395 @@
+/// every error goes through an ErrorRenderer on its way to stderr, the
+/// English messages in CATALOG are only the default wording.
+///
+/// errors are raised with a code from CATALOG and the values from the
+/// program that fill it in, the words are only chosen when it is
+/// reported, so an embedder can word it in another language and a tool
+/// can match on the code
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::{compat, Error};
+
+/// code and English template of each message, `{}` marks a value
+/// taken from the program. more specific templates come first
+pub const CATALOG: &[(&str, &str)] = &[
+    // scanning
+    ("E0101", "Unexpected character."),
+    ("E0102", "Unterminated string."),
+    ("E0103", "Invalid number."),
+    ("E0104", "String literal is longer than {} characters."),
+    // parsing
+    ("E0201", "Expect expression."),
+    ("E0202", "Invalid assignment target."),
+    ("E0203", "Can't have more than {} {}."),
+    ("E0204", "Duplicate keyword argument."),
+    ("E0205", "Expect '}' after block"),
+    ("E0206", "Expect '{}' after {}."),
+    ("E0207", "Expect '{}' before {}."),
+    ("E0208", "Expect '{}' between {}."),
+    ("E0209", "Expect {} after {}."),
+    ("E0210", "Expect {}."),
+    ("E0211", "Cannot assign to a property of 'super'."),
+    ("E0212", "'super' is not a value, use 'super.name'."),
+    ("E0213", "Integer literal cannot have a fractional part."),
+    ("E0214", "Nesting is deeper than {} levels."),
+    ("E0215", "A parameter without a default cannot follow one with a default."),
+    ("E0216", "Program failed to compile."),
+    ("E0217", "Map pattern keys must be literals."),
+    // resolving
+    ("E0301", "Variable with this name already declared in this scope."),
+    ("E0302", "Cannot read local variable in its own initializer."),
+    ("E0303", "Cannot use 'this' outside of a class."),
+    ("E0304", "Cannot use 'super' outside of a class."),
+    ("E0305", "Cannot use 'super' in a class with no superclass."),
+    ("E0306", "Can't return from top-level code."),
+    ("E0307", "Cannot return a value from an initializer."),
+    ("E0308", "Cannot return from a deferred statement."),
+    ("E0309", "Cannot use '{}' inside a deferred statement."),
+    ("E0310", "Cannot use '{}' outside of a loop."),
+    ("E0311", "Undefined label '{}'."),
+    ("E0312", "Label already used by an enclosing loop."),
+    ("E0313", "A class cannot inherit from itself."),
+    ("E0314", "A frozen literal can only hold constant values."),
+    ("E0315", "Program failed to resolve."),
+    // running
+    ("E0401", "Undefined variable '{}'."),
+    ("E0402", "Undefined variable {}."),
+    ("E0403", "Variable '{}' used before being assigned."),
+    ("E0404", "Undefined property '{}'."),
+    ("E0405", "Only instances have properties."),
+    ("E0406", "Only instances have fields."),
+    ("E0407", "Operand must be a number."),
+    ("E0408", "Operand of {} must be a number."),
+    ("E0409", "Operands must be numbers."),
+    ("E0410", "Operands must be two numbers or two strings."),
+    ("E0411", "Operands of {} must be two numbers or two strings."),
+    ("E0412", "Cannot compare {} and {} with {}."),
+    ("E0413", "Division by zero."),
+    ("E0414", "Condition must be a boolean."),
+    ("E0415", "Can only call functions and classes."),
+    ("E0416", "Expected {} arguments but got {}."),
+    ("E0417", "Superclass must be a class."),
+    ("E0418", "Stack overflow."),
+    ("E0419", "Out of fuel."),
+    ("E0420", "Expected integer got {}"),
+    ("E0421", "Expected string got {}"),
+    ("E0422", "Expected list got {}"),
+    ("E0423", "Index out of range: {}"),
+    ("E0424", "Assertion failed: {}: {}"),
+    ("E0425", "Assertion failed: {}"),
+    ("E0426", "No match arm fits {}."),
+    ("E0427", "'{}' in a pattern must be a class."),
+    ("E0428", "Expected {} values but got {}."),
+    ("E0429", "Cannot destructure a {} into {} variables."),
+    ("E0430", "'{}' has no parameter '{}'."),
+    ("E0431", "Argument '{}' was already passed by position."),
+    ("E0432", "Missing argument '{}'."),
+    ("E0433", "'{}' does not take keyword arguments."),
+    ("E0434", "Cannot leave function '{}' with break or continue."),
+    ("E0435", "Method '{}' is not bound to an instance."),
+    ("E0436", "Exit status must be an integer, got {}."),
+    ("E0437", "Exit status {} is out of range."),
+    (
+        "E0438",
+        "Internal error: {}. This is a bug in the interpreter, please report it.",
+    ),
+    ("E0439", "'super' only finds methods, '{}' is a field, use 'this.{}'."),
+    ("E0440", "Cannot modify a frozen list."),
+    (
+        "E0441",
+        "Strings cannot be changed, build a new one with slices and '+'.",
+    ),
+    ("E0442", "A {} has no method '{}'."),
+    ("E0443", "Cannot slice a map."),
+    ("E0444", "Undefined key '{}'."),
+    ("E0445", "Map keys must be numbers, strings, booleans or nil, got {}."),
+    ("E0446", "Cannot modify a frozen map."),
+    ("E0447", "Cannot index '{}' because it is nil."),
+    ("E0448", "Cannot index nil."),
+    ("E0449", "Uncaught exception: {}."),
+    ("E0450", "Cannot iterate over a {}."),
+    ("E0451", "Slice step cannot be zero."),
+    ("E0452", "A slice with a step needs a list of {} items."),
+    ("E0453", "Right operand of 'is' must be a class, got {}."),
+    ("E0454", "Cannot iterate over an instance without hasNext() and next() methods."),
+    ("E0455", "Unsupported unary operation"),
+    ("E0456", "Unsupported binary operation"),
+    // natives
+    ("E0501", "Expected a function but got {}."),
+    ("E0502", "Cannot bind an argument to a function that takes none."),
+    ("E0503", "Expected a list."),
+    ("E0504", "Sort comparator must return a number."),
+    ("E0505", "Could not write output: {}."),
+    ("E0506", "Store path must be a string."),
+    ("E0507", "Store key must be a string."),
+    ("E0508", "Expected a store opened by storeOpen."),
+    ("E0509", "Only numbers, strings, booleans and nil can be stored."),
+    ("E0510", "Could not open store '{}': {}."),
+    ("E0511", "Could not write store '{}': {}."),
+    ("E0512", "Corrupted store '{}' at line {}."),
+    ("E0513", "No documentation for {}."),
+    ("E0514", "scan() takes a string and a pattern string."),
+    ("E0515", "Matches was changed from outside."),
+    ("E0516", "Invalid pattern '{}': {}."),
+    ("E0517", "template() takes a string and a map or an instance."),
+    ("E0518", "expandEnv() takes a string."),
+    ("E0519", "No value for '{}' in template."),
+    ("E0520", "Unclosed '${' in template."),
+    ("E0521", "Precision must be nil or an integer from 0 to {}."),
+    ("E0522", "fromMap() takes a class and a map."),
+    ("E0523", "Field names must be strings, got {}."),
+    ("E0524", "Expected a number but got {}."),
+    ("E0525", "Cannot sort {} and {} without a comparator."),
+    ("E0526", "range() takes integers, got {}."),
+    ("E0527", "range() step cannot be zero."),
+    ("E0528", "Cannot pop from an empty list."),
+    ("E0529", "List index must be an integer, got {}."),
+    ("E0530", "{}() takes strings, got {}."),
+    ("E0531", "Could not read input: {}."),
+    ("E0532", "Could not read file '{}': {}."),
+    ("E0533", "Could not write file '{}': {}."),
+    ("E0534", "toString() must return a string, got {}."),
+    ("E0535", "Expected a boolean but got {}."),
+    ("E0536", "The iterator has no more elements."),
+    ("E0537", "The iterator's fields were changed."),
+    ("E0538", "Could not write trace: {}."),
+    // writing source
+    ("E0601", "Cannot write {} as Lox source."),
+];
+
+/// the code of control flow unwinding through `Err`, which has no message
+pub const UNKNOWN: &str = "E0000";
+
+/// the English template of `code`
+pub fn template(code: &str) -> Option<&'static str> {
+    CATALOG
+        .iter()
+        .find(|(entry, _)| *entry == code)
+        .map(|(_, template)| *template)
+}
+
+/// the English message of `code`, each `{}` of its template filled with
+/// the next of `args`
+pub fn english(code: &str, args: &[String]) -> String {
+    let Some(template) = template(code) else {
+        return format!("{} {}", code, args.join(", "));
+    };
+    let mut args = args.iter();
+    let mut pieces = template.split("{}");
+    let mut message = pieces.next().unwrap_or_default().to_string();
+    for piece in pieces {
+        message.push_str(args.next().map_or("", String::as_str));
+        message.push_str(piece);
+    }
+    message
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Phase {
+    Scan,
+    /// parsing and resolving, both report before anything runs
+    Compile,
+    Runtime,
+}
+
+/// what an error points at
+#[derive(Debug, Clone, PartialEq)]
+pub enum Location {
+    None,
+    End,
+    Token(String),
+    /// a character the scanner stopped at
+    Character(char),
+}
+
+/// one error ready to be worded and printed
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub code: &'static str,
+    pub args: Vec<String>,
+    pub phase: Phase,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub location: Location,
+}
+
+impl Diagnostic {
+    pub fn new(phase: Phase, error: &Error) -> Self {
+        Self {
+            code: error.code,
+            args: error.args.clone(),
+            phase,
+            line: None,
+            column: None,
+            location: Location::None,
+        }
+    }
+
+    pub fn at(mut self, line: usize, location: Location) -> Self {
+        self.line = Some(line);
+        self.location = location;
+        self
+    }
+
+    pub fn column(mut self, column: usize) -> Self {
+        self.column = Some(column);
+        self
+    }
+
+    /// the English message, as `CATALOG` words it
+    pub fn english(&self) -> String {
+        english(self.code, &self.args)
+    }
+}
+
+/// words diagnostics, `message` is enough to translate them and
+/// `render` changes how the whole report looks
+pub trait ErrorRenderer {
+    /// the text of the message alone
+    fn message(&self, diagnostic: &Diagnostic) -> String {
+        match compat::message(diagnostic.code) {
+            Some(message) if diagnostic.phase == Phase::Compile && compat::jlox() => {
+                message.to_string()
+            }
+            _ => diagnostic.english(),
+        }
+    }
+
+    /// the full report printed to stderr, without a trailing newline
+    fn render(&self, diagnostic: &Diagnostic) -> String {
+        let message = self.message(diagnostic);
+        let line = match diagnostic.line {
+            Some(line) => line,
+            None if diagnostic.phase == Phase::Runtime => return message,
+            None => return format!("Error: {}", message),
+        };
+        match (diagnostic.phase, &diagnostic.location) {
+            (Phase::Runtime, _) if compat::jlox() => format!("{}\n[line {}]", message, line),
+            (Phase::Runtime, Location::Token(lexeme)) => {
+                format!("{}\n[line {}] Error at {}", message, line, lexeme)
+            }
+            (Phase::Runtime, _) => format!("{}\n[line {}]", message, line),
+            (Phase::Scan, location) => match (diagnostic.column, location) {
+                (Some(column), Location::Character(c)) if !compat::jlox() => format!(
+                    "[line {}, column {}] Error at '{}' (U+{:04X}): {}",
+                    line, column, c, *c as u32, message
+                ),
+                (Some(column), _) if !compat::jlox() => {
+                    format!("[line {}, column {}] Error: {}", line, column, message)
+                }
+                _ => format!("[line {}] Error: {}", line, message),
+            },
+            (Phase::Compile, Location::End) => {
+                format!("[line {}] Error at end: {}", line, message)
+            }
+            (Phase::Compile, Location::Token(lexeme)) => {
+                format!("[line {}] Error at '{}': {}", line, lexeme, message)
+            }
+            (Phase::Compile, _) => format!("[line {}] Error: {}", line, message),
+        }
+    }
+}
+
+/// the messages as the catalog has them
+pub struct English;
+
+impl ErrorRenderer for English {}
+
+thread_local! {
+    static RENDERER: RefCell<Rc<dyn ErrorRenderer>> = RefCell::new(Rc::new(English));
+
+    /// where `emit` puts diagnostics while `capture` is collecting them
+    static CAPTURED: RefCell<Option<Vec<Diagnostic>>> = const { RefCell::new(None) };
+}
+
+/// word every error reported on this thread with `renderer`
+pub fn set_renderer(renderer: impl ErrorRenderer + 'static) {
+    RENDERER.with(|current| *current.borrow_mut() = Rc::new(renderer));
+}
+
+pub fn render(diagnostic: &Diagnostic) -> String {
+    let renderer = RENDERER.with(|current| current.borrow().clone());
+    renderer.render(diagnostic)
+}
+
+/// render a diagnostic and print it to stderr, or keep it when it is
+/// being captured
+pub fn emit(diagnostic: &Diagnostic) {
+    let kept = CAPTURED.with(|captured| match captured.borrow_mut().as_mut() {
+        Some(captured) => {
+            captured.push(diagnostic.clone());
+            true
+        }
+        None => false,
+    });
+    if !kept {
+        eprintln!("{}", render(diagnostic));
+    }
+}
+
+/// run `f`, collecting the diagnostics it emits instead of printing them
+pub fn capture<T>(f: impl FnOnce() -> T) -> (T, Vec<Diagnostic>) {
+    let outer = CAPTURED.with(|captured| captured.replace(Some(Vec::new())));
+    let result = f();
+    let diagnostics = CAPTURED.with(|captured| captured.replace(outer));
+    (result, diagnostics.unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_english() {
+        assert_eq!(
+            english("E0206", &args(&[")", "arguments"])),
+            "Expect ')' after arguments."
+        );
+        assert_eq!(english("E0401", &args(&["x"])), "Undefined variable 'x'.");
+        // a value that looks like part of the template stays one value
+        assert_eq!(
+            english("E0412", &args(&["a and b", "nil", ">"])),
+            "Cannot compare a and b and nil with >."
+        );
+    }
+
+    #[test]
+    fn test_codes_are_unique() {
+        let mut codes: Vec<&str> = CATALOG.iter().map(|(code, _)| *code).collect();
+        codes.sort();
+        codes.dedup();
+        assert_eq!(codes.len(), CATALOG.len());
+    }
+
+    struct French;
+
+    impl ErrorRenderer for French {
+        fn message(&self, diagnostic: &Diagnostic) -> String {
+            match diagnostic.code {
+                "E0401" => format!("Variable '{}' non définie.", diagnostic.args[0]),
+                _ => diagnostic.english(),
+            }
+        }
+    }
+
+    #[test]
+    fn test_custom_renderer() {
+        let diagnostic = Diagnostic::new(Phase::Runtime, &Error::coded("E0401", &[&"x"]))
+            .at(3, Location::Token("x".to_string()));
+        assert_eq!(
+            render(&diagnostic),
+            "Undefined variable 'x'.\n[line 3] Error at x"
+        );
+        set_renderer(French);
+        assert_eq!(
+            render(&diagnostic),
+            "Variable 'x' non définie.\n[line 3] Error at x"
+        );
+        set_renderer(English);
+    }
+}
@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 use std::mem;
+use std::rc::Rc;
 
 use super::*;
 
@@ -18,19 +19,28 @@ enum ClassType {
 
 pub struct Resolver<'a> {
     interpreter: &'a mut Interpreter,
+    /// the tree the statements being resolved are in
+    ast: &'a Ast,
     scopes: Vec<HashMap<String, bool>>,
     current_function: FunctionType,
     current_class: ClassType,
+    /// labels of the enclosing loops, innermost last
+    loops: Vec<Option<String>>,
+    /// inside a defer, which has nowhere to return or break to
+    deferring: bool,
     pub has_error: bool,
 }
 
 impl<'a> Resolver<'a> {
-    pub fn new(interpreter: &'a mut Interpreter) -> Self {
+    pub fn new(interpreter: &'a mut Interpreter, ast: &'a Ast) -> Self {
         Self {
             interpreter,
+            ast,
             scopes: Vec::new(),
             current_function: FunctionType::None,
             current_class: ClassType::None,
+            loops: Vec::new(),
+            deferring: false,
             has_error: false,
         }
     }
@@ -43,52 +53,83 @@ impl<'a> Resolver<'a> {
         self.scopes.pop();
     }
 
-    fn resolve_stmt(&mut self, stmt: &Stmt) -> Result<(), Error> {
-        stmt.accept(self)
+    fn resolve_stmt(&mut self, stmt: StmtId) -> Result<(), Error> {
+        let ast = self.ast;
+        stmt.accept(ast, self)
     }
 
-    pub fn resolve_stmts(&mut self, stmts: &Vec<Stmt>) -> Result<(), Error> {
+    pub fn resolve_stmts(&mut self, stmts: &[StmtId]) -> Result<(), Error> {
         for stmt in stmts {
-            self.resolve_stmt(stmt)?;
+            self.resolve_stmt(*stmt)?;
         }
         Ok(())
     }
 
-    fn resolve_expr(&mut self, expr: &Expr) -> Result<(), Error> {
-        expr.accept(self)
+    fn resolve_expr(&mut self, expr: ExprId) -> Result<(), Error> {
+        let ast = self.ast;
+        expr.accept(ast, self)
     }
 
     fn resolve_function(
         &mut self,
-        params: &Vec<Token>,
-        body: &Vec<Stmt>,
+        params: &[Token],
+        defaults: &[ExprId],
+        body: &[StmtId],
         func_type: FunctionType,
     ) -> Result<(), Error> {
         let enclosing_function = mem::replace(&mut self.current_function, func_type);
+        // break and continue can't reach loops outside the function
+        let enclosing_loops = mem::take(&mut self.loops);
+        let enclosing_deferring = mem::replace(&mut self.deferring, false);
         self.begin_scope();
-        for param in params {
+        let first_default = params.len() - defaults.len();
+        for (index, param) in params.iter().enumerate() {
+            // a default is evaluated in the call, seeing the params before it
+            if let Some(default) = index.checked_sub(first_default).map(|i| defaults[i]) {
+                self.resolve_expr(default)?;
+            }
             self.declare(param)?;
             self.define(param)?;
         }
         self.resolve_stmts(body)?;
         self.end_scope();
+        self.deferring = enclosing_deferring;
+        self.loops = enclosing_loops;
         self.current_function = enclosing_function;
         Ok(())
     }
 
-    fn resolve_class(&mut self, methods: &Vec<Stmt>, class_type: ClassType) -> Result<(), Error> {
+    /// methods close over a scope holding `super`, for subclasses, and
+    /// inside it one holding `this`, the same environments the
+    /// interpreter makes for the class and when binding a method
+    fn resolve_class(&mut self, methods: &[StmtId], class_type: ClassType) -> Result<(), Error> {
+        let is_subclass = matches!(class_type, ClassType::Subclass);
         let enclosing_class = mem::replace(&mut self.current_class, class_type);
+        if is_subclass {
+            self.begin_scope();
+            if let Some(scope) = self.scopes.last_mut() {
+                scope.insert(String::from("super"), true);
+            }
+        }
         self.begin_scope();
         if let Some(scope) = self.scopes.last_mut() {
             scope.insert(String::from("this"), true);
         }
 
+        let ast = self.ast;
         for method in methods {
             let decl = FunctionType::Method;
-            match method {
-                Stmt::FunStmt { params, body, name } => {
+            match &ast[*method] {
+                Stmt::FunStmt {
+                    params,
+                    defaults,
+                    body,
+                    name,
+                    ..
+                } => {
                     self.resolve_function(
                         params,
+                        defaults,
                         body,
                         if name.lexeme != "init" {
                             decl
@@ -101,20 +142,55 @@ impl<'a> Resolver<'a> {
             }
         }
         self.end_scope();
+        if is_subclass {
+            self.end_scope();
+        }
         self.current_class = enclosing_class;
         Ok(())
     }
 
+    fn resolve_pattern(&mut self, pattern: &Pattern) -> Result<(), Error> {
+        match pattern {
+            Pattern::Wildcard | Pattern::Literal(_) | Pattern::Range { .. } => Ok(()),
+            Pattern::Binding(name) => {
+                self.declare(name)?;
+                self.define(name)
+            }
+            Pattern::List { elements, rest, .. } => {
+                for element in elements {
+                    self.resolve_pattern(element)?;
+                }
+                if let Some(rest) = rest {
+                    self.resolve_pattern(rest)?;
+                }
+                Ok(())
+            }
+            Pattern::Instance { class, fields } => {
+                self.resolve_expr(*class)?;
+                for (_, pattern) in fields {
+                    self.resolve_pattern(pattern)?;
+                }
+                Ok(())
+            }
+            Pattern::Map { entries, .. } => {
+                for (_, pattern) in entries {
+                    self.resolve_pattern(pattern)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
     fn declare(&mut self, name: &Token) -> Result<(), Error> {
         if let Some(scope) = self.scopes.last_mut() {
             if scope.contains_key(&name.lexeme) {
-                parse_error(
-                    name,
-                    "Variable with this name already declared in this scope.",
-                );
+                parse_error(name, "E0301", &[]);
                 self.has_error = true;
             }
             scope.insert(name.lexeme.clone(), false);
+        } else {
+            // globals get their slot now so later references can use it
+            self.interpreter.globals.borrow_mut().reserve(&name.lexeme);
         }
         Ok(())
     }
@@ -126,344 +202,555 @@ impl<'a> Resolver<'a> {
         Ok(())
     }
 
-    fn resolve_local(&mut self, _expr: &Expr, name: &Token) -> Result<(), Error> {
-        let len = self.scopes.len();
-        for (i, scope) in self.scopes.iter().enumerate().rev() {
-            if scope.contains_key(&name.lexeme) {
-                self.interpreter.resolve(name, len - i - 1);
-                return Ok(());
+    /// check that a break or continue has a loop to go to
+    fn resolve_loop_control(&mut self, keyword: &Token, label: Option<&Token>) {
+        if self.loops.is_empty() && self.deferring {
+            parse_error(keyword, "E0309", &[&keyword.lexeme]);
+            self.has_error = true;
+        } else if self.loops.is_empty() {
+            parse_error(keyword, "E0310", &[&keyword.lexeme]);
+            self.has_error = true;
+        } else if let Some(label) = label {
+            if !self.loops.contains(&Some(label.lexeme.clone())) {
+                parse_error(label, "E0311", &[&label.lexeme]);
+                self.has_error = true;
             }
         }
+    }
+
+    /// how many scopes up from the innermost one `name` is declared
+    fn depth_of(&self, name: &str) -> Option<usize> {
+        self.scopes
+            .iter()
+            .rev()
+            .position(|scope| scope.contains_key(name))
+    }
+
+    fn resolve_local(&mut self, id: ExprId, name: &Token) -> Result<(), Error> {
+        if let Some(depth) = self.depth_of(&name.lexeme) {
+            self.interpreter.resolve(id, depth);
+            return Ok(());
+        }
+        // not found, so it is a global: known globals get a direct slot,
+        // the rest are looked up by name when they run
+        let slot = self.interpreter.globals.borrow().slot(&name.lexeme);
+        if let Some(slot) = slot {
+            self.interpreter.resolve_global(id, slot);
+        }
         Ok(())
-        // not found
-        // we assume it a global variable
     }
 }
 
 impl<'a> expr::Visitor<()> for Resolver<'a> {
-    fn visit_variable_expr(&mut self, expr: &Expr) -> Result<(), Error> {
-        match expr {
-            Expr::Variable { name } => {
-                if let Some(scope) = self.scopes.last_mut() {
-                    if scope.get(&name.lexeme) == Some(&false) {
-                        parse_error(name, "Cannot read local variable in its own initializer.");
-                        self.has_error = true;
-                    }
-                }
-                self.resolve_local(expr, name)?;
-                Ok(())
+    fn visit_variable_expr(&mut self, name: &Token, id: ExprId) -> Result<(), Error> {
+        if let Some(scope) = self.scopes.last_mut() {
+            if scope.get(&name.lexeme) == Some(&false) {
+                parse_error(name, "E0302", &[]);
+                self.has_error = true;
             }
-            _ => unreachable!(),
         }
+        self.resolve_local(id, name)?;
+        Ok(())
     }
-    fn visit_assign_expr(&mut self, expr: &Expr) -> Result<(), Error> {
-        match expr {
-            Expr::Assign { name, value } => {
-                self.resolve_expr(value)?;
-                self.resolve_local(expr, name)?;
-                Ok(())
-            }
-            _ => unreachable!(),
+    fn visit_assign_expr(&mut self, name: &Token, value: ExprId, id: ExprId) -> Result<(), Error> {
+        self.resolve_expr(value)?;
+        self.resolve_local(id, name)?;
+        Ok(())
+    }
+    fn visit_binary_expr(
+        &mut self,
+        left: ExprId,
+        _operator: &Token,
+        right: ExprId,
+    ) -> Result<(), Error> {
+        self.resolve_expr(left)?;
+        self.resolve_expr(right)?;
+        Ok(())
+    }
+    fn visit_index_expr(
+        &mut self,
+        left: ExprId,
+        _operator: &Token,
+        right: ExprId,
+        index_end: Option<ExprId>,
+        index_step: Option<ExprId>,
+        _optional: bool,
+    ) -> Result<(), Error> {
+        self.resolve_expr(left)?;
+        self.resolve_expr(right)?;
+        for bound in index_end.iter().chain(index_step.iter()) {
+            self.resolve_expr(*bound)?;
         }
+        Ok(())
     }
-    fn visit_binary_expr(&mut self, expr: &Expr) -> Result<(), Error> {
-        match expr {
-            Expr::Binary { left, right, .. } => {
-                self.resolve_expr(left)?;
-                self.resolve_expr(right)?;
-                Ok(())
-            }
-            _ => unreachable!(),
+    fn visit_call_expr(
+        &mut self,
+        callee: ExprId,
+        _paren: &Token,
+        arguments: &[ExprId],
+        keywords: &[(Token, ExprId)],
+    ) -> Result<(), Error> {
+        self.resolve_expr(callee)?;
+        for argument in arguments {
+            self.resolve_expr(*argument)?;
         }
+        for (_, value) in keywords {
+            self.resolve_expr(*value)?;
+        }
+        Ok(())
     }
-    fn visit_index_expr(&mut self, expr: &Expr) -> Result<(), Error> {
-        match expr {
-            Expr::Index {
-                object: left,
-                index: right,
-                index_end,
-                ..
-            } => {
-                self.resolve_expr(left)?;
-                self.resolve_expr(right)?;
-                if let Some(index_end) = index_end {
-                    self.resolve_expr(index_end)?;
-                }
-                Ok(())
-            }
-            _ => unreachable!(),
+    fn visit_grouping_expr(&mut self, expression: ExprId) -> Result<(), Error> {
+        self.resolve_expr(expression)?;
+        Ok(())
+    }
+    fn visit_literal_expr(&mut self, _value: &Literal) -> Result<(), Error> {
+        Ok(())
+    }
+    fn visit_logic_expr(
+        &mut self,
+        left: ExprId,
+        _operator: &Token,
+        right: ExprId,
+    ) -> Result<(), Error> {
+        self.resolve_expr(left)?;
+        self.resolve_expr(right)?;
+        Ok(())
+    }
+    fn visit_unary_expr(&mut self, _operator: &Token, right: ExprId) -> Result<(), Error> {
+        self.resolve_expr(right)?;
+        Ok(())
+    }
+    fn visit_get_expr(
+        &mut self,
+        object: ExprId,
+        _name: &Token,
+        _cache: &MethodCache,
+        _optional: bool,
+    ) -> Result<(), Error> {
+        self.resolve_expr(object)?;
+        Ok(())
+    }
+    fn visit_index_set_expr(
+        &mut self,
+        object: ExprId,
+        index: ExprId,
+        index_end: Option<ExprId>,
+        index_step: Option<ExprId>,
+        value: ExprId,
+        _operator: &Token,
+    ) -> Result<(), Error> {
+        self.resolve_expr(object)?;
+        self.resolve_expr(index)?;
+        for bound in index_end.iter().chain(index_step.iter()) {
+            self.resolve_expr(*bound)?;
         }
+        self.resolve_expr(value)?;
+        Ok(())
     }
-    fn visit_call_expr(&mut self, expr: &Expr) -> Result<(), Error> {
-        match expr {
-            Expr::Call {
-                callee, arguments, ..
-            } => {
-                self.resolve_expr(callee)?;
-                for argument in arguments {
-                    self.resolve_expr(argument)?;
-                }
-                Ok(())
-            }
-            _ => unreachable!(),
+    fn visit_set_expr(
+        &mut self,
+        object: ExprId,
+        _name: &Token,
+        value: ExprId,
+    ) -> Result<(), Error> {
+        self.resolve_expr(object)?;
+        self.resolve_expr(value)?;
+        Ok(())
+    }
+    fn visit_this_expr(&mut self, keyword: &Token, id: ExprId) -> Result<(), Error> {
+        if let ClassType::None = self.current_class {
+            parse_error(keyword, "E0303", &[]);
+            self.has_error = true;
+            return Ok(());
         }
+        self.resolve_local(id, keyword)
     }
-    fn visit_grouping_expr(&mut self, expr: &Expr) -> Result<(), Error> {
-        match expr {
-            Expr::Grouping { expression } => {
-                self.resolve_expr(expression)?;
-                Ok(())
-            }
-            _ => unreachable!(),
+    fn visit_super_expr(
+        &mut self,
+        keyword: &Token,
+        _method: &Token,
+        id: ExprId,
+    ) -> Result<(), Error> {
+        if let ClassType::None = self.current_class {
+            parse_error(keyword, "E0304", &[]);
+            self.has_error = true;
+        } else if let ClassType::Class = self.current_class {
+            parse_error(keyword, "E0305", &[]);
+            self.has_error = true;
         }
+        // the method is bound to the instance of the same class, however
+        // many functions deep inside the method this is
+        if let Some(depth) = self.depth_of("this") {
+            self.interpreter.resolve_this(id, depth);
+        }
+        self.resolve_local(id, keyword)
     }
-    fn visit_literal_expr(&mut self, _value: &Literal) -> Result<(), Error> {
+    fn visit_list_expr(&mut self, _keyword: &Token, elements: &[ExprId]) -> Result<(), Error> {
+        for element in elements {
+            self.resolve_expr(*element)?;
+        }
         Ok(())
     }
-    fn visit_logic_expr(&mut self, expr: &Expr) -> Result<(), Error> {
-        match expr {
-            Expr::Logical { left, right, .. } => {
-                self.resolve_expr(left)?;
-                self.resolve_expr(right)?;
-                Ok(())
-            }
-            _ => unreachable!(),
+    fn visit_map_expr(
+        &mut self,
+        _brace: &Token,
+        keys: &[ExprId],
+        values: &[ExprId],
+    ) -> Result<(), Error> {
+        for (key, value) in keys.iter().zip(values) {
+            self.resolve_expr(*key)?;
+            self.resolve_expr(*value)?;
         }
+        Ok(())
     }
-    fn visit_unary_expr(&mut self, expr: &Expr) -> Result<(), Error> {
-        match expr {
-            Expr::Unary { right, .. } => {
-                self.resolve_expr(right)?;
-                Ok(())
-            }
-            _ => unreachable!(),
+    fn visit_comparison_expr(
+        &mut self,
+        operands: &[ExprId],
+        _operators: &[Token],
+    ) -> Result<(), Error> {
+        for operand in operands {
+            self.resolve_expr(*operand)?;
         }
+        Ok(())
     }
-    fn visit_get_expr(&mut self, expr: &Expr) -> Result<(), Error> {
-        match expr {
-            Expr::Get { object, .. } => {
-                self.resolve_expr(object)?;
-                Ok(())
-            }
-            _ => unreachable!(),
+    fn visit_if_expr(
+        &mut self,
+        _keyword: &Token,
+        condition: ExprId,
+        then_branch: ExprId,
+        else_branch: Option<ExprId>,
+    ) -> Result<(), Error> {
+        self.resolve_expr(condition)?;
+        self.resolve_expr(then_branch)?;
+        if let Some(else_branch) = else_branch {
+            self.resolve_expr(else_branch)?;
         }
+        Ok(())
     }
-    fn visit_index_set_expr(&mut self, expr: &Expr) -> Result<(), Error> {
-        match expr {
-            Expr::IndexSet {
-                object,
-                index,
-                index_end,
-                value,
-                ..
-            } => {
-                self.resolve_expr(object)?;
-                self.resolve_expr(index)?;
-                if let Some(index_end) = index_end {
-                    self.resolve_expr(index_end)?;
-                }
-                self.resolve_expr(value)?;
-                Ok(())
-            }
-            _ => unreachable!(),
+    fn visit_block_expr(
+        &mut self,
+        statements: &[StmtId],
+        value: Option<ExprId>,
+    ) -> Result<(), Error> {
+        self.begin_scope();
+        self.resolve_stmts(statements)?;
+        if let Some(value) = value {
+            self.resolve_expr(value)?;
         }
+        self.end_scope();
+        Ok(())
     }
-    fn visit_set_expr(&mut self, expr: &Expr) -> Result<(), Error> {
-        match expr {
-            Expr::Set { object, value, .. } => {
-                self.resolve_expr(object)?;
-                self.resolve_expr(value)?;
-                Ok(())
-            }
-            _ => unreachable!(),
+    fn visit_tuple_expr(&mut self, elements: &[ExprId]) -> Result<(), Error> {
+        for element in elements {
+            self.resolve_expr(*element)?;
         }
+        Ok(())
     }
-    fn visit_this_expr(&mut self, expr: &Expr) -> Result<(), Error> {
-        match expr {
-            Expr::This { keyword } => {
-                if let ClassType::None = self.current_class {
-                    parse_error(keyword, "Cannot use 'this' outside of a class.");
-                    self.has_error = true;
-                    return Ok(());
-                }
-                Ok(self.resolve_local(expr, keyword)?)
-            }
-            _ => unreachable!(),
+    fn visit_interpolation_expr(&mut self, _token: &Token, parts: &[ExprId]) -> Result<(), Error> {
+        for part in parts {
+            self.resolve_expr(*part)?;
         }
+        Ok(())
     }
-    fn visit_super_expr(&mut self, expr: &Expr) -> Result<(), Error> {
-        match expr {
-            Expr::Super { keyword, .. } => {
-                if let ClassType::None = self.current_class {
-                    parse_error(keyword, "Cannot use 'super' outside of a class.");
-                    self.has_error = true;
-                } else if let ClassType::Class = self.current_class {
-                    parse_error(keyword, "Cannot use 'super' in a class with no superclass.");
-                    self.has_error = true;
-                }
-                Ok(self.resolve_local(expr, keyword)?)
-            }
-            _ => unreachable!(),
+    fn visit_const_expr(
+        &mut self,
+        keyword: &Token,
+        value: ExprId,
+        _id: ExprId,
+    ) -> Result<(), Error> {
+        // the value is built once and hoisted out of every later
+        // evaluation, so nothing in it may depend on where it runs
+        if !is_constant(self.ast, value) {
+            parse_error(keyword, "E0314", &[]);
+            self.has_error = true;
         }
+        Ok(())
     }
-    fn visit_list_expr(&mut self, expr: &Expr) -> Result<(), Error> {
-        match expr {
-            Expr::List { elements, .. } => {
-                for element in elements {
-                    self.resolve_expr(element)?;
-                }
-                Ok(())
-            }
-            _ => unreachable!(),
+    fn visit_match_expr(
+        &mut self,
+        _keyword: &Token,
+        subject: ExprId,
+        arms: &[MatchArm],
+        _strings: &StringArms,
+    ) -> Result<(), Error> {
+        self.resolve_expr(subject)?;
+        for arm in arms {
+            // the names an arm binds are only visible in its body
+            self.begin_scope();
+            self.resolve_pattern(&arm.pattern)?;
+            self.resolve_expr(arm.body)?;
+            self.end_scope();
         }
+        Ok(())
     }
 }
 
 impl<'a> stmt::Visitor<()> for Resolver<'a> {
-    fn visit_block_stmt(&mut self, stmt: &Stmt) -> Result<(), Error> {
-        match stmt {
-            Stmt::BlockStmt { statements } => {
-                self.begin_scope();
-                self.resolve_stmts(statements)?;
-                self.end_scope();
-                Ok(())
-            }
-            _ => unreachable!(),
+    fn visit_block_stmt(&mut self, statements: &[StmtId]) -> Result<(), Error> {
+        self.begin_scope();
+        self.resolve_stmts(statements)?;
+        self.end_scope();
+        Ok(())
+    }
+    fn visit_var_stmt(&mut self, name: &Token, initializer: Option<ExprId>) -> Result<(), Error> {
+        self.declare(name)?;
+        if let Some(initializer) = initializer {
+            self.resolve_expr(initializer)?;
         }
+        self.define(name)?;
+        Ok(())
     }
-    fn visit_var_stmt(&mut self, stmt: &Stmt) -> Result<(), Error> {
-        match stmt {
-            Stmt::VarStmt { name, initializer } => {
-                self.declare(name)?;
-                if let Some(initializer) = initializer {
-                    self.resolve_expr(initializer)?;
-                }
-                self.define(name)?;
-                Ok(())
-            }
-            _ => unreachable!(),
+    fn visit_destructure_stmt(
+        &mut self,
+        names: &[Token],
+        initializer: ExprId,
+    ) -> Result<(), Error> {
+        for name in names {
+            self.declare(name)?;
         }
+        self.resolve_expr(initializer)?;
+        for name in names {
+            self.define(name)?;
+        }
+        Ok(())
     }
-    fn visit_func_stmt(&mut self, stmt: &Stmt) -> Result<(), Error> {
+    fn visit_func_stmt(
+        &mut self,
+        name: &Token,
+        params: &[Token],
+        defaults: &[ExprId],
+        body: &Rc<[StmtId]>,
+        _doc: Option<&str>,
+        captures: &[ExprId],
+    ) -> Result<(), Error> {
         trace!("Visiting function statement");
-        match stmt {
-            Stmt::FunStmt { name, params, body } => {
-                self.declare(name)?;
-                self.define(name)?;
-
-                self.resolve_function(params, body, FunctionType::Function)?;
+        // captured variables are read where the function is declared
+        for capture in captures {
+            self.resolve_expr(*capture)?;
+        }
+        self.declare(name)?;
+        self.define(name)?;
 
-                Ok(())
+        if captures.is_empty() {
+            self.resolve_function(params, defaults, body, FunctionType::Function)?;
+        } else {
+            // the copies live in a scope between the body and the outside
+            self.begin_scope();
+            let ast = self.ast;
+            for capture in captures {
+                if let Expr::Variable { name } = &ast[*capture] {
+                    self.declare(name)?;
+                    self.define(name)?;
+                }
             }
-            _ => unreachable!(),
+            self.resolve_function(params, defaults, body, FunctionType::Function)?;
+            self.end_scope();
+        }
+
+        Ok(())
+    }
+    fn visit_expr_stmt(&mut self, expression: ExprId) -> Result<(), Error> {
+        self.resolve_expr(expression)?;
+        Ok(())
+    }
+    fn visit_if_stmt(
+        &mut self,
+        _keyword: &Token,
+        condition: ExprId,
+        then_branch: StmtId,
+        else_branch: Option<StmtId>,
+    ) -> Result<(), Error> {
+        self.resolve_expr(condition)?;
+        self.resolve_stmt(then_branch)?;
+        if let Some(else_branch) = else_branch {
+            self.resolve_stmt(else_branch)?;
         }
+        Ok(())
     }
-    fn visit_expr_stmt(&mut self, stmt: &Stmt) -> Result<(), Error> {
-        match stmt {
-            Stmt::ExprStmt { expression } => {
-                self.resolve_expr(expression)?;
-                Ok(())
+    fn visit_print_stmt(&mut self, _keyword: &Token, expression: ExprId) -> Result<(), Error> {
+        self.resolve_expr(expression)?;
+        Ok(())
+    }
+    fn visit_return_stmt(&mut self, keyword: &Token, value: Option<ExprId>) -> Result<(), Error> {
+        // a return at the top level ends the script, see Loxer::run
+        if let FunctionType::None = self.current_function {
+            if compat::jlox() {
+                parse_error(keyword, "E0306", &[]);
+                self.has_error = true;
             }
-            _ => unreachable!(),
-        }
-    }
-    fn visit_if_stmt(&mut self, stmt: &Stmt) -> Result<(), Error> {
-        match stmt {
-            Stmt::IfStmt {
-                condition,
-                then_branch,
-                else_branch,
-            } => {
-                self.resolve_expr(condition)?;
-                self.resolve_stmt(then_branch)?;
-                if let Some(else_branch) = else_branch {
-                    self.resolve_stmt(else_branch)?;
-                }
-                Ok(())
+        }
+        if self.deferring {
+            parse_error(keyword, "E0308", &[]);
+            self.has_error = true;
+        }
+        if let FunctionType::Initializer = self.current_function {
+            if value.is_some() {
+                parse_error(keyword, "E0307", &[]);
+                self.has_error = true;
             }
-            _ => unreachable!(),
         }
+        if let Some(value) = value {
+            self.resolve_expr(value)?;
+        }
+        Ok(())
     }
-    fn visit_print_stmt(&mut self, stmt: &Stmt) -> Result<(), Error> {
-        match stmt {
-            Stmt::PrintStmt { expression } => {
-                self.resolve_expr(expression)?;
-                Ok(())
+    fn visit_while_stmt(
+        &mut self,
+        _keyword: &Token,
+        condition: ExprId,
+        body: StmtId,
+        increment: Option<ExprId>,
+        label: Option<&Token>,
+    ) -> Result<(), Error> {
+        self.resolve_expr(condition)?;
+        if let Some(label) = label {
+            if self.loops.contains(&Some(label.lexeme.clone())) {
+                parse_error(label, "E0312", &[]);
+                self.has_error = true;
             }
-            _ => unreachable!(),
         }
+        self.loops
+            .push(label.as_ref().map(|label| label.lexeme.clone()));
+        self.resolve_stmt(body)?;
+        self.loops.pop();
+        if let Some(increment) = increment {
+            self.resolve_expr(increment)?;
+        }
+        Ok(())
     }
-    fn visit_return_stmt(&mut self, stmt: &Stmt) -> Result<(), Error> {
-        match stmt {
-            Stmt::ReturnStmt { value, keyword } => {
-                if let FunctionType::None = self.current_function {
-                    parse_error(keyword, "Cannot return from top-level code.");
-                    self.has_error = true;
-                } else if let FunctionType::Initializer = self.current_function {
-                    if !value.is_none() {
-                        parse_error(keyword, "Cannot return a value from an initializer.");
-                        self.has_error = true;
-                    }
-                }
-                if let Some(value) = value {
-                    self.resolve_expr(value)?;
-                }
-                Ok(())
+    fn visit_for_in_stmt(
+        &mut self,
+        _keyword: &Token,
+        name: &Token,
+        iterable: ExprId,
+        body: StmtId,
+        label: Option<&Token>,
+    ) -> Result<(), Error> {
+        self.resolve_expr(iterable)?;
+        if let Some(label) = label {
+            if self.loops.contains(&Some(label.lexeme.clone())) {
+                parse_error(label, "E0312", &[]);
+                self.has_error = true;
             }
-            _ => unreachable!(),
         }
+        // the loop variable is in a scope of its own, fresh each iteration
+        self.begin_scope();
+        self.declare(name)?;
+        self.define(name)?;
+        self.loops
+            .push(label.as_ref().map(|label| label.lexeme.clone()));
+        self.resolve_stmt(body)?;
+        self.loops.pop();
+        self.end_scope();
+        Ok(())
     }
-    fn visit_while_stmt(&mut self, stmt: &Stmt) -> Result<(), Error> {
-        match stmt {
-            Stmt::WhileStmt { condition, body } => {
-                self.resolve_expr(condition)?;
-                self.resolve_stmt(body)?;
-                Ok(())
-            }
-            _ => unreachable!(),
+    fn visit_break_stmt(&mut self, keyword: &Token, label: Option<&Token>) -> Result<(), Error> {
+        self.resolve_loop_control(keyword, label);
+        Ok(())
+    }
+    fn visit_assert_stmt(
+        &mut self,
+        _keyword: &Token,
+        condition: ExprId,
+        message: Option<ExprId>,
+        _source: &str,
+    ) -> Result<(), Error> {
+        self.resolve_expr(condition)?;
+        if let Some(message) = message {
+            self.resolve_expr(message)?;
+        }
+        Ok(())
+    }
+    fn visit_defer_stmt(&mut self, _keyword: &Token, body: StmtId) -> Result<(), Error> {
+        let enclosing_loops = mem::take(&mut self.loops);
+        let enclosing_deferring = mem::replace(&mut self.deferring, true);
+        self.resolve_stmt(body)?;
+        self.deferring = enclosing_deferring;
+        self.loops = enclosing_loops;
+        Ok(())
+    }
+    fn visit_try_stmt(
+        &mut self,
+        _keyword: &Token,
+        body: &[StmtId],
+        catch: Option<(&Token, &[StmtId])>,
+        finally: Option<&[StmtId]>,
+    ) -> Result<(), Error> {
+        self.visit_block_stmt(body)?;
+        if let Some((name, handler)) = catch {
+            self.begin_scope();
+            self.declare(name)?;
+            self.define(name)?;
+            self.resolve_stmts(handler)?;
+            self.end_scope();
+        }
+        if let Some(finally) = finally {
+            self.visit_block_stmt(finally)?;
         }
+        Ok(())
     }
-    fn visit_class_stmt(&mut self, stmt: &Stmt) -> Result<(), Error> {
-        match stmt {
-            Stmt::ClassStmt {
-                name,
-                methods,
-                super_class,
-            } => {
-                self.declare(name)?;
-                self.define(name)?;
-
-                let mut current_class = ClassType::Class;
+    fn visit_throw_stmt(&mut self, _keyword: &Token, value: ExprId) -> Result<(), Error> {
+        self.resolve_expr(value)
+    }
+    fn visit_continue_stmt(&mut self, keyword: &Token, label: Option<&Token>) -> Result<(), Error> {
+        self.resolve_loop_control(keyword, label);
+        Ok(())
+    }
+    fn visit_class_stmt(
+        &mut self,
+        name: &Token,
+        super_class: Option<ExprId>,
+        fields: &[StmtId],
+        methods: &[StmtId],
+        _doc: Option<&str>,
+    ) -> Result<(), Error> {
+        self.declare(name)?;
+        self.define(name)?;
 
-                if let Some(super_class_inner) = super_class {
-                    if let Expr::Variable { name: super_name } = super_class_inner {
-                        if name.lexeme == super_name.lexeme {
-                            parse_error(name, "A class cannot inherit from itself.");
-                            self.has_error = true;
-                        }
-                    }
-                    super_class_inner.accept(self)?;
+        let mut current_class = ClassType::Class;
 
-                    current_class = ClassType::Subclass;
+        let ast = self.ast;
+        if let Some(super_class_inner) = super_class {
+            if let Expr::Variable { name: super_name } = &ast[super_class_inner] {
+                if name.lexeme == super_name.lexeme {
+                    parse_error(name, "E0313", &[]);
+                    self.has_error = true;
                 }
+            }
+            self.resolve_expr(super_class_inner)?;
 
-                if super_class.is_some() {
-                    self.begin_scope();
-                    self.scopes.last_mut().map(|scope| {
-                        scope.insert(String::from("super"), true);
-                        Some(())
-                    });
-                }
+            current_class = ClassType::Subclass;
+        }
 
-                self.resolve_class(methods, current_class)?;
+        // field initializers run in the scope the class is declared in,
+        // before init, so there is no `this` for them
+        for field in fields {
+            if let Stmt::VarStmt {
+                initializer: Some(initializer),
+                ..
+            } = &ast[*field]
+            {
+                self.resolve_expr(*initializer)?;
+            }
+        }
 
-                if super_class.is_some() {
-                    self.end_scope();
-                }
+        self.resolve_class(methods, current_class)?;
+        Ok(())
+    }
+}
 
-                Ok(())
-            }
-            _ => unreachable!(),
+/// literals, negated numbers and lists of them, the values a frozen
+/// literal can hold
+fn is_constant(ast: &Ast, expr: ExprId) -> bool {
+    match &ast[expr] {
+        Expr::Literal { .. } | Expr::Const { .. } => true,
+        Expr::Unary { operator, right } => {
+            operator.token_type == TokenType::Minus
+                && std::matches!(ast[*right], Expr::Literal { value: Literal::Number(_) })
         }
+        Expr::Grouping { expression } => is_constant(ast, *expression),
+        Expr::List { elements, .. } => elements.iter().all(|e| is_constant(ast, *e)),
+        Expr::Map { keys, values, .. } => keys.iter().chain(values).all(|e| is_constant(ast, *e)),
+        _ => false,
     }
 }
@@ -1,13 +1,31 @@
 use super::*;
+use std::fmt::Display;
+
+/// a comment kept as trivia, spanning `start..end` bytes of the source
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Comment {
+    pub text: String,
+    pub line: usize,
+    pub start: usize,
+    pub end: usize,
+}
 
 #[derive(Debug)]
 pub struct Scanner {
-    source: String, // source code
-    start: usize,   // start of current token
-    current: usize, // current position in source code
-    line: usize,    // current line
-    column: usize,  // current column
+    source: String,    // source code
+    start: usize,      // start of current token
+    current: usize,    // current position in source code
+    line: usize,       // current line
+    column: usize,     // column of the current token
+    line_start: usize, // where the current line starts
+    start_line: usize, // line of the current token's first character
+    done: bool,        // whether Eof has been handed out
+    keep_comments: bool,
+    pending_doc: Option<String>,
+    /// for each `${` not closed yet, how many `{` inside it are open
+    interpolations: Vec<usize>,
     pub tokens: Vec<Token>,
+    pub comments: Vec<Comment>,
     pub had_error: bool,
     pub errors: Vec<Error>,
 }
@@ -20,29 +38,102 @@ impl Scanner {
             current: 0,
             line: 1,
             column: 1,
+            line_start: 0,
+            start_line: 1,
+            done: false,
+            keep_comments: false,
+            pending_doc: None,
+            interpolations: Vec::new(),
             tokens: Vec::new(),
+            comments: Vec::new(),
             had_error: false,
             errors: Vec::new(),
         }
     }
 
+    /// scan `source` starting at byte `offset`, which is on `line`
+    pub fn resume(source: &str, offset: usize, line: usize) -> Self {
+        Self {
+            start: offset,
+            current: offset,
+            line,
+            line_start: source[..offset].rfind('\n').map_or(0, |i| i + 1),
+            ..Self::new(source)
+        }
+    }
+
+    /// where the scanner is in the source
+    pub fn position(&self) -> usize {
+        self.current
+    }
+
+    /// record comments in `comments` instead of dropping them,
+    /// whitespace can be recovered from the token and comment spans
+    pub fn set_keep_comments(&mut self, keep_comments: bool) {
+        self.keep_comments = keep_comments;
+    }
+
     pub fn scan_tokens(&mut self) {
         // loop until we reach the end of the source code
-        while !self.is_end() {
-            self.scan_token();
-            self.start = self.current;
+        while let Some(result) = self.next() {
+            match result {
+                Ok(token) => self.tokens.push(token),
+                Err(error) => {
+                    self.errors.push(error);
+                    self.had_error = true;
+                }
+            }
         }
-        self.tokens.push(self.get_token(TokenType::Eof));
     }
 
     /// return a token, this is where the magic happens
-    fn scan_token(&mut self) {
+    /// None means the characters were skipped (whitespace, comments)
+    fn scan_token(&mut self) -> Option<Result<Token, Error>> {
         let c = self.consume();
 
+        if compat::jlox() && compat::is_extension_character(c) {
+            return Some(Err(self.error("E0101", &[])));
+        }
+
+        // `->`, `?[`, `?.`, `??`, `..` and `...` start with characters that are tokens on their own
+        if c == '-' && self.mat('>') {
+            return Some(Ok(self.add_token(TokenType::Arrow, Literal::Nil)));
+        }
+        if c == '?' && self.mat('[') {
+            return Some(Ok(self.add_token(TokenType::QuestionBracket, Literal::Nil)));
+        }
+        if c == '?' && self.mat('.') {
+            return Some(Ok(self.add_token(TokenType::QuestionDot, Literal::Nil)));
+        }
+        if c == '?' && self.mat('?') {
+            return Some(Ok(self.add_token(TokenType::QuestionQuestion, Literal::Nil)));
+        }
+        if c == '.' && self.mat('.') {
+            let token_type = if self.mat('.') {
+                TokenType::Ellipsis
+            } else {
+                TokenType::DotDot
+            };
+            return Some(Ok(self.add_token(token_type, Literal::Nil)));
+        }
+
+        // braces inside `${...}` are counted so the `}` closing it goes
+        // back to scanning the rest of the string
+        if let Some(depth) = self.interpolations.last_mut() {
+            match c {
+                '{' => *depth += 1,
+                '}' if *depth == 0 => {
+                    self.interpolations.pop();
+                    return Some(self.check_string());
+                }
+                '}' => *depth -= 1,
+                _ => {}
+            }
+        }
+
         // check if the character is a single character token
         if let Some(token_type) = Token::check_single_character_token(c) {
-            self.add_token(token_type, Literal::Nil);
-            return;
+            return Some(Ok(self.add_token(token_type, Literal::Nil)));
         }
 
         // deal with operators
@@ -56,8 +147,7 @@ impl Scanner {
                 }
                 _ => {}
             }
-            self.add_token(token_type, Literal::Nil);
-            return;
+            return Some(Ok(self.add_token(token_type, Literal::Nil)));
         }
 
         // longer tokens
@@ -70,47 +160,77 @@ impl Scanner {
                     while self.peak() != '\n' && !self.is_end() {
                         self.consume();
                     }
+                    let text = &self.source[self.start..self.current];
+                    if let Some(line) = text.strip_prefix("///") {
+                        // doc comment, attached to the next token
+                        let line = line.strip_prefix(' ').unwrap_or(line).to_string();
+                        self.pending_doc = Some(match self.pending_doc.take() {
+                            Some(doc) => doc + "\n" + &line,
+                            None => line,
+                        });
+                    }
+                    if self.keep_comments {
+                        self.comments.push(Comment {
+                            text: self.source[self.start..self.current].to_string(),
+                            line: self.line,
+                            start: self.start,
+                            end: self.current,
+                        });
+                    }
                 } else {
-                    self.add_token(TokenType::Slash, Literal::Nil);
+                    return Some(Ok(self.add_token(TokenType::Slash, Literal::Nil)));
+                }
+            }
+            '#' if self.start == 0 && self.mat('!') => {
+                // a shebang line, so scripts can be run directly on unix
+                while self.peak() != '\n' && !self.is_end() {
+                    self.consume();
                 }
             }
             ' ' | '\r' | '\t' => {}
             '\n' => {
                 self.line += 1;
-                self.column = 1;
+                self.line_start = self.current;
             }
             '"' => {
                 // String
-                self.check_string();
+                return Some(self.check_string());
             }
             '0'..='9' => {
                 // Number
-                self.check_number();
+                return Some(Ok(self.check_number()));
             }
             'a'..='z' | 'A'..='Z' | '_' => {
                 // Identifier or Keyword
-                self.check_identifier();
+                return Some(Ok(self.check_identifier()));
             }
             _ => {
-                self.error(self.line, "Unexpected character.");
+                return Some(Err(self.error("E0101", &[])));
             }
         };
+        None
     }
 
     /// return a token, according to token_type and literal
     fn get_token(&self, token_type: TokenType) -> Token {
-        log::trace!("{}", &self.source[self.start..self.current]);
-        Token::new(
-            &self.source[self.start..self.current],
-            token_type,
-            self.line,
-            self.column,
-        )
+        trace!("{}", &self.source[self.start..self.current]);
+        Token {
+            offset: self.start,
+            ..Token::new(
+                &self.source[self.start..self.current],
+                token_type,
+                self.line,
+                self.column,
+            )
+        }
     }
 
-    /// add a token to the tokens vector
-    fn add_token(&mut self, token_type: TokenType, _literal: Literal) {
-        self.tokens.push(self.get_token(token_type));
+    /// make the token spanning the current lexeme
+    fn add_token(&self, token_type: TokenType, literal: Literal) -> Token {
+        Token {
+            literal,
+            ..self.get_token(token_type)
+        }
     }
 
     /// return true if we have reached the end of the source code
@@ -118,28 +238,23 @@ impl Scanner {
         self.current >= self.source.len()
     }
 
-    /// return the current character without advancing the current position
+    /// return the current character without advancing the current position,
+    /// positions are byte offsets so this never walks the source from the start
     fn peak(&self) -> char {
-        if self.is_end() {
-            '\0'
-        } else {
-            self.source.chars().nth(self.current).unwrap()
-        }
+        self.source[self.current..].chars().next().unwrap_or('\0')
     }
 
     /// return the next next character without advancing the current position
     fn peak_next(&self) -> char {
-        if self.current + 1 >= self.source.len() {
-            '\0'
-        } else {
-            self.source.chars().nth(self.current + 1).unwrap()
-        }
+        self.source[self.current..].chars().nth(1).unwrap_or('\0')
     }
 
     /// return the current character and advance the current position
     fn consume(&mut self) -> char {
         let c = self.peak();
-        self.current += 1;
+        if !self.is_end() {
+            self.current += c.len_utf8();
+        }
         c
     }
 
@@ -150,61 +265,144 @@ impl Scanner {
         if self.is_end() {
             return false;
         }
-        if self.source.chars().nth(self.current).unwrap() != expected {
+        if self.peak() != expected {
             return false;
         }
-        self.current += 1;
+        self.current += expected.len_utf8();
         true
     }
 
-    fn check_string(&mut self) {
+    /// the rest of a string, up to the closing `"` or up to and
+    /// including a `${`, which makes it an Interpolation token
+    fn check_string(&mut self) -> Result<Token, Error> {
         while self.peak() != '"' && !self.is_end() {
             if self.peak() == '\n' {
                 self.line += 1;
-                self.column = 1;
+                self.line_start = self.current + 1;
+            }
+            if !compat::jlox() {
+                if self.peak() == '\\' && self.peak_next() == '$' {
+                    // `\${` is a literal `${`
+                    self.consume();
+                } else if self.peak() == '$' && self.peak_next() == '{' {
+                    self.consume();
+                    self.consume();
+                    self.interpolations.push(0);
+                    return self.string_token(TokenType::Interpolation);
+                }
             }
             self.consume();
         }
 
         if self.is_end() {
-            self.error(self.line, "Unterminated string.");
-            return;
+            return Err(self.error("E0102", &[]));
         }
 
         // the closing "
         self.consume();
 
-        // trim the surrounding quotes
-        let value = &self.source[self.start + 1..self.current - 1];
-        self.add_token(TokenType::String, Literal::String(value.to_string()));
+        self.string_token(TokenType::String)
+    }
+
+    /// the string scanned so far as a token, unless it is longer than
+    /// the limit allows
+    fn string_token(&self, token_type: TokenType) -> Result<Token, Error> {
+        // the opening `"` or `}`, and the closing `"` or `${`
+        let delimiters = match token_type {
+            TokenType::Interpolation => 3,
+            _ => 2,
+        };
+        let lexeme = &self.source[self.start..self.current];
+        let length = lexeme.chars().count() - delimiters;
+        match limits::limits().max_string_length {
+            Some(max) if length > max => Err(self.error("E0104", &[&max])),
+            _ => {
+                let text = &lexeme[1..lexeme.len() - (delimiters - 1)];
+                let value = if compat::jlox() {
+                    text.to_string()
+                } else {
+                    text.replace("\\${", "${")
+                };
+                Ok(self.add_token(token_type, Literal::String(value)))
+            }
+        }
     }
 
-    fn check_number(&mut self) {
-        while is_digit(self.peak()) {
+    /// a run of digits, `_` can separate them as in `1_000`
+    fn digits(&mut self) {
+        while is_digit(self.peak())
+            || (!compat::jlox() && self.peak() == '_' && is_digit(self.peak_next()))
+        {
             self.consume();
         }
+    }
+
+    fn check_number(&mut self) -> Token {
+        // `0x`, `0b` and `0o` take every letter, digit and `_` after them,
+        // whether they are digits of that base is decided when reading them
+        if !compat::jlox()
+            && &self.source[self.start..self.current] == "0"
+            && std::matches!(self.peak(), 'x' | 'b' | 'o')
+            && self.peak_next().is_ascii_alphanumeric()
+        {
+            while self.peak().is_ascii_alphanumeric() || self.peak() == '_' {
+                self.consume();
+            }
+            return self.number_token();
+        }
+
+        self.digits();
 
         // look for a fractional part
         if self.peak() == '.' && is_digit(self.peak_next()) {
             // consume the "."
             self.consume();
+            self.digits();
+        }
 
-            while is_digit(self.peak()) {
+        // an exponent, `1e9` or `1.5e-3`, only when digits follow the `e`
+        if !compat::jlox() && std::matches!(self.peak(), 'e' | 'E') {
+            let exponent = &self.source[self.current + 1..];
+            let digits = exponent.strip_prefix(['+', '-']).unwrap_or(exponent);
+            if digits.starts_with(|c: char| c.is_ascii_digit()) {
                 self.consume();
+                self.mat('+');
+                self.mat('-');
+                self.digits();
             }
         }
 
-        self.add_token(TokenType::Number, Literal::Nil);
+        // `1f` is a float and `1i` an integer, whatever the digits say
+        if !compat::jlox()
+            && std::matches!(self.peak(), 'f' | 'i')
+            && !is_alpha_numeric(self.peak_next())
+        {
+            self.consume();
+        }
+
+        self.number_token()
+    }
+
+    /// the number scanned so far as a token, with no value when its
+    /// digits don't make one, the parser reports that
+    fn number_token(&self) -> Token {
+        let literal = match number_value(&self.source[self.start..self.current]) {
+            Some(number) => Literal::Number(number),
+            None => Literal::Nil,
+        };
+        self.add_token(TokenType::Number, literal)
     }
 
-    fn check_identifier(&mut self) {
+    fn check_identifier(&mut self) -> Token {
         while is_alpha_numeric(self.peak()) {
             self.consume();
         }
 
         let text = &self.source[self.start..self.current];
         // let token_type = Token::check_keyword(text).unwrap_or(TokenType::Identifier);
-        if let Some(token_type) = Token::check_keyword(text) {
+        let keyword = Token::check_keyword(text)
+            .filter(|_| !(compat::jlox() && compat::is_extension_keyword(text)));
+        if let Some(token_type) = keyword {
             // keyword
             let literal = match token_type {
                 TokenType::True => Literal::Boolean(true),
@@ -212,32 +410,88 @@ impl Scanner {
                 TokenType::Nil => Literal::Nil,
                 _ => Literal::Nil,
             };
-            self.add_token(token_type, literal);
+            self.add_token(token_type, literal)
         } else {
             //identifier
-            self.add_token(TokenType::Identifier, Literal::Nil);
+            self.add_token(TokenType::Identifier, Literal::Nil)
         }
     }
 
-    fn error(&mut self, line: usize, message: &str) {
-        self.errors.push(Error {
-            message: message.to_string(),
-            error_type: ErrorType::ScanError(line),
-        });
-        self.had_error = true;
+    /// an error about the text scanned since the start of the token
+    fn error(&self, code: &'static str, args: &[&dyn Display]) -> Error {
+        Error::coded(code, args).with_type(ErrorType::ScanError {
+            line: self.start_line,
+            column: self.column,
+            span: self.start..self.current,
+            text: self.source[self.start..self.current].to_string(),
+        })
     }
 
     pub fn report_errors(&self) {
         for error in self.errors.iter() {
-            if let ErrorType::ScanError(line) = error.error_type {
-                eprintln!("[line {}] Error: {}", line, error.message);
-            } else {
-                eprintln!("Error: {}", error.message);
+            report_scan_error(error);
+        }
+    }
+}
+
+/// lex on demand, one token at a time, ending with Eof
+impl Iterator for Scanner {
+    type Item = Result<Token, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            self.start = self.current;
+            self.start_line = self.line;
+            self.column = self.source[self.line_start..self.start].chars().count() + 1;
+            if self.is_end() {
+                if self.done {
+                    return None;
+                }
+                self.done = true;
+                return Some(Ok(self.get_token(TokenType::Eof)));
+            }
+            if let Some(result) = self.scan_token() {
+                return Some(result.map(|token| Token {
+                    doc: self.pending_doc.take(),
+                    ..token
+                }));
             }
         }
     }
 }
 
+/// the value of a number literal, `1f` is a float and `1i` an integer
+/// whatever the digits say
+fn number_value(lexeme: &str) -> Option<NumberType> {
+    // `_` between digits is only there for the reader
+    let lexeme = lexeme.replace('_', "");
+    if compat::jlox() {
+        // every number is a double in jlox
+        return lexeme.parse().ok().map(NumberType::Float);
+    }
+    let radix = match lexeme.get(..2) {
+        Some("0x") => Some(16),
+        Some("0b") => Some(2),
+        Some("0o") => Some(8),
+        _ => None,
+    };
+    if let Some(radix) = radix {
+        return i64::from_str_radix(&lexeme[2..], radix)
+            .ok()
+            .map(NumberType::Integer);
+    }
+    if let Some(digits) = lexeme.strip_suffix('f') {
+        return digits.parse().ok().map(NumberType::Float);
+    }
+    if let Some(digits) = lexeme.strip_suffix('i') {
+        return digits.parse().ok().map(NumberType::Integer);
+    }
+    match lexeme.parse() {
+        Ok(number) => Some(NumberType::Integer(number)),
+        Err(_) => lexeme.parse().ok().map(NumberType::Float),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -280,14 +534,73 @@ mod tests {
             println!("{:?}", token);
         }
     }
+    #[test]
+    fn test_string_literal() {
+        let mut scanner = Scanner::new(r#""a \${b}" "x${1}y""#);
+        scanner.scan_tokens();
+        let strings: Vec<_> = scanner
+            .tokens
+            .iter()
+            .filter_map(|t| match &t.literal {
+                Literal::String(s) => Some(s.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(strings, ["a ${b}", "x", "y"]);
+    }
+
+    #[test]
+    fn test_interpolation() {
+        let mut scanner = Scanner::new("\"a ${ {1: 2}[1] } b ${c}\"");
+        scanner.scan_tokens();
+        let lexemes: Vec<&str> = scanner.tokens.iter().map(|t| t.lexeme.as_str()).collect();
+        assert_eq!(
+            lexemes,
+            ["\"a ${", "{", "1", ":", "2", "}", "[", "1", "]", "} b ${", "c", "}\"", ""]
+        );
+        assert_eq!(scanner.tokens[0].token_type, TokenType::Interpolation);
+        assert_eq!(scanner.tokens[11].token_type, TokenType::String);
+    }
+
     #[test]
     fn test_number() {
-        let mut scanner = Scanner::new("123 123.456");
+        let mut scanner = Scanner::new("123 123.456 0x1F 1.5i");
         scanner.scan_tokens();
-        for token in scanner.tokens.iter() {
-            println!("{:?}", token);
-        }
+        let literals: Vec<_> = scanner.tokens.iter().map(|t| t.literal.clone()).collect();
+        assert_eq!(
+            literals,
+            [
+                Literal::Number(NumberType::Integer(123)),
+                Literal::Number(NumberType::Float(123.456)),
+                Literal::Number(NumberType::Integer(31)),
+                // left for the parser to report
+                Literal::Nil,
+                Literal::Nil,
+            ]
+        );
     }
+    #[test]
+    fn test_radix_and_exponent() {
+        let mut scanner = Scanner::new("0xFF 0b10 0o7 1e9 1.5e-3 2E+2 1else 0x");
+        scanner.scan_tokens();
+        let lexemes: Vec<_> = scanner.tokens.iter().map(|t| t.lexeme.as_str()).collect();
+        assert_eq!(
+            lexemes,
+            ["0xFF", "0b10", "0o7", "1e9", "1.5e-3", "2E+2", "1", "else", "0", "x", ""]
+        );
+    }
+
+    #[test]
+    fn test_number_separators() {
+        let mut scanner = Scanner::new("1_000_000 1_0.5_5 0xFF_FF 1_ 1__0");
+        scanner.scan_tokens();
+        let lexemes: Vec<_> = scanner.tokens.iter().map(|t| t.lexeme.as_str()).collect();
+        assert_eq!(
+            lexemes,
+            ["1_000_000", "1_0.5_5", "0xFF_FF", "1", "_", "1", "__0", ""]
+        );
+    }
+
     #[test]
     fn test_keyword() {
         let mut scanner = Scanner::new(
@@ -299,6 +612,115 @@ mod tests {
         }
     }
     #[test]
+    fn test_iterator() {
+        let tokens: Vec<_> = Scanner::new("var a = @;").collect();
+        assert_eq!(tokens.len(), 6);
+        assert!(tokens[3].is_err());
+        assert_eq!(
+            tokens.last().unwrap().as_ref().unwrap().token_type,
+            TokenType::Eof
+        );
+
+        // the lexer is lazy, later errors are not reached yet
+        let mut scanner = Scanner::new("print 1; \"unterminated");
+        assert_eq!(scanner.next().unwrap().unwrap().lexeme, "print");
+        assert!(scanner.errors.is_empty());
+    }
+    #[test]
+    fn test_keep_comments() {
+        let source = "// leading\nprint 1; // trailing\n";
+        let mut scanner = Scanner::new(source);
+        scanner.scan_tokens();
+        assert!(scanner.comments.is_empty());
+
+        let mut scanner = Scanner::new(source);
+        scanner.set_keep_comments(true);
+        scanner.scan_tokens();
+        assert_eq!(scanner.tokens.len(), 4);
+        assert_eq!(scanner.comments.len(), 2);
+        let trailing = &scanner.comments[1];
+        assert_eq!(trailing.text, "// trailing");
+        assert_eq!(trailing.line, 2);
+        assert_eq!(&source[trailing.start..trailing.end], "// trailing");
+    }
+    #[test]
+    fn test_doc_comment() {
+        let mut scanner = Scanner::new("/// first\n///second\nfun f() {}\n// plain\nfun g() {}");
+        scanner.scan_tokens();
+        assert_eq!(scanner.tokens[0].doc.as_deref(), Some("first\nsecond"));
+        assert_eq!(scanner.tokens[1].doc, None);
+        assert_eq!(scanner.tokens[6].lexeme, "fun");
+        assert_eq!(scanner.tokens[6].doc, None);
+    }
+    #[test]
+    fn test_eof() {
+        let mut scanner = Scanner::new("");
+        scanner.scan_tokens();
+        assert_eq!(scanner.tokens.len(), 1);
+        assert_eq!(scanner.tokens[0].token_type, TokenType::Eof);
+
+        let mut scanner = Scanner::new("print 1;\n");
+        scanner.scan_tokens();
+        let eof = scanner.tokens.last().unwrap();
+        assert_eq!(eof.token_type, TokenType::Eof);
+        assert_eq!((eof.line, eof.offset), (2, 9));
+    }
+    #[test]
+    fn test_scan_error_position() {
+        let mut scanner = Scanner::new("var a = 1;\nvar b = @ + 2;\n\"open");
+        scanner.scan_tokens();
+        // the rest of the source is still scanned
+        assert_eq!(scanner.tokens.len(), 12);
+        assert_eq!(scanner.tokens[8].column, 11);
+        assert_eq!(scanner.errors.len(), 2);
+        match &scanner.errors[0].error_type {
+            ErrorType::ScanError {
+                line,
+                column,
+                span,
+                text,
+            } => {
+                assert_eq!((*line, *column, span.clone()), (2, 9, 19..20));
+                assert_eq!(text, "@");
+            }
+            _ => unreachable!(),
+        }
+        match &scanner.errors[1].error_type {
+            ErrorType::ScanError { line, column, .. } => assert_eq!((*line, *column), (3, 1)),
+            _ => unreachable!(),
+        }
+    }
+    #[test]
+    fn test_multibyte_characters() {
+        let mut scanner = Scanner::new("var s = \"héllo\"; // ünïcode\nĿ s;\nvar t = \"ééé\"; @");
+        scanner.scan_tokens();
+        assert_eq!(scanner.tokens[3].lexeme, "\"héllo\"");
+        assert_eq!(scanner.tokens[5].lexeme, "s");
+        assert_eq!(scanner.errors.len(), 2);
+        match &scanner.errors[0].error_type {
+            ErrorType::ScanError { text, .. } => assert_eq!(text, "Ŀ"),
+            _ => unreachable!(),
+        }
+        // columns count characters, not bytes
+        match &scanner.errors[1].error_type {
+            ErrorType::ScanError { line, column, .. } => assert_eq!((*line, *column), (3, 16)),
+            _ => unreachable!(),
+        }
+    }
+    #[test]
+    fn test_shebang() {
+        let mut scanner = Scanner::new("#!/usr/bin/env lox\nprint 1;");
+        scanner.scan_tokens();
+        assert!(!scanner.had_error);
+        assert_eq!(scanner.tokens.len(), 4);
+        assert_eq!(scanner.tokens[0].line, 2);
+
+        // only the very first line can be a shebang
+        let mut scanner = Scanner::new("print 1;\n#!/usr/bin/env lox");
+        scanner.scan_tokens();
+        assert!(scanner.had_error);
+    }
+    #[test]
     fn test_identifier() {
         let mut scanner = Scanner::new("a+b");
         scanner.scan_tokens();
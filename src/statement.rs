@@ -1,20 +1,84 @@
-use std::fmt::Display;
+use std::rc::Rc;
 
-use super::{Expr, Token};
-use crate::{AstPrinter, Error};
+use super::{Token, TokenType};
+use crate::{grow_stack, Ast, AstPrinter, Error, ExprId, StmtId};
 
 pub mod stmt {
-    use super::{Error, Stmt};
+    use super::{Error, ExprId, Rc, StmtId, Token};
+    /// each visit method gets the fields of its statement, like expr::Visitor
     pub trait Visitor<T> {
-        fn visit_expr_stmt(&mut self, stmt: &Stmt) -> Result<T, Error>;
-        fn visit_if_stmt(&mut self, stmt: &Stmt) -> Result<T, Error>;
-        fn visit_print_stmt(&mut self, stmt: &Stmt) -> Result<T, Error>;
-        fn visit_var_stmt(&mut self, stmt: &Stmt) -> Result<T, Error>;
-        fn visit_block_stmt(&mut self, stmt: &Stmt) -> Result<T, Error>;
-        fn visit_while_stmt(&mut self, stmt: &Stmt) -> Result<T, Error>;
-        fn visit_func_stmt(&mut self, stmt: &Stmt) -> Result<T, Error>;
-        fn visit_return_stmt(&mut self, stmt: &Stmt) -> Result<T, Error>;
-        fn visit_class_stmt(&mut self, stmt: &Stmt) -> Result<T, Error>;
+        fn visit_expr_stmt(&mut self, expression: ExprId) -> Result<T, Error>;
+        fn visit_if_stmt(
+            &mut self,
+            keyword: &Token,
+            condition: ExprId,
+            then_branch: StmtId,
+            else_branch: Option<StmtId>,
+        ) -> Result<T, Error>;
+        fn visit_print_stmt(&mut self, keyword: &Token, expression: ExprId) -> Result<T, Error>;
+        fn visit_var_stmt(&mut self, name: &Token, initializer: Option<ExprId>) -> Result<T, Error>;
+        fn visit_destructure_stmt(
+            &mut self,
+            names: &[Token],
+            initializer: ExprId,
+        ) -> Result<T, Error>;
+        fn visit_block_stmt(&mut self, statements: &[StmtId]) -> Result<T, Error>;
+        fn visit_while_stmt(
+            &mut self,
+            keyword: &Token,
+            condition: ExprId,
+            body: StmtId,
+            increment: Option<ExprId>,
+            label: Option<&Token>,
+        ) -> Result<T, Error>;
+        fn visit_for_in_stmt(
+            &mut self,
+            keyword: &Token,
+            name: &Token,
+            iterable: ExprId,
+            body: StmtId,
+            label: Option<&Token>,
+        ) -> Result<T, Error>;
+        fn visit_func_stmt(
+            &mut self,
+            name: &Token,
+            params: &[Token],
+            defaults: &[ExprId],
+            body: &Rc<[StmtId]>,
+            doc: Option<&str>,
+            captures: &[ExprId],
+        ) -> Result<T, Error>;
+        fn visit_return_stmt(&mut self, keyword: &Token, value: Option<ExprId>) -> Result<T, Error>;
+        fn visit_class_stmt(
+            &mut self,
+            name: &Token,
+            super_class: Option<ExprId>,
+            fields: &[StmtId],
+            methods: &[StmtId],
+            doc: Option<&str>,
+        ) -> Result<T, Error>;
+        fn visit_break_stmt(&mut self, keyword: &Token, label: Option<&Token>) -> Result<T, Error>;
+        fn visit_continue_stmt(
+            &mut self,
+            keyword: &Token,
+            label: Option<&Token>,
+        ) -> Result<T, Error>;
+        fn visit_assert_stmt(
+            &mut self,
+            keyword: &Token,
+            condition: ExprId,
+            message: Option<ExprId>,
+            source: &str,
+        ) -> Result<T, Error>;
+        fn visit_defer_stmt(&mut self, keyword: &Token, body: StmtId) -> Result<T, Error>;
+        fn visit_try_stmt(
+            &mut self,
+            keyword: &Token,
+            body: &[StmtId],
+            catch: Option<(&Token, &[StmtId])>,
+            finally: Option<&[StmtId]>,
+        ) -> Result<T, Error>;
+        fn visit_throw_stmt(&mut self, keyword: &Token, value: ExprId) -> Result<T, Error>;
     }
 }
 
@@ -25,6 +89,12 @@ pub mod stmt {
 ///                | whileStmt ;
 ///                | forStmt ;
 ///                | returnStmt
+///                | breakStmt
+///                | continueStmt
+///                | assertStmt
+///                | deferStmt
+///                | tryStmt
+///                | throwStmt ;
 /// exprStmt       → expression ";" ;
 /// ifStmt         → "if" "(" expression ")" statement ( "else" statement )? ;
 /// printStmt      → "print" expression ";" ;
@@ -33,204 +103,513 @@ pub mod stmt {
 /// forStmt        | "for" "(" ( varDecl | exprStmt | ";" )
 ///                         expression? ";"
 ///                         expression? ")" statement ;
+/// forInStmt      | "for" "(" "var" IDENTIFIER "in" expression ")" statement ;
 /// returnStmt     | "return" expression? ";" ;
+/// breakStmt      | "break" IDENTIFIER? ";" ;
+/// continueStmt   | "continue" IDENTIFIER? ";" ;
+/// assertStmt     | "assert" expression ( "," expression )? ";" ;
+/// tryStmt        | "try" block ( "catch" "(" IDENTIFIER ")" block )? ( "finally" block )? ;
+/// throwStmt      | "throw" expression ";" ;
 #[derive(Debug, Clone)]
 pub enum Stmt {
     ExprStmt {
-        expression: Expr,
+        expression: ExprId,
     },
     IfStmt {
-        condition: Expr,
-        then_branch: Box<Stmt>,
-        else_branch: Option<Box<Stmt>>,
+        keyword: Token,
+        condition: ExprId,
+        then_branch: StmtId,
+        else_branch: Option<StmtId>,
     },
     PrintStmt {
-        expression: Expr,
+        keyword: Token,
+        expression: ExprId,
     },
     VarStmt {
         name: Token,
-        initializer: Option<Expr>,
+        initializer: Option<ExprId>,
+    },
+    /// `var a, b = f();`, one name for each value of a tuple or list
+    DestructureStmt {
+        names: Vec<Token>,
+        initializer: ExprId,
     },
     BlockStmt {
-        statements: Vec<Stmt>,
+        statements: Vec<StmtId>,
+        /// the keyword of the statement a desugared block was made from,
+        /// `for`, so it is reported where and as what it was written
+        origin: Option<Token>,
     },
     WhileStmt {
-        condition: Expr,
-        body: Box<Stmt>,
+        /// `while`, or `for` for a desugared for loop
+        keyword: Token,
+        condition: ExprId,
+        body: StmtId,
+        /// run after every iteration, even one left by `continue`
+        increment: Option<ExprId>,
+        label: Option<Token>,
+    },
+    /// `for (var x in xs)`, the body runs once for each element of a
+    /// list or tuple, character of a string or key of a map, with `x`
+    /// bound to it in a scope of its own
+    ForInStmt {
+        keyword: Token,
+        name: Token,
+        iterable: ExprId,
+        body: StmtId,
+        label: Option<Token>,
     },
     FunStmt {
         name: Token,
         params: Vec<Token>,
-        body: Vec<Stmt>,
+        /// `fun f(a, b = 1)`, the values of the last `defaults.len()`
+        /// params when a call leaves them out
+        defaults: Vec<ExprId>,
+        body: Rc<[StmtId]>,
+        doc: Option<String>,
+        /// `fun name[a, b]()` copies a and b when the closure is created,
+        /// each one an Expr::Variable read where the function is declared
+        captures: Vec<ExprId>,
     },
     ReturnStmt {
         keyword: Token,
-        value: Option<Expr>,
+        value: Option<ExprId>,
     },
     ClassStmt {
         name: Token,
-        super_class: Option<Expr>,
-        methods: Vec<Stmt>,
+        super_class: Option<ExprId>,
+        /// `var x = 0;` in the class body, a VarStmt for each field every
+        /// instance starts with
+        fields: Vec<StmtId>,
+        methods: Vec<StmtId>,
+        doc: Option<String>,
+    },
+    BreakStmt {
+        keyword: Token,
+        label: Option<Token>,
+    },
+    ContinueStmt {
+        keyword: Token,
+        label: Option<Token>,
+    },
+    AssertStmt {
+        keyword: Token,
+        condition: ExprId,
+        message: Option<ExprId>,
+        /// the condition as it was written, for the error message
+        source: String,
+    },
+    /// `defer stmt;` runs stmt when the enclosing block is left, in
+    /// reverse order of the defers, however the block ends
+    DeferStmt {
+        keyword: Token,
+        body: StmtId,
+    },
+    /// errors and thrown values raised in `body` go to the catch block,
+    /// bound to its name, and `finally` runs however the others end
+    TryStmt {
+        keyword: Token,
+        body: Vec<StmtId>,
+        catch: Option<(Token, Vec<StmtId>)>,
+        finally: Option<Vec<StmtId>>,
     },
+    ThrowStmt {
+        keyword: Token,
+        value: ExprId,
+    },
+}
+
+impl StmtId {
+    pub fn accept<T>(self, ast: &Ast, visitor: &mut impl stmt::Visitor<T>) -> Result<T, Error> {
+        grow_stack(|| ast[self].dispatch(visitor))
+    }
 }
 
 impl Stmt {
-    pub fn accept<T>(&self, visitor: &mut impl stmt::Visitor<T>) -> Result<T, Error> {
+    /// the token the statement starts with, if it keeps one
+    pub fn token<'a>(&'a self, ast: &'a Ast) -> Option<&'a Token> {
         match self {
-            Stmt::ExprStmt { .. } => visitor.visit_expr_stmt(self),
-            Stmt::IfStmt { .. } => visitor.visit_if_stmt(self),
-            Stmt::PrintStmt { .. } => visitor.visit_print_stmt(self),
-            Stmt::VarStmt { .. } => visitor.visit_var_stmt(self),
-            Stmt::BlockStmt { .. } => visitor.visit_block_stmt(self),
-            Stmt::WhileStmt { .. } => visitor.visit_while_stmt(self),
-            Stmt::FunStmt { .. } => visitor.visit_func_stmt(self),
-            Stmt::ReturnStmt { .. } => visitor.visit_return_stmt(self),
-            Stmt::ClassStmt { .. } => visitor.visit_class_stmt(self),
+            Stmt::ExprStmt { expression } => ast[*expression].token(ast),
+            Stmt::BlockStmt { statements, origin } => origin
+                .as_ref()
+                .or_else(|| statements.iter().find_map(|s| ast[*s].token(ast))),
+            Stmt::PrintStmt { keyword, .. }
+            | Stmt::IfStmt { keyword, .. }
+            | Stmt::WhileStmt { keyword, .. }
+            | Stmt::ForInStmt { keyword, .. }
+            | Stmt::ReturnStmt { keyword, .. }
+            | Stmt::BreakStmt { keyword, .. }
+            | Stmt::ContinueStmt { keyword, .. }
+            | Stmt::AssertStmt { keyword, .. }
+            | Stmt::DeferStmt { keyword, .. }
+            | Stmt::TryStmt { keyword, .. }
+            | Stmt::ThrowStmt { keyword, .. } => Some(keyword),
+            Stmt::DestructureStmt { names, .. } => names.first(),
+            Stmt::VarStmt { name, .. }
+            | Stmt::FunStmt { name, .. }
+            | Stmt::ClassStmt { name, .. } => Some(name),
         }
     }
-}
 
-impl Display for Stmt {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let mut printer = AstPrinter::new();
-        write!(f, "{}", self.accept(&mut printer).unwrap())
+    /// the line the statement starts on, if any token of it says so
+    pub fn line(&self, ast: &Ast) -> Option<usize> {
+        self.token(ast).map(|token| token.line)
     }
-}
 
-impl stmt::Visitor<String> for AstPrinter {
-    fn visit_block_stmt(&mut self, stmt: &Stmt) -> Result<String, Error> {
-        match stmt {
-            Stmt::BlockStmt { statements } => {
-                let mut s = String::new();
-                s.push_str("block: {\n");
-                for stmt in statements {
-                    s.push_str(stmt.accept(self)?.as_str());
-                    s.push('\n');
-                }
-                s.push('}');
-                Ok(s)
-            }
-            _ => unreachable!(),
-        }
-    }
-    fn visit_class_stmt(&mut self, stmt: &Stmt) -> Result<String, Error> {
-        match stmt {
-            Stmt::ClassStmt { name, methods, .. } => {
-                let mut s = String::new();
-                s.push_str("class: ");
-                s.push_str(name.lexeme.as_str());
-                s.push_str(" {\n");
-                for stmt in methods {
-                    s.push_str(stmt.accept(self)?.as_str());
-                    s.push('\n');
-                }
-                s.push('}');
-                Ok(s)
-            }
-            _ => unreachable!(),
-        }
-    }
-    fn visit_expr_stmt(&mut self, stmt: &Stmt) -> Result<String, Error> {
-        match stmt {
-            Stmt::ExprStmt { expression } => Ok(expression.accept(self)?.as_str().to_string()),
-            _ => unreachable!(),
-        }
-    }
-    fn visit_func_stmt(&mut self, stmt: &Stmt) -> Result<String, Error> {
-        match stmt {
-            Stmt::FunStmt { name, params, body } => {
-                let mut s = String::new();
-                s.push_str("fun: ");
-                s.push_str(name.lexeme.as_str());
-                s.push_str(" (");
-                for param in params {
-                    s.push_str(param.lexeme.as_str());
-                    s.push_str(", ");
-                }
-                s.push_str(") {\n");
-                for stmt in body {
-                    s.push_str(stmt.accept(self)?.as_str());
-                    s.push('\n');
-                }
-                s.push('}');
-                Ok(s)
-            }
-            _ => unreachable!(),
+    /// a short name for the kind of statement
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Stmt::ExprStmt { .. } => "expr",
+            Stmt::IfStmt { .. } => "if",
+            Stmt::PrintStmt { .. } => "print",
+            Stmt::VarStmt { .. } | Stmt::DestructureStmt { .. } => "var",
+            Stmt::BlockStmt {
+                origin: Some(origin),
+                ..
+            } => match origin.token_type {
+                TokenType::For => "for",
+                _ => "block",
+            },
+            Stmt::BlockStmt { .. } => "block",
+            Stmt::WhileStmt { .. } => "while",
+            Stmt::ForInStmt { .. } => "for",
+            Stmt::FunStmt { .. } => "fun",
+            Stmt::ReturnStmt { .. } => "return",
+            Stmt::ClassStmt { .. } => "class",
+            Stmt::BreakStmt { .. } => "break",
+            Stmt::ContinueStmt { .. } => "continue",
+            Stmt::AssertStmt { .. } => "assert",
+            Stmt::DeferStmt { .. } => "defer",
+            Stmt::TryStmt { .. } => "try",
+            Stmt::ThrowStmt { .. } => "throw",
         }
     }
-    fn visit_if_stmt(&mut self, stmt: &Stmt) -> Result<String, Error> {
-        match stmt {
+
+    fn dispatch<T>(&self, visitor: &mut impl stmt::Visitor<T>) -> Result<T, Error> {
+        match self {
+            Stmt::ExprStmt { expression } => visitor.visit_expr_stmt(*expression),
             Stmt::IfStmt {
+                keyword,
                 condition,
                 then_branch,
                 else_branch,
-            } => {
-                let mut s = String::new();
-                s.push_str("if: ");
-                s.push_str(condition.accept(self)?.as_str());
-                s.push_str(" then: ");
-                s.push_str(then_branch.accept(self)?.as_str());
-                if let Some(else_branch) = else_branch {
-                    s.push_str(" else: ");
-                    s.push_str(else_branch.accept(self)?.as_str());
-                }
-                Ok(s)
+            } => visitor.visit_if_stmt(keyword, *condition, *then_branch, *else_branch),
+            Stmt::PrintStmt {
+                keyword,
+                expression,
+            } => visitor.visit_print_stmt(keyword, *expression),
+            Stmt::VarStmt { name, initializer } => {
+                visitor.visit_var_stmt(name, *initializer)
+            }
+            Stmt::DestructureStmt { names, initializer } => {
+                visitor.visit_destructure_stmt(names, *initializer)
+            }
+            Stmt::BlockStmt { statements, .. } => visitor.visit_block_stmt(statements),
+            Stmt::WhileStmt {
+                keyword,
+                condition,
+                body,
+                increment,
+                label,
+            } => visitor.visit_while_stmt(
+                keyword,
+                *condition,
+                *body,
+                *increment,
+                label.as_ref(),
+            ),
+            Stmt::ForInStmt {
+                keyword,
+                name,
+                iterable,
+                body,
+                label,
+            } => visitor.visit_for_in_stmt(keyword, name, *iterable, *body, label.as_ref()),
+            Stmt::FunStmt {
+                name,
+                params,
+                defaults,
+                body,
+                doc,
+                captures,
+            } => visitor.visit_func_stmt(name, params, defaults, body, doc.as_deref(), captures),
+            Stmt::ReturnStmt { keyword, value } => {
+                visitor.visit_return_stmt(keyword, *value)
+            }
+            Stmt::ClassStmt {
+                name,
+                super_class,
+                fields,
+                methods,
+                doc,
+            } => visitor.visit_class_stmt(
+                name,
+                *super_class,
+                fields,
+                methods,
+                doc.as_deref(),
+            ),
+            Stmt::BreakStmt { keyword, label } => visitor.visit_break_stmt(keyword, label.as_ref()),
+            Stmt::ContinueStmt { keyword, label } => {
+                visitor.visit_continue_stmt(keyword, label.as_ref())
             }
-            _ => unreachable!(),
+            Stmt::AssertStmt {
+                keyword,
+                condition,
+                message,
+                source,
+            } => visitor.visit_assert_stmt(keyword, *condition, *message, source),
+            Stmt::DeferStmt { keyword, body } => visitor.visit_defer_stmt(keyword, *body),
+            Stmt::TryStmt {
+                keyword,
+                body,
+                catch,
+                finally,
+            } => visitor.visit_try_stmt(
+                keyword,
+                body,
+                catch.as_ref().map(|(name, body)| (name, body.as_slice())),
+                finally.as_deref(),
+            ),
+            Stmt::ThrowStmt { keyword, value } => visitor.visit_throw_stmt(keyword, *value),
         }
     }
-    fn visit_print_stmt(&mut self, stmt: &Stmt) -> Result<String, Error> {
-        match stmt {
-            Stmt::PrintStmt { expression } => {
-                let mut s = String::new();
-                s.push_str("print: ");
-                s.push_str(expression.accept(self)?.as_str());
-                Ok(s)
-            }
-            _ => unreachable!(),
+}
+
+impl stmt::Visitor<String> for AstPrinter<'_> {
+    fn visit_block_stmt(&mut self, statements: &[StmtId]) -> Result<String, Error> {
+        let mut s = String::new();
+        s.push_str("block: {\n");
+        for stmt in statements {
+            s.push_str(self.print_stmt(*stmt)?.as_str());
+            s.push('\n');
         }
+        s.push('}');
+        Ok(s)
     }
-    fn visit_return_stmt(&mut self, stmt: &Stmt) -> Result<String, Error> {
-        match stmt {
-            Stmt::ReturnStmt { keyword, value } => {
-                let mut s = String::new();
-                s.push_str("return: ");
-                s.push_str(keyword.lexeme.as_str());
-                if let Some(value) = value {
-                    s.push(' ');
-                    s.push_str(value.accept(self)?.as_str());
-                }
-                Ok(s)
-            }
-            _ => unreachable!(),
+    fn visit_class_stmt(
+        &mut self,
+        name: &Token,
+        _super_class: Option<ExprId>,
+        fields: &[StmtId],
+        methods: &[StmtId],
+        _doc: Option<&str>,
+    ) -> Result<String, Error> {
+        let mut s = String::new();
+        s.push_str("class: ");
+        s.push_str(name.lexeme.as_str());
+        s.push_str(" {\n");
+        for stmt in fields.iter().chain(methods) {
+            s.push_str(self.print_stmt(*stmt)?.as_str());
+            s.push('\n');
         }
+        s.push('}');
+        Ok(s)
     }
-    fn visit_var_stmt(&mut self, stmt: &Stmt) -> Result<String, Error> {
-        match stmt {
-            Stmt::VarStmt { name, initializer } => {
-                let mut s = String::new();
-                s.push_str("var: ");
-                s.push_str(name.lexeme.as_str());
-                if let Some(initializer) = initializer {
-                    s.push_str(" = ");
-                    s.push_str(initializer.accept(self)?.as_str());
-                }
-                Ok(s)
+    fn visit_expr_stmt(&mut self, expression: ExprId) -> Result<String, Error> {
+        Ok(self.print(expression)?.as_str().to_string())
+    }
+    fn visit_func_stmt(
+        &mut self,
+        name: &Token,
+        params: &[Token],
+        defaults: &[ExprId],
+        body: &Rc<[StmtId]>,
+        _doc: Option<&str>,
+        captures: &[ExprId],
+    ) -> Result<String, Error> {
+        let mut s = String::new();
+        s.push_str("fun: ");
+        s.push_str(name.lexeme.as_str());
+        if !captures.is_empty() {
+            s.push_str(" [");
+            for capture in captures {
+                s.push_str(&self.print(*capture)?);
+                s.push_str(", ");
             }
-            _ => unreachable!(),
-        }
-    }
-    fn visit_while_stmt(&mut self, stmt: &Stmt) -> Result<String, Error> {
-        match stmt {
-            Stmt::WhileStmt { condition, body } => {
-                let mut s = String::new();
-                s.push_str("while: ");
-                s.push_str(condition.accept(self)?.as_str());
-                s.push_str(" body: ");
-                s.push_str(body.accept(self)?.as_str());
-                Ok(s)
+            s.push(']');
+        }
+        s.push_str(" (");
+        let first_default = params.len() - defaults.len();
+        for (index, param) in params.iter().enumerate() {
+            s.push_str(param.lexeme.as_str());
+            if let Some(default) = index.checked_sub(first_default).map(|i| &defaults[i]) {
+                s.push_str(" = ");
+                s.push_str(&self.print(*default)?);
             }
-            _ => unreachable!(),
+            s.push_str(", ");
+        }
+        s.push_str(") {\n");
+        for stmt in body.iter() {
+            s.push_str(self.print_stmt(*stmt)?.as_str());
+            s.push('\n');
+        }
+        s.push('}');
+        Ok(s)
+    }
+    fn visit_if_stmt(
+        &mut self,
+        _keyword: &Token,
+        condition: ExprId,
+        then_branch: StmtId,
+        else_branch: Option<StmtId>,
+    ) -> Result<String, Error> {
+        let mut s = String::new();
+        s.push_str("if: ");
+        s.push_str(self.print(condition)?.as_str());
+        s.push_str(" then: ");
+        s.push_str(self.print_stmt(then_branch)?.as_str());
+        if let Some(else_branch) = else_branch {
+            s.push_str(" else: ");
+            s.push_str(self.print_stmt(else_branch)?.as_str());
+        }
+        Ok(s)
+    }
+    fn visit_print_stmt(&mut self, _keyword: &Token, expression: ExprId) -> Result<String, Error> {
+        let mut s = String::new();
+        s.push_str("print: ");
+        s.push_str(self.print(expression)?.as_str());
+        Ok(s)
+    }
+    fn visit_return_stmt(
+        &mut self,
+        keyword: &Token,
+        value: Option<ExprId>,
+    ) -> Result<String, Error> {
+        let mut s = String::new();
+        s.push_str("return: ");
+        s.push_str(keyword.lexeme.as_str());
+        if let Some(value) = value {
+            s.push(' ');
+            s.push_str(self.print(value)?.as_str());
         }
+        Ok(s)
+    }
+    fn visit_var_stmt(
+        &mut self,
+        name: &Token,
+        initializer: Option<ExprId>,
+    ) -> Result<String, Error> {
+        let mut s = String::new();
+        s.push_str("var: ");
+        s.push_str(name.lexeme.as_str());
+        if let Some(initializer) = initializer {
+            s.push_str(" = ");
+            s.push_str(self.print(initializer)?.as_str());
+        }
+        Ok(s)
+    }
+    fn visit_destructure_stmt(
+        &mut self,
+        names: &[Token],
+        initializer: ExprId,
+    ) -> Result<String, Error> {
+        let names: Vec<&str> = names.iter().map(|name| name.lexeme.as_str()).collect();
+        Ok(format!("var: {} = {}", names.join(", "), self.print(initializer)?))
+    }
+    fn visit_while_stmt(
+        &mut self,
+        _keyword: &Token,
+        condition: ExprId,
+        body: StmtId,
+        increment: Option<ExprId>,
+        label: Option<&Token>,
+    ) -> Result<String, Error> {
+        let mut s = String::new();
+        if let Some(label) = label {
+            s.push_str(label.lexeme.as_str());
+            s.push_str(": ");
+        }
+        s.push_str("while: ");
+        s.push_str(self.print(condition)?.as_str());
+        s.push_str(" body: ");
+        s.push_str(self.print_stmt(body)?.as_str());
+        if let Some(increment) = increment {
+            s.push_str(" increment: ");
+            s.push_str(self.print(increment)?.as_str());
+        }
+        Ok(s)
+    }
+    fn visit_for_in_stmt(
+        &mut self,
+        _keyword: &Token,
+        name: &Token,
+        iterable: ExprId,
+        body: StmtId,
+        label: Option<&Token>,
+    ) -> Result<String, Error> {
+        let mut s = String::new();
+        if let Some(label) = label {
+            s.push_str(label.lexeme.as_str());
+            s.push_str(": ");
+        }
+        s.push_str(&format!("for: {} in {}", name.lexeme, self.print(iterable)?));
+        s.push_str(" body: ");
+        s.push_str(self.print_stmt(body)?.as_str());
+        Ok(s)
+    }
+    fn visit_break_stmt(
+        &mut self,
+        _keyword: &Token,
+        label: Option<&Token>,
+    ) -> Result<String, Error> {
+        Ok(match label {
+            Some(label) => format!("break: {}", label.lexeme),
+            None => "break".to_string(),
+        })
+    }
+    fn visit_continue_stmt(
+        &mut self,
+        _keyword: &Token,
+        label: Option<&Token>,
+    ) -> Result<String, Error> {
+        Ok(match label {
+            Some(label) => format!("continue: {}", label.lexeme),
+            None => "continue".to_string(),
+        })
+    }
+    fn visit_assert_stmt(
+        &mut self,
+        _keyword: &Token,
+        condition: ExprId,
+        message: Option<ExprId>,
+        _source: &str,
+    ) -> Result<String, Error> {
+        let mut s = String::new();
+        s.push_str("assert: ");
+        s.push_str(self.print(condition)?.as_str());
+        if let Some(message) = message {
+            s.push_str(", ");
+            s.push_str(self.print(message)?.as_str());
+        }
+        Ok(s)
+    }
+    fn visit_defer_stmt(&mut self, _keyword: &Token, body: StmtId) -> Result<String, Error> {
+        Ok(format!("defer: {}", self.print_stmt(body)?))
+    }
+    fn visit_try_stmt(
+        &mut self,
+        _keyword: &Token,
+        body: &[StmtId],
+        catch: Option<(&Token, &[StmtId])>,
+        finally: Option<&[StmtId]>,
+    ) -> Result<String, Error> {
+        let mut s = String::new();
+        s.push_str("try: ");
+        s.push_str(self.visit_block_stmt(body)?.as_str());
+        if let Some((name, handler)) = catch {
+            s.push_str(" catch ");
+            s.push_str(name.lexeme.as_str());
+            s.push_str(": ");
+            s.push_str(self.visit_block_stmt(handler)?.as_str());
+        }
+        if let Some(finally) = finally {
+            s.push_str(" finally: ");
+            s.push_str(self.visit_block_stmt(finally)?.as_str());
+        }
+        Ok(s)
+    }
+    fn visit_throw_stmt(&mut self, _keyword: &Token, value: ExprId) -> Result<String, Error> {
+        Ok(format!("throw: {}", self.print(value)?))
     }
 }
@@ -0,0 +1,30 @@
+/// `debugEnv()` prints the bindings visible where it is called, one
+/// per line with the number of scopes out they were found
+use super::native;
+use crate::{Environment, Error, Function, Interpreter, Object};
+
+pub fn define(globals: &mut Environment) {
+    globals.define("debugEnv", native("debugEnv", 0, debug_env));
+}
+
+/// `[depth] name = value` for every visible binding, leaving out the
+/// natives every program starts with
+pub fn describe(environment: &Environment) -> Vec<String> {
+    environment
+        .flatten()
+        .into_iter()
+        .filter(|binding| match &binding.value {
+            Object::Callable(function) => !matches!(**function, Function::Native { .. }),
+            _ => true,
+        })
+        .map(|binding| format!("[{}] {} = {}", binding.depth, binding.name, binding.value))
+        .collect()
+}
+
+fn debug_env(interpreter: &mut Interpreter, _: &[Object]) -> Result<Object, Error> {
+    let lines = describe(&interpreter.environment().borrow());
+    for line in lines {
+        interpreter.print_line(&line)?;
+    }
+    Ok(Object::Nil)
+}
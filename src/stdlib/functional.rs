@@ -0,0 +1,106 @@
+/// natives that build new functions out of other callables
+use std::rc::Rc;
+
+use super::native;
+use crate::{Environment, Error, Function, Interpreter, NativeClosureFn, Object};
+
+pub fn define(globals: &mut Environment) {
+    globals.define("compose", native("compose", 2, compose));
+    globals.define("partial", native("partial", 2, partial));
+    globals.define("curry", native("curry", 1, curry));
+}
+
+/// `compose(f, g)`, a function that calls `g` and passes its result to `f`
+fn compose(interpreter: &mut Interpreter, args: &[Object]) -> Result<Object, Error> {
+    arity(&args[0])?;
+    let arity = arity(&args[1])?;
+    Ok(closure(
+        interpreter,
+        "compose",
+        arity,
+        args.to_vec(),
+        composed,
+    ))
+}
+
+fn composed(
+    interpreter: &mut Interpreter,
+    captures: &[Object],
+    args: &[Object],
+) -> Result<Object, Error> {
+    let inner = interpreter.call_value(&captures[1], args)?;
+    interpreter.call_value(&captures[0], &[inner])
+}
+
+/// `partial(f, arg)`, `f` with its first argument filled in
+fn partial(interpreter: &mut Interpreter, args: &[Object]) -> Result<Object, Error> {
+    let arity = arity(&args[0])?;
+    if arity == 0 {
+        return Err(Error::coded("E0502", &[]));
+    }
+    Ok(closure(
+        interpreter,
+        "partial",
+        arity - 1,
+        args.to_vec(),
+        with_bound,
+    ))
+}
+
+/// call the captured function with the captured arguments first
+fn with_bound(
+    interpreter: &mut Interpreter,
+    captures: &[Object],
+    args: &[Object],
+) -> Result<Object, Error> {
+    let mut all = captures[1..].to_vec();
+    all.extend_from_slice(args);
+    interpreter.call_value(&captures[0], &all)
+}
+
+/// `curry(f)`, a function taking `f`'s arguments one call at a time
+fn curry(interpreter: &mut Interpreter, args: &[Object]) -> Result<Object, Error> {
+    if arity(&args[0])? <= 1 {
+        return Ok(args[0].clone());
+    }
+    Ok(closure(interpreter, "curry", 1, args.to_vec(), curried))
+}
+
+/// collect one more argument, calling the function once it has all of them
+fn curried(
+    interpreter: &mut Interpreter,
+    captures: &[Object],
+    args: &[Object],
+) -> Result<Object, Error> {
+    let mut collected = captures.to_vec();
+    collected.extend_from_slice(args);
+    if collected.len() - 1 == arity(&captures[0])? {
+        with_bound(interpreter, &collected, &[])
+    } else {
+        Ok(closure(interpreter, "curry", 1, collected, curried))
+    }
+}
+
+fn closure(
+    interpreter: &mut Interpreter,
+    name: &str,
+    arity: usize,
+    captures: Vec<Object>,
+    body: NativeClosureFn,
+) -> Object {
+    interpreter.allocate(Object::Callable(Rc::new(Function::NativeClosure {
+        name: name.to_string(),
+        arity,
+        body: Box::new(body),
+        captures: Rc::new(captures),
+    })))
+}
+
+/// how many arguments a callable takes
+fn arity(callee: &Object) -> Result<usize, Error> {
+    match callee {
+        Object::Callable(function) => Ok(function.arity()),
+        Object::Class(class) => Ok(class.borrow().arity()),
+        _ => Err(Error::coded("E0501", &[&callee.type_name()])),
+    }
+}
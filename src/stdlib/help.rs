@@ -0,0 +1,27 @@
+/// `help(x)` prints the `///` documentation of a function or class
+use super::native;
+use crate::{Environment, Error, Interpreter, Object};
+
+pub fn define(globals: &mut Environment) {
+    globals.define("help", native("help", 1, help));
+}
+
+/// the documentation of a function, a class or the class of an instance
+pub fn documentation(object: &Object) -> Option<String> {
+    match object {
+        Object::Callable(function) => function.doc().map(|doc| doc.to_string()),
+        Object::Class(class) => class.borrow().doc.clone(),
+        Object::Instance(instance) => instance.borrow().class().borrow().doc.clone(),
+        _ => None,
+    }
+}
+
+/// the text `help` and the REPL's `:doc` show for a value
+pub fn help_text(object: &Object) -> String {
+    documentation(object).unwrap_or_else(|| format!("No documentation for {}.", object))
+}
+
+fn help(interpreter: &mut Interpreter, args: &[Object]) -> Result<Object, Error> {
+    interpreter.print_line(&help_text(&args[0]))?;
+    Ok(Object::Nil)
+}
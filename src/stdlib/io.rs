@@ -0,0 +1,122 @@
+/// printing as functions, so it can be passed around, used without
+/// ending the line or laid out over several lines, reading input and
+/// reading and writing files
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+
+use super::methods::string_argument;
+use super::{native, native_arities};
+use crate::object::set_float_precision;
+use crate::{Environment, Error, Interpreter, NumberType, Object};
+
+pub fn define(globals: &mut Environment) {
+    globals.define("print", native("print", 1, println));
+    globals.define("println", native("println", 1, println));
+    globals.define("write", native("write", 1, write));
+    globals.define("eprint", native("eprint", 1, eprint));
+    globals.define("pp", native("pp", 1, pp));
+    globals.define("flush", native("flush", 0, flush));
+    globals.define("input", native_arities("input", 0..=1, input));
+    globals.define("readFile", native("readFile", 1, read_file));
+    globals.define("writeFile", native("writeFile", 2, write_file));
+    globals.define("appendFile", native("appendFile", 2, append_file));
+    globals.define("setPrecision", native("setPrecision", 1, set_precision));
+}
+
+/// `println(x)`, the same as the print statement
+fn println(interpreter: &mut Interpreter, args: &[Object]) -> Result<Object, Error> {
+    let text = interpreter.display(&args[0])?;
+    interpreter.print_line(&text)?;
+    Ok(Object::Nil)
+}
+
+/// `write(x)`, print without a newline
+fn write(interpreter: &mut Interpreter, args: &[Object]) -> Result<Object, Error> {
+    let text = interpreter.display(&args[0])?;
+    interpreter.print(&text)?;
+    Ok(Object::Nil)
+}
+
+/// `eprint(x)`, a line on the error output
+fn eprint(interpreter: &mut Interpreter, args: &[Object]) -> Result<Object, Error> {
+    let text = interpreter.display(&args[0])?;
+    interpreter.eprint_line(&text)?;
+    Ok(Object::Nil)
+}
+
+/// `pp(x)`, print nested values over several lines when they are long
+fn pp(interpreter: &mut Interpreter, args: &[Object]) -> Result<Object, Error> {
+    let text = interpreter.pretty_printer().format(&args[0]);
+    interpreter.print_line(&text)?;
+    Ok(Object::Nil)
+}
+
+/// `flush()`, write out what was printed so far right away
+fn flush(interpreter: &mut Interpreter, _args: &[Object]) -> Result<Object, Error> {
+    interpreter.flush()?;
+    Ok(Object::Nil)
+}
+
+/// `input(prompt)`, print the prompt and read a line, nil at the end
+/// of the input. the prompt can be left out
+fn input(interpreter: &mut Interpreter, args: &[Object]) -> Result<Object, Error> {
+    if let Some(prompt) = args.first() {
+        let prompt = interpreter.display(prompt)?;
+        interpreter.print(&prompt)?;
+    }
+    Ok(match interpreter.read_line()? {
+        Some(line) => interpreter.allocate(Object::String(line.into())),
+        None => Object::Nil,
+    })
+}
+
+/// `readFile(path)`, the whole file as a string
+fn read_file(interpreter: &mut Interpreter, args: &[Object]) -> Result<Object, Error> {
+    let path = string_argument("readFile", &args[0])?;
+    let text = fs::read_to_string(path).map_err(|err| Error::coded("E0532", &[&path, &err]))?;
+    Ok(interpreter.allocate(Object::String(text.into())))
+}
+
+/// `writeFile(path, text)`, replace the file with text, creating it if
+/// it isn't there
+fn write_file(_: &mut Interpreter, args: &[Object]) -> Result<Object, Error> {
+    let path = string_argument("writeFile", &args[0])?;
+    let text = string_argument("writeFile", &args[1])?;
+    fs::write(path, text).map_err(|err| write_error(path, err))?;
+    Ok(Object::Nil)
+}
+
+/// `appendFile(path, text)`, add text to the end of the file, creating
+/// it if it isn't there
+fn append_file(_: &mut Interpreter, args: &[Object]) -> Result<Object, Error> {
+    let path = string_argument("appendFile", &args[0])?;
+    let text = string_argument("appendFile", &args[1])?;
+    OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(text.as_bytes()))
+        .map_err(|err| write_error(path, err))?;
+    Ok(Object::Nil)
+}
+
+fn write_error(path: &str, err: std::io::Error) -> Error {
+    Error::coded("E0533", &[&path, &err])
+}
+
+/// more digits than a float carries would only show rounding noise
+pub const MAX_PRECISION: i64 = 17;
+
+/// `setPrecision(n)`, show floats with at most n digits after the
+/// point, `setPrecision(nil)` goes back to showing all of them
+fn set_precision(_: &mut Interpreter, args: &[Object]) -> Result<Object, Error> {
+    let digits = match &args[0] {
+        Object::Nil => None,
+        Object::Number(NumberType::Integer(n)) if (0..=MAX_PRECISION).contains(n) => {
+            Some(*n as usize)
+        }
+        _ => return Err(Error::coded("E0521", &[&MAX_PRECISION])),
+    };
+    set_float_precision(digits);
+    Ok(Object::Nil)
+}
@@ -0,0 +1,102 @@
+/// the protocol for-in follows. an instance is iterated through what its
+/// `iter()` method gives, or through itself when it has none, calling
+/// `hasNext()` before each `next()`. lists, strings, maps and tuples hand
+/// out an instance of the native `Iterator` class from `iter()`, so code
+/// written against the protocol takes them too
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use super::native;
+use crate::{
+    instance_field, ClassBuilder, ClassRef, Environment, Error, Interpreter, List, LoxInstance,
+    NumberType, Object,
+};
+
+thread_local! {
+    static ITERATOR: ClassRef = ClassBuilder::new("Iterator")
+        .doc("the elements of a list, string, map or tuple, one at a time")
+        .method("hasNext", 0, has_next)
+        .method("next", 0, next)
+        .build();
+}
+
+pub fn define(globals: &mut Environment) {
+    globals.define("iter", native("iter", 1, iter));
+}
+
+/// the elements a built-in value is iterated over, as they are now so
+/// changing the value afterwards doesn't upset the iteration. None for
+/// values that aren't collections
+pub fn elements(value: &Object) -> Option<Vec<Object>> {
+    match value {
+        Object::List(list) => Some(list.borrow().inner.clone()),
+        Object::Tuple(values) => Some(values.to_vec()),
+        Object::Map(map) => Some(map.borrow().iter().map(|(key, _)| key.clone()).collect()),
+        Object::String(s) => Some(
+            s.chars()
+                .map(|c| Object::String(c.to_string().into()))
+                .collect(),
+        ),
+        _ => None,
+    }
+}
+
+/// `iter(value)`, an iterator over a list, string, map or tuple, or
+/// what an instance's `iter()` method gives
+fn iter(interpreter: &mut Interpreter, args: &[Object]) -> Result<Object, Error> {
+    let value = &args[0];
+    if let Object::Instance(instance) = value {
+        let method = instance.borrow().class().borrow().get_method("iter");
+        return match method {
+            Some(method) => method.call_bound(interpreter, value.clone(), &[]),
+            None => Ok(value.clone()),
+        };
+    }
+    iter_method(interpreter, value, &[])
+}
+
+/// `value.iter()` for the built-in collections
+pub fn iter_method(
+    interpreter: &mut Interpreter,
+    this: &Object,
+    _: &[Object],
+) -> Result<Object, Error> {
+    let Some(elements) = elements(this) else {
+        return Err(Error::coded("E0450", &[&this.type_name()]));
+    };
+    let items = interpreter.allocate(Object::List(Rc::new(RefCell::new(List::from(elements)))));
+    let mut iterator = LoxInstance::new(ITERATOR.with(Rc::clone));
+    iterator.set("items", &items);
+    iterator.set("index", &Object::Number(NumberType::Integer(0)));
+    Ok(interpreter.allocate(Object::Instance(Rc::new(RefCell::new(iterator)))))
+}
+
+/// the element an `Iterator` hands out next, if any, and its index
+fn position(this: &Object) -> Result<(Option<Object>, usize), Error> {
+    match (
+        instance_field(this, "items")?,
+        instance_field(this, "index")?,
+    ) {
+        (Object::List(items), Object::Number(NumberType::Integer(index))) => {
+            let item = items.borrow().inner.get(index as usize).cloned();
+            Ok((item, index as usize))
+        }
+        _ => Err(Error::coded("E0537", &[])),
+    }
+}
+
+fn has_next(_interpreter: &mut Interpreter, this: &Object, _: &[Object]) -> Result<Object, Error> {
+    let (item, _) = position(this)?;
+    Ok(Object::Boolean(item.is_some()))
+}
+
+fn next(_interpreter: &mut Interpreter, this: &Object, _: &[Object]) -> Result<Object, Error> {
+    let (Some(item), index) = position(this)? else {
+        return Err(Error::coded("E0536", &[]));
+    };
+    if let Object::Instance(instance) = this {
+        let index = Object::Number(NumberType::Integer(index as i64 + 1));
+        instance.borrow_mut().set("index", &index);
+    }
+    Ok(item)
+}
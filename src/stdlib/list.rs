@@ -0,0 +1,110 @@
+/// higher-order natives over lists, calling back into Lox
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::rc::Rc;
+
+use super::{native, native_arities};
+use crate::{Environment, Error, Interpreter, List, NumberType, Object};
+
+pub fn define(globals: &mut Environment) {
+    globals.define("map", native("map", 2, map));
+    globals.define("sort", native_arities("sort", 1..=2, sort));
+    globals.define("range", native_arities("range", 1..=3, range));
+}
+
+/// `map(list, fn)`, a new list with `fn` applied to every element
+pub(super) fn map(interpreter: &mut Interpreter, args: &[Object]) -> Result<Object, Error> {
+    let elements = elements(&args[0])?;
+    let mut mapped = Vec::with_capacity(elements.len());
+    for element in elements {
+        // a return in the callback ends the callback, any other
+        // error unwinds through here to the Lox code calling map
+        mapped.push(interpreter.call_value(&args[1], &[element])?);
+    }
+    Ok(interpreter.allocate(new_list(mapped)))
+}
+
+/// `sort(list, compare)`, a new sorted list. `compare(a, b)` returns a
+/// negative number when a goes first, a positive one when b does.
+/// without `compare` numbers and strings sort in their natural order
+fn sort(interpreter: &mut Interpreter, args: &[Object]) -> Result<Object, Error> {
+    let mut elements = elements(&args[0])?;
+    let mut error: Option<Error> = None;
+    elements.sort_by(|a, b| {
+        if error.is_some() {
+            return Ordering::Equal;
+        }
+        let result = match args.get(1) {
+            Some(compare) => interpreter
+                .call_value(compare, &[a.clone(), b.clone()])
+                .and_then(|order| match order {
+                    Object::Number(n) => {
+                        Ok(n.as_float().partial_cmp(&0.0).unwrap_or(Ordering::Equal))
+                    }
+                    _ => Err(Error::coded("E0504", &[])),
+                }),
+            None => natural_order(a, b),
+        };
+        result.unwrap_or_else(|err| {
+            error = Some(err);
+            Ordering::Equal
+        })
+    });
+    match error {
+        Some(error) => Err(error),
+        None => Ok(interpreter.allocate(new_list(elements))),
+    }
+}
+
+/// the order `sort` uses without a comparator
+fn natural_order(a: &Object, b: &Object) -> Result<Ordering, Error> {
+    match (a, b) {
+        (Object::Number(a), Object::Number(b)) => Ok(a.compare(b).unwrap_or(Ordering::Equal)),
+        (Object::String(a), Object::String(b)) => Ok(a.cmp(b)),
+        _ => Err(Error::coded("E0525", &[&a.type_name(), &b.type_name()])),
+    }
+}
+
+/// `range(stop)`, `range(start, stop)` or `range(start, stop, step)`,
+/// a list of the integers from start, 0 if left out, up to but not
+/// including stop, counting by step, 1 if left out
+fn range(interpreter: &mut Interpreter, args: &[Object]) -> Result<Object, Error> {
+    let bounds = args
+        .iter()
+        .map(|arg| match arg {
+            Object::Number(NumberType::Integer(i)) => Ok(*i),
+            _ => Err(Error::coded("E0526", &[&arg.type_name()])),
+        })
+        .collect::<Result<Vec<i64>, Error>>()?;
+    let (start, stop, step) = match bounds[..] {
+        [stop] => (0, stop, 1),
+        [start, stop] => (start, stop, 1),
+        [start, stop, step] => (start, stop, step),
+        _ => unreachable!(),
+    };
+    if step == 0 {
+        return Err(Error::coded("E0527", &[]));
+    }
+    let mut numbers = Vec::new();
+    let mut i = start;
+    while (step > 0 && i < stop) || (step < 0 && i > stop) {
+        numbers.push(Object::Number(NumberType::Integer(i)));
+        i = match i.checked_add(step) {
+            Some(i) => i,
+            None => break,
+        };
+    }
+    Ok(interpreter.allocate(new_list(numbers)))
+}
+
+/// a snapshot of the elements, so callbacks can change the list freely
+fn elements(list: &Object) -> Result<Vec<Object>, Error> {
+    match list {
+        Object::List(list) => Ok(list.borrow().inner.clone()),
+        _ => Err(Error::coded("E0503", &[])),
+    }
+}
+
+fn new_list(elements: Vec<Object>) -> Object {
+    Object::List(Rc::new(RefCell::new(List::from(elements))))
+}
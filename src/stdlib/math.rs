@@ -0,0 +1,112 @@
+/// the usual math functions, an integer in gives an integer out where
+/// the answer is a whole number that fits, a float otherwise
+use super::native;
+use super::number::number;
+use crate::{Environment, Error, Interpreter, NumberType, Object};
+
+pub fn define(globals: &mut Environment) {
+    globals.define("sqrt", native("sqrt", 1, sqrt));
+    globals.define("abs", native("abs", 1, abs));
+    globals.define("floor", native("floor", 1, floor));
+    globals.define("ceil", native("ceil", 1, ceil));
+    globals.define("pow", native("pow", 2, pow));
+    globals.define("min", native("min", 2, min));
+    globals.define("max", native("max", 2, max));
+    globals.define("sin", native("sin", 1, sin));
+    globals.define("cos", native("cos", 1, cos));
+}
+
+fn sqrt(_: &mut Interpreter, args: &[Object]) -> Result<Object, Error> {
+    float(&args[0], f64::sqrt)
+}
+
+fn sin(_: &mut Interpreter, args: &[Object]) -> Result<Object, Error> {
+    float(&args[0], f64::sin)
+}
+
+fn cos(_: &mut Interpreter, args: &[Object]) -> Result<Object, Error> {
+    float(&args[0], f64::cos)
+}
+
+fn abs(_: &mut Interpreter, args: &[Object]) -> Result<Object, Error> {
+    Ok(Object::Number(absolute(number(&args[0])?)))
+}
+
+/// `floor(n)`, the largest whole number not above `n`
+fn floor(_: &mut Interpreter, args: &[Object]) -> Result<Object, Error> {
+    whole(&args[0], f64::floor)
+}
+
+/// `ceil(n)`, the smallest whole number not below `n`
+fn ceil(_: &mut Interpreter, args: &[Object]) -> Result<Object, Error> {
+    whole(&args[0], f64::ceil)
+}
+
+/// `pow(base, exponent)`, an integer when both are and the exponent
+/// isn't negative
+fn pow(_: &mut Interpreter, args: &[Object]) -> Result<Object, Error> {
+    let (base, exponent) = (number(&args[0])?, number(&args[1])?);
+    if let (NumberType::Integer(base), NumberType::Integer(exponent)) = (base, exponent) {
+        let exact = u32::try_from(*exponent)
+            .ok()
+            .and_then(|exponent| base.checked_pow(exponent));
+        if let Some(power) = exact {
+            return Ok(Object::Number(NumberType::Integer(power)));
+        }
+    }
+    Ok(Object::Number(NumberType::Float(
+        base.as_float().powf(exponent.as_float()),
+    )))
+}
+
+/// `min(a, b)`, the smaller of the two as it was passed in
+fn min(_: &mut Interpreter, args: &[Object]) -> Result<Object, Error> {
+    let (a, b) = (number(&args[0])?, number(&args[1])?);
+    Ok(if b.compare(a).is_some_and(|order| order.is_lt()) {
+        args[1].clone()
+    } else {
+        args[0].clone()
+    })
+}
+
+/// `max(a, b)`, the larger of the two as it was passed in
+fn max(_: &mut Interpreter, args: &[Object]) -> Result<Object, Error> {
+    let (a, b) = (number(&args[0])?, number(&args[1])?);
+    Ok(if b.compare(a).is_some_and(|order| order.is_gt()) {
+        args[1].clone()
+    } else {
+        args[0].clone()
+    })
+}
+
+/// `|n|`, an integer too large to negate becomes a float
+pub(super) fn absolute(n: &NumberType) -> NumberType {
+    match n {
+        NumberType::Integer(i) => match i.checked_abs() {
+            Some(i) => NumberType::Integer(i),
+            None => NumberType::Float((*i as f64).abs()),
+        },
+        NumberType::Float(f) => NumberType::Float(f.abs()),
+    }
+}
+
+fn float(value: &Object, f: fn(f64) -> f64) -> Result<Object, Error> {
+    let n = number(value)?;
+    Ok(Object::Number(NumberType::Float(f(n.as_float()))))
+}
+
+/// round with `f`, an integer stays as it is and a float that rounds
+/// to one in range becomes one
+fn whole(value: &Object, f: fn(f64) -> f64) -> Result<Object, Error> {
+    Ok(Object::Number(match number(value)? {
+        NumberType::Integer(i) => NumberType::Integer(*i),
+        NumberType::Float(x) => {
+            let rounded = f(*x);
+            if rounded.is_finite() && rounded.abs() < i64::MAX as f64 {
+                NumberType::Integer(rounded as i64)
+            } else {
+                NumberType::Float(rounded)
+            }
+        }
+    }))
+}
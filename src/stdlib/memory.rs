@@ -0,0 +1,38 @@
+/// `memoryStats()` counts the live objects by kind, `collectGarbage()`
+/// frees the ones only Rc cycles keep alive and says how many it freed
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use super::native;
+use crate::{Environment, Error, Interpreter, LoxClass, LoxInstance, NumberType, Object};
+
+pub fn define(globals: &mut Environment) {
+    globals.define("memoryStats", native("memoryStats", 0, memory_stats));
+    globals.define(
+        "collectGarbage",
+        native("collectGarbage", 0, collect_garbage),
+    );
+}
+
+fn memory_stats(interpreter: &mut Interpreter, _: &[Object]) -> Result<Object, Error> {
+    let stats = interpreter.heap_mut().stats();
+    let class = LoxClass::new("MemoryStats".to_string(), HashMap::new(), None);
+    let mut instance = LoxInstance::new(Rc::new(RefCell::new(class)));
+    let counts = [
+        ("instances", stats.instances),
+        ("lists", stats.lists),
+        ("functions", stats.functions),
+        ("classes", stats.classes),
+        ("environments", stats.environments),
+    ];
+    for (name, count) in counts {
+        instance.set(name, &Object::Number(NumberType::Integer(count as i64)));
+    }
+    Ok(interpreter.allocate(Object::Instance(Rc::new(RefCell::new(instance)))))
+}
+
+fn collect_garbage(interpreter: &mut Interpreter, _: &[Object]) -> Result<Object, Error> {
+    let freed = interpreter.heap_mut().collect();
+    Ok(Object::Number(NumberType::Integer(freed as i64)))
+}
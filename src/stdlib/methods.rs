@@ -0,0 +1,309 @@
+/// methods on values that are not instances, `"abc".len()` or
+/// `list(1, 2).map(f)`, looked up by the value's type so every built-in
+/// type gets its methods the same way instead of each one being a
+/// global native. a method is only ever bound to values of its own
+/// type, so the bodies can take the type of `this` for granted
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use super::NativeMethodFn;
+use crate::{Error, Function, Interpreter, List, MapRef, NumberType, Object};
+
+/// a method table for each type name, as `Object::type_name` gives it
+#[derive(Default)]
+pub struct BuiltinMethods {
+    tables: HashMap<&'static str, HashMap<String, Function>>,
+}
+
+impl BuiltinMethods {
+    /// the methods every interpreter starts with
+    pub fn standard() -> Self {
+        let mut methods = Self::default();
+        methods.define("string", "len", 0, string_len);
+        methods.define("string", "upper", 0, string_upper);
+        methods.define("string", "lower", 0, string_lower);
+        methods.define("string", "trim", 0, string_trim);
+        methods.define("string", "split", 1, string_split);
+        methods.define("string", "replace", 2, string_replace);
+        methods.define("string", "find", 1, string_find);
+        methods.define("list", "len", 0, list_len);
+        methods.define("list", "map", 1, list_map);
+        methods.define("list", "push", 1, list_push);
+        methods.define("list", "pop", 0, list_pop);
+        methods.define("list", "insert", 2, list_insert);
+        methods.define("list", "remove", 1, list_remove);
+        methods.define("list", "contains", 1, list_contains);
+        methods.define("number", "abs", 0, number_abs);
+        methods.define("map", "len", 0, map_len);
+        methods.define("map", "has", 1, map_has);
+        methods.define("map", "keys", 0, map_keys);
+        methods.define("map", "values", 0, map_values);
+        methods.define("map", "remove", 1, map_remove);
+        for type_name in ["list", "string", "map", "tuple"] {
+            methods.define(type_name, "iter", 0, super::iter::iter_method);
+        }
+        methods
+    }
+
+    /// add or replace the method `name` of values of `type_name`
+    pub fn define(
+        &mut self,
+        type_name: &'static str,
+        name: &str,
+        arity: usize,
+        body: NativeMethodFn,
+    ) {
+        self.tables.entry(type_name).or_default().insert(
+            name.to_string(),
+            Function::NativeMethod {
+                name: name.to_string(),
+                arity,
+                body: Box::new(body),
+                this: None,
+            },
+        );
+    }
+
+    /// the method `name` of `value`, not bound to it yet
+    pub fn get(&self, value: &Object, name: &str) -> Option<&Function> {
+        self.tables.get(value.type_name())?.get(name)
+    }
+
+    /// whether values of this type have any methods at all
+    pub fn has_methods(&self, value: &Object) -> bool {
+        self.tables.contains_key(value.type_name())
+    }
+}
+
+fn string_len(_: &mut Interpreter, this: &Object, _: &[Object]) -> Result<Object, Error> {
+    match this {
+        Object::String(s) => Ok(Object::Number(
+            NumberType::Integer(s.chars().count() as i64),
+        )),
+        _ => unreachable!(),
+    }
+}
+
+fn string_upper(
+    interpreter: &mut Interpreter,
+    this: &Object,
+    _: &[Object],
+) -> Result<Object, Error> {
+    let upper = string(this).to_uppercase();
+    Ok(interpreter.allocate(Object::String(upper.into())))
+}
+
+fn string_lower(
+    interpreter: &mut Interpreter,
+    this: &Object,
+    _: &[Object],
+) -> Result<Object, Error> {
+    let lower = string(this).to_lowercase();
+    Ok(interpreter.allocate(Object::String(lower.into())))
+}
+
+/// `s.trim()`, without the whitespace at either end
+fn string_trim(
+    interpreter: &mut Interpreter,
+    this: &Object,
+    _: &[Object],
+) -> Result<Object, Error> {
+    let trimmed = string(this).trim();
+    Ok(interpreter.allocate(Object::String(trimmed.into())))
+}
+
+/// `s.split(separator)`, a list of the pieces between separators, an
+/// empty separator splits into characters
+fn string_split(
+    interpreter: &mut Interpreter,
+    this: &Object,
+    args: &[Object],
+) -> Result<Object, Error> {
+    let s = string(this);
+    let separator = string_argument("split", &args[0])?;
+    let pieces: Vec<Object> = if separator.is_empty() {
+        s.chars()
+            .map(|c| Object::String(c.to_string().into()))
+            .collect()
+    } else {
+        s.split(separator)
+            .map(|piece| Object::String(piece.into()))
+            .collect()
+    };
+    Ok(interpreter.allocate(Object::List(Rc::new(RefCell::new(List::from(pieces))))))
+}
+
+/// `s.replace(from, to)`, every `from` in `s` changed to `to`
+fn string_replace(
+    interpreter: &mut Interpreter,
+    this: &Object,
+    args: &[Object],
+) -> Result<Object, Error> {
+    let from = string_argument("replace", &args[0])?;
+    let to = string_argument("replace", &args[1])?;
+    let replaced = string(this).replace(from, to);
+    Ok(interpreter.allocate(Object::String(replaced.into())))
+}
+
+/// `s.find(part)`, the character index where `part` first shows up in
+/// `s`, nil when it doesn't
+fn string_find(_: &mut Interpreter, this: &Object, args: &[Object]) -> Result<Object, Error> {
+    let s = string(this);
+    let part = string_argument("find", &args[0])?;
+    Ok(match s.find(part) {
+        Some(at) => Object::Number(NumberType::Integer(s[..at].chars().count() as i64)),
+        None => Object::Nil,
+    })
+}
+
+fn string(this: &Object) -> &str {
+    match this {
+        Object::String(s) => s,
+        _ => unreachable!(),
+    }
+}
+
+pub(super) fn string_argument<'a>(method: &str, arg: &'a Object) -> Result<&'a str, Error> {
+    match arg {
+        Object::String(s) => Ok(s),
+        _ => Err(Error::coded("E0530", &[&method, &arg.type_name()])),
+    }
+}
+
+fn list_len(_: &mut Interpreter, this: &Object, _: &[Object]) -> Result<Object, Error> {
+    match this {
+        Object::List(list) => Ok(Object::Number(NumberType::Integer(
+            list.borrow().inner.len() as i64,
+        ))),
+        _ => unreachable!(),
+    }
+}
+
+/// `list.map(fn)`, the same as `map(list, fn)`
+fn list_map(
+    interpreter: &mut Interpreter,
+    this: &Object,
+    args: &[Object],
+) -> Result<Object, Error> {
+    super::list::map(interpreter, &[this.clone(), args[0].clone()])
+}
+
+/// `list.push(x)`, add `x` at the end
+fn list_push(_: &mut Interpreter, this: &Object, args: &[Object]) -> Result<Object, Error> {
+    let list = modifiable_list(this)?;
+    list.borrow_mut().push(args[0].clone());
+    Ok(Object::Nil)
+}
+
+/// `list.pop()`, take the last element off and give it back
+fn list_pop(_: &mut Interpreter, this: &Object, _: &[Object]) -> Result<Object, Error> {
+    let list = modifiable_list(this)?;
+    let popped = list.borrow_mut().inner.pop();
+    popped.ok_or_else(|| Error::coded("E0528", &[]))
+}
+
+/// `list.insert(index, x)`, put `x` before the element at `index`, an
+/// index of `len()` adds it at the end
+fn list_insert(_: &mut Interpreter, this: &Object, args: &[Object]) -> Result<Object, Error> {
+    let list = modifiable_list(this)?;
+    let len = list.borrow().inner.len();
+    let index = list_index(&args[0], len + 1)?;
+    list.borrow_mut().inner.insert(index, args[1].clone());
+    Ok(Object::Nil)
+}
+
+/// `list.remove(index)`, take the element at `index` out and give it back
+fn list_remove(_: &mut Interpreter, this: &Object, args: &[Object]) -> Result<Object, Error> {
+    let list = modifiable_list(this)?;
+    let len = list.borrow().inner.len();
+    let index = list_index(&args[0], len)?;
+    let removed = list.borrow_mut().inner.remove(index);
+    Ok(removed)
+}
+
+/// `list.contains(x)`, whether an element equals `x`
+fn list_contains(_: &mut Interpreter, this: &Object, args: &[Object]) -> Result<Object, Error> {
+    let Object::List(list) = this else {
+        unreachable!()
+    };
+    let found = list
+        .borrow()
+        .inner
+        .iter()
+        .any(|element| element.equals(&args[0]));
+    Ok(Object::Boolean(found))
+}
+
+/// the list `this` is, unless it is frozen
+fn modifiable_list(this: &Object) -> Result<&Rc<RefCell<List>>, Error> {
+    match this {
+        Object::List(list) if list.borrow().is_frozen() => Err(Error::coded("E0440", &[])),
+        Object::List(list) => Ok(list),
+        _ => unreachable!(),
+    }
+}
+
+/// `index` as a position below `end`
+fn list_index(index: &Object, end: usize) -> Result<usize, Error> {
+    match index {
+        Object::Number(NumberType::Integer(i)) if (0..end as i64).contains(i) => Ok(*i as usize),
+        Object::Number(NumberType::Integer(i)) => Err(Error::coded("E0423", &[&i])),
+        _ => Err(Error::coded("E0529", &[&index.type_name()])),
+    }
+}
+
+fn number_abs(_: &mut Interpreter, this: &Object, _: &[Object]) -> Result<Object, Error> {
+    match this {
+        Object::Number(n) => Ok(Object::Number(super::math::absolute(n))),
+        _ => unreachable!(),
+    }
+}
+
+fn map_len(_: &mut Interpreter, this: &Object, _: &[Object]) -> Result<Object, Error> {
+    Ok(Object::Number(NumberType::Integer(
+        map(this).borrow().len() as i64,
+    )))
+}
+
+/// `map.has(key)`, whether indexing with `key` would find a value
+fn map_has(_: &mut Interpreter, this: &Object, args: &[Object]) -> Result<Object, Error> {
+    Ok(Object::Boolean(map(this).borrow().get(&args[0]).is_some()))
+}
+
+/// `map.keys()`, a list of the keys in the order they were added
+fn map_keys(interpreter: &mut Interpreter, this: &Object, _: &[Object]) -> Result<Object, Error> {
+    let keys: Vec<Object> = map(this)
+        .borrow()
+        .iter()
+        .map(|(key, _)| key.clone())
+        .collect();
+    Ok(interpreter.allocate(Object::List(Rc::new(RefCell::new(List::from(keys))))))
+}
+
+fn map_values(interpreter: &mut Interpreter, this: &Object, _: &[Object]) -> Result<Object, Error> {
+    let values: Vec<Object> = map(this)
+        .borrow()
+        .iter()
+        .map(|(_, value)| value.clone())
+        .collect();
+    Ok(interpreter.allocate(Object::List(Rc::new(RefCell::new(List::from(values))))))
+}
+
+/// `map.remove(key)`, take the entry out and give back its value, nil
+/// when there was none
+fn map_remove(_: &mut Interpreter, this: &Object, args: &[Object]) -> Result<Object, Error> {
+    let map = map(this);
+    if map.borrow().is_frozen() {
+        return Err(Error::coded("E0446", &[]));
+    }
+    let removed = map.borrow_mut().remove(&args[0]);
+    Ok(removed.unwrap_or(Object::Nil))
+}
+
+fn map(this: &Object) -> &MapRef {
+    match this {
+        Object::Map(map) => map,
+        _ => unreachable!(),
+    }
+}
@@ -0,0 +1,80 @@
+/// native functions available to every Lox program
+pub mod debug;
+pub mod functional;
+pub mod help;
+pub mod io;
+pub mod iter;
+pub mod list;
+pub mod math;
+pub mod memory;
+pub mod methods;
+pub mod number;
+pub mod reflect;
+pub mod stats;
+pub mod store;
+pub mod text;
+pub mod time;
+
+pub use methods::BuiltinMethods;
+pub use store::{Store, StoreRef};
+
+use std::ops::RangeInclusive;
+use std::rc::Rc;
+
+use crate::{Environment, Error, Function, Interpreter, Object};
+
+/// the signature every native body has to follow, the interpreter
+/// lets a native call back into Lox with `Interpreter::call_value`
+pub type NativeFn = fn(&mut Interpreter, &[Object]) -> Result<Object, Error>;
+
+/// natives used as methods also receive the bound `this`
+pub type NativeMethodFn = fn(&mut Interpreter, &Object, &[Object]) -> Result<Object, Error>;
+
+/// functions built at run time get back the values they captured
+pub type NativeClosureFn = fn(&mut Interpreter, &[Object], &[Object]) -> Result<Object, Error>;
+
+/// wrap a rust function into a callable Lox object
+pub fn native(name: &str, arity: usize, body: NativeFn) -> Object {
+    native_arities(name, arity..=arity, body)
+}
+
+/// a native whose last arguments are optional, `body` gets however many
+/// of `arities` the call passed
+pub fn native_arities(name: &str, arities: RangeInclusive<usize>, body: NativeFn) -> Object {
+    Object::Callable(Rc::new(Function::Native {
+        name: name.to_string(),
+        arity: *arities.start(),
+        max_arity: *arities.end(),
+        body: Box::new(body),
+        doc: None,
+    }))
+}
+
+/// a native that `help` has something to say about
+pub fn documented(name: &str, arity: usize, doc: &'static str, body: NativeFn) -> Object {
+    Object::Callable(Rc::new(Function::Native {
+        name: name.to_string(),
+        arity,
+        max_arity: arity,
+        body: Box::new(body),
+        doc: Some(doc),
+    }))
+}
+
+/// define all the natives in the global environment
+pub fn define_globals(globals: &mut Environment) {
+    debug::define(globals);
+    functional::define(globals);
+    help::define(globals);
+    io::define(globals);
+    iter::define(globals);
+    list::define(globals);
+    math::define(globals);
+    memory::define(globals);
+    number::define(globals);
+    reflect::define(globals);
+    stats::define(globals);
+    store::define(globals);
+    text::define(globals);
+    time::define(globals);
+}
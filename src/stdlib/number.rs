@@ -0,0 +1,28 @@
+/// converting between integers and floats, for when the kind a number
+/// literal or an operation gave isn't the one wanted
+use super::native;
+use crate::{Environment, Error, Interpreter, NumberType, Object};
+
+pub fn define(globals: &mut Environment) {
+    globals.define("toInt", native("toInt", 1, to_int));
+    globals.define("toFloat", native("toFloat", 1, to_float));
+}
+
+/// `toInt(n)`, `n` as an integer, a float is truncated toward zero
+fn to_int(_: &mut Interpreter, args: &[Object]) -> Result<Object, Error> {
+    let n = number(&args[0])?;
+    Ok(Object::Number(NumberType::Integer(n.as_integer())))
+}
+
+/// `toFloat(n)`, `n` as a float
+fn to_float(_: &mut Interpreter, args: &[Object]) -> Result<Object, Error> {
+    let n = number(&args[0])?;
+    Ok(Object::Number(NumberType::Float(n.as_float())))
+}
+
+pub(super) fn number(value: &Object) -> Result<&NumberType, Error> {
+    match value {
+        Object::Number(n) => Ok(n),
+        _ => Err(Error::coded("E0524", &[&value.type_name()])),
+    }
+}
@@ -0,0 +1,156 @@
+/// looking into instances from Lox, for code that works on any class
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use super::{native, native_arities};
+use crate::{Environment, Error, InstanceRef, Interpreter, List, LoxInstance, Map, Object};
+
+pub fn define(globals: &mut Environment) {
+    globals.define("fieldsOf", native("fieldsOf", 1, fields_of));
+    globals.define("fields", native("fields", 1, fields_of));
+    globals.define("getattr", native_arities("getattr", 2..=3, getattr));
+    globals.define("setattr", native("setattr", 3, setattr));
+    globals.define("hasattr", native("hasattr", 2, hasattr));
+    globals.define("toMap", native("toMap", 1, to_map));
+    globals.define("fromMap", native("fromMap", 2, from_map));
+    globals.define("hasField", native("hasField", 2, has_field));
+    globals.define("removeField", native("removeField", 2, remove_field));
+    globals.define("type", native_arities("type", 1..=2, type_of));
+}
+
+/// `type(value)`, the name of the value's type, "number", "string",
+/// "bool", "nil", "function", "class", "instance", "list" and so on.
+/// `type(obj, true)` gives the class name of an instance instead
+fn type_of(interpreter: &mut Interpreter, args: &[Object]) -> Result<Object, Error> {
+    let class_name = match args.get(1) {
+        None => false,
+        Some(Object::Boolean(class_name)) => *class_name,
+        Some(other) => return Err(Error::coded("E0535", &[&other.type_name()])),
+    };
+    let name = match &args[0] {
+        Object::Instance(instance) if class_name => {
+            instance.borrow().class().borrow().name().to_string()
+        }
+        Object::Boolean(_) => "bool".to_string(),
+        value => value.type_name().to_string(),
+    };
+    Ok(interpreter.allocate(Object::String(name.into())))
+}
+
+/// `fieldsOf(obj)`, the names of the fields of an instance in sorted
+/// order, methods are left out
+fn fields_of(interpreter: &mut Interpreter, args: &[Object]) -> Result<Object, Error> {
+    let Object::Instance(instance) = &args[0] else {
+        return Err(Error::coded("E0406", &[]));
+    };
+    let mut names: Vec<String> = instance
+        .borrow()
+        .fields()
+        .map(|(name, _)| name.clone())
+        .collect();
+    names.sort();
+    let names: Vec<Object> = names
+        .into_iter()
+        .map(|name| interpreter.allocate(Object::String(name.into())))
+        .collect();
+    let list = Object::List(Rc::new(RefCell::new(List::from(names))));
+    Ok(interpreter.allocate(list))
+}
+
+/// `hasField(obj, name)`, whether the instance has the field, a method
+/// of the same name doesn't count
+fn has_field(_interpreter: &mut Interpreter, args: &[Object]) -> Result<Object, Error> {
+    let (instance, name) = instance_and_name(args)?;
+    let has = instance.borrow().field(name).is_some();
+    Ok(Object::Boolean(has))
+}
+
+/// `removeField(obj, name)`, take the field off the instance and give
+/// back its value, nil when there was none. methods of the same name
+/// show through again afterwards
+fn remove_field(_interpreter: &mut Interpreter, args: &[Object]) -> Result<Object, Error> {
+    let (instance, name) = instance_and_name(args)?;
+    let removed = instance.borrow_mut().remove(name);
+    Ok(removed.unwrap_or(Object::Nil))
+}
+
+/// `getattr(obj, name)`, what `obj.name` gives with the name in a
+/// string, a field or a bound method. `getattr(obj, name, default)`
+/// gives the default instead of an error when there is neither
+fn getattr(interpreter: &mut Interpreter, args: &[Object]) -> Result<Object, Error> {
+    let (instance, name) = instance_and_name(args)?;
+    if let Some(field) = instance.borrow().field(name) {
+        return Ok(field.clone());
+    }
+    let method = instance.borrow().class().borrow().get_method(name);
+    match (method, args.get(2)) {
+        (Some(method), _) => {
+            let method = method.bind(args[0].clone());
+            Ok(interpreter.allocate(Object::Callable(Rc::new(method))))
+        }
+        (None, Some(default)) => Ok(default.clone()),
+        (None, None) => Err(Error::coded("E0404", &[&name])),
+    }
+}
+
+/// `setattr(obj, name, value)`, `obj.name = value` with the name in a
+/// string
+fn setattr(_interpreter: &mut Interpreter, args: &[Object]) -> Result<Object, Error> {
+    let (instance, name) = instance_and_name(args)?;
+    instance.borrow_mut().set(name, &args[2]);
+    Ok(args[2].clone())
+}
+
+/// `hasattr(obj, name)`, whether `obj.name` would find a field or a
+/// method
+fn hasattr(_interpreter: &mut Interpreter, args: &[Object]) -> Result<Object, Error> {
+    let (instance, name) = instance_and_name(args)?;
+    let instance = instance.borrow();
+    let has =
+        instance.field(name).is_some() || instance.class().borrow().get_method(name).is_some();
+    Ok(Object::Boolean(has))
+}
+
+fn instance_and_name(args: &[Object]) -> Result<(&InstanceRef, &str), Error> {
+    let Object::Instance(instance) = &args[0] else {
+        return Err(Error::coded("E0406", &[]));
+    };
+    let Object::String(name) = &args[1] else {
+        return Err(Error::coded("E0523", &[&args[1].type_name()]));
+    };
+    Ok((instance, name))
+}
+
+/// `toMap(obj)`, a map from the names of an instance's fields to their
+/// values, in sorted order like `fieldsOf`
+fn to_map(interpreter: &mut Interpreter, args: &[Object]) -> Result<Object, Error> {
+    let Object::Instance(instance) = &args[0] else {
+        return Err(Error::coded("E0406", &[]));
+    };
+    let instance = instance.borrow();
+    let mut fields: Vec<(&String, &Object)> = instance.fields().collect();
+    fields.sort_by(|a, b| a.0.cmp(b.0));
+    let mut map = Map::new();
+    for (name, value) in fields {
+        let name = interpreter.allocate(Object::String(name.as_str().into()));
+        map.insert(name, value.clone());
+    }
+    Ok(interpreter.allocate(Object::Map(Rc::new(RefCell::new(map)))))
+}
+
+/// `fromMap(Class, map)`, an instance of the class with a field for
+/// every entry of the map. `init` is not run, so this gives back what
+/// `toMap` took apart
+fn from_map(interpreter: &mut Interpreter, args: &[Object]) -> Result<Object, Error> {
+    let (Object::Class(class), Object::Map(map)) = (&args[0], &args[1]) else {
+        return Err(Error::coded("E0522", &[]));
+    };
+    let mut instance = LoxInstance::new(class.clone());
+    for (key, value) in map.borrow().iter() {
+        let Object::String(name) = key else {
+            return Err(Error::coded("E0523", &[&key.type_name()]));
+        };
+        instance.set(name, value);
+    }
+    Ok(interpreter.allocate(Object::Instance(Rc::new(RefCell::new(instance)))))
+}
@@ -0,0 +1,28 @@
+/// `stats()`, the interpreter's runtime counters as an instance with
+/// the fields statements, calls, allocations and maxCallDepth
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use super::native;
+use crate::{Environment, Error, Interpreter, LoxClass, LoxInstance, NumberType, Object};
+
+pub fn define(globals: &mut Environment) {
+    globals.define("stats", native("stats", 0, stats));
+}
+
+fn stats(interpreter: &mut Interpreter, _: &[Object]) -> Result<Object, Error> {
+    let stats = interpreter.stats();
+    let class = LoxClass::new("Stats".to_string(), HashMap::new(), None);
+    let mut instance = LoxInstance::new(Rc::new(RefCell::new(class)));
+    let counters = [
+        ("statements", stats.statements),
+        ("calls", stats.calls),
+        ("allocations", stats.allocations),
+        ("maxCallDepth", stats.max_call_depth),
+    ];
+    for (name, count) in counters {
+        instance.set(name, &Object::Number(NumberType::Integer(count as i64)));
+    }
+    Ok(interpreter.allocate(Object::Instance(Rc::new(RefCell::new(instance)))))
+}
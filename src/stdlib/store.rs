@@ -0,0 +1,222 @@
+/// a tiny persistent key-value store
+///
+/// every store is backed by a text file, one entry per line:
+/// `key<TAB>value`, where the value is written the way it would be
+/// spelled in Lox source (`"string"`, `1`, `1.5`, `true`, `nil`).
+/// changes are written through to disk immediately.
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::fs;
+use std::rc::Rc;
+
+use super::native;
+use crate::{Environment, Error, Interpreter, NumberType, Object};
+
+pub type StoreRef = Rc<RefCell<Store>>;
+
+#[derive(Debug, Clone)]
+pub struct Store {
+    path: String,
+    entries: BTreeMap<String, Object>,
+}
+
+impl Store {
+    /// open the store at `path`, a missing file is an empty store
+    pub fn open(path: &str) -> Result<Self, Error> {
+        let mut entries = BTreeMap::new();
+        match fs::read_to_string(path) {
+            Ok(content) => {
+                for (i, line) in content.lines().enumerate() {
+                    if line.is_empty() {
+                        continue;
+                    }
+                    let (key, value) = decode_entry(line)
+                        .ok_or_else(|| Error::coded("E0512", &[&path, &(i + 1)]))?;
+                    entries.insert(key, value);
+                }
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => return Err(Error::coded("E0510", &[&path, &err])),
+        }
+        Ok(Self {
+            path: path.to_string(),
+            entries,
+        })
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Object> {
+        self.entries.get(key)
+    }
+
+    pub fn set(&mut self, key: &str, value: Object) -> Result<(), Error> {
+        encode_value(&value)?;
+        self.entries.insert(key.to_string(), value);
+        self.save()
+    }
+
+    pub fn delete(&mut self, key: &str) -> Result<bool, Error> {
+        if self.entries.remove(key).is_none() {
+            return Ok(false);
+        }
+        self.save()?;
+        Ok(true)
+    }
+
+    /// write the whole store, through a temporary file so a crash
+    /// never leaves a half written store behind
+    fn save(&self) -> Result<(), Error> {
+        let mut content = String::new();
+        for (key, value) in self.entries.iter() {
+            content.push_str(&escape(key));
+            content.push('\t');
+            content.push_str(&encode_value(value)?);
+            content.push('\n');
+        }
+        let tmp = format!("{}.tmp", self.path);
+        fs::write(&tmp, content)
+            .and_then(|_| fs::rename(&tmp, &self.path))
+            .map_err(|err| Error::coded("E0511", &[&self.path, &err]))
+    }
+}
+
+impl Display for Store {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<store {}>", self.path)
+    }
+}
+
+pub fn define(globals: &mut Environment) {
+    globals.define("storeOpen", native("storeOpen", 1, store_open));
+    globals.define("storeGet", native("storeGet", 2, store_get));
+    globals.define("storeSet", native("storeSet", 3, store_set));
+    globals.define("storeDelete", native("storeDelete", 2, store_delete));
+}
+
+fn store_open(_: &mut Interpreter, args: &[Object]) -> Result<Object, Error> {
+    match &args[0] {
+        Object::String(path) => Ok(Object::Store(Rc::new(RefCell::new(Store::open(path)?)))),
+        _ => Err(Error::coded("E0506", &[])),
+    }
+}
+
+fn store_get(_: &mut Interpreter, args: &[Object]) -> Result<Object, Error> {
+    let (store, key) = store_and_key(args)?;
+    let value = store.borrow().get(key).cloned();
+    Ok(value.unwrap_or(Object::Nil))
+}
+
+fn store_set(_: &mut Interpreter, args: &[Object]) -> Result<Object, Error> {
+    let (store, key) = store_and_key(args)?;
+    store.borrow_mut().set(key, args[2].clone())?;
+    Ok(args[2].clone())
+}
+
+fn store_delete(_: &mut Interpreter, args: &[Object]) -> Result<Object, Error> {
+    let (store, key) = store_and_key(args)?;
+    let removed = store.borrow_mut().delete(key)?;
+    Ok(Object::Boolean(removed))
+}
+
+fn store_and_key(args: &[Object]) -> Result<(&StoreRef, &str), Error> {
+    match (&args[0], &args[1]) {
+        (Object::Store(store), Object::String(key)) => Ok((store, key)),
+        (Object::Store(_), _) => Err(Error::coded("E0507", &[])),
+        _ => Err(Error::coded("E0508", &[])),
+    }
+}
+
+fn encode_value(value: &Object) -> Result<String, Error> {
+    match value {
+        Object::Nil => Ok("nil".to_string()),
+        Object::Boolean(b) => Ok(b.to_string()),
+        Object::Number(NumberType::Integer(i)) => Ok(i.to_string()),
+        // debug format keeps the ".0" so floats stay floats
+        Object::Number(NumberType::Float(f)) => Ok(format!("{:?}", f)),
+        Object::String(s) => Ok(format!("\"{}\"", escape(s))),
+        _ => Err(Error::coded("E0509", &[])),
+    }
+}
+
+fn decode_entry(line: &str) -> Option<(String, Object)> {
+    let (key, value) = line.split_once('\t')?;
+    let value = match value {
+        "nil" => Object::Nil,
+        "true" => Object::Boolean(true),
+        "false" => Object::Boolean(false),
+        _ if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') => {
+            Object::String(unescape(&value[1..value.len() - 1])?.into())
+        }
+        _ => {
+            if let Ok(i) = value.parse::<i64>() {
+                Object::Number(NumberType::Integer(i))
+            } else {
+                Object::Number(NumberType::Float(value.parse::<f64>().ok()?))
+            }
+        }
+    };
+    Some((unescape(key)?, value))
+}
+
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '"' => escaped.push_str("\\\""),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn unescape(s: &str) -> Option<String> {
+    let mut unescaped = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next()? {
+            '\\' => unescaped.push('\\'),
+            't' => unescaped.push('\t'),
+            'n' => unescaped.push('\n'),
+            'r' => unescaped.push('\r'),
+            '"' => unescaped.push('"'),
+            _ => return None,
+        }
+    }
+    Some(unescaped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_entry_round_trip() {
+        let values = vec![
+            Object::Nil,
+            Object::Boolean(false),
+            Object::Number(NumberType::Integer(42)),
+            Object::Number(NumberType::Float(1.0)),
+            Object::String("tab\there \"quoted\"\nnew line".into()),
+        ];
+        for value in values {
+            let line = format!("{}\t{}", escape("a\tkey"), encode_value(&value).unwrap());
+            let (key, decoded) = decode_entry(&line).unwrap();
+            assert_eq!(key, "a\tkey");
+            assert!(decoded.equals(&value), "{} != {}", decoded, value);
+        }
+    }
+
+    #[test]
+    fn test_corrupted_entry() {
+        assert!(decode_entry("no tab here").is_none());
+        assert!(decode_entry("key\tnot a value").is_none());
+    }
+}
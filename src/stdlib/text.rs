@@ -0,0 +1,193 @@
+/// natives for working through text with regular expressions, filling
+/// in `${name}` placeholders and turning values into text
+///
+/// a string literal interpolates `${...}` itself before any native sees
+/// it, so placeholders meant for `template` or `expandEnv` have to be
+/// written `\${name}` in source
+///
+/// `scan` hands back an iterator: an object whose `next()` gives one
+/// value per call and nil once there are no more, so a long input is
+/// matched a piece at a time instead of all at once
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use regex::Regex;
+
+use super::{documented, native};
+use crate::{
+    instance_field, ClassBuilder, ClassRef, Environment, Error, Interpreter, List, LoxInstance,
+    NumberType, Object,
+};
+
+thread_local! {
+    /// compiled patterns, `next()` is called far more often than `scan`
+    static PATTERNS: RefCell<HashMap<String, Regex>> = RefCell::new(HashMap::new());
+
+    /// the class of the iterators `scan` returns
+    static MATCHES: ClassRef = ClassBuilder::new("Matches")
+        .method("next", 0, next_match)
+        .build();
+}
+
+pub fn define(globals: &mut Environment) {
+    globals.define("str", native("str", 1, str));
+    globals.define("scan", native("scan", 2, scan));
+    globals.define(
+        "template",
+        documented("template", 2, TEMPLATE_DOC, template),
+    );
+    globals.define(
+        "expandEnv",
+        documented("expandEnv", 1, EXPAND_ENV_DOC, expand_env),
+    );
+}
+
+/// `scan(s, pattern)`, an iterator over the matches of `pattern` in `s`.
+/// a match is the matched text, or a list of it and its capture groups
+/// when the pattern has any
+fn scan(interpreter: &mut Interpreter, args: &[Object]) -> Result<Object, Error> {
+    let (Object::String(_), Object::String(pattern)) = (&args[0], &args[1]) else {
+        return Err(Error::coded("E0514", &[]));
+    };
+    compiled(pattern)?;
+    let mut matches = LoxInstance::new(MATCHES.with(ClassRef::clone));
+    matches.set("text", &args[0]);
+    matches.set("pattern", &args[1]);
+    matches.set("position", &Object::Number(NumberType::Integer(0)));
+    Ok(interpreter.allocate(Object::Instance(Rc::new(RefCell::new(matches)))))
+}
+
+/// `matches.next()`, the next match or nil after the last one
+fn next_match(
+    interpreter: &mut Interpreter,
+    this: &Object,
+    _args: &[Object],
+) -> Result<Object, Error> {
+    let (
+        Object::String(text),
+        Object::String(pattern),
+        Object::Number(NumberType::Integer(position)),
+    ) = (
+        instance_field(this, "text")?,
+        instance_field(this, "pattern")?,
+        instance_field(this, "position")?,
+    )
+    else {
+        return Err(Error::coded("E0515", &[]));
+    };
+    let regex = compiled(&pattern)?;
+    let start = position as usize;
+    let mut groups = regex.capture_locations();
+    if start > text.len() || regex.captures_read_at(&mut groups, &text, start).is_none() {
+        return Ok(Object::Nil);
+    }
+    let (from, to) = groups.get(0).unwrap();
+    // an empty match would be found again at the same place
+    let next = if from == to {
+        to + text[to..].chars().next().map_or(1, char::len_utf8)
+    } else {
+        to
+    };
+    if let Object::Instance(instance) = this {
+        instance.borrow_mut().set(
+            "position",
+            &Object::Number(NumberType::Integer(next as i64)),
+        );
+    }
+
+    let mut string = |text: Option<&str>| match text {
+        Some(text) => interpreter.allocate(Object::String(text.into())),
+        None => Object::Nil,
+    };
+    if groups.len() == 1 {
+        return Ok(string(Some(&text[from..to])));
+    }
+    let groups: Vec<Object> = (0..groups.len())
+        .map(|i| string(groups.get(i).map(|(from, to)| &text[from..to])))
+        .collect();
+    Ok(interpreter.allocate(Object::List(Rc::new(RefCell::new(List::from(groups))))))
+}
+
+/// `str(x)`, x as print would show it
+fn str(interpreter: &mut Interpreter, args: &[Object]) -> Result<Object, Error> {
+    let text = interpreter.display(&args[0])?;
+    Ok(interpreter.allocate(Object::String(text.into())))
+}
+
+const TEMPLATE_DOC: &str = "Fill each ${name} in s from the map or instance values. \
+In a string literal write the placeholder as \\${name}, a bare ${name} is interpolated first.";
+
+const EXPAND_ENV_DOC: &str = "Replace each ${name} in s with the environment variable name. \
+In a string literal write the placeholder as \\${name}, a bare ${name} is interpolated first.";
+
+/// `template(s, values)`, `s` with every `${name}` replaced by the
+/// value of the key `name` of the map `values`, or the field `name`
+/// when `values` is an instance, see `TEMPLATE_DOC` for how to write
+/// the placeholders in a string literal
+fn template(interpreter: &mut Interpreter, args: &[Object]) -> Result<Object, Error> {
+    let (Object::String(s), Object::Map(_) | Object::Instance(_)) = (&args[0], &args[1]) else {
+        return Err(Error::coded("E0517", &[]));
+    };
+    let value = |name: &str| match &args[1] {
+        Object::Map(map) => map.borrow().get(&Object::String(name.into())).cloned(),
+        Object::Instance(instance) => instance.borrow().field(name).cloned(),
+        _ => None,
+    };
+    let filled = substitute(s, |name| match value(name) {
+        Some(value) => interpreter.display(&value),
+        None => Err(Error::coded("E0519", &[&name])),
+    })?;
+    Ok(interpreter.allocate(Object::String(filled.into())))
+}
+
+/// `expandEnv(s)`, `s` with every `${name}` replaced by the environment
+/// variable `name`, or nothing when it is unset like a shell does
+fn expand_env(interpreter: &mut Interpreter, args: &[Object]) -> Result<Object, Error> {
+    let Object::String(s) = &args[0] else {
+        return Err(Error::coded("E0518", &[]));
+    };
+    let expanded = substitute(s, |name| Ok(std::env::var(name).unwrap_or_default()))?;
+    Ok(interpreter.allocate(Object::String(expanded.into())))
+}
+
+/// `s` with each `${name}` replaced by `value(name)`, a `$` not
+/// followed by `{` is kept as it is
+fn substitute(
+    s: &str,
+    mut value: impl FnMut(&str) -> Result<String, Error>,
+) -> Result<String, Error> {
+    let mut filled = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find("${") {
+        filled.push_str(&rest[..start]);
+        let Some(end) = rest[start..].find('}') else {
+            return Err(Error::coded("E0520", &[]));
+        };
+        filled.push_str(&value(rest[start + 2..start + end].trim())?);
+        rest = &rest[start + end + 1..];
+    }
+    filled.push_str(rest);
+    Ok(filled)
+}
+
+/// the compiled form of `pattern`, compiling it the first time
+fn compiled(pattern: &str) -> Result<Regex, Error> {
+    PATTERNS.with(|patterns| {
+        if let Some(regex) = patterns.borrow().get(pattern) {
+            return Ok(regex.clone());
+        }
+        let regex = Regex::new(pattern).map_err(|err| {
+            // the parser's message ends with a line saying what is wrong,
+            // above it is a drawing of where
+            let err = err.to_string();
+            let reason = err.lines().last().unwrap_or_default();
+            let reason = reason.trim_start_matches("error: ");
+            Error::coded("E0516", &[&pattern, &reason])
+        })?;
+        patterns
+            .borrow_mut()
+            .insert(pattern.to_string(), regex.clone());
+        Ok(regex)
+    })
+}
@@ -0,0 +1,23 @@
+/// reading the clock the interpreter was given
+use super::native;
+use crate::{Environment, Error, Interpreter, NumberType, Object};
+
+pub fn define(globals: &mut Environment) {
+    globals.define("clock", native("clock", 0, clock));
+    globals.define(
+        "monotonicMillis",
+        native("monotonicMillis", 0, monotonic_millis),
+    );
+}
+
+/// `clock()`, seconds since the unix epoch
+fn clock(interpreter: &mut Interpreter, _: &[Object]) -> Result<Object, Error> {
+    Ok(Object::Number(NumberType::Float(interpreter.clock().now())))
+}
+
+/// `monotonicMillis()`, milliseconds since the interpreter started, for
+/// timing code, changing the system time doesn't affect it
+fn monotonic_millis(interpreter: &mut Interpreter, _: &[Object]) -> Result<Object, Error> {
+    let millis = interpreter.clock().monotonic_millis();
+    Ok(Object::Number(NumberType::Integer(millis as i64)))
+}
@@ -2,13 +2,18 @@ use std::fmt::Display;
 
 use crate::NumberType;
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Token {
     pub lexeme: String,
     pub token_type: TokenType,
-    // pub literal: Literal,
+    /// the value of a number or string token, read by the scanner
+    pub literal: Literal,
     pub line: usize,
     pub column: usize,
+    /// byte offset of the lexeme in the source
+    pub offset: usize,
+    /// the `///` comment lines right before the token
+    pub doc: Option<String>,
 }
 
 impl Token {
@@ -16,8 +21,11 @@ impl Token {
         Self {
             lexeme: lexeme.to_string(),
             token_type,
+            literal: Literal::Nil,
             line,
             column,
+            offset: 0,
+            doc: None,
         }
     }
     pub fn check_single_character_token(ch: char) -> Option<TokenType> {
@@ -71,6 +79,18 @@ impl Token {
             "var" => Some(TokenType::Var),
             "while" => Some(TokenType::While),
             "list" => Some(TokenType::List),
+            "break" => Some(TokenType::Break),
+            "continue" => Some(TokenType::Continue),
+            "assert" => Some(TokenType::Assert),
+            "match" => Some(TokenType::Match),
+            "defer" => Some(TokenType::Defer),
+            "const" => Some(TokenType::Const),
+            "try" => Some(TokenType::Try),
+            "catch" => Some(TokenType::Catch),
+            "finally" => Some(TokenType::Finally),
+            "throw" => Some(TokenType::Throw),
+            "in" => Some(TokenType::In),
+            "is" => Some(TokenType::Is),
             _ => None,
         }
     }
@@ -110,10 +130,24 @@ pub enum TokenType {
     GreaterEqual,
     Less,
     LessEqual,
+    /// `?[`, indexing that gives nil when the value indexed is nil
+    QuestionBracket,
+    /// `?.`, property access that gives nil when the object is nil
+    QuestionDot,
+    /// `??`, the left operand unless it is nil
+    QuestionQuestion,
+    /// `->` between a match pattern and its value
+    Arrow,
+    /// `..` between the bounds of a range pattern
+    DotDot,
+    /// `...` before the rest of a list pattern
+    Ellipsis,
 
     // Literals.
     Identifier,
     String,
+    /// the part of a string up to a `${`, or between a `}` and the next `${`
+    Interpolation,
     Number,
 
     // Keywords.
@@ -134,11 +168,23 @@ pub enum TokenType {
     Var,
     While,
     List,
+    Break,
+    Continue,
+    Assert,
+    Match,
+    Defer,
+    Const,
+    Try,
+    Catch,
+    Finally,
+    Throw,
+    In,
+    Is,
 
     Eof,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum Literal {
     String(String),
     Number(NumberType),
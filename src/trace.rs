@@ -0,0 +1,87 @@
+/// the `--trace-exec` output, one JSON object per line for every
+/// executed statement, call and variable write, so two runs of a
+/// program can be diffed
+use std::io::Write;
+
+use crate::{Ast, Error, Object, Stmt};
+
+pub struct Trace {
+    out: Box<dyn Write>,
+}
+
+impl Trace {
+    pub fn new(out: Box<dyn Write>) -> Self {
+        Self { out }
+    }
+
+    pub fn stmt(&mut self, stmt: &Stmt, ast: &Ast) -> Result<(), Error> {
+        self.event(&format!(
+            r#"{{"event":"stmt","line":{},"kind":"{}"}}"#,
+            line(stmt.line(ast)),
+            stmt.kind()
+        ))
+    }
+
+    pub fn call(&mut self, line: usize, callee: &Object, args: usize) -> Result<(), Error> {
+        let callee = match callee {
+            Object::Callable(function) => function.name().to_string(),
+            callee => callee.to_string(),
+        };
+        self.event(&format!(
+            r#"{{"event":"call","line":{},"callee":{},"args":{}}}"#,
+            line,
+            json_string(&callee),
+            args
+        ))
+    }
+
+    /// a `var` declaration or an assignment
+    pub fn write(&mut self, line: usize, name: &str, value: &Object) -> Result<(), Error> {
+        self.event(&format!(
+            r#"{{"event":"write","line":{},"name":{},"value":{}}}"#,
+            line,
+            json_string(name),
+            json_string(&value.to_string())
+        ))
+    }
+
+    fn event(&mut self, json: &str) -> Result<(), Error> {
+        writeln!(self.out, "{}", json).map_err(|err| Error::coded("E0538", &[&err]))
+    }
+}
+
+/// statements made of literals alone don't know their line
+fn line(line: Option<usize>) -> String {
+    match line {
+        Some(line) => line.to_string(),
+        None => "null".to_string(),
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut json = String::with_capacity(s.len() + 2);
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_string() {
+        assert_eq!(json_string("a\"b\\c\nd\u{1}"), r#""a\"b\\c\nd\u0001""#);
+    }
+}
@@ -1,3 +1,5 @@
+use std::cell::Cell;
+
 
 pub fn is_digit(c: char) -> bool {
     c.is_ascii_digit()
@@ -11,4 +13,32 @@ pub fn is_alpha_numeric(c: char) -> bool {
     is_alpha(c) || is_digit(c)
 }
 
+/// room left on the stack before a deeper call moves to a new segment,
+/// enough for the levels between two checks
+const RED_ZONE: usize = 2 * 1024 * 1024;
+/// size of every extra stack segment
+const STACK_SEGMENT: usize = 8 * 1024 * 1024;
+/// the stack is only measured every this many levels, measuring on
+/// every node costs a quarter of the run time of a call heavy program
+const CHECK_EVERY: usize = 32;
+
+thread_local! {
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+/// run `f` on a fresh stack segment when the current one is nearly
+/// full, so deeply nested programs don't overflow the host stack
+pub fn grow_stack<R>(f: impl FnOnce() -> R) -> R {
+    let depth = DEPTH.with(|depth| {
+        depth.set(depth.get() + 1);
+        depth.get()
+    });
+    let result = if depth.is_multiple_of(CHECK_EVERY) {
+        stacker::maybe_grow(RED_ZONE, STACK_SEGMENT, f)
+    } else {
+        f()
+    };
+    DEPTH.with(|depth| depth.set(depth.get() - 1));
+    result
+}
 
@@ -1,4 +1,4 @@
-/// reference: https://github.com/ceronman/loxido
+// reference: https://github.com/ceronman/loxido
 
 use std::path::PathBuf;
 use std::{env, fs, process::Command};
@@ -29,6 +29,8 @@ struct RuntimeError {
 }
 
 struct Expected {
+    /// command line options from a `// flags:` comment
+    flags: Vec<String>,
     out: Vec<String>,
     compile_err: Vec<String>,
     runtime_err: Option<RuntimeError>,
@@ -39,8 +41,10 @@ fn parse_comments(path: &PathBuf) -> Expected {
     let error_re = Regex::new(r"// (Error.*)").unwrap();
     let error_line_re = Regex::new(r"// \[(?:c )?line (\d+)\] (Error.*)").unwrap();
     let runtime_error_re = Regex::new(r"// expect runtime error: (.+)").unwrap();
+    let flags_re = Regex::new(r"^// flags: (.+)").unwrap();
 
     let mut expected = Expected {
+        flags: vec![],
         out: vec![],
         compile_err: vec![],
         runtime_err: None,
@@ -49,6 +53,10 @@ fn parse_comments(path: &PathBuf) -> Expected {
     println!("{}", path.display());
     let content = fs::read_to_string(path).unwrap();
     for (i, line) in content.lines().enumerate() {
+        if let Some(m) = flags_re.captures(line) {
+            let flags = m.get(1).unwrap().as_str().split_whitespace();
+            expected.flags.extend(flags.map(|flag| flag.to_owned()));
+        }
         if let Some(m) = output_re.captures(line) {
             let s = m.get(1).unwrap().as_str().to_owned();
             expected.out.push(s);
@@ -82,7 +90,11 @@ fn run_file_test(filename: &str) {
     path.push(filename);
     let expected = parse_comments(&path);
 
-    let output = loxido_command().arg(path).output().unwrap();
+    let output = loxido_command()
+        .args(&expected.flags)
+        .arg(path)
+        .output()
+        .unwrap();
 
     let out: Vec<String> = String::from_utf8(output.stdout)
         .unwrap()